@@ -189,7 +189,8 @@ class M3Env(ninjagen.Env):
             vars = { 'cargoflags' : 'build -Z build-std=core,alloc --target ' + env['TRIPLE'] + ' ' + ' '.join(env['CRGFLAGS']) }
         ))
 
-    def build_fs(self, gen, out, dir, blocks, inodes):
+    def build_fs(self, gen, out, dir, blocks, inodes, journal = 0, hashdirs = False,
+                 reflink = False):
         deps = [ninjagen.BuildPath(env['TOOLDIR'] + '/mkm3fs')]
 
         global bins
@@ -207,6 +208,12 @@ class M3Env(ninjagen.Env):
                 self.install_as(gen, dst, src, flags = '-d')
             deps += [dst]
 
+        flags = []
+        if hashdirs:
+            flags += ['-hashdirs']
+        if reflink:
+            flags += ['-reflink']
+
         out = ninjagen.BuildPath(env['BUILDDIR'] + '/' + out)
         gen.add_build(ninjagen.BuildEdge(
             'mkm3fs',
@@ -216,7 +223,9 @@ class M3Env(ninjagen.Env):
             vars = {
                 'dir' : ninjagen.BuildPath.new(self, dir),
                 'blocks' : blocks,
-                'inodes' : inodes
+                'inodes' : inodes,
+                'journal' : journal,
+                'flags' : ' '.join(flags)
             }
         ))
         return out
@@ -343,7 +352,7 @@ else:
 gen = ninjagen.Generator()
 
 gen.add_rule('mkm3fs', ninjagen.Rule(
-    cmd = env['TOOLDIR'] + '/mkm3fs $out $dir $blocks $inodes 0',
+    cmd = env['TOOLDIR'] + '/mkm3fs $out $dir $blocks $inodes 0 -j $journal $flags',
     desc = 'MKFS $out',
 ))
 gen.add_rule('elf2hex', ninjagen.Rule(
@@ -50,15 +50,15 @@ pub fn main() -> i32 {
     let mut buffer = [0u8; 1024];
 
     if proto == "tcp" {
-        let mut tcp_socket = TcpSocket::new(
+        let mut tcp_listener = TcpSocket::new(
             StreamSocketArgs::new(nm)
                 .send_buffer(64 * 1024)
                 .recv_buffer(768 * 1024),
         )
         .expect("creating TCP socket failed");
 
-        tcp_socket.listen(port).expect("listen failed");
-        let ep = tcp_socket.accept().expect("accept failed");
+        tcp_listener.listen(port).expect("listen failed");
+        let (mut tcp_socket, ep) = tcp_listener.accept().expect("accept failed");
         println!("Accepted remote endpoint {}", ep);
 
         for _ in 0..repeats {
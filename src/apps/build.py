@@ -16,6 +16,7 @@ dirs = [
     'libctest',
     'msgchan',
     'netechoserver',
+    'netstat',
     'noop',
     'parchksum',
     'ping',
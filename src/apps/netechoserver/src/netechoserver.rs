@@ -17,11 +17,12 @@
 #![no_std]
 
 use m3::com::Semaphore;
+use m3::errors::Code;
 use m3::net::{
     DGramSocket, DgramSocketArgs, State, StreamSocket, StreamSocketArgs, TcpSocket, UdpSocket,
 };
 use m3::session::NetworkManager;
-use m3::vfs::{FileEvent, FileWaiter};
+use m3::vfs::{File, FileEvent, FileRef, FileWaiter};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -34,12 +35,16 @@ pub fn main() -> i32 {
     )
     .expect("creating UDP socket failed");
 
-    let mut tcp_socket = TcpSocket::new(
+    let mut tcp_listener = TcpSocket::new(
         StreamSocketArgs::new(nm)
             .send_buffer(64 * 1024)
             .recv_buffer(256 * 1024),
     )
     .expect("creating TCP socket failed");
+    // we only use the listener to accept connections
+    tcp_listener
+        .set_blocking(false)
+        .expect("making TCP socket non-blocking failed");
 
     udp_socket.bind(1337).expect("bind failed");
 
@@ -52,14 +57,28 @@ pub fn main() -> i32 {
 
     let mut buffer = [0u8; 1024];
 
+    tcp_listener.listen(1338).expect("listen failed");
+    sem_tcp.up().expect("tcp up failed");
+
     let mut waiter = FileWaiter::default();
-    waiter.add(tcp_socket.fd(), FileEvent::INPUT);
+    waiter.add(tcp_listener.fd(), FileEvent::INPUT);
     waiter.add(udp_socket.fd(), FileEvent::INPUT);
 
+    // we serve one TCP client at a time; the clients synchronize via the semaphore
+    let mut tcp_socket: Option<FileRef<TcpSocket>> = None;
+
     loop {
-        if tcp_socket.state() == State::Closed {
-            tcp_socket.listen(1338).expect("listen failed");
-            sem_tcp.up().expect("tcp up failed");
+        if tcp_socket.is_none() {
+            match tcp_listener.accept() {
+                Ok((socket, _ep)) => {
+                    // don't wake up for further connections until this one is done
+                    waiter.remove(tcp_listener.fd());
+                    waiter.add(socket.fd(), FileEvent::INPUT);
+                    tcp_socket = Some(socket);
+                },
+                Err(e) if e.code() == Code::WouldBlock => {},
+                Err(e) => panic!("accept failed: {}", e),
+            }
         }
 
         if udp_socket.has_data() {
@@ -69,16 +88,27 @@ pub fn main() -> i32 {
             }
         }
 
-        if tcp_socket.has_data() {
+        let tcp_has_data = tcp_socket.as_ref().map_or(false, |s| s.has_data());
+        if tcp_has_data {
+            let socket = tcp_socket.as_mut().unwrap();
             // ignore errors
-            if let Ok(size) = tcp_socket.recv(&mut buffer) {
-                tcp_socket.send(&buffer[0..size]).ok();
+            if let Ok(size) = socket.recv(&mut buffer) {
+                socket.send(&buffer[0..size]).ok();
             }
         }
 
-        if !udp_socket.has_data() && !tcp_socket.has_data() {
-            if tcp_socket.state() == State::RemoteClosed {
-                tcp_socket.abort().unwrap();
+        if !udp_socket.has_data() && !tcp_has_data {
+            let tcp_done = tcp_socket.as_ref().map_or(false, |s| {
+                s.state() == State::RemoteClosed || s.state() == State::Closed
+            });
+            if tcp_done {
+                let mut socket = tcp_socket.take().unwrap();
+                waiter.remove(socket.fd());
+                socket.abort().unwrap();
+                drop(socket);
+
+                waiter.add(tcp_listener.fd(), FileEvent::INPUT);
+                sem_tcp.up().expect("tcp up failed");
             }
             else {
                 // we only care about input here, because we operate in blocking mode and only want
@@ -0,0 +1,104 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+#![no_std]
+
+use m3::col::{String, ToString, Vec};
+use m3::env;
+use m3::net::{Endpoint, SocketType};
+use m3::println;
+use m3::session::NetworkManager;
+
+fn usage() -> ! {
+    println!("Usage: {} [<service>]", env::args().next().unwrap());
+    println!();
+    println!("Prints the interface counters and the sockets of the network service <service>");
+    println!("(default: net). The sockets of all sessions are only visible to admin sessions.");
+    m3::exit(1);
+}
+
+fn type_name(ty: SocketType) -> &'static str {
+    match ty {
+        SocketType::Stream => "tcp",
+        SocketType::Dgram => "udp",
+        SocketType::Raw => "raw",
+        SocketType::Undefined => "?",
+    }
+}
+
+fn ep_name(ep: &Endpoint) -> String {
+    if ep.addr.is_unspecified() && ep.port == 0 {
+        "*".to_string()
+    }
+    else {
+        ep.to_string()
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let args: Vec<&str> = env::args().collect();
+    let service = match args.len() {
+        1 => "net",
+        2 if !args[1].starts_with('-') => args[1],
+        _ => usage(),
+    };
+
+    let nm = NetworkManager::new(service).expect("connecting to network service failed");
+
+    let iface = nm.iface_stats().expect("Unable to get interface counters");
+    println!(
+        "RX: {} packets, {} bytes, {} errors",
+        iface.rx_packets, iface.rx_bytes, iface.rx_errors
+    );
+    println!(
+        "TX: {} packets, {} bytes, {} errors",
+        iface.tx_packets, iface.tx_bytes, iface.tx_errors
+    );
+    println!();
+
+    let sockets = nm.socket_stats().expect("Unable to get sockets");
+    println!(
+        "{:>4} {:>3} {:5} {:12} {:>24} {:>24} {:>10} {:>10} {:>10} {:>10} {:>6}",
+        "Sess",
+        "Sd",
+        "Proto",
+        "State",
+        "Local",
+        "Remote",
+        "RxPackets",
+        "RxBytes",
+        "TxPackets",
+        "TxBytes",
+        "Drops"
+    );
+    for s in sockets {
+        println!(
+            "{:>4} {:>3} {:5} {:12} {:>24} {:>24} {:>10} {:>10} {:>10} {:>10} {:>6}",
+            s.session,
+            s.sd,
+            type_name(s.ty),
+            s.state,
+            ep_name(&s.local),
+            ep_name(&s.remote),
+            s.rx_packets,
+            s.rx_bytes,
+            s.tx_packets,
+            s.tx_bytes,
+            s.drops
+        );
+    }
+    0
+}
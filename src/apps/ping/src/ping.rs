@@ -21,7 +21,7 @@ use m3::env;
 use m3::errors::{Code, Error, VerboseError};
 use m3::format;
 use m3::mem;
-use m3::net::{self, IpAddr, RawSocket, RawSocketArgs, DNS};
+use m3::net::{self, IpAddr, Ipv4Addr, RawSocket, RawSocketArgs, DNS};
 use m3::println;
 use m3::session::NetworkManager;
 use m3::time::{TimeDuration, TimeInstant};
@@ -64,8 +64,8 @@ const ICMP_CMD_ECHO_REPLY: u8 = 0x00;
 fn send_echo(
     buf: &mut [u8],
     sock: &FileRef<RawSocket>,
-    src: IpAddr,
-    dest: IpAddr,
+    src: Ipv4Addr,
+    dest: Ipv4Addr,
     nbytes: usize,
     seq: u16,
     ttl: u8,
@@ -153,7 +153,7 @@ fn recv_reply(
         let ip = unsafe { &*buf.as_mut_ptr().cast::<IPv4Header>() };
         let total = u16::from_be(ip.packet_size);
         let ttl = ip.ttl;
-        let src = IpAddr::new_from_raw(u32::from_be(ip.src));
+        let src = Ipv4Addr(u32::from_be(ip.src));
 
         println!(
             "{} bytes from {}: icmp_seq={}, ttl={}, time={} us",
@@ -274,12 +274,20 @@ pub fn main() -> i32 {
     )
     .expect("creating raw socket failed");
 
-    let src_ip = nm.ip_addr().expect("Unable to get own IP address");
+    let src_ip = match nm.ip_addr().expect("Unable to get own IP address") {
+        IpAddr::V4(addr) => addr,
+        IpAddr::V6(_) => panic!("Own IP address is no IPv4 address"),
+    };
 
     let mut dns = DNS::default();
-    let dest_ip = dns
+    let dest_ip = match dns
         .get_addr(nm, &settings.dest, TimeDuration::from_secs(3))
-        .unwrap_or_else(|_| panic!("Unable to resolve name '{}'", settings.dest));
+        .unwrap_or_else(|_| panic!("Unable to resolve name '{}'", settings.dest))
+    {
+        IpAddr::V4(addr) => addr,
+        // raw sockets only support IPv4 so far
+        IpAddr::V6(_) => panic!("Pinging IPv6 addresses is not supported"),
+    };
 
     let total = mem::size_of::<IPv4Header>() + mem::size_of::<ICMP>() + settings.nbytes;
     let mut buf = vec![0u8; total];
@@ -25,13 +25,18 @@ use m3::net::IpAddr;
 use m3::test::{DefaultWvTester, WvTester};
 use m3::{println, wv_run_suite};
 
+mod tdhcp;
+mod tdns;
 mod traw;
+mod tshaper;
 mod ttcp;
 mod tudp;
 
 pub static NET0_IP: LazyStaticCell<IpAddr> = LazyStaticCell::default();
 pub static NET1_IP: LazyStaticCell<IpAddr> = LazyStaticCell::default();
 pub static DST_IP: LazyStaticCell<IpAddr> = LazyStaticCell::default();
+pub static DST_IP6: LazyStaticCell<IpAddr> = LazyStaticCell::default();
+pub static REMOTE_IP: LazyStaticCell<IpAddr> = LazyStaticCell::default();
 
 fn parse_ip(ip: &str) -> IpAddr {
     ip.parse::<IpAddr>()
@@ -41,19 +46,46 @@ fn parse_ip(ip: &str) -> IpAddr {
 #[no_mangle]
 pub fn main() -> i32 {
     let args: Vec<&str> = env::args().collect();
-    if args.len() != 4 {
-        println!("Usage: {} <net0-IP> <net1-IP> <dst-IP>", args[0]);
+    if args.len() == 5 && args[1] == "dhcp" {
+        return run_dhcp(&args);
+    }
+    if args.len() != 4 && args.len() != 5 {
+        println!(
+            "Usage: {} <net0-IP> <net1-IP> <dst-IP> [<dst-IPv6>]",
+            args[0]
+        );
+        println!(
+            "       {} dhcp <leased-IP> <dhcp-server-IP> <IP-behind-gateway>",
+            args[0]
+        );
         m3::exit(1);
     }
 
     NET0_IP.set(parse_ip(args[1]));
     NET1_IP.set(parse_ip(args[2]));
     DST_IP.set(parse_ip(args[3]));
+    if let Some(ip6) = args.get(4) {
+        DST_IP6.set(parse_ip(ip6));
+    }
 
     let mut tester = DefaultWvTester::default();
+    wv_run_suite!(tester, tdns::run);
     wv_run_suite!(tester, traw::run);
     wv_run_suite!(tester, tudp::run);
+    wv_run_suite!(tester, tshaper::run);
     wv_run_suite!(tester, ttcp::run);
     println!("{}", tester);
     0
 }
+
+/// Runs the tests against a DHCP server on the host (see boot/rust-net-dhcp.xml)
+fn run_dhcp(args: &[&str]) -> i32 {
+    NET0_IP.set(parse_ip(args[2]));
+    DST_IP.set(parse_ip(args[3]));
+    REMOTE_IP.set(parse_ip(args[4]));
+
+    let mut tester = DefaultWvTester::default();
+    wv_run_suite!(tester, tdhcp::run);
+    println!("{}", tester);
+    0
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use m3::net::{IpAddr, DNS};
+use m3::rc::Rc;
+use m3::session::NetworkManager;
+use m3::test::WvTester;
+use m3::tiles::Activity;
+use m3::time::TimeDuration;
+use m3::{wv_assert_eq, wv_assert_ok, wv_run_test};
+
+const TIMEOUT: TimeDuration = TimeDuration::from_secs(1);
+
+// the name and address that dnsmasq is configured with (see src/tools/dhcp-test.sh)
+const NAME: &str = "m3.test";
+const NAME_ADDR: IpAddr = IpAddr::new(10, 0, 99, 42);
+
+pub fn run(t: &mut dyn WvTester) {
+    wv_run_test!(t, lease);
+    wv_run_test!(t, nameserver);
+    wv_run_test!(t, gateway);
+}
+
+/// Waits until the net service has obtained a lease and returns its IP address
+fn leased_ip(nm: &Rc<NetworkManager>) -> IpAddr {
+    let unspec = IpAddr::new(0, 0, 0, 0);
+    let mut ip = unspec;
+    // the DHCP exchange starts with the net service and might take a bit
+    for _ in 0..1000 {
+        ip = nm.ip_addr().unwrap_or(unspec);
+        if ip != unspec {
+            break;
+        }
+        Activity::own()
+            .sleep_for(TimeDuration::from_millis(10))
+            .ok();
+    }
+    ip
+}
+
+fn lease(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    wv_assert_eq!(t, leased_ip(&nm), crate::NET0_IP.get());
+}
+
+fn nameserver(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    leased_ip(&nm);
+
+    // dnsmasq announces itself as nameserver
+    wv_assert_eq!(t, nm.nameserver().ok(), Some(crate::DST_IP.get()));
+
+    let mut dns = DNS::default();
+    wv_assert_eq!(t, dns.resolve(nm, NAME, TIMEOUT).ok(), Some(NAME_ADDR));
+}
+
+fn gateway(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    leased_ip(&nm);
+
+    // the remote address is not within our subnet, so that we only get an answer if the leased
+    // default gateway is used
+    let mut dns = DNS::default();
+    dns.add_nameserver(crate::REMOTE_IP.get());
+    wv_assert_eq!(t, dns.resolve(nm, NAME, TIMEOUT).ok(), Some(NAME_ADDR));
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use m3::errors::Code;
+use m3::net::{IpAddr, RecordType, DNS};
+use m3::session::NetworkManager;
+use m3::test::WvTester;
+use m3::time::TimeDuration;
+use m3::{wv_assert_eq, wv_assert_ok, wv_run_test};
+
+const TIMEOUT: TimeDuration = TimeDuration::from_secs(1);
+
+pub fn run(t: &mut dyn WvTester) {
+    wv_run_test!(t, addresses);
+    wv_run_test!(t, hosts);
+    wv_run_test!(t, no_nameserver);
+}
+
+fn addresses(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    let mut dns = DNS::default();
+
+    wv_assert_eq!(
+        t,
+        dns.get_addr(nm.clone(), "192.168.112.1", TIMEOUT).ok(),
+        Some(IpAddr::new(192, 168, 112, 1))
+    );
+    wv_assert_eq!(
+        t,
+        dns.get_addr(nm, "fd00::1", TIMEOUT).ok(),
+        Some(IpAddr::new_v6(0xfd00, 0, 0, 0, 0, 0, 0, 1))
+    );
+}
+
+fn hosts(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    let mut dns = DNS::default();
+
+    wv_assert_eq!(
+        t,
+        dns.get_addr(nm.clone(), "localhost", TIMEOUT).ok(),
+        Some(IpAddr::new(127, 0, 0, 1))
+    );
+    // aliases and names are case-insensitive
+    wv_assert_eq!(
+        t,
+        dns.resolve(nm.clone(), "NetEcho", TIMEOUT).ok(),
+        Some(IpAddr::new(192, 168, 112, 1))
+    );
+
+    let addrs =
+        wv_assert_ok!(dns.resolve_all(nm.clone(), "ip6-localhost", RecordType::AAAA, TIMEOUT));
+    wv_assert_eq!(t, addrs, m3::vec![IpAddr::new_v6(0, 0, 0, 0, 0, 0, 0, 1)]);
+
+    let addrs = wv_assert_ok!(dns.resolve_all(nm, "net0.", RecordType::AAAA, TIMEOUT));
+    wv_assert_eq!(t, addrs, m3::vec![IpAddr::new_v6(
+        0xfd00, 0, 0, 0, 0, 0, 0, 2
+    )]);
+}
+
+fn no_nameserver(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    let mut dns = DNS::default();
+
+    // neither in the hosts file nor can we ask a nameserver, because net0 has none
+    wv_assert_eq!(
+        t,
+        dns.resolve(nm.clone(), "example.com", TIMEOUT)
+            .map_err(|e| e.code()),
+        Err(Code::NotSup)
+    );
+
+    // without hosts file, localhost is unknown as well
+    dns.set_hosts_file("/etc/nonexisting");
+    wv_assert_eq!(
+        t,
+        dns.resolve(nm, "localhost", TIMEOUT).map_err(|e| e.code()),
+        Err(Code::NotSup)
+    );
+}
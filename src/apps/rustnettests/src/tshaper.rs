@@ -0,0 +1,127 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use core::cmp;
+
+use m3::errors::Code;
+use m3::net::{DGramSocket, DgramSocketArgs, Endpoint, Port, UdpSocket, MTU};
+use m3::session::NetworkManager;
+use m3::test::WvTester;
+use m3::tiles::Activity;
+use m3::time::{TimeDuration, TimeInstant};
+use m3::vfs::FileRef;
+use m3::{wv_assert, wv_assert_eq, wv_assert_ok, wv_run_test};
+
+// the rate limit of the net-rate session (see boot script)
+const RATE: u64 = 32 * 1024;
+// the quantum each session gets per scheduling round at the net service
+const QUANTUM: usize = 16 * MTU;
+
+const PKT_SIZE: usize = 1024;
+const TIMEOUT: TimeDuration = TimeDuration::from_secs(10);
+
+pub fn run(t: &mut dyn WvTester) {
+    // the UDP echo server is already running (see tudp)
+    wv_run_test!(t, rate_limit);
+    wv_run_test!(t, fairness);
+}
+
+fn tx_bytes(nm: &NetworkManager, port: Port) -> u64 {
+    let sockets = nm.socket_stats().unwrap();
+    sockets
+        .iter()
+        .find(|s| s.local.port == port)
+        .map(|s| s.tx_bytes)
+        .unwrap_or(0)
+}
+
+fn rate_limit(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net-rate"));
+
+    let mut socket = wv_assert_ok!(UdpSocket::new(
+        DgramSocketArgs::new(nm.clone()).send_buffer(64, 64 * 1024)
+    ));
+    wv_assert_ok!(socket.bind(2004));
+
+    let dest = Endpoint::new(crate::DST_IP.get(), 1337);
+    let total = 48 * PKT_SIZE;
+
+    let start = TimeInstant::now();
+    for _ in 0..total / PKT_SIZE {
+        wv_assert_ok!(socket.send_to(&[0u8; PKT_SIZE], dest));
+    }
+
+    // wait until everything has been sent
+    while tx_bytes(&nm, 2004) < total as u64 && start.elapsed() < TIMEOUT {
+        Activity::own()
+            .sleep_for(TimeDuration::from_millis(10))
+            .ok();
+    }
+    let elapsed = start.elapsed();
+    wv_assert_eq!(t, tx_bytes(&nm, 2004), total as u64);
+
+    // the token bucket allows a burst of 100ms (but at least two packets) and can be overdrawn by
+    // one packet; everything beyond that has to adhere to the rate
+    let burst = cmp::max(RATE / 10, 2 * MTU as u64);
+    let limited = total as u64 - burst - PKT_SIZE as u64;
+    let min = TimeDuration::from_nanos(limited * 1_000_000_000 / RATE);
+    wv_assert!(t, elapsed >= min);
+}
+
+fn flood(t: &mut dyn WvTester, socket: &mut FileRef<UdpSocket>, dest: Endpoint) {
+    loop {
+        if let Err(e) = socket.send_to(&[0u8; PKT_SIZE], dest) {
+            wv_assert_eq!(t, e.code(), Code::WouldBlock);
+            break;
+        }
+    }
+}
+
+fn fairness(t: &mut dyn WvTester) {
+    let admin = wv_assert_ok!(NetworkManager::new("net"));
+    let bulk_nm = wv_assert_ok!(NetworkManager::new("net-bulk"));
+    let light_nm = wv_assert_ok!(NetworkManager::new("net-light"));
+
+    let mut bulk = wv_assert_ok!(UdpSocket::new(
+        DgramSocketArgs::new(bulk_nm).send_buffer(64, 64 * 1024)
+    ));
+    wv_assert_ok!(bulk.bind(2002));
+    wv_assert_ok!(bulk.set_blocking(false));
+    let mut light = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(light_nm)));
+    wv_assert_ok!(light.bind(2003));
+
+    let dest = Endpoint::new(crate::DST_IP.get(), 1337);
+
+    // keep the bulk session backlogged
+    flood(t, &mut bulk, dest);
+    let bulk_start = tx_bytes(&admin, 2002);
+
+    let light_total = 8 * PKT_SIZE;
+    for _ in 0..light_total / PKT_SIZE {
+        wv_assert_ok!(light.send_to(&[0u8; PKT_SIZE], dest));
+    }
+
+    let start = TimeInstant::now();
+    while tx_bytes(&admin, 2003) < light_total as u64 && start.elapsed() < TIMEOUT {
+        flood(t, &mut bulk, dest);
+    }
+    wv_assert_eq!(t, tx_bytes(&admin, 2003), light_total as u64);
+
+    // the light session needs less than one quantum and therefore has to be done within the first
+    // round after its data arrived. Thus, the bulk session can only transfer its quantum in this
+    // round, plus the rounds until our data arrived and until we noticed the completion.
+    let bulk_sent = (tx_bytes(&admin, 2002) - bulk_start) as usize;
+    wv_assert!(t, bulk_sent <= light_total + 4 * QUANTUM);
+}
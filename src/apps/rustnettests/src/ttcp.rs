@@ -16,10 +16,11 @@
 use m3::cap::Selector;
 use m3::com::Semaphore;
 use m3::errors::Code;
-use m3::net::{Endpoint, IpAddr, State, StreamSocket, StreamSocketArgs, TcpSocket};
+use m3::net::{Endpoint, IpAddr, SocketOpt, State, StreamSocket, StreamSocketArgs, TcpSocket};
 use m3::session::NetworkManager;
 use m3::test::{DefaultWvTester, WvTester};
 use m3::tiles::{Activity, ActivityArgs, ChildActivity, RunningActivity, Tile};
+use m3::time::TimeDuration;
 use m3::vec::Vec;
 use m3::vfs::{File, FileEvent, FileWaiter};
 use m3::{vec, wv_assert_eq, wv_assert_err, wv_assert_ok, wv_run_test};
@@ -31,7 +32,11 @@ pub fn run(t: &mut dyn WvTester) {
     wv_run_test!(t, nonblocking_server);
     wv_run_test!(t, open_close);
     wv_run_test!(t, receive_after_close);
+    wv_run_test!(t, backlog);
+    wv_run_test!(t, many_conns);
+    wv_run_test!(t, options);
     wv_run_test!(t, data);
+    wv_run_test!(t, filtered);
 }
 
 fn basics(t: &mut dyn WvTester) {
@@ -202,22 +207,26 @@ fn nonblocking_server(t: &mut dyn WvTester) {
         let mut waiter = FileWaiter::default();
         waiter.add(socket.fd(), FileEvent::INPUT);
 
-        wv_assert_err!(t, socket.accept(), Code::InProgress);
-        while socket.state() == State::Connecting {
-            wv_assert_err!(t, socket.accept(), Code::AlreadyInProgress);
-            waiter.wait();
-        }
-        assert!(socket.state() == State::Connected || socket.state() == State::RemoteClosed);
+        let (mut conn, ep) = loop {
+            match socket.accept() {
+                Ok(res) => break res,
+                Err(e) => {
+                    wv_assert_eq!(t, e.code(), Code::WouldBlock);
+                    waiter.wait();
+                },
+            }
+        };
+        wv_assert_eq!(t, socket.state(), State::Listening);
+        assert!(conn.state() == State::Connected || conn.state() == State::RemoteClosed);
 
-        wv_assert_eq!(
-            t,
-            socket.local_endpoint(),
-            Some(Endpoint::new(net1_ip, 3000))
-        );
-        wv_assert_eq!(t, socket.remote_endpoint().unwrap().addr, net0_ip);
+        wv_assert_eq!(t, conn.local_endpoint(), Some(Endpoint::new(net1_ip, 3000)));
+        wv_assert_eq!(t, ep.addr, net0_ip);
+        wv_assert_eq!(t, conn.remote_endpoint(), Some(ep));
 
+        wv_assert_ok!(conn.close());
         wv_assert_ok!(socket.set_blocking(true));
         wv_assert_ok!(socket.close());
+        wv_assert_eq!(t, socket.state(), State::Closed);
 
         0
     }));
@@ -285,16 +294,16 @@ fn receive_after_close(t: &mut dyn WvTester) {
         wv_assert_eq!(t, socket.state(), State::Listening);
         wv_assert_ok!(sem.up());
 
-        let ep = wv_assert_ok!(socket.accept());
+        let (mut conn, ep) = wv_assert_ok!(socket.accept());
         wv_assert_eq!(t, ep.addr, net0_ip);
-        wv_assert_eq!(t, socket.state(), State::Connected);
+        wv_assert_eq!(t, conn.state(), State::Connected);
 
         let mut buf = [0u8; 32];
-        wv_assert_eq!(t, socket.recv(&mut buf), Ok(32));
-        wv_assert_eq!(t, socket.send(&buf), Ok(32));
+        wv_assert_eq!(t, conn.recv(&mut buf), Ok(32));
+        wv_assert_eq!(t, conn.send(&buf), Ok(32));
 
-        wv_assert_ok!(socket.close());
-        wv_assert_eq!(t, socket.state(), State::Closed);
+        wv_assert_ok!(conn.close());
+        wv_assert_eq!(t, conn.state(), State::Closed);
 
         0
     }));
@@ -324,6 +333,217 @@ fn receive_after_close(t: &mut dyn WvTester) {
     wv_assert_eq!(t, act.wait(), Ok(0));
 }
 
+fn backlog(t: &mut dyn WvTester) {
+    let tile = wv_assert_ok!(Tile::get("clone|own"));
+    let mut act = wv_assert_ok!(ChildActivity::new_with(
+        tile,
+        ActivityArgs::new("tcp-server")
+    ));
+
+    let sem = wv_assert_ok!(Semaphore::create(0));
+    wv_assert_ok!(act.delegate_obj(sem.sel()));
+
+    let mut dst = act.data_sink();
+    dst.push(sem.sel());
+    dst.push(&m3::format!("{}", crate::NET0_IP.get()));
+
+    let act = wv_assert_ok!(act.run(|| {
+        let mut t = DefaultWvTester::default();
+        let mut src = Activity::own().data_source();
+        let sem_sel: Selector = src.pop().unwrap();
+        let net0_ip: IpAddr = src.pop::<&str>().unwrap().parse().unwrap();
+
+        let sem = Semaphore::bind(sem_sel);
+
+        let nm = wv_assert_ok!(NetworkManager::new("net1"));
+
+        let mut socket = wv_assert_ok!(TcpSocket::new(
+            StreamSocketArgs::new(nm)
+                .send_buffer(8 * 1024)
+                .recv_buffer(8 * 1024)
+                .backlog(2)
+        ));
+
+        wv_assert_ok!(socket.listen(3000));
+        wv_assert_ok!(sem.up());
+
+        // both clients connect before we accept any of the connections
+        let (mut conn1, ep1) = wv_assert_ok!(socket.accept());
+        let (mut conn2, ep2) = wv_assert_ok!(socket.accept());
+        wv_assert_eq!(t, socket.state(), State::Listening);
+        wv_assert_eq!(t, ep1.addr, net0_ip);
+        wv_assert_eq!(t, ep2.addr, net0_ip);
+        assert!(ep1.port != ep2.port);
+
+        // echo the data in the opposite order to make sure that the connections are independent
+        for conn in [&mut conn2, &mut conn1] {
+            let mut buf = [0u8; 1];
+            wv_assert_eq!(t, conn.recv(&mut buf), Ok(1));
+            wv_assert_eq!(t, conn.send(&buf), Ok(1));
+            wv_assert_ok!(conn.close());
+        }
+
+        wv_assert_ok!(socket.close());
+        wv_assert_eq!(t, socket.state(), State::Closed);
+
+        0
+    }));
+
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    let mut sockets = Vec::new();
+    for _ in 0..2 {
+        sockets.push(wv_assert_ok!(TcpSocket::new(
+            StreamSocketArgs::new(nm.clone())
+                .send_buffer(8 * 1024)
+                .recv_buffer(8 * 1024)
+        )));
+    }
+
+    wv_assert_ok!(sem.down());
+
+    for s in &mut sockets {
+        wv_assert_ok!(s.connect(Endpoint::new(crate::NET1_IP.get(), 3000)));
+    }
+    for (i, s) in sockets.iter_mut().enumerate() {
+        wv_assert_eq!(t, s.send(&[i as u8]), Ok(1));
+    }
+    for (i, s) in sockets.iter_mut().enumerate() {
+        let mut buf = [0u8; 1];
+        wv_assert_eq!(t, s.recv(&mut buf), Ok(1));
+        wv_assert_eq!(t, buf[0], i as u8);
+        wv_assert_ok!(s.close());
+    }
+
+    wv_assert_eq!(t, act.wait(), Ok(0));
+}
+
+fn many_conns(t: &mut dyn WvTester) {
+    // more connections than the network stack has sockets, which are therefore reused
+    const CONNS: usize = 80;
+
+    let tile = wv_assert_ok!(Tile::get("clone|own"));
+    let mut act = wv_assert_ok!(ChildActivity::new_with(
+        tile,
+        ActivityArgs::new("tcp-server")
+    ));
+
+    let sem = wv_assert_ok!(Semaphore::create(0));
+    wv_assert_ok!(act.delegate_obj(sem.sel()));
+
+    let mut dst = act.data_sink();
+    dst.push(sem.sel());
+
+    let act = wv_assert_ok!(act.run(|| {
+        let mut t = DefaultWvTester::default();
+        let mut src = Activity::own().data_source();
+        let sem_sel: Selector = src.pop().unwrap();
+
+        let sem = Semaphore::bind(sem_sel);
+
+        let nm = wv_assert_ok!(NetworkManager::new("net1"));
+
+        let mut socket = wv_assert_ok!(TcpSocket::new(
+            StreamSocketArgs::new(nm)
+                .send_buffer(8 * 1024)
+                .recv_buffer(8 * 1024)
+                .backlog(2)
+        ));
+
+        wv_assert_ok!(socket.listen(3000));
+        wv_assert_ok!(sem.up());
+
+        for _ in 0..CONNS {
+            // the connection is removed when it is dropped
+            let (mut conn, _ep) = wv_assert_ok!(socket.accept());
+            let mut buf = [0u8; 1];
+            wv_assert_eq!(t, conn.recv(&mut buf), Ok(1));
+            wv_assert_eq!(t, conn.send(&buf), Ok(1));
+            wv_assert_ok!(conn.close());
+        }
+
+        wv_assert_ok!(socket.close());
+
+        0
+    }));
+
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    wv_assert_ok!(sem.down());
+
+    for i in 0..CONNS {
+        let mut socket = wv_assert_ok!(TcpSocket::new(
+            StreamSocketArgs::new(nm.clone())
+                .send_buffer(8 * 1024)
+                .recv_buffer(8 * 1024)
+        ));
+        wv_assert_ok!(socket.connect(Endpoint::new(crate::NET1_IP.get(), 3000)));
+
+        let mut buf = [i as u8];
+        wv_assert_eq!(t, socket.send(&buf), Ok(1));
+        wv_assert_eq!(t, socket.recv(&mut buf), Ok(1));
+        wv_assert_eq!(t, buf[0], i as u8);
+        wv_assert_ok!(socket.close());
+    }
+
+    wv_assert_eq!(t, act.wait(), Ok(0));
+}
+
+fn options(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    let mut socket = wv_assert_ok!(TcpSocket::new(
+        StreamSocketArgs::new(nm).recv_buffer(16 * 1024)
+    ));
+
+    // Nagle's algorithm is disabled by default
+    wv_assert_eq!(t, socket.get_option(SocketOpt::NO_DELAY), Ok(1));
+    wv_assert_ok!(socket.set_option(SocketOpt::NO_DELAY, 0));
+    wv_assert_eq!(t, socket.get_option(SocketOpt::NO_DELAY), Ok(0));
+    wv_assert_ok!(socket.set_option(SocketOpt::NO_DELAY, 1));
+
+    let second = TimeDuration::from_secs(1).as_nanos() as u64;
+    wv_assert_eq!(t, socket.get_option(SocketOpt::KEEP_ALIVE), Ok(0));
+    wv_assert_ok!(socket.set_option(SocketOpt::KEEP_ALIVE, second));
+    wv_assert_eq!(t, socket.get_option(SocketOpt::KEEP_ALIVE), Ok(second));
+    wv_assert_ok!(socket.set_option(SocketOpt::LINGER, second));
+    wv_assert_eq!(t, socket.get_option(SocketOpt::LINGER), Ok(second));
+
+    // buffers can be resized as long as the socket is closed
+    wv_assert_eq!(t, socket.get_option(SocketOpt::RECV_BUF), Ok(16 * 1024));
+    wv_assert_ok!(socket.set_option(SocketOpt::RECV_BUF, 8 * 1024));
+    wv_assert_eq!(t, socket.get_option(SocketOpt::RECV_BUF), Ok(8 * 1024));
+    wv_assert_err!(
+        t,
+        socket.set_option(SocketOpt::SEND_BUF, 1024 * 1024),
+        Code::NoSpace
+    );
+
+    wv_assert_ok!(Semaphore::attach("net-tcp").unwrap().down());
+
+    wv_assert_ok!(socket.connect(Endpoint::new(crate::DST_IP.get(), 1338)));
+    wv_assert_err!(
+        t,
+        socket.set_option(SocketOpt::RECV_BUF, 4 * 1024),
+        Code::InvState
+    );
+
+    // the echo server does not send anything unless we do
+    let mut buf = [0u8; 32];
+    wv_assert_ok!(socket.set_option(
+        SocketOpt::RECV_TIMEOUT,
+        TimeDuration::from_millis(100).as_nanos() as u64
+    ));
+    wv_assert_err!(t, socket.recv(&mut buf), Code::Timeout);
+
+    // but data is still received if available
+    wv_assert_eq!(t, socket.send(&buf), Ok(buf.len()));
+    wv_assert_ok!(socket.set_option(SocketOpt::RECV_TIMEOUT, 0));
+    wv_assert_ok!(socket.recv(&mut buf));
+
+    wv_assert_ok!(socket.close());
+}
+
 fn data(t: &mut dyn WvTester) {
     let nm = wv_assert_ok!(NetworkManager::new("net0"));
 
@@ -363,3 +583,86 @@ fn data(t: &mut dyn WvTester) {
         }
     }
 }
+
+fn filtered(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    // net0 must not connect to the discard port (see boot script)
+    let mut socket = wv_assert_ok!(TcpSocket::new(StreamSocketArgs::new(nm.clone())));
+    wv_assert_err!(
+        t,
+        socket.connect(Endpoint::new(crate::DST_IP.get(), 9)),
+        Code::NoPerm
+    );
+    wv_assert_eq!(t, socket.state(), State::Closed);
+    drop(socket);
+
+    // net1 refuses connections from the IPv6 address of net0 (see boot script)
+    if !crate::DST_IP6.is_some() {
+        return;
+    }
+
+    let tile = wv_assert_ok!(Tile::get("clone|own"));
+    let mut act = wv_assert_ok!(ChildActivity::new_with(
+        tile,
+        ActivityArgs::new("tcp-server")
+    ));
+
+    let sem = wv_assert_ok!(Semaphore::create(0));
+    wv_assert_ok!(act.delegate_obj(sem.sel()));
+
+    let mut dst = act.data_sink();
+    dst.push(sem.sel());
+    dst.push(&m3::format!("{}", crate::NET0_IP.get()));
+
+    let act = wv_assert_ok!(act.run(|| {
+        let mut t = DefaultWvTester::default();
+        let mut src = Activity::own().data_source();
+        let sem_sel: Selector = src.pop().unwrap();
+        let net0_ip: IpAddr = src.pop::<&str>().unwrap().parse().unwrap();
+
+        let sem = Semaphore::bind(sem_sel);
+
+        let nm = wv_assert_ok!(NetworkManager::new("net1"));
+
+        let mut socket = wv_assert_ok!(TcpSocket::new(StreamSocketArgs::new(nm)));
+
+        wv_assert_ok!(socket.listen(3000));
+        wv_assert_ok!(sem.up());
+
+        // the refused IPv6 connection is never handed out
+        let (mut conn, ep) = wv_assert_ok!(socket.accept());
+        wv_assert_eq!(t, ep.addr, net0_ip);
+
+        wv_assert_ok!(conn.close());
+        wv_assert_ok!(socket.close());
+
+        0
+    }));
+
+    wv_assert_ok!(sem.down());
+
+    {
+        let mut socket = wv_assert_ok!(TcpSocket::new(StreamSocketArgs::new(nm.clone())));
+
+        // net1 aborts the connection as soon as it is established, which we might notice already
+        // during the connect
+        match socket.connect(Endpoint::new(crate::DST_IP6.get(), 3000)) {
+            Err(e) => wv_assert_eq!(t, e.code(), Code::ConnectionFailed),
+            Ok(_) => {
+                let mut waiter = FileWaiter::default();
+                waiter.add(socket.fd(), FileEvent::INPUT);
+                while socket.state() == State::Connected {
+                    waiter.wait();
+                }
+                wv_assert_eq!(t, socket.state(), State::Closed);
+            },
+        }
+    }
+
+    let mut socket = wv_assert_ok!(TcpSocket::new(StreamSocketArgs::new(nm)));
+    wv_assert_ok!(socket.connect(Endpoint::new(crate::NET1_IP.get(), 3000)));
+    wv_assert_ok!(socket.close());
+
+    wv_assert_eq!(t, act.wait(), Ok(0));
+}
@@ -13,14 +13,19 @@
  * General Public License version 2 for more details.
  */
 
+use m3::col::Vec;
 use m3::com::Semaphore;
 use m3::errors::{Code, Error};
-use m3::net::{DGramSocket, DgramSocketArgs, Endpoint, State, UdpSocket, MTU};
+use m3::io::Read;
+use m3::net::{DGramSocket, DgramSocketArgs, Endpoint, IpAddr, SocketOpt, State, UdpSocket, MTU};
 use m3::session::NetworkManager;
 use m3::test::WvTester;
+use m3::tiles::Activity;
 use m3::time::TimeDuration;
-use m3::vfs::{File, FileEvent, FileRef, FileWaiter};
-use m3::{wv_assert_eq, wv_assert_err, wv_assert_ok, wv_run_test};
+use m3::vfs::{File, FileEvent, FileRef, FileWaiter, OpenFlags, VFS};
+use m3::{
+    println, wv_assert, wv_assert_eq, wv_assert_err, wv_assert_ok, wv_assert_some, wv_run_test,
+};
 
 const TIMEOUT: TimeDuration = TimeDuration::from_secs(1);
 
@@ -31,6 +36,12 @@ pub fn run(t: &mut dyn WvTester) {
     wv_run_test!(t, basics);
     wv_run_test!(t, connect);
     wv_run_test!(t, data);
+    wv_run_test!(t, data_ipv6);
+    wv_run_test!(t, multicast);
+    wv_run_test!(t, broadcast);
+    wv_run_test!(t, filter);
+    wv_run_test!(t, stats);
+    wv_run_test!(t, capture);
 }
 
 fn basics(t: &mut dyn WvTester) {
@@ -144,3 +155,261 @@ fn data(t: &mut dyn WvTester) {
         }
     }
 }
+
+fn data_ipv6(t: &mut dyn WvTester) {
+    if !crate::DST_IP6.is_some() {
+        println!("Skipping IPv6 test: no IPv6 destination given");
+        return;
+    }
+
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    // the IPv4 address comes first, followed by at least one IPv6 address
+    let addrs = wv_assert_ok!(nm.ip_addrs());
+    wv_assert_eq!(t, addrs.first(), Some(&crate::NET0_IP.get()));
+    wv_assert!(t, addrs.iter().any(|a| a.is_ipv6()));
+
+    let mut socket = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm)));
+
+    let dest = Endpoint::new(crate::DST_IP6.get(), 1337);
+
+    let mut send_buf = [0u8; 256];
+    for (i, bufi) in send_buf.iter_mut().enumerate() {
+        *bufi = i as u8;
+    }
+
+    let mut recv_buf = [0u8; 256];
+
+    let mut waiter = FileWaiter::default();
+    waiter.add(socket.fd(), FileEvent::INPUT);
+
+    // the first packet might take longer due to neighbor discovery
+    let mut timeout = TimeDuration::from_secs(6);
+    for pkt_size in &[1, 64, 256] {
+        loop {
+            if let Ok((recv_size, src)) = send_recv(
+                &mut waiter,
+                &mut socket,
+                dest,
+                &send_buf[0..*pkt_size],
+                &mut recv_buf,
+                timeout,
+            ) {
+                wv_assert_eq!(t, *pkt_size, recv_size as usize);
+                wv_assert_eq!(t, src, dest);
+                wv_assert_eq!(t, &recv_buf[0..recv_size], &send_buf[0..recv_size]);
+                break;
+            }
+        }
+        timeout = TIMEOUT;
+    }
+}
+
+fn multicast(t: &mut dyn WvTester) {
+    // the NIC drivers don't deliver our own multicast packets back to us
+    if crate::NET0_IP.get() != crate::NET1_IP.get() {
+        println!("Skipping multicast test: requires the loopback driver");
+        return;
+    }
+
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    let mut rsock = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm.clone())));
+    let mut ssock = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm)));
+    wv_assert_ok!(rsock.bind(2000));
+    wv_assert_ok!(ssock.bind(2001));
+    wv_assert_ok!(rsock.set_option(SocketOpt::RECV_TIMEOUT, TIMEOUT.as_nanos() as u64));
+
+    let group = IpAddr::new(239, 1, 2, 3);
+    wv_assert_err!(t, rsock.join_group(crate::NET0_IP.get()), Code::InvArgs);
+    wv_assert_err!(t, rsock.leave_group(group), Code::InvArgs);
+    wv_assert_ok!(rsock.join_group(group));
+    wv_assert_err!(t, rsock.join_group(group), Code::Exists);
+
+    let mut buf = [0u8; 16];
+    wv_assert_ok!(ssock.send_to(b"group", Endpoint::new(group, 2000)));
+    let (size, ep) = wv_assert_ok!(rsock.recv_from(&mut buf));
+    wv_assert_eq!(t, &buf[0..size], b"group");
+    wv_assert_eq!(t, ep.port, 2001);
+
+    // after leaving the group, packets to the group are no longer received
+    wv_assert_ok!(rsock.leave_group(group));
+    wv_assert_ok!(ssock.send_to(b"group", Endpoint::new(group, 2000)));
+    wv_assert_err!(t, rsock.recv_from(&mut buf), Code::Timeout);
+}
+
+fn broadcast(t: &mut dyn WvTester) {
+    // the NIC drivers don't deliver our own broadcast packets back to us
+    if crate::NET0_IP.get() != crate::NET1_IP.get() {
+        println!("Skipping broadcast test: requires the loopback driver");
+        return;
+    }
+
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+
+    let mut rsock = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm.clone())));
+    let mut ssock = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm)));
+    wv_assert_ok!(rsock.bind(2000));
+    wv_assert_ok!(ssock.bind(2001));
+    wv_assert_ok!(rsock.set_option(SocketOpt::RECV_TIMEOUT, TIMEOUT.as_nanos() as u64));
+
+    let dest = Endpoint::new(IpAddr::new(255, 255, 255, 255), 2000);
+
+    // broadcasts need to be enabled explicitly
+    wv_assert_eq!(t, ssock.get_option(SocketOpt::BROADCAST), Ok(0));
+    wv_assert_err!(t, ssock.send_to(b"all", dest), Code::NoPerm);
+
+    wv_assert_ok!(ssock.set_option(SocketOpt::BROADCAST, 1));
+    wv_assert_eq!(t, ssock.get_option(SocketOpt::BROADCAST), Ok(1));
+    wv_assert_ok!(ssock.send_to(b"all", dest));
+
+    let mut buf = [0u8; 16];
+    let (size, ep) = wv_assert_ok!(rsock.recv_from(&mut buf));
+    wv_assert_eq!(t, &buf[0..size], b"all");
+    wv_assert_eq!(t, ep.port, 2001);
+}
+
+fn filter(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    let admin = wv_assert_ok!(NetworkManager::new("net"));
+
+    // only privileged sessions can query the counters
+    wv_assert_err!(t, nm.filter_stats(), Code::NoPerm);
+
+    // net0 denies all UDP packets to the discard port (see boot script)
+    let dropped = |admin: &NetworkManager| {
+        let rules = admin.filter_stats().unwrap();
+        rules
+            .iter()
+            .find(|r| r.session.is_some() && r.rule == "deny,out,udp,any,9")
+            .map(|r| (r.packets, r.bytes))
+    };
+    let (packets, bytes) = wv_assert_some!(dropped(&admin));
+
+    let mut socket = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm)));
+
+    // the send succeeds, but the packet is dropped by the net service
+    wv_assert_ok!(socket.send_to(&[0u8; 16], Endpoint::new(crate::DST_IP.get(), 9)));
+
+    // the packet is handled asynchronously, so that we might need to wait a bit
+    let mut counters = (packets, bytes);
+    for _ in 0..100 {
+        counters = wv_assert_some!(dropped(&admin));
+        if counters.0 != packets {
+            break;
+        }
+        Activity::own()
+            .sleep_for(TimeDuration::from_millis(10))
+            .ok();
+    }
+    wv_assert_eq!(t, counters, (packets + 1, bytes + 16));
+
+    // the net service reports the denied packet, so that the next send fails once
+    wv_assert_err!(
+        t,
+        socket.send_to(&[0u8; 16], Endpoint::new(crate::DST_IP.get(), 9)),
+        Code::NoPerm
+    );
+}
+
+fn stats(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    let admin = wv_assert_ok!(NetworkManager::new("net"));
+
+    let mut socket = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm.clone())));
+    wv_assert_ok!(socket.bind(2001));
+
+    let find = |nm: &NetworkManager| {
+        let sockets = nm.socket_stats().unwrap();
+        sockets.into_iter().find(|s| s.local.port == 2001)
+    };
+
+    // the packet to the discard port is dropped by the packet filter (see boot script)
+    wv_assert_ok!(socket.send_to(&[0u8; 16], Endpoint::new(crate::DST_IP.get(), 9)));
+
+    let mut sock = wv_assert_some!(find(&nm));
+    for _ in 0..100 {
+        if sock.drops != 0 {
+            break;
+        }
+        Activity::own()
+            .sleep_for(TimeDuration::from_millis(10))
+            .ok();
+        sock = wv_assert_some!(find(&nm));
+    }
+    wv_assert_eq!(t, sock.state, "Bound");
+    wv_assert_eq!(t, sock.local, Endpoint::new(crate::NET0_IP.get(), 2001));
+    wv_assert_eq!(t, sock.drops, 1);
+    wv_assert_eq!(t, sock.tx_packets, 0);
+
+    // other sessions only see their own sockets, whereas the admin session sees all sockets
+    let own = wv_assert_ok!(nm.socket_stats());
+    wv_assert!(t, own.iter().all(|s| s.session == sock.session));
+    let all = wv_assert_ok!(admin.socket_stats());
+    wv_assert!(
+        t,
+        all.iter()
+            .any(|s| s.session == sock.session && s.sd == sock.sd)
+    );
+    wv_assert!(t, all.len() >= own.len());
+
+    let iface = wv_assert_ok!(nm.iface_stats());
+    wv_assert!(t, iface.rx_packets > 0);
+    wv_assert!(t, iface.tx_packets > 0);
+}
+
+fn capture(t: &mut dyn WvTester) {
+    let nm = wv_assert_ok!(NetworkManager::new("net0"));
+    let admin = wv_assert_ok!(NetworkManager::new("net"));
+
+    // only privileged sessions can control the capture
+    wv_assert_err!(t, nm.start_capture("/net.pcap", ""), Code::NoPerm);
+    wv_assert_err!(t, nm.stop_capture(), Code::NoPerm);
+    wv_assert_err!(t, admin.start_capture("/net.pcap", "foo"), Code::InvArgs);
+
+    // capture only the sent UDP packets to the echo server
+    wv_assert_ok!(admin.start_capture("/net.pcap", "allow,out,udp,any,1337; deny,any,any"));
+
+    let mut socket = wv_assert_ok!(UdpSocket::new(DgramSocketArgs::new(nm)));
+    wv_assert_ok!(socket.set_blocking(false));
+
+    let mut waiter = FileWaiter::default();
+    waiter.add(socket.fd(), FileEvent::INPUT);
+
+    let dest = Endpoint::new(crate::DST_IP.get(), 1337);
+    let send_buf = [0u8; 16];
+    let mut recv_buf = [0u8; 16];
+    let mut sent = 0;
+    // the echo reply guarantees that our packet has been sent
+    while sent < 5 {
+        sent += 1;
+        if send_recv(
+            &mut waiter,
+            &mut socket,
+            dest,
+            &send_buf,
+            &mut recv_buf,
+            TIMEOUT,
+        )
+        .is_ok()
+        {
+            break;
+        }
+    }
+
+    let frames = wv_assert_ok!(admin.stop_capture());
+    wv_assert!(t, frames >= 1 && frames <= sent);
+
+    // pcap header followed by a record header and the frame (Ethernet + IPv4 + UDP + payload)
+    let mut file = wv_assert_ok!(VFS::open("/net.pcap", OpenFlags::R));
+    let mut content = Vec::new();
+    wv_assert_ok!(file.read_to_end(&mut content));
+    wv_assert_eq!(
+        t,
+        content.len(),
+        24 + frames as usize * (16 + 14 + 20 + 8 + 16)
+    );
+    wv_assert_eq!(t, &content[0..4], &0xa1b2_c3d4u32.to_le_bytes());
+
+    wv_assert_ok!(VFS::unlink("/net.pcap"));
+}
@@ -18,6 +18,8 @@
 
 #![no_std]
 
+use m3::col::Vec;
+use m3::env;
 use m3::test::{DefaultWvTester, WvTester};
 use m3::{println, wv_run_suite};
 
@@ -42,11 +44,17 @@ mod tserver;
 mod tsgate;
 #[cfg(not(target_vendor = "host"))]
 mod tsrvmsgs;
+mod tswap;
 mod tsyscalls;
 mod ttreap;
 
 #[no_mangle]
 pub fn main() -> i32 {
+    let args: Vec<&str> = env::args().collect();
+    if args.len() == 2 && args[1] == "swap" {
+        return run_swap();
+    }
+
     let mut tester = DefaultWvTester::default();
     wv_run_suite!(tester, tboxlist::run);
     wv_run_suite!(tester, tbufio::run);
@@ -75,3 +83,11 @@ pub fn main() -> i32 {
     println!("{}", tester);
     0
 }
+
+/// Runs the tests that need a pager with swapping (see boot/rust-unittests-swap.xml)
+fn run_swap() -> i32 {
+    let mut tester = DefaultWvTester::default();
+    wv_run_suite!(tester, tswap::run);
+    println!("{}", tester);
+    0
+}
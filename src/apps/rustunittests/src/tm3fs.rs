@@ -16,18 +16,29 @@
  * General Public License version 2 for more details.
  */
 
-use m3::col::ToString;
+use m3::col::{ToString, Vec};
 use m3::errors::Code;
-use m3::io::Write;
+use m3::io::{Read, Write};
+use m3::session::{DeriveArgs, M3FS};
 use m3::test::WvTester;
-use m3::vfs::{FileMode, OpenFlags, VFS};
-use m3::{wv_assert_eq, wv_assert_err, wv_assert_ok, wv_run_test};
+use m3::tiles::Activity;
+use m3::vfs::{read_dir, FAllocMode, FileMode, OpenFlags, Seek, SeekMode, VFS};
+use m3::{format, vec, wv_assert, wv_assert_eq, wv_assert_err, wv_assert_ok, wv_run_test};
 
 pub fn run(t: &mut dyn WvTester) {
     wv_run_test!(t, paths);
     wv_run_test!(t, mkdir_rmdir);
     wv_run_test!(t, link_unlink);
     wv_run_test!(t, rename);
+    wv_run_test!(t, large_dir);
+    wv_run_test!(t, symlinks);
+    wv_run_test!(t, permissions);
+    wv_run_test!(t, restricted);
+    wv_run_test!(t, quotas);
+    wv_run_test!(t, xattrs);
+    wv_run_test!(t, check);
+    wv_run_test!(t, sparse);
+    wv_run_test!(t, reflink);
 }
 
 fn setup() {
@@ -252,3 +263,706 @@ fn rename(t: &mut dyn WvTester) {
 
     teardown();
 }
+
+fn large_dir(t: &mut dyn WvTester) {
+    const COUNT: usize = 200;
+
+    setup();
+
+    // create enough entries to require multiple directory blocks (or buckets)
+    wv_assert_ok!(VFS::mkdir("/large", FileMode::from_bits(0o755).unwrap()));
+    for i in 0..COUNT {
+        wv_assert_ok!(VFS::link("/example/myfile", &format!("/large/entry-{}", i)));
+    }
+    wv_assert_err!(
+        t,
+        VFS::link("/example/myfile", "/large/entry-17"),
+        Code::Exists
+    );
+
+    // all entries can be found
+    let file_info = wv_assert_ok!(VFS::stat("/example/myfile"));
+    wv_assert_eq!(t, file_info.links, COUNT as u32 + 1);
+    for i in 0..COUNT {
+        let info = wv_assert_ok!(VFS::stat(&format!("/large/entry-{}", i)));
+        wv_assert_eq!(t, info.inode, file_info.inode);
+    }
+    wv_assert_err!(t, VFS::stat("/large/entry-200"), Code::NoSuchFile);
+
+    // and are listed exactly once
+    let mut seen = vec![false; COUNT];
+    let mut others = 0;
+    for e in wv_assert_ok!(read_dir("/large")) {
+        match e.file_name().strip_prefix("entry-") {
+            Some(no) => {
+                let no = no.parse::<usize>().unwrap();
+                wv_assert!(t, !seen[no]);
+                seen[no] = true;
+            },
+            None => others += 1,
+        }
+    }
+    wv_assert!(t, seen.iter().all(|s| *s));
+    wv_assert_eq!(t, others, 2);
+
+    // remove every second entry and check that the rest is still there
+    for i in (0..COUNT).step_by(2) {
+        wv_assert_ok!(VFS::unlink(&format!("/large/entry-{}", i)));
+    }
+    for i in 0..COUNT {
+        let res = VFS::stat(&format!("/large/entry-{}", i));
+        wv_assert_eq!(t, res.is_ok(), i % 2 == 1);
+    }
+    wv_assert_err!(t, VFS::rmdir("/large"), Code::DirNotEmpty);
+
+    // remove the rest; the directory is empty afterwards
+    for i in (1..COUNT).step_by(2) {
+        wv_assert_ok!(VFS::unlink(&format!("/large/entry-{}", i)));
+    }
+    wv_assert_eq!(t, wv_assert_ok!(read_dir("/large")).count(), 2);
+    wv_assert_ok!(VFS::rmdir("/large"));
+
+    teardown();
+}
+
+fn symlinks(t: &mut dyn WvTester) {
+    setup();
+
+    // test symlink errors
+    wv_assert_err!(t, VFS::symlink("", "/example/link"), Code::InvArgs);
+    wv_assert_err!(t, VFS::symlink("myfile", "/example/myfile"), Code::Exists);
+    wv_assert_err!(t, VFS::symlink("myfile", "/foo/link"), Code::NoSuchFile);
+    wv_assert_err!(t, VFS::readlink("/example/myfile"), Code::InvArgs);
+
+    // relative and absolute targets
+    wv_assert_ok!(VFS::symlink("myfile", "/example/rel"));
+    wv_assert_ok!(VFS::symlink("/example", "/abs"));
+    wv_assert_eq!(t, VFS::readlink("/example/rel"), Ok("myfile".to_string()));
+    wv_assert_eq!(t, VFS::readlink("/abs"), Ok("/example".to_string()));
+
+    // stat and open follow the links
+    let info = wv_assert_ok!(VFS::stat("/abs/rel"));
+    wv_assert!(t, info.mode.is_reg());
+    wv_assert_eq!(t, info.size, 5);
+    let info = wv_assert_ok!(VFS::stat("/abs"));
+    wv_assert!(t, info.mode.is_dir());
+    wv_assert_ok!(VFS::open("/abs/rel", OpenFlags::R));
+
+    // creating through a dangling link creates the target
+    wv_assert_ok!(VFS::symlink("newfile", "/example/dangling"));
+    wv_assert_err!(t, VFS::stat("/example/dangling"), Code::NoSuchFile);
+    wv_assert_ok!(VFS::open(
+        "/example/dangling",
+        OpenFlags::W | OpenFlags::CREATE
+    ));
+    wv_assert_ok!(VFS::stat("/example/newfile"));
+
+    // loops are detected
+    wv_assert_ok!(VFS::symlink("loop2", "/example/loop1"));
+    wv_assert_ok!(VFS::symlink("loop1", "/example/loop2"));
+    wv_assert_err!(t, VFS::stat("/example/loop1"), Code::LinkLoop);
+    wv_assert_err!(t, VFS::open("/example/loop2", OpenFlags::R), Code::LinkLoop);
+
+    // unlink and rmdir do not follow the link
+    wv_assert_err!(t, VFS::rmdir("/abs"), Code::IsNoDir);
+    wv_assert_ok!(VFS::unlink("/abs"));
+    wv_assert_ok!(VFS::stat("/example"));
+    for link in &["rel", "dangling", "newfile", "loop1", "loop2"] {
+        wv_assert_ok!(VFS::unlink(&format!("/example/{}", link)));
+    }
+
+    teardown();
+}
+
+fn permissions(t: &mut dyn WvTester) {
+    setup();
+
+    let mode = |bits| FileMode::from_bits(bits).unwrap();
+
+    // we are root and can therefore change everything
+    wv_assert_ok!(VFS::chmod("/example/myfile", mode(0o640)));
+    wv_assert_ok!(VFS::chown("/example/myfile", 1000, 100));
+    wv_assert_ok!(VFS::utime("/example/myfile", 1234, 5678));
+    let info = wv_assert_ok!(VFS::stat("/example/myfile"));
+    wv_assert!(t, info.mode.is_reg());
+    wv_assert_eq!(t, info.mode & FileMode::PERM, mode(0o640));
+    wv_assert_eq!(t, info.uid, 1000);
+    wv_assert_eq!(t, info.gid, 100);
+    wv_assert_eq!(t, info.lastaccess, 1234);
+    wv_assert_eq!(t, info.lastmod, 5678);
+
+    // derive a session for another user in the same group and mount it
+    {
+        let root = Activity::own().mounts().get_by_path("/").unwrap();
+        let id = Activity::own().mounts().alloc_id();
+        let user = wv_assert_ok!(root
+            .borrow()
+            .as_any()
+            .downcast_ref::<M3FS>()
+            .unwrap()
+            .derive(id, &DeriveArgs::default().creds(2000, 100)));
+
+        // non-root users cannot derive sessions for other users
+        let id = Activity::own().mounts().alloc_id();
+        wv_assert_err!(
+            t,
+            user.borrow()
+                .as_any()
+                .downcast_ref::<M3FS>()
+                .unwrap()
+                .derive(id, &DeriveArgs::default().creds(0, 0)),
+            Code::NoPerm
+        );
+
+        wv_assert_ok!(Activity::own().mounts().add("/user", user));
+    }
+
+    // group members can read, but neither write nor change the file
+    wv_assert_ok!(VFS::open("/user/example/myfile", OpenFlags::R));
+    wv_assert_err!(
+        t,
+        VFS::open("/user/example/myfile", OpenFlags::W),
+        Code::NoPerm
+    );
+    wv_assert_err!(
+        t,
+        VFS::chmod("/user/example/myfile", mode(0o666)),
+        Code::NoPerm
+    );
+    wv_assert_err!(
+        t,
+        VFS::chown("/user/example/myfile", 2000, 100),
+        Code::NoPerm
+    );
+    wv_assert_err!(t, VFS::utime("/user/example/myfile", 0, 0), Code::NoPerm);
+
+    // the directory is only writable for root
+    wv_assert_err!(
+        t,
+        VFS::mkdir("/user/example/dir", mode(0o755)),
+        Code::NoPerm
+    );
+    wv_assert_err!(
+        t,
+        VFS::open("/user/example/new", OpenFlags::W | OpenFlags::CREATE),
+        Code::NoPerm
+    );
+    wv_assert_err!(t, VFS::unlink("/user/example/myfile"), Code::NoPerm);
+    wv_assert_err!(
+        t,
+        VFS::rename("/user/example/myfile", "/user/example/other"),
+        Code::NoPerm
+    );
+
+    // in sticky directories, users can only remove their own files
+    wv_assert_ok!(VFS::chmod("/example", mode(0o1777)));
+    wv_assert_ok!(VFS::open(
+        "/user/example/new",
+        OpenFlags::W | OpenFlags::CREATE
+    ));
+    let info = wv_assert_ok!(VFS::stat("/example/new"));
+    wv_assert_eq!(t, info.uid, 2000);
+    wv_assert_eq!(t, info.gid, 100);
+    wv_assert_err!(t, VFS::unlink("/user/example/myfile"), Code::NoPerm);
+    wv_assert_ok!(VFS::unlink("/user/example/new"));
+
+    // directories without search permission cannot be traversed
+    wv_assert_ok!(VFS::chmod("/example", mode(0o700)));
+    wv_assert_err!(t, VFS::stat("/user/example/myfile"), Code::NoPerm);
+    wv_assert_ok!(VFS::chmod("/example", mode(0o755)));
+
+    wv_assert_ok!(Activity::own().mounts().remove("/user"));
+
+    teardown();
+}
+
+fn restricted(t: &mut dyn WvTester) {
+    setup();
+
+    let mode = |bits| FileMode::from_bits(bits).unwrap();
+    wv_assert_ok!(VFS::mkdir("/example/sub", mode(0o755)));
+
+    // derive a read-only session and one that is confined to /example
+    {
+        let root = Activity::own().mounts().get_by_path("/").unwrap();
+        let root = root.borrow();
+        let m3fs = root.as_any().downcast_ref::<M3FS>().unwrap();
+
+        let id = Activity::own().mounts().alloc_id();
+        let ro = wv_assert_ok!(m3fs.derive(id, &DeriveArgs::default().readonly(true)));
+        wv_assert_ok!(Activity::own().mounts().add("/ro", ro));
+
+        let id = Activity::own().mounts().alloc_id();
+        let sub = wv_assert_ok!(m3fs.derive(id, &DeriveArgs::default().root("/example")));
+        wv_assert_ok!(Activity::own().mounts().add("/sub", sub));
+
+        // the root has to be an existing directory
+        let id = Activity::own().mounts().alloc_id();
+        wv_assert_err!(
+            t,
+            m3fs.derive(id, &DeriveArgs::default().root("/example/myfile")),
+            Code::IsNoDir
+        );
+        wv_assert_err!(
+            t,
+            m3fs.derive(id, &DeriveArgs::default().root("/foo")),
+            Code::NoSuchFile
+        );
+    }
+
+    // the read-only session can read, but not change anything
+    wv_assert_ok!(VFS::open("/ro/example/myfile", OpenFlags::R));
+    wv_assert_err!(
+        t,
+        VFS::open("/ro/example/myfile", OpenFlags::W),
+        Code::ReadOnly
+    );
+    wv_assert_err!(
+        t,
+        VFS::open("/ro/example/new", OpenFlags::R | OpenFlags::CREATE),
+        Code::ReadOnly
+    );
+    wv_assert_err!(
+        t,
+        VFS::mkdir("/ro/example/dir", mode(0o755)),
+        Code::ReadOnly
+    );
+    wv_assert_err!(t, VFS::rmdir("/ro/example/sub"), Code::ReadOnly);
+    wv_assert_err!(t, VFS::unlink("/ro/example/myfile"), Code::ReadOnly);
+    wv_assert_err!(
+        t,
+        VFS::rename("/ro/example/myfile", "/ro/example/other"),
+        Code::ReadOnly
+    );
+    wv_assert_err!(
+        t,
+        VFS::chmod("/ro/example/myfile", mode(0o600)),
+        Code::ReadOnly
+    );
+
+    // the confined session sees /example as its root
+    let info = wv_assert_ok!(VFS::stat("/sub/myfile"));
+    wv_assert_eq!(t, info.size, 5);
+    wv_assert_ok!(VFS::open("/sub/myfile", OpenFlags::W));
+    wv_assert_err!(t, VFS::stat("/sub/example"), Code::NoSuchFile);
+    wv_assert_err!(t, VFS::rmdir("/sub"), Code::InvArgs);
+
+    // symbolic links cannot be used to leave the root
+    wv_assert_ok!(VFS::symlink("/myfile", "/example/abs"));
+    wv_assert_ok!(VFS::symlink("../../test.txt", "/example/sub/up"));
+    wv_assert_ok!(VFS::stat("/sub/abs"));
+    wv_assert_err!(t, VFS::stat("/example/abs"), Code::NoSuchFile);
+    wv_assert_ok!(VFS::stat("/example/sub/up"));
+    wv_assert_err!(t, VFS::stat("/sub/sub/up"), Code::NoSuchFile);
+
+    wv_assert_ok!(Activity::own().mounts().remove("/sub"));
+    wv_assert_ok!(Activity::own().mounts().remove("/ro"));
+
+    wv_assert_ok!(VFS::unlink("/example/abs"));
+    wv_assert_ok!(VFS::unlink("/example/sub/up"));
+    wv_assert_ok!(VFS::rmdir("/example/sub"));
+    teardown();
+}
+
+fn quotas(t: &mut dyn WvTester) {
+    setup();
+
+    let blocksize = wv_assert_ok!(VFS::stat("/example/myfile")).blocksize as usize;
+
+    // derive a session that can allocate at most 4 blocks and 2 inodes
+    {
+        let root = Activity::own().mounts().get_by_path("/").unwrap();
+        let root = root.borrow();
+        let m3fs = root.as_any().downcast_ref::<M3FS>().unwrap();
+
+        let id = Activity::own().mounts().alloc_id();
+        let args = DeriveArgs::default().root("/example").quota(4, 2);
+        let quota = wv_assert_ok!(m3fs.derive(id, &args));
+        wv_assert_ok!(Activity::own().mounts().add("/quota", quota));
+    }
+
+    let get_quota = || {
+        let fs = Activity::own().mounts().get_by_path("/quota").unwrap();
+        let fs = fs.borrow();
+        let m3fs = fs.as_any().downcast_ref::<M3FS>().unwrap();
+        m3fs.quota().unwrap()
+    };
+
+    let (blocks, inodes) = get_quota();
+    wv_assert_eq!(t, blocks.total(), 4);
+    wv_assert_eq!(t, blocks.left(), 4);
+    wv_assert_eq!(t, inodes.total(), 2);
+    wv_assert_eq!(t, inodes.left(), 2);
+
+    // writing stops as soon as the block quota is exhausted
+    {
+        let mut file = wv_assert_ok!(VFS::open("/quota/big", OpenFlags::W | OpenFlags::CREATE));
+        let buf = vec![0u8; blocksize * 8];
+        wv_assert_err!(t, file.write_all(&buf), Code::NoSpace);
+    }
+    let info = wv_assert_ok!(VFS::stat("/quota/big"));
+    wv_assert_eq!(t, info.size, blocksize * 4);
+
+    let (blocks, inodes) = get_quota();
+    wv_assert_eq!(t, blocks.left(), 0);
+    wv_assert_eq!(t, inodes.left(), 1);
+
+    // the inode quota is enforced as well
+    wv_assert_ok!(VFS::open("/quota/small", OpenFlags::W | OpenFlags::CREATE));
+    wv_assert_err!(
+        t,
+        VFS::open("/quota/toomuch", OpenFlags::W | OpenFlags::CREATE),
+        Code::NoSpace
+    );
+    wv_assert_err!(
+        t,
+        VFS::mkdir("/quota/dir", FileMode::from_bits(0o755).unwrap()),
+        Code::NoSpace
+    );
+
+    // removing files gives the space back
+    wv_assert_ok!(VFS::unlink("/quota/big"));
+    wv_assert_ok!(VFS::unlink("/quota/small"));
+    let (blocks, inodes) = get_quota();
+    wv_assert_eq!(t, blocks.left(), 4);
+    wv_assert_eq!(t, inodes.left(), 2);
+
+    // the block for extended attributes is charged as well
+    wv_assert_ok!(VFS::open("/quota/attrs", OpenFlags::W | OpenFlags::CREATE));
+    wv_assert_ok!(VFS::setxattr("/quota/attrs", "user.foo", b"bar"));
+    wv_assert_eq!(t, get_quota().0.left(), 3);
+    wv_assert_ok!(VFS::removexattr("/quota/attrs", "user.foo"));
+    wv_assert_eq!(t, get_quota().0.left(), 4);
+    wv_assert_ok!(VFS::unlink("/quota/attrs"));
+
+    // files stay charged to the session that created them, even if they are removed elsewhere
+    {
+        let mut file = wv_assert_ok!(VFS::open("/quota/other", OpenFlags::W | OpenFlags::CREATE));
+        wv_assert_ok!(file.write_all(&vec![0u8; blocksize * 2]));
+    }
+    let (blocks, inodes) = get_quota();
+    wv_assert_eq!(t, blocks.left(), 2);
+    wv_assert_eq!(t, inodes.left(), 1);
+    wv_assert_ok!(VFS::unlink("/example/other"));
+    let (blocks, inodes) = get_quota();
+    wv_assert_eq!(t, blocks.left(), 4);
+    wv_assert_eq!(t, inodes.left(), 2);
+
+    wv_assert_ok!(Activity::own().mounts().remove("/quota"));
+    teardown();
+}
+
+fn xattrs(t: &mut dyn WvTester) {
+    setup();
+
+    let path = "/example/myfile";
+    wv_assert_eq!(t, VFS::listxattr(path), Ok(vec![]));
+    wv_assert_err!(t, VFS::getxattr(path, "user.foo"), Code::NotFound);
+
+    // set, get, and list attributes
+    wv_assert_ok!(VFS::setxattr(path, "user.foo", b"bar"));
+    wv_assert_ok!(VFS::setxattr(path, "user.empty", b""));
+    wv_assert_eq!(t, VFS::getxattr(path, "user.foo"), Ok(b"bar".to_vec()));
+    wv_assert_eq!(t, VFS::getxattr(path, "user.empty"), Ok(vec![]));
+    wv_assert_eq!(
+        t,
+        VFS::listxattr(path),
+        Ok(vec!["user.foo".to_string(), "user.empty".to_string()])
+    );
+
+    // overwrite and remove attributes
+    wv_assert_ok!(VFS::setxattr(path, "user.foo", b"a longer value"));
+    wv_assert_eq!(
+        t,
+        VFS::getxattr(path, "user.foo"),
+        Ok(b"a longer value".to_vec())
+    );
+    wv_assert_ok!(VFS::removexattr(path, "user.empty"));
+    wv_assert_err!(t, VFS::removexattr(path, "user.empty"), Code::NotFound);
+    wv_assert_eq!(t, VFS::listxattr(path), Ok(vec!["user.foo".to_string()]));
+
+    // values are arbitrary bytes
+    let binary = (0..=255u8).rev().collect::<Vec<_>>();
+    wv_assert_ok!(VFS::setxattr(path, "user.bin", &binary[..9]));
+    wv_assert_eq!(t, VFS::getxattr(path, "user.bin"), Ok(binary[..9].to_vec()));
+    wv_assert_ok!(VFS::setxattr(path, "user.bin", &binary[200..]));
+    wv_assert_eq!(
+        t,
+        VFS::getxattr(path, "user.bin"),
+        Ok(binary[200..].to_vec())
+    );
+    wv_assert_ok!(VFS::removexattr(path, "user.bin"));
+
+    // invalid names and values
+    wv_assert_err!(t, VFS::setxattr(path, "", b"val"), Code::InvArgs);
+    let long = "x".repeat(256);
+    wv_assert_err!(t, VFS::setxattr(path, &long, b"val"), Code::InvArgs);
+    wv_assert_err!(
+        t,
+        VFS::setxattr(path, "user.foo", long.as_bytes()),
+        Code::InvArgs
+    );
+
+    // all attributes need to fit into a single block
+    let blocksize = wv_assert_ok!(VFS::stat(path)).blocksize as usize;
+    let value = vec![b'v'; 200];
+    let mut i = 0;
+    let res = loop {
+        let res = VFS::setxattr(path, &format!("user.attr{}", i), &value);
+        if res.is_err() || i > blocksize / value.len() {
+            break res;
+        }
+        i += 1;
+    };
+    wv_assert_err!(t, res, Code::NoSpace);
+    wv_assert_eq!(t, VFS::getxattr(path, "user.attr0"), Ok(value.clone()));
+
+    teardown();
+}
+
+fn check(t: &mut dyn WvTester) {
+    setup();
+
+    let root = Activity::own().mounts().get_by_path("/").unwrap();
+    let root = root.borrow();
+    let m3fs = root.as_any().downcast_ref::<M3FS>().unwrap();
+
+    let report = wv_assert_ok!(m3fs.check(false));
+    wv_assert!(t, report.is_clean());
+
+    // blocks that are currently used for an append are not leaked
+    {
+        let mut file = wv_assert_ok!(VFS::open(
+            "/example/myfile",
+            OpenFlags::W | OpenFlags::APPEND
+        ));
+        wv_assert_ok!(file.write_all(&vec![1u8; 1024]));
+
+        let report = wv_assert_ok!(m3fs.check(false));
+        wv_assert!(t, report.is_clean());
+    }
+
+    // a clean file system stays clean when repairing it
+    let report = wv_assert_ok!(m3fs.check(true));
+    wv_assert!(t, report.is_clean());
+
+    // only root is allowed to check the file system and repairing requires write access
+    {
+        let id = Activity::own().mounts().alloc_id();
+        let user = wv_assert_ok!(m3fs.derive(id, &DeriveArgs::default().creds(1, 1)));
+        let user = user.borrow();
+        let user = user.as_any().downcast_ref::<M3FS>().unwrap();
+        wv_assert_err!(t, user.check(false), Code::NoPerm);
+
+        let id = Activity::own().mounts().alloc_id();
+        let ro = wv_assert_ok!(m3fs.derive(id, &DeriveArgs::default().readonly(true)));
+        let ro = ro.borrow();
+        let ro = ro.as_any().downcast_ref::<M3FS>().unwrap();
+        wv_assert_ok!(ro.check(false));
+        wv_assert_err!(t, ro.check(true), Code::ReadOnly);
+    }
+
+    teardown();
+}
+
+fn sparse(t: &mut dyn WvTester) {
+    setup();
+
+    let blocksize = wv_assert_ok!(VFS::stat("/example/myfile")).blocksize as usize;
+
+    let read_all = || {
+        let mut file = wv_assert_ok!(VFS::open("/example/sparse", OpenFlags::R));
+        let mut buf = Vec::new();
+        wv_assert_ok!(file.read_to_end(&mut buf));
+        buf
+    };
+
+    // writing behind the end leaves a hole that reads as zeros
+    {
+        let mut file = wv_assert_ok!(VFS::open(
+            "/example/sparse",
+            OpenFlags::RW | OpenFlags::CREATE
+        ));
+        wv_assert_eq!(
+            t,
+            file.seek(blocksize * 4, SeekMode::SET),
+            Ok(blocksize * 4)
+        );
+        wv_assert_ok!(write!(file, "tail"));
+    }
+    wv_assert_eq!(
+        t,
+        wv_assert_ok!(VFS::stat("/example/sparse")).size,
+        blocksize * 4 + 4
+    );
+    let data = read_all();
+    wv_assert_eq!(t, data.len(), blocksize * 4 + 4);
+    wv_assert!(t, data[0..blocksize * 4].iter().all(|b| *b == 0));
+    wv_assert_eq!(t, &data[blocksize * 4..], b"tail");
+
+    // truncating to a larger size extends the file with a hole
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/sparse", OpenFlags::W));
+        wv_assert_ok!(file.truncate(blocksize * 8));
+    }
+    let data = read_all();
+    wv_assert_eq!(t, data.len(), blocksize * 8);
+    wv_assert_eq!(t, &data[blocksize * 4..blocksize * 4 + 4], b"tail");
+    wv_assert!(t, data[blocksize * 4 + 4..].iter().all(|b| *b == 0));
+
+    // writes into holes allocate blocks and punching holes keeps the size
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/sparse", OpenFlags::W));
+        wv_assert_ok!(file.write_all(&vec![1u8; blocksize * 2]));
+        wv_assert_ok!(file.fallocate(blocksize / 2, blocksize, FAllocMode::PUNCH_HOLE));
+    }
+    let data = read_all();
+    wv_assert_eq!(t, data.len(), blocksize * 8);
+    wv_assert!(t, data[0..blocksize / 2].iter().all(|b| *b == 1));
+    wv_assert!(
+        t,
+        data[blocksize / 2..blocksize * 3 / 2]
+            .iter()
+            .all(|b| *b == 0)
+    );
+    wv_assert!(
+        t,
+        data[blocksize * 3 / 2..blocksize * 2]
+            .iter()
+            .all(|b| *b == 1)
+    );
+
+    // preallocating behind the end extends the file
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/sparse", OpenFlags::W));
+        wv_assert_ok!(file.fallocate(blocksize * 8, blocksize, FAllocMode::empty()));
+        wv_assert_err!(t, file.fallocate(0, 0, FAllocMode::empty()), Code::InvArgs);
+    }
+    wv_assert_eq!(
+        t,
+        wv_assert_ok!(VFS::stat("/example/sparse")).size,
+        blocksize * 9
+    );
+
+    // read-only files cannot be changed
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/sparse", OpenFlags::R));
+        wv_assert_err!(
+            t,
+            file.fallocate(0, blocksize, FAllocMode::PUNCH_HOLE),
+            Code::NoPerm
+        );
+    }
+
+    // holes are not reported as inconsistencies
+    {
+        let root = Activity::own().mounts().get_by_path("/").unwrap();
+        let root = root.borrow();
+        let m3fs = root.as_any().downcast_ref::<M3FS>().unwrap();
+        let report = wv_assert_ok!(m3fs.check(false));
+        wv_assert!(t, report.is_clean());
+    }
+
+    wv_assert_ok!(VFS::unlink("/example/sparse"));
+    teardown();
+}
+
+fn reflink(t: &mut dyn WvTester) {
+    setup();
+
+    let blocksize = wv_assert_ok!(VFS::stat("/example/myfile")).blocksize as usize;
+
+    let read_all = |path: &str| {
+        let mut file = wv_assert_ok!(VFS::open(path, OpenFlags::R));
+        let mut buf = Vec::new();
+        wv_assert_ok!(file.read_to_end(&mut buf));
+        buf
+    };
+
+    let mut orig = Vec::new();
+    for i in 0..blocksize * 3 {
+        orig.push((i / blocksize + 1) as u8);
+    }
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/orig", OpenFlags::W | OpenFlags::CREATE));
+        wv_assert_ok!(file.write_all(&orig));
+    }
+
+    // the clone has the same content
+    wv_assert_ok!(VFS::reflink("/example/orig", "/example/clone"));
+    wv_assert_eq!(
+        t,
+        wv_assert_ok!(VFS::stat("/example/clone")).size,
+        blocksize * 3
+    );
+    wv_assert_eq!(t, read_all("/example/clone"), orig);
+
+    // writing to the clone does not change the original and vice versa
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/clone", OpenFlags::W));
+        wv_assert_ok!(file.seek(blocksize + 10, SeekMode::SET));
+        wv_assert_ok!(write!(file, "clone"));
+    }
+    {
+        let mut file = wv_assert_ok!(VFS::open("/example/orig", OpenFlags::W));
+        wv_assert_ok!(file.seek(blocksize * 2, SeekMode::SET));
+        wv_assert_ok!(write!(file, "orig"));
+    }
+
+    let clone = read_all("/example/clone");
+    wv_assert_eq!(t, &clone[blocksize + 10..blocksize + 15], b"clone");
+    wv_assert_eq!(
+        t,
+        &clone[blocksize * 2..blocksize * 2 + 4],
+        &orig[blocksize * 2..blocksize * 2 + 4]
+    );
+    let modified = read_all("/example/orig");
+    wv_assert_eq!(
+        t,
+        &modified[blocksize + 10..blocksize + 15],
+        &orig[blocksize + 10..blocksize + 15]
+    );
+    wv_assert_eq!(t, &modified[blocksize * 2..blocksize * 2 + 4], b"orig");
+
+    // the destination must not exist and directories cannot be cloned
+    wv_assert_err!(
+        t,
+        VFS::reflink("/example/orig", "/example/clone"),
+        Code::Exists
+    );
+    wv_assert_err!(t, VFS::reflink("/example", "/example/dir"), Code::IsDir);
+
+    // files cannot be cloned while they are open for writing
+    {
+        let _file = wv_assert_ok!(VFS::open("/example/orig", OpenFlags::W));
+        wv_assert_err!(
+            t,
+            VFS::reflink("/example/orig", "/example/clone2"),
+            Code::InUse
+        );
+        wv_assert_err!(t, VFS::stat("/example/clone2"), Code::NoSuchFile);
+
+        // readers are fine, though
+        let _reader = wv_assert_ok!(VFS::open("/example/clone", OpenFlags::R));
+        wv_assert_ok!(VFS::reflink("/example/clone", "/example/clone2"));
+    }
+    wv_assert_ok!(VFS::reflink("/example/orig", "/example/clone3"));
+    wv_assert_ok!(VFS::unlink("/example/clone2"));
+    wv_assert_ok!(VFS::unlink("/example/clone3"));
+
+    // shared blocks are not reported as inconsistencies
+    {
+        let root = Activity::own().mounts().get_by_path("/").unwrap();
+        let root = root.borrow();
+        let m3fs = root.as_any().downcast_ref::<M3FS>().unwrap();
+        let report = wv_assert_ok!(m3fs.check(false));
+        wv_assert!(t, report.is_clean());
+    }
+
+    // the shared blocks stay in use until both files are removed
+    wv_assert_ok!(VFS::unlink("/example/orig"));
+    wv_assert_eq!(t, read_all("/example/clone"), clone);
+    wv_assert_ok!(VFS::unlink("/example/clone"));
+
+    teardown();
+}
@@ -13,14 +13,21 @@
  * General Public License version 2 for more details.
  */
 
+use m3::cfg;
 use m3::com::MemGate;
+use m3::errors::Code;
+use m3::goff;
 use m3::kif::Perm;
-use m3::test::WvTester;
-use m3::tiles::Activity;
-use m3::{wv_assert_ok, wv_run_test};
+use m3::session::MapFlags;
+use m3::test::{DefaultWvTester, WvTester};
+use m3::tiles::{Activity, ActivityArgs, ChildActivity, Tile};
+use m3::{wv_assert_eq, wv_assert_err, wv_assert_ok, wv_run_test};
 
 pub fn run(t: &mut dyn WvTester) {
     wv_run_test!(t, large_pages);
+    wv_run_test!(t, large_anon);
+    wv_run_test!(t, advise);
+    wv_run_test!(t, shared_mem);
 }
 
 fn large_pages(_t: &mut dyn WvTester) {
@@ -41,3 +48,198 @@ fn large_pages(_t: &mut dyn WvTester) {
         m3::println!("Skipping paging test without pager");
     }
 }
+
+fn large_anon(t: &mut dyn WvTester) {
+    if let Some(pager) = Activity::own().pager() {
+        const VIRT: goff = 0x3400_0000;
+        const LPAGE: usize = cfg::LPAGE_SIZE;
+        const PAGE: usize = cfg::PAGE_SIZE;
+        const WORDS_PER_PAGE: usize = PAGE / 8;
+
+        // address and size need to be aligned
+        wv_assert_err!(
+            t,
+            pager.map_anon(VIRT + PAGE as goff, LPAGE, Perm::RW, MapFlags::LARGE),
+            Code::InvArgs
+        );
+        wv_assert_err!(
+            t,
+            pager.map_anon(VIRT, LPAGE + PAGE, Perm::RW, MapFlags::LARGE),
+            Code::InvArgs
+        );
+
+        // touch a page right below, so that the page table for the large pages exists already and
+        // only page tables for small pages can be allocated below
+        const BELOW: goff = VIRT - LPAGE as goff;
+        wv_assert_ok!(pager.map_anon(BELOW, PAGE, Perm::RW, MapFlags::PRIVATE));
+        unsafe { (BELOW as *mut u64).write(1) };
+
+        let free_pts = || {
+            let quota = Activity::own().tile().quota().unwrap();
+            quota.page_tables().left()
+        };
+
+        wv_assert_ok!(pager.map_anon(VIRT, 2 * LPAGE, Perm::RW, MapFlags::LARGE));
+
+        // write the first word of every page
+        let pts = free_pts();
+        let ptr = VIRT as *mut u64;
+        for i in (0..2 * LPAGE / 8).step_by(WORDS_PER_PAGE) {
+            unsafe { ptr.add(i).write(i as u64 + 1) };
+        }
+        // large pages don't need a page table, whereas small pages would have needed two
+        wv_assert_eq!(t, free_pts(), pts);
+
+        // changing single pages splits the large page
+        let page = (LPAGE + PAGE) as goff;
+        wv_assert_ok!(pager.protect(VIRT + page, PAGE, Perm::R));
+        wv_assert_ok!(pager.dont_need(VIRT + page + PAGE as goff, PAGE));
+        for i in (0..2 * LPAGE / 8).step_by(WORDS_PER_PAGE) {
+            let expected = if i * 8 == page as usize + PAGE {
+                0
+            }
+            else {
+                i as u64 + 1
+            };
+            wv_assert_eq!(t, unsafe { ptr.add(i).read() }, expected);
+        }
+        // only the split large page needs a page table
+        wv_assert_eq!(t, free_pts(), pts - 1);
+
+        wv_assert_ok!(pager.unmap(VIRT));
+        wv_assert_ok!(pager.unmap(BELOW));
+    }
+    else {
+        m3::println!("Skipping paging test without pager");
+    }
+}
+
+fn advise(t: &mut dyn WvTester) {
+    if let Some(pager) = Activity::own().pager() {
+        const VIRT: goff = 0x3100_0000;
+        const PAGE: usize = cfg::PAGE_SIZE;
+        const WORDS_PER_PAGE: usize = PAGE / 8;
+        wv_assert_ok!(pager.map_anon(VIRT, 4 * PAGE, Perm::RW, MapFlags::PRIVATE));
+
+        // populate all pages in advance and fill them
+        wv_assert_ok!(pager.will_need(VIRT, 4 * PAGE));
+        let ptr = VIRT as *mut u64;
+        for i in 0..4 * WORDS_PER_PAGE {
+            unsafe { ptr.add(i).write(i as u64 + 1) };
+        }
+
+        // read-only pages can still be read
+        wv_assert_ok!(pager.protect(VIRT + PAGE as goff, PAGE, Perm::R));
+        wv_assert_eq!(
+            t,
+            unsafe { ptr.add(WORDS_PER_PAGE).read() },
+            WORDS_PER_PAGE as u64 + 1
+        );
+        wv_assert_ok!(pager.protect(VIRT + PAGE as goff, PAGE, Perm::RW));
+        unsafe { ptr.add(WORDS_PER_PAGE).write(42) };
+
+        // dropped pages are zeroed
+        wv_assert_ok!(pager.dont_need(VIRT + 2 * PAGE as goff, 2 * PAGE));
+        for i in 0..4 * WORDS_PER_PAGE {
+            let expected = match i {
+                WORDS_PER_PAGE => 42,
+                i if i < 2 * WORDS_PER_PAGE => i as u64 + 1,
+                _ => 0,
+            };
+            wv_assert_eq!(t, unsafe { ptr.add(i).read() }, expected);
+        }
+
+        wv_assert_err!(t, pager.protect(VIRT, 0, Perm::R), Code::InvArgs);
+        wv_assert_err!(
+            t,
+            pager.dont_need(VIRT + 4 * PAGE as goff, PAGE),
+            Code::NotFound
+        );
+
+        wv_assert_ok!(pager.unmap(VIRT));
+    }
+    else {
+        m3::println!("Skipping paging test without pager");
+    }
+}
+
+fn shared_mem(t: &mut dyn WvTester) {
+    if let Some(pager) = Activity::own().pager() {
+        const VIRT1: goff = 0x3200_0000;
+        const VIRT2: goff = 0x3300_0000;
+        const VIRT3: goff = 0x3500_0000;
+        const SIZE: usize = 2 * cfg::PAGE_SIZE;
+
+        wv_assert_ok!(pager.map_shm("tpaging-shm", VIRT1, SIZE, Perm::RW, true));
+        wv_assert_err!(
+            t,
+            pager.map_shm("tpaging-shm", VIRT2, SIZE, Perm::RW, true),
+            Code::Exists
+        );
+        wv_assert_err!(
+            t,
+            pager.map_shm("tpaging-shm", VIRT2, 2 * SIZE, Perm::R, false),
+            Code::InvArgs
+        );
+        wv_assert_ok!(pager.map_shm("tpaging-shm", VIRT2, SIZE, Perm::R, false));
+
+        // writes through the first mapping are visible in the second one
+        let ptr1 = VIRT1 as *mut u64;
+        let ptr2 = VIRT2 as *const u64;
+        unsafe {
+            wv_assert_eq!(t, ptr2.read(), 0);
+            ptr1.write(0xdead_beef);
+            ptr1.add(SIZE / 8 - 1).write(0x1234);
+            wv_assert_eq!(t, ptr2.read(), 0xdead_beef);
+            wv_assert_eq!(t, ptr2.add(SIZE / 8 - 1).read(), 0x1234);
+        }
+
+        // other activities with their own pager session can map it as well, but only with the
+        // permissions of the creator
+        let tile = wv_assert_ok!(Tile::get("clone|own"));
+        let act = wv_assert_ok!(ChildActivity::new_with(tile, ActivityArgs::new("shm")));
+        let act = wv_assert_ok!(act.run(|| {
+            let mut t = DefaultWvTester::default();
+            let pager = Activity::own().pager().unwrap();
+            wv_assert_err!(
+                t,
+                pager.map_shm("tpaging-shm", VIRT3, SIZE, Perm::RWX, false),
+                Code::NoPerm
+            );
+            wv_assert_ok!(pager.map_shm("tpaging-shm", VIRT3, SIZE, Perm::RW, false));
+
+            let ptr = VIRT3 as *mut u64;
+            unsafe {
+                wv_assert_eq!(t, ptr.read(), 0xdead_beef);
+                ptr.add(1).write(0x5678);
+            }
+            wv_assert_ok!(pager.unmap(VIRT3));
+            0
+        }));
+        wv_assert_eq!(t, act.wait(), Ok(0));
+        unsafe {
+            wv_assert_eq!(t, ptr2.add(1).read(), 0x5678);
+        }
+
+        // read-only mappings cannot be made writable
+        wv_assert_err!(t, pager.protect(VIRT2, SIZE, Perm::RW), Code::NoPerm);
+
+        // existing mappings survive unlinking
+        wv_assert_ok!(pager.unlink_shm("tpaging-shm"));
+        wv_assert_err!(t, pager.unlink_shm("tpaging-shm"), Code::NotFound);
+        wv_assert_err!(
+            t,
+            pager.map_shm("tpaging-shm", VIRT2, SIZE, Perm::R, false),
+            Code::NotFound
+        );
+        unsafe {
+            wv_assert_eq!(t, ptr2.read(), 0xdead_beef);
+        }
+
+        wv_assert_ok!(pager.unmap(VIRT1));
+        wv_assert_ok!(pager.unmap(VIRT2));
+    }
+    else {
+        m3::println!("Skipping paging test without pager");
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use m3::cfg;
+use m3::goff;
+use m3::kif::Perm;
+use m3::session::MapFlags;
+use m3::test::WvTester;
+use m3::tiles::Activity;
+use m3::{wv_assert_eq, wv_assert_ok, wv_run_test};
+
+// twice as much as our memory quota (see boot/rust-unittests-swap.xml)
+const SIZE: usize = 32 * 1024 * 1024;
+const VIRT: goff = 0x3800_0000;
+const WORDS_PER_PAGE: usize = cfg::PAGE_SIZE / 8;
+
+pub fn run(t: &mut dyn WvTester) {
+    wv_run_test!(t, swap_anon);
+    wv_run_test!(t, swap_freed);
+}
+
+fn pattern(page: usize, round: u64) -> u64 {
+    (round << 32) | page as u64
+}
+
+fn write_and_check(t: &mut dyn WvTester, round: u64) {
+    let pager = Activity::own().pager().unwrap();
+    wv_assert_ok!(pager.map_anon(VIRT, SIZE, Perm::RW, MapFlags::PRIVATE));
+
+    // touching all pages exceeds our quota, which forces the pager to swap out the least recently
+    // used memory
+    let ptr = VIRT as *mut u64;
+    for page in 0..SIZE / cfg::PAGE_SIZE {
+        unsafe { ptr.add(page * WORDS_PER_PAGE).write(pattern(page, round)) };
+    }
+
+    // the first pages have been swapped out by now and are read back from the swap file
+    for page in 0..SIZE / cfg::PAGE_SIZE {
+        wv_assert_eq!(
+            t,
+            unsafe { ptr.add(page * WORDS_PER_PAGE).read() },
+            pattern(page, round)
+        );
+    }
+
+    wv_assert_ok!(pager.unmap(VIRT));
+}
+
+fn swap_anon(t: &mut dyn WvTester) {
+    write_and_check(t, 1);
+}
+
+fn swap_freed(t: &mut dyn WvTester) {
+    // the swap file only fits the memory of one mapping, so that this only works if the swap space
+    // of the previous mappings has been freed on unmap
+    for round in 2..5 {
+        write_and_check(t, round);
+    }
+}
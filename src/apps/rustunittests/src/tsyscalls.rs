@@ -23,6 +23,7 @@ use m3::cpu;
 use m3::errors::{Code, Error};
 use m3::goff;
 use m3::kif::syscalls::{ActivityOp, SemOp};
+use m3::kif::tilemux::{Priority, PRIO_COUNT};
 use m3::kif::{CapRngDesc, CapType, Perm, INVALID_SEL, SEL_ACT, SEL_KMEM, SEL_TILE};
 use m3::math;
 use m3::server::{Handler, Server, SessId, SessionContainer};
@@ -354,35 +355,48 @@ fn create_activity(t: &mut dyn WvTester) {
     // invalid dest selector
     wv_assert_err!(
         t,
-        syscalls::create_activity(SEL_KMEM, "test", tile.sel(), kmem),
+        syscalls::create_activity(SEL_KMEM, "test", tile.sel(), kmem, None),
         Code::InvArgs
     );
 
     // invalid name
     wv_assert_err!(
         t,
-        syscalls::create_activity(sels, "", tile.sel(), kmem),
+        syscalls::create_activity(sels, "", tile.sel(), kmem, None),
         Code::InvArgs
     );
 
     // invalid kmem
     wv_assert_err!(
         t,
-        syscalls::create_activity(sels, "test", tile.sel(), INVALID_SEL),
+        syscalls::create_activity(sels, "test", tile.sel(), INVALID_SEL, None),
         Code::InvArgs
     );
     wv_assert_err!(
         t,
-        syscalls::create_activity(sels, "test", tile.sel(), SEL_ACT),
+        syscalls::create_activity(sels, "test", tile.sel(), SEL_ACT, None),
         Code::InvArgs
     );
 
-    wv_assert_ok!(syscalls::create_activity(sels, "test", tile.sel(), kmem));
+    // invalid priority
+    wv_assert_err!(
+        t,
+        syscalls::create_activity(sels, "test", tile.sel(), kmem, Some(PRIO_COUNT as Priority)),
+        Code::InvArgs
+    );
+
+    wv_assert_ok!(syscalls::create_activity(
+        sels,
+        "test",
+        tile.sel(),
+        kmem,
+        None
+    ));
     if !tile.desc().has_virtmem() {
         let new_sels = Activity::own().alloc_sels(3);
         wv_assert_err!(
             t,
-            syscalls::create_activity(new_sels, "test", tile.sel(), kmem),
+            syscalls::create_activity(new_sels, "test", tile.sel(), kmem, None),
             Code::NotSup
         );
     }
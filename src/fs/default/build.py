@@ -3,4 +3,7 @@ def build(gen, env):
         blocks = 160 * 1024
     else:
         blocks = 32 * 1024
-    env.build_fs(gen, out = 'default.img', dir = '.', blocks = blocks, inodes = 512)
+    # the journal is only used with the disk backend. directories are hashed so that the tests with
+    # large directories exercise the hashed lookups, whereas the bench image uses linear directories
+    env.build_fs(gen, out = 'default.img', dir = '.', blocks = blocks, inodes = 512, journal = 256,
+                 hashdirs = True, reflink = True)
@@ -82,6 +82,9 @@ struct Errors {
         UTF8_ERROR,
         BAD_FD,
         SEEK_PIPE,
+        LINK_LOOP,
+        READ_ONLY,
+        IN_USE,
         // networking
         INV_STATE,
         WOULD_BLOCK,
@@ -258,6 +258,7 @@ struct KIF {
             xfer_t dst_sel;
             xfer_t tile_sel;
             xfer_t kmem_sel;
+            xfer_t prio;
             xfer_t namelen;
             char name[MAX_STR_SIZE];
         } PACKED;
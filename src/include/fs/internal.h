@@ -64,6 +64,8 @@ using mode_t = uint32_t;
 using inodeno_t = uint32_t;
 using blockno_t = uint32_t;
 using time_t = uint32_t;
+using uid_t = uint32_t;
+using gid_t = uint32_t;
 
 enum {
     INODE_DIR_COUNT = 3,
@@ -71,11 +73,15 @@ enum {
 };
 
 constexpr inodeno_t INVALID_INO = static_cast<inodeno_t>(-1);
+// the block number reported for blocks within holes of sparse files
+constexpr blockno_t HOLE_BNO = static_cast<blockno_t>(-1);
 
 #define M3FS_SEEK_SET 0
 #define M3FS_SEEK_CUR 1
 #define M3FS_SEEK_END 2
 
+#define M3FS_FALLOC_PUNCH_HOLE 1
+
 enum {
     FILE_R = 1,
     FILE_W = 2,
@@ -102,6 +108,8 @@ struct FileInfo {
     dev_t devno;
     inodeno_t inode;
     mode_t mode;
+    uid_t uid;
+    gid_t gid;
     unsigned links;
     size_t size;
     time_t lastaccess;
@@ -112,7 +120,7 @@ struct FileInfo {
     blockno_t firstblock;
 };
 
-// should be 64 bytes large
+// should be 128 bytes large
 struct alignas(8) INode {
     dev_t devno;
     uint8_t : 8;
@@ -126,18 +134,51 @@ struct alignas(8) INode {
     Extent direct[INODE_DIR_COUNT];
     blockno_t indirect;
     blockno_t dindirect;
+    uid_t uid;
+    gid_t gid;
+    blockno_t xattr;
+    // the quota that is charged for this inode and the mount the quota belongs to
+    uint32_t quota_mount;
+    uint32_t quota;
+    uint32_t reserved[11];
 } PACKED;
 
+// entries with namelen = 0 are empty and only denote free space. they are left behind if the only
+// entry of a block is removed and are used for empty buckets in hashed directories.
 struct DirEntry {
+    // the hash of a name, determining its bucket in hashed directories (FNV-1a)
+    static uint32_t hash(const char *name, size_t len) {
+        uint32_t h = 0x811C9DC5;
+        for(size_t i = 0; i < len; ++i)
+            h = (h ^ static_cast<uint8_t>(name[i])) * 0x01000193;
+        return h;
+    }
+
     inodeno_t nodeno;
     uint32_t namelen;
     uint32_t next;
     char name[];
 } PACKED;
 
+// the extended attributes of an inode are stored as a list of these entries in a single block,
+// terminated by an entry with namelen = 0 or the end of the block.
+struct XAttrEntry {
+    uint8_t namelen;
+    uint8_t valuelen;
+    // the name, directly followed by the value
+    char data[];
+} PACKED;
+
 struct SuperBlock {
+    enum {
+        // directories consist of 2^n blocks, each holding the entries whose names hash to it
+        FEAT_HASHED_DIRS = 1,
+        // data blocks can be shared between files, which is tracked in a reference-count table
+        FEAT_REFLINK = 2,
+    };
+
     blockno_t first_inodebm_block() const {
-        static_assert(sizeof(INode) == 64, "INode not 64-byte large");
+        static_assert(sizeof(INode) == 128, "INode not 128-byte large");
         return 1;
     }
     blockno_t inodebm_blocks() const {
@@ -155,9 +196,19 @@ struct SuperBlock {
     blockno_t inode_blocks() const {
         return (total_inodes * sizeof(INode) + blocksize - 1) / blocksize;
     }
-    blockno_t first_data_block() const {
+    blockno_t first_journal_block() const {
         return first_inode_block() + inode_blocks();
     }
+    blockno_t first_refcnt_block() const {
+        return first_journal_block() + journal_blocks;
+    }
+    // one byte per block with the number of additional references
+    blockno_t refcnt_blocks() const {
+        return reflink() ? (total_blocks + blocksize - 1) / blocksize : 0;
+    }
+    blockno_t first_data_block() const {
+        return first_refcnt_block() + refcnt_blocks();
+    }
     uint extents_per_block() const {
         return blocksize / sizeof(Extent);
     }
@@ -166,7 +217,22 @@ struct SuperBlock {
     }
     uint32_t get_checksum() const {
         return 1 + blocksize * 2 + total_inodes * 3 + total_blocks * 5 + free_inodes * 7 +
-               free_blocks * 11 + first_free_inode * 13 + first_free_block * 17;
+               free_blocks * 11 + first_free_inode * 13 + first_free_block * 17 +
+               journal_blocks * 19 + features * 23;
+    }
+    bool hashed_dirs() const {
+        return features & FEAT_HASHED_DIRS;
+    }
+    bool reflink() const {
+        return features & FEAT_REFLINK;
+    }
+    // the number of buckets of a hashed directory with given size (blocks behind are unused)
+    uint32_t dir_buckets(uint64_t size) const {
+        uint32_t blocks = static_cast<uint32_t>(size / blocksize);
+        uint32_t buckets = blocks ? 1 : 0;
+        while(buckets * 2 <= blocks)
+            buckets *= 2;
+        return buckets;
     }
 
     uint32_t blocksize;
@@ -177,6 +243,26 @@ struct SuperBlock {
     uint32_t first_free_inode;
     uint32_t first_free_block;
     uint32_t checksum;
+    uint32_t journal_blocks;
+    uint32_t features;
+    // the number of times the file system has been mounted, which identifies the quotas of a mount
+    uint32_t mounts;
+} PACKED;
+
+enum {
+    JOURNAL_MAGIC = 0x4D334A4E,
+    JOURNAL_HEADER = 1,
+    JOURNAL_DESC = 2,
+    JOURNAL_COMMIT = 3,
+};
+
+// the header of all journal-internal blocks (the journal header, descriptors, and commits)
+struct JournalHeader {
+    uint32_t magic;
+    uint32_t kind;
+    uint32_t seq;
+    uint32_t count;
+    uint32_t checksum;
 } PACKED;
 
 class Bitmap {
@@ -287,7 +373,7 @@ static UNUSED m3::blockno_t get_block_no_rec(const m3::INode &ino, m3::blockno_t
         }
         else {
             if(extents[i].length > no) {
-                res = extents[i].start + no;
+                res = extents[i].start ? extents[i].start + no : m3::HOLE_BNO;
                 break;
             }
             no -= extents[i].length;
@@ -299,7 +385,7 @@ static UNUSED m3::blockno_t get_block_no_rec(const m3::INode &ino, m3::blockno_t
 static UNUSED m3::blockno_t get_block_no(const m3::INode &ino, size_t no) {
     for(size_t i = 0; i < m3::INODE_DIR_COUNT; ++i) {
         if(ino.direct[i].length > no)
-            return ino.direct[i].start + no;
+            return ino.direct[i].start ? ino.direct[i].start + no : m3::HOLE_BNO;
         no -= ino.direct[i].length;
     }
 
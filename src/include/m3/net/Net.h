@@ -52,6 +52,24 @@ public:
         _addr = addr;
     }
 
+    /**
+     * The network service exchanges all addresses as 128-bit IPv6 addresses, split into the upper
+     * and lower 64 bits. IPv4 addresses are represented as IPv4-mapped IPv6 addresses
+     * (::ffff:a.b.c.d). Note that the C++ library only supports IPv4; other IPv6 addresses are
+     * turned into the unspecified address.
+     */
+    static IpAddr from_raw(uint64_t hi, uint64_t lo) noexcept {
+        if(hi != 0 || (lo >> 32) != 0xFFFF)
+            return IpAddr();
+        return IpAddr(static_cast<uint32_t>(lo));
+    }
+    uint64_t raw_hi() const noexcept {
+        return 0;
+    }
+    uint64_t raw_lo() const noexcept {
+        return (static_cast<uint64_t>(0xFFFF) << 32) | _addr;
+    }
+
 private:
     uint32_t _addr;
 };
@@ -48,14 +48,14 @@ public:
     } PACKED;
 
     struct DataMessage : public ControlMessage {
-        uint64_t addr;
+        uint64_t addr[2];
         uint64_t port;
         uint64_t size;
         uchar data[0];
     } PACKED;
 
     struct ConnectedMessage : public ControlMessage {
-        uint64_t addr;
+        uint64_t addr[2];
         uint64_t port;
     } PACKED;
 
@@ -60,6 +60,7 @@ class NetworkManager : public ClientSession {
         SET_DEST = GenericFile::SET_DEST,
         ENABLE_NOTIFY = GenericFile::ENABLE_NOTIFY,
         REQ_NOTIFY = GenericFile::REQ_NOTIFY,
+        FALLOCATE = GenericFile::FALLOCATE,
         BIND,
         LISTEN,
         CONNECT,
@@ -69,6 +70,16 @@ class NetworkManager : public ClientSession {
         GET_NAMESRV,
         GET_SGATE,
         OPEN_FILE,
+        ACCEPT,
+        SET_OPTION,
+        GET_OPTION,
+        JOIN_GROUP,
+        LEAVE_GROUP,
+        FILTER_STATS,
+        IFACE_STATS,
+        SOCKET_STATS,
+        CAPTURE_START,
+        CAPTURE_STOP,
     };
 
 public:
@@ -94,7 +105,7 @@ private:
     int32_t create(SocketType type, uint8_t protocol, const SocketArgs &args, capsel_t *caps);
     IpAddr get_nameserver();
     std::pair<IpAddr, port_t> bind(int32_t sd, port_t port);
-    IpAddr listen(int32_t sd, port_t port);
+    IpAddr listen(int32_t sd, port_t port, size_t backlog);
     Endpoint connect(int32_t sd, Endpoint remote_ep);
     void abort(int32_t sd, bool remove);
 
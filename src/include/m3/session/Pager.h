@@ -46,6 +46,11 @@ public:
         MAP_MEM,
         UNMAP,
         CLOSE,
+        PROTECT,
+        DONTNEED,
+        WILLNEED,
+        MAP_SHM,
+        UNLINK_SHM,
         COUNT,
     };
 
@@ -54,6 +59,7 @@ public:
         MAP_SHARED = 0x2000,
         MAP_UNINIT = 0x4000,
         MAP_NOLPAGE = 0x8000,
+        MAP_LARGE = 0x10000,
     };
 
     enum Prot {
@@ -80,7 +86,12 @@ public:
     void map_ds(goff_t *virt, size_t len, int prot, int flags, const ClientSession &sess,
                 size_t offset);
     void map_mem(goff_t *virt, MemGate &mem, size_t len, int prot);
+    void map_shm(const char *name, goff_t *virt, size_t len, int prot, bool create);
+    void unlink_shm(const char *name);
     void unmap(goff_t virt);
+    void protect(goff_t virt, size_t len, int prot);
+    void dont_need(goff_t virt, size_t len);
+    void will_need(goff_t virt, size_t len);
 
 private:
     capsel_t get_sgate();
@@ -28,7 +28,7 @@ namespace m3 {
 
 class Pipes : public ClientSession {
     enum {
-        OPEN_PIPE = GenericFile::REQ_NOTIFY + 1,
+        OPEN_PIPE = GenericFile::FALLOCATE + 1,
         OPEN_CHAN,
         SET_MEM,
         CLOSE_PIPE,
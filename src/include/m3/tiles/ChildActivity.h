@@ -46,11 +46,21 @@ public:
         _kmem = kmem;
         return *this;
     }
+    /**
+     * Sets the scheduling priority (lower values are preferred), which cannot be higher than the
+     * priority of the own activity. By default, the default priority is used or the own priority,
+     * if lower.
+     */
+    ActivityArgs &prio(uint prio) noexcept {
+        _prio = prio;
+        return *this;
+    }
 
 private:
     ResMng *_rmng;
     Reference<Pager> _pager;
     Reference<KMem> _kmem;
+    xfer_t _prio;
 };
 
 /**
@@ -223,6 +223,14 @@ public:
         throw Exception(Errors::NOT_SUP);
     }
 
+    /**
+     * Allocates the range <off>..<off>+<len>, extending the file if necessary. With
+     * M3FS_FALLOC_PUNCH_HOLE in <mode>, the range is deallocated instead and reads as zeros.
+     */
+    virtual void fallocate(UNUSED size_t off, UNUSED size_t len, UNUSED uint mode) {
+        throw Exception(Errors::NOT_SUP);
+    }
+
     /**
      * @return the absolute path for this file, including its mount point
      */
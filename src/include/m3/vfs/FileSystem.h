@@ -51,6 +51,7 @@ public:
         SET_DEST = GenericFile::SET_DEST,
         ENABLE_NOTIFY = GenericFile::ENABLE_NOTIFY,
         REQ_NOTIFY = GenericFile::REQ_NOTIFY,
+        FALLOCATE = GenericFile::FALLOCATE,
         STAT,
         MKDIR,
         RMDIR,
@@ -62,6 +63,20 @@ public:
         GET_MEM,
         DEL_EP,
         OPEN_PRIV,
+        SYMLINK,
+        READLINK,
+        CHMOD,
+        CHOWN,
+        UTIME,
+        DERIVE,
+        CHROOT,
+        GET_QUOTA,
+        GETXATTR,
+        SETXATTR,
+        LISTXATTR,
+        REMOVEXATTR,
+        CHECK,
+        REFLINK,
     };
 
     explicit FileSystem(size_t id) noexcept : RefCounted(), _id(id) {
@@ -52,6 +52,7 @@ public:
         SET_DEST,
         ENABLE_NOTIFY,
         REQ_NOTIFY,
+        FALLOCATE,
     };
 
     explicit GenericFile(int flags, capsel_t caps, size_t fs_id, size_t id = 0,
@@ -73,6 +74,7 @@ public:
     virtual Option<size_t> write(const void *buffer, size_t count) override;
 
     virtual void truncate(size_t length) override;
+    virtual void fallocate(size_t off, size_t len, uint mode) override;
 
     virtual std::string path() override;
 
@@ -25,10 +25,25 @@ static BUF: StaticRefCell<[u8; 2048]> = StaticRefCell::new([0u8; 2048]);
 static BR1: LazyStaticRefCell<Bridge> = LazyStaticRefCell::default();
 static BR2: LazyStaticRefCell<Bridge> = LazyStaticRefCell::default();
 
+// see linux/if_tun.h
+const TUNSETIFF: libc::c_ulong = 0x400454CA;
+const IFF_TAP: libc::c_short = 0x0002;
+const IFF_NO_PI: libc::c_short = 0x1000;
+
+#[repr(C)]
+struct IfReq {
+    name: [u8; 16],
+    flags: libc::c_short,
+    _pad: [u8; 22],
+}
+
 struct Bridge {
     src_fd: i32,
+    // whether `src_fd` is a TAP device instead of a socket
+    src_tap: bool,
     dst_fd: i32,
-    dst_sock: libc::sockaddr_un,
+    // the socket to send to or None if `dst_fd` is a TAP device
+    dst_sock: Option<libc::sockaddr_un>,
 }
 
 fn get_sock_addr(addr: &str) -> libc::sockaddr_un {
@@ -41,14 +56,72 @@ fn get_sock_addr(addr: &str) -> libc::sockaddr_un {
     sockaddr
 }
 
+fn open_tap(dev: &str) -> i32 {
+    let mut req = IfReq {
+        name: [0; 16],
+        flags: IFF_TAP | IFF_NO_PI,
+        _pad: [0; 22],
+    };
+    assert!(dev.len() < req.name.len(), "TAP device name too long");
+    req.name[0..dev.len()].copy_from_slice(dev.as_bytes());
+
+    let fd = unsafe {
+        libc::open(
+            "/dev/net/tun\0".as_ptr() as *const libc::c_char,
+            libc::O_RDWR | libc::O_NONBLOCK,
+        )
+    };
+    assert!(fd != -1, "Unable to open /dev/net/tun: errno={}", unsafe {
+        *libc::__errno_location()
+    });
+    unsafe {
+        assert!(
+            libc::ioctl(fd, TUNSETIFF, &mut req as *mut IfReq) == 0,
+            "Unable to attach to TAP device {}: errno={}",
+            dev,
+            *libc::__errno_location()
+        );
+    }
+    fd
+}
+
 impl Bridge {
     fn new(from: String, to: String) -> Self {
-        let src_fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
-        assert!(src_fd != -1);
+        Self {
+            src_fd: Self::bind(from),
+            src_tap: false,
+            dst_fd: Self::unbound(),
+            dst_sock: Some(get_sock_addr(&format!("\0m3_net_{}", to))),
+        }
+    }
+
+    fn new_to_tap(from: String, tap_fd: i32) -> Self {
+        Self {
+            src_fd: Self::bind(from),
+            src_tap: false,
+            dst_fd: tap_fd,
+            dst_sock: None,
+        }
+    }
+
+    fn new_from_tap(tap_fd: i32, to: String) -> Self {
+        Self {
+            src_fd: tap_fd,
+            src_tap: true,
+            dst_fd: Self::unbound(),
+            dst_sock: Some(get_sock_addr(&format!("\0m3_net_{}", to))),
+        }
+    }
+
+    fn unbound() -> i32 {
         let dst_fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
         assert!(dst_fd != -1);
+        dst_fd
+    }
 
-        let dst_sock = get_sock_addr(&format!("\0m3_net_{}", to));
+    fn bind(from: String) -> i32 {
+        let src_fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
+        assert!(src_fd != -1);
 
         let src_sock = get_sock_addr(&format!("\0m3_net_{}", from));
         unsafe {
@@ -62,42 +135,56 @@ impl Bridge {
                 (*libc::__errno_location()) as i32
             );
         }
-
-        Self {
-            src_fd,
-            dst_fd,
-            dst_sock,
-        }
+        src_fd
     }
 
     fn check(&self) {
         let mut buf = BUF.borrow_mut();
 
         let res = unsafe {
-            libc::recvfrom(
-                self.src_fd,
-                buf.as_mut_ptr() as *mut libc::c_void,
-                buf.len(),
-                libc::MSG_DONTWAIT,
-                ptr::null_mut(),
-                ptr::null_mut(),
-            )
+            if self.src_tap {
+                libc::read(
+                    self.src_fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            }
+            else {
+                libc::recvfrom(
+                    self.src_fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    libc::MSG_DONTWAIT,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                )
+            }
         };
         if res <= 0 {
             return;
         }
 
         unsafe {
-            assert!(
-                libc::sendto(
-                    self.dst_fd,
-                    buf.as_ptr() as *const libc::c_void,
-                    res as usize,
-                    0,
-                    &self.dst_sock as *const _ as *const libc::sockaddr,
-                    mem::size_of::<libc::sockaddr_un>() as u32,
-                ) != -1
-            )
+            match self.dst_sock {
+                Some(ref dst_sock) => assert!(
+                    libc::sendto(
+                        self.dst_fd,
+                        buf.as_ptr() as *const libc::c_void,
+                        res as usize,
+                        0,
+                        dst_sock as *const _ as *const libc::sockaddr,
+                        mem::size_of::<libc::sockaddr_un>() as u32,
+                    ) != -1
+                ),
+                // the TAP device might drop frames if it is not up (yet)
+                None => {
+                    libc::write(
+                        self.dst_fd,
+                        buf.as_ptr() as *const libc::c_void,
+                        res as usize,
+                    );
+                },
+            }
         };
     }
 }
@@ -106,14 +193,22 @@ pub fn create_bridge(names: &str) {
     let parts: Vec<&str> = names.split('-').collect();
     assert!(parts.len() == 2);
 
-    BR1.set(Bridge::new(
-        parts[0].to_string() + "_out",
-        parts[1].to_string() + "_in",
-    ));
-    BR2.set(Bridge::new(
-        parts[1].to_string() + "_out",
-        parts[0].to_string() + "_in",
-    ));
+    if let Some(dev) = parts[1].strip_prefix("tap:") {
+        // connect the net service to a TAP device on the host (e.g., to talk to a DHCP server)
+        let tap_fd = open_tap(dev);
+        BR1.set(Bridge::new_to_tap(parts[0].to_string() + "_out", tap_fd));
+        BR2.set(Bridge::new_from_tap(tap_fd, parts[0].to_string() + "_in"));
+    }
+    else {
+        BR1.set(Bridge::new(
+            parts[0].to_string() + "_out",
+            parts[1].to_string() + "_in",
+        ));
+        BR2.set(Bridge::new(
+            parts[1].to_string() + "_out",
+            parts[0].to_string() + "_in",
+        ));
+    }
 
     // wake up if there is anything to read
     TCU::add_wait_fd(BR1.borrow().src_fd);
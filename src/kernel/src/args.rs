@@ -81,7 +81,8 @@ fn usage() -> ! {
         "\nUsage: {} [-m <kmem>] [-f <fsimg>] [-b <bridge>] [-d]
           -m: the kernel memory size (> FIXED_KMEM)
           -f: the file system image to load (host only)
-          -b: the network bridge to create (host only)
+          -b: the network bridge to create (host only): <net1>-<net2> connects two net
+              services, whereas <net>-tap:<dev> connects a net service to a TAP device
           -d: enable disk device (host only)",
         env::args().next().unwrap()
     );
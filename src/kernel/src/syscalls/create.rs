@@ -18,10 +18,11 @@ use base::cfg;
 use base::col::ToString;
 use base::errors::{Code, VerboseError};
 use base::goff;
-use base::kif::{syscalls, CapRngDesc, CapSel, CapType, PageFlags, Perm};
+use base::kif::{self, syscalls, CapRngDesc, CapSel, CapType, PageFlags, Perm};
 use base::mem::{GlobAddr, MsgBuf};
 use base::rc::Rc;
 use base::tcu;
+use core::cmp;
 
 use crate::cap::{Capability, KObject, SelRange};
 use crate::cap::{
@@ -255,11 +256,12 @@ pub fn create_activity_async(
     let r: syscalls::CreateActivity<'_> = get_request(msg)?;
     sysc_log!(
         act,
-        "create_activity(dst={}, name={}, tile={}, kmem={})",
+        "create_activity(dst={}, name={}, tile={}, kmem={}, prio={:?})",
         r.dst,
         r.name,
         r.tile,
-        r.kmem
+        r.kmem,
+        r.prio
     );
 
     if !act
@@ -278,6 +280,22 @@ pub fn create_activity_async(
         sysc_err!(Code::InvArgs, "Invalid name");
     }
 
+    // by default, use the default priority, but never a higher priority than the parent's
+    let prio = r
+        .prio
+        .unwrap_or_else(|| cmp::max(kif::tilemux::DEF_PRIO, act.prio()));
+    if prio as usize >= kif::tilemux::PRIO_COUNT {
+        sysc_err!(Code::InvArgs, "Invalid priority {}", prio);
+    }
+    if prio < act.prio() {
+        sysc_err!(
+            Code::NoPerm,
+            "Priority {} is higher than the parent's priority {}",
+            prio,
+            act.prio()
+        );
+    }
+
     let tile = get_kobj!(act, r.tile, Tile);
     if !tile.has_quota(tcu::STD_EPS_COUNT as u32) {
         sysc_err!(
@@ -304,11 +322,17 @@ pub fn create_activity_async(
     drop(tilemux);
 
     // create activity
-    let nact =
-        match ActivityMng::create_activity_async(r.name, tile, eps, kmem, ActivityFlags::empty()) {
-            Ok(nact) => nact,
-            Err(e) => sysc_err!(e.code(), "Unable to create Activity"),
-        };
+    let nact = match ActivityMng::create_activity_async(
+        r.name,
+        prio,
+        tile,
+        eps,
+        kmem,
+        ActivityFlags::empty(),
+    ) {
+        Ok(nact) => nact,
+        Err(e) => sysc_err!(e.code(), "Unable to create Activity"),
+    };
 
     // give activity cap to the parent
     let cap = Capability::new(r.dst, KObject::Activity(Rc::downgrade(&nact)));
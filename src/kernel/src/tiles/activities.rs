@@ -19,7 +19,7 @@ use base::cell::{Cell, RefCell, StaticRefCell};
 use base::col::{String, ToString, Vec};
 use base::errors::{Code, Error};
 use base::goff;
-use base::kif::{self, CapRngDesc, CapSel, CapType, TileDesc};
+use base::kif::{self, tilemux::Priority, CapRngDesc, CapSel, CapType, TileDesc};
 use base::mem::MsgBuf;
 use base::rc::{Rc, SRc};
 use base::tcu::Label;
@@ -63,6 +63,7 @@ static EXIT_LISTENERS: StaticRefCell<Vec<ExitWait>> = StaticRefCell::new(Vec::ne
 pub struct Activity {
     id: ActId,
     name: String,
+    prio: Priority,
     flags: ActivityFlags,
     eps_start: EpId,
 
@@ -86,6 +87,7 @@ impl Activity {
     pub fn new(
         name: &str,
         id: ActId,
+        prio: Priority,
         tile: SRc<TileObject>,
         eps_start: EpId,
         kmem: SRc<KMemObject>,
@@ -94,6 +96,7 @@ impl Activity {
         let act = Rc::new(Activity {
             id,
             name: name.to_string(),
+            prio,
             flags,
             eps_start,
             kmem,
@@ -272,6 +275,10 @@ impl Activity {
         &self.name
     }
 
+    pub fn prio(&self) -> Priority {
+        self.prio
+    }
+
     pub fn obj_caps(&self) -> &RefCell<CapTable> {
         &self.obj_caps
     }
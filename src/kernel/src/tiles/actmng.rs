@@ -83,6 +83,7 @@ impl ActivityMng {
 
     pub fn create_activity_async(
         name: &str,
+        prio: kif::tilemux::Priority,
         tile: SRc<TileObject>,
         eps_start: tcu::EpId,
         kmem: SRc<KMemObject>,
@@ -91,13 +92,14 @@ impl ActivityMng {
         let id: tcu::ActId = Self::get_id()?;
         let tile_id = tile.tile();
 
-        let act = Activity::new(name, id, tile, eps_start, kmem, flags)?;
+        let act = Activity::new(name, id, prio, tile, eps_start, kmem, flags)?;
 
         klog!(
             ACTIVITIES,
-            "Created Activity {} [id={}, tile={}]",
+            "Created Activity {} [id={}, prio={}, tile={}]",
             name,
             id,
+            prio,
             tile_id
         );
 
@@ -121,6 +123,7 @@ impl ActivityMng {
             TileMux::activity_init_async(
                 tilemng::tilemux(act.tile_id()),
                 act.id(),
+                act.prio(),
                 act.tile().time_quota_id(),
                 act.tile().pt_quota_id(),
                 act.eps_start(),
@@ -173,6 +176,7 @@ impl ActivityMng {
         let kmem = KMemObject::new(args::get().kmem - cfg::FIXED_KMEM);
         let act = Self::create_activity_async(
             "root",
+            kif::tilemux::MAX_PRIO,
             tile,
             tcu::FIRST_USER_EP,
             kmem,
@@ -399,6 +399,7 @@ impl TileMux {
     pub fn activity_init_async(
         tilemux: RefMut<'_, Self>,
         act: ActId,
+        prio: kif::tilemux::Priority,
         time_quota: quota::Id,
         pt_quota: quota::Id,
         eps_start: EpId,
@@ -409,6 +410,7 @@ impl TileMux {
             kif::tilemux::Sidecalls::ACT_INIT,
             kif::tilemux::ActInit {
                 act_id: act as u64,
+                prio,
                 time_quota,
                 pt_quota,
                 eps_start,
@@ -655,6 +657,7 @@ impl TileMux {
     pub fn activity_init_async(
         _tilemux: RefMut<'_, Self>,
         _act: ActId,
+        _prio: base::kif::tilemux::Priority,
         _time_quota: quota::Id,
         _pt_quota: quota::Id,
         _eps_start: EpId,
@@ -81,6 +81,9 @@ static const char *errmsgs[] = {
     "UTF-8 error",
     "Bad file descriptor",
     "Invalid seek",
+    "Too many levels of symbolic links",
+    "Read-only file system",
+    "Resource in use",
 
     /* Socket */
     "Invalid state",
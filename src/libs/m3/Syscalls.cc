@@ -128,13 +128,14 @@ void Syscalls::create_map(capsel_t dst, capsel_t act, capsel_t mgate, capsel_t f
 }
 
 std::pair<epid_t, actid_t> Syscalls::create_activity(capsel_t dst, const std::string_view &name,
-                                                     capsel_t tile, capsel_t kmem) {
+                                                     capsel_t tile, capsel_t kmem, xfer_t prio) {
     MsgBuf req_buf;
     auto &req = req_buf.cast<KIF::Syscall::CreateActivity>();
     req.opcode = KIF::Syscall::CREATE_ACT;
     req.dst_sel = dst;
     req.tile_sel = tile;
     req.kmem_sel = kmem;
+    req.prio = prio;
     req.namelen = Math::min(name.length(), sizeof(req.name));
     memcpy(req.name, name.data(), req.namelen);
 
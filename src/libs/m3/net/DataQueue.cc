@@ -26,7 +26,7 @@ DataQueue::Item::Item(NetEventChannel::DataMessage const *msg,
 }
 
 IpAddr DataQueue::Item::src_addr() const noexcept {
-    return IpAddr(_msg->addr);
+    return IpAddr::from_raw(_msg->addr[0], _msg->addr[1]);
 }
 
 port_t DataQueue::Item::src_port() const noexcept {
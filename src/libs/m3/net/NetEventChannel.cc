@@ -36,7 +36,8 @@ Errors::Code NetEventChannel::build_data_message(void *buffer, size_t buf_size,
 
     auto msg = reinterpret_cast<DataMessage *>(buffer);
     msg->type = Data;
-    msg->addr = static_cast<uint64_t>(ep.addr.addr());
+    msg->addr[0] = ep.addr.raw_hi();
+    msg->addr[1] = ep.addr.raw_lo();
     msg->port = static_cast<uint64_t>(ep.port);
     msg->size = static_cast<uint64_t>(payload_size);
     memcpy(msg->data, payload, payload_size);
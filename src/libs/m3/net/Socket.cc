@@ -88,15 +88,17 @@ void Socket::process_message(const NetEventChannel::ControlMessage &message,
 void Socket::handle_data(NetEventChannel::DataMessage const &msg, NetEventChannel::Event &event) {
     log_net(NetLogEvent::RecvPacket, _sd, msg.size);
     LLOG(NET, "socket " << _sd << ": received data with " << msg.size << "b"
-                        << " from " << IpAddr(msg.addr) << ":" << msg.port);
+                        << " from " << IpAddr::from_raw(msg.addr[0], msg.addr[1]) << ":"
+                        << msg.port);
     _recv_queue.append(new DataQueue::Item(&msg, std::move(event)));
 }
 
 void Socket::handle_connected(NetEventChannel::ConnectedMessage const &msg) {
     log_net(NetLogEvent::RecvConnected, _sd, msg.port);
-    LLOG(NET, "socket " << _sd << ": connected to " << IpAddr(msg.addr) << ":" << msg.port);
+    IpAddr addr = IpAddr::from_raw(msg.addr[0], msg.addr[1]);
+    LLOG(NET, "socket " << _sd << ": connected to " << addr << ":" << msg.port);
     _state = Connected;
-    _remote_ep.addr = IpAddr(msg.addr);
+    _remote_ep.addr = addr;
     _remote_ep.port = msg.port;
 }
 
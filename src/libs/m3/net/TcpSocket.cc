@@ -42,7 +42,8 @@ void TcpSocket::listen(port_t port) {
     if(_state != State::Closed)
         throw Exception(Errors::INV_STATE);
 
-    IpAddr addr = _nm.listen(sd(), port);
+    // without a backlog, the net server uses this socket for the accepted connection
+    IpAddr addr = _nm.listen(sd(), port, 0);
     _local_ep.addr = addr;
     _local_ep.port = port;
     _state = State::Listening;
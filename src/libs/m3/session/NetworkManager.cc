@@ -60,43 +60,45 @@ int32_t NetworkManager::create(SocketType type, uint8_t protocol, const SocketAr
 IpAddr NetworkManager::ip_addr() {
     GateIStream reply = send_receive_vmsg(_metagate, GET_IP);
     reply.pull_result();
-    uint32_t addr;
-    reply >> addr;
-    return IpAddr(addr);
+    // the IPv4 address always comes first; ignore the IPv6 addresses
+    size_t count;
+    uint64_t hi, lo;
+    reply >> count >> hi >> lo;
+    return IpAddr::from_raw(hi, lo);
 }
 
 IpAddr NetworkManager::get_nameserver() {
     GateIStream reply = send_receive_vmsg(_metagate, GET_NAMESRV);
     reply.pull_result();
-    uint32_t addr;
-    reply >> addr;
-    return IpAddr(addr);
+    uint64_t hi, lo;
+    reply >> hi >> lo;
+    return IpAddr::from_raw(hi, lo);
 }
 
 std::pair<IpAddr, port_t> NetworkManager::bind(int32_t sd, port_t port) {
     GateIStream reply = send_receive_vmsg(_metagate, BIND, sd, port);
     reply.pull_result();
-    uint32_t addr;
-    reply >> addr >> port;
-    return std::make_pair(IpAddr(addr), port);
+    uint64_t hi, lo;
+    reply >> hi >> lo >> port;
+    return std::make_pair(IpAddr::from_raw(hi, lo), port);
 }
 
-IpAddr NetworkManager::listen(int32_t sd, port_t port) {
-    GateIStream reply = send_receive_vmsg(_metagate, LISTEN, sd, port);
+IpAddr NetworkManager::listen(int32_t sd, port_t port, size_t backlog) {
+    GateIStream reply = send_receive_vmsg(_metagate, LISTEN, sd, port, backlog);
     reply.pull_result();
-    uint32_t addr;
-    reply >> addr;
-    return IpAddr(addr);
+    uint64_t hi, lo;
+    reply >> hi >> lo;
+    return IpAddr::from_raw(hi, lo);
 }
 
 Endpoint NetworkManager::connect(int32_t sd, Endpoint remote_ep) {
-    GateIStream reply =
-        send_receive_vmsg(_metagate, CONNECT, sd, remote_ep.addr.addr(), remote_ep.port);
+    GateIStream reply = send_receive_vmsg(_metagate, CONNECT, sd, remote_ep.addr.raw_hi(),
+                                          remote_ep.addr.raw_lo(), remote_ep.port);
     reply.pull_result();
-    uint32_t addr;
+    uint64_t hi, lo;
     port_t port;
-    reply >> addr >> port;
-    return Endpoint(IpAddr(addr), port);
+    reply >> hi >> lo >> port;
+    return Endpoint(IpAddr::from_raw(hi, lo), port);
 }
 
 void NetworkManager::abort(int32_t sd, bool remove) {
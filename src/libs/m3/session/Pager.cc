@@ -98,11 +98,37 @@ void Pager::map_mem(goff_t *virt, MemGate &mem, size_t len, int prot) {
     is >> *virt;
 }
 
+void Pager::map_shm(const char *name, goff_t *virt, size_t len, int prot, bool create) {
+    GateIStream reply = send_receive_vmsg(_req_sgate, MAP_SHM, *virt, len, prot, create, name);
+    reply.pull_result();
+    reply >> *virt;
+}
+
+void Pager::unlink_shm(const char *name) {
+    GateIStream reply = send_receive_vmsg(_req_sgate, UNLINK_SHM, name);
+    reply.pull_result();
+}
+
 void Pager::unmap(goff_t virt) {
     GateIStream reply = send_receive_vmsg(_req_sgate, UNMAP, virt);
     reply.pull_result();
 }
 
+void Pager::protect(goff_t virt, size_t len, int prot) {
+    GateIStream reply = send_receive_vmsg(_req_sgate, PROTECT, virt, len, prot);
+    reply.pull_result();
+}
+
+void Pager::dont_need(goff_t virt, size_t len) {
+    GateIStream reply = send_receive_vmsg(_req_sgate, DONTNEED, virt, len);
+    reply.pull_result();
+}
+
+void Pager::will_need(goff_t virt, size_t len) {
+    GateIStream reply = send_receive_vmsg(_req_sgate, WILLNEED, virt, len);
+    reply.pull_result();
+}
+
 Reference<Pager> Pager::create_clone() {
     KIF::CapRngDesc caps;
     {
@@ -28,7 +28,11 @@ namespace m3 {
 
 const size_t ChildActivity::BUF_SIZE = 4096;
 
-ActivityArgs::ActivityArgs() noexcept : _rmng(nullptr), _pager(), _kmem() {
+ActivityArgs::ActivityArgs() noexcept
+    : _rmng(nullptr),
+      _pager(),
+      _kmem(),
+      _prio(static_cast<xfer_t>(-1)) {
 }
 
 ActivityArgs &ActivityArgs::pager(Reference<Pager> pager) noexcept {
@@ -57,7 +61,7 @@ ChildActivity::ChildActivity(const Reference<class Tile> &tile, const std::strin
     if(_pager) {
         // now create activity, which implicitly obtains the gate cap from us
         const auto [eps_start, id] =
-            Syscalls::create_activity(sel(), name, tile->sel(), _kmem->sel());
+            Syscalls::create_activity(sel(), name, tile->sel(), _kmem->sel(), args._prio);
         _eps_start = eps_start;
         _id = id;
         // delegate activity cap to pager
@@ -65,7 +69,7 @@ ChildActivity::ChildActivity(const Reference<class Tile> &tile, const std::strin
     }
     else {
         const auto [eps_start, id] =
-            Syscalls::create_activity(sel(), name, tile->sel(), _kmem->sel());
+            Syscalls::create_activity(sel(), name, tile->sel(), _kmem->sel(), args._prio);
         _eps_start = eps_start;
         _id = id;
     }
@@ -171,6 +171,17 @@ void GenericFile::truncate(size_t length) {
     _pos = _len = 0;
 }
 
+void GenericFile::fallocate(size_t off, size_t len, uint mode) {
+    if(_writing)
+        commit();
+
+    GateIStream reply = send_receive_vmsg(*_sg, FALLOCATE, _id, off, len, mode);
+    reply.pull_result();
+    // the extents might have changed, so start again at the current position
+    reply >> _goff >> _off;
+    _pos = _len = 0;
+}
+
 NOINLINE bool GenericFile::receive_notify(uint event, bool fetch) {
     // not received the event yet?
     if((_notify_received & event) == 0) {
@@ -84,6 +84,9 @@ pub enum Code {
     Utf8Error,
     BadFd,
     SeekPipe,
+    LinkLoop,
+    ReadOnly,
+    InUse,
     // networking
     InvState,
     WouldBlock,
@@ -19,7 +19,10 @@
 //! The system call interface
 
 use crate::goff;
-use crate::kif::{tilemux::QuotaId, CapRngDesc, CapSel, Perm};
+use crate::kif::{
+    tilemux::{Priority, QuotaId},
+    CapRngDesc, CapSel, Perm,
+};
 use crate::mem::GlobAddr;
 use crate::serialize::{Deserialize, Serialize};
 use crate::tcu::{ActId, EpId, Label};
@@ -134,6 +137,7 @@ pub struct CreateActivity<'s> {
     pub dst: CapSel,
     pub tile: CapSel,
     pub kmem: CapSel,
+    pub prio: Option<Priority>,
     pub name: &'s str,
 }
 
@@ -33,6 +33,16 @@ pub type QuotaId = u64;
 
 pub const DEF_QUOTA_ID: QuotaId = 1;
 
+/// The scheduling priority of an activity; activities with lower values are preferred
+pub type Priority = u8;
+
+/// The number of priorities
+pub const PRIO_COUNT: usize = 4;
+/// The highest priority
+pub const MAX_PRIO: Priority = 0;
+/// The default priority
+pub const DEF_PRIO: Priority = 2;
+
 int_enum! {
     /// The sidecalls from the kernel to TileMux
     pub struct Sidecalls : u64 {
@@ -63,6 +73,7 @@ int_enum! {
 #[repr(C)]
 pub struct ActInit {
     pub act_id: u64,
+    pub prio: Priority,
     pub time_quota: QuotaId,
     pub pt_quota: QuotaId,
     pub eps_start: EpId,
@@ -74,6 +74,21 @@ impl<'de> M3Deserializer<'de> {
         self.do_pop_str(|slice, pos, len| unsafe { str_slice_from(&slice[pos..], len - 1) })
     }
 
+    #[inline(always)]
+    fn pop_bytes(&mut self) -> Result<&'de [u8], Error> {
+        let len = self.pop_word()? as usize;
+
+        let npos = self.pos + (len + 7) / 8;
+        if npos > self.slice.len() {
+            return Err(Error::new(Code::InvArgs));
+        }
+
+        let words = &self.slice[self.pos..npos];
+        self.pos = npos;
+        // safety: the words cover at least `len` bytes and u8 has no alignment requirements
+        Ok(unsafe { core::slice::from_raw_parts(words.as_ptr() as *const u8, len) })
+    }
+
     fn do_pop_str<T, F>(&mut self, f: F) -> Result<T, Error>
     where
         F: Fn(&'de [u64], usize, usize) -> T,
@@ -215,19 +230,19 @@ impl<'de, 'a> Deserializer<'de> for &'a mut M3Deserializer<'de> {
     }
 
     #[inline(always)]
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_borrowed_bytes(self.pop_bytes()?)
     }
 
     #[inline(always)]
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_byte_buf(self.pop_bytes()?.to_vec())
     }
 
     #[inline(always)]
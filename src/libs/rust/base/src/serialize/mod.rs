@@ -29,6 +29,18 @@ pub use serde::{self, Deserialize, Deserializer, Serialize, Serializer};
 use crate::col::{String, Vec};
 use crate::libc;
 
+/// A byte slice that is serialized as a whole instead of element by element
+///
+/// The receiver obtains the bytes by popping a `&[u8]`.
+#[derive(Debug)]
+pub struct Bytes<'b>(pub &'b [u8]);
+
+impl<'b> Serialize for Bytes<'b> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
 #[macro_export]
 macro_rules! build_vmsg {
     ( $msg:expr, $( $args:expr ),* ) => ({
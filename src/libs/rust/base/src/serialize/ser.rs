@@ -216,8 +216,14 @@ impl<'a, S: Sink> Serializer for &'a mut M3Serializer<S> {
     }
 
     #[inline(always)]
-    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        unimplemented!()
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.push_word(v.len() as u64);
+        for chunk in v.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.push_word(u64::from_ne_bytes(word));
+        }
+        Ok(())
     }
 
     #[inline(always)]
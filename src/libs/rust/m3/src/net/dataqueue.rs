@@ -17,7 +17,7 @@
 use core::cmp;
 
 use crate::col::DList;
-use crate::net::{event, Endpoint, IpAddr, NetEvent, Port};
+use crate::net::{event, Endpoint, NetEvent};
 
 struct Item {
     event: NetEvent,
@@ -33,14 +33,6 @@ impl Item {
         self.msg().size as usize
     }
 
-    fn addr(&self) -> IpAddr {
-        IpAddr(self.msg().addr as u32)
-    }
-
-    fn port(&self) -> Port {
-        self.msg().port as Port
-    }
-
     fn msg(&self) -> &event::DataMessage {
         self.event.msg::<event::DataMessage>()
     }
@@ -71,7 +63,7 @@ impl DataQueue {
         if let Some(first) = self.items.front_mut() {
             let data = first.data();
             let amount = cmp::min(len, data.len());
-            let ep = Endpoint::new(first.addr(), first.port());
+            let ep = first.msg().endpoint();
             let (amount, res) = consume(&data[0..amount], ep);
             if amount >= data.len() {
                 self.items.pop_front();
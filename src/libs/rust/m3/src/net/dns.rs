@@ -13,66 +13,143 @@
  * General Public License version 2 for more details.
  */
 
-use core::mem;
 use core::str::FromStr;
 
-use base::col::ToString;
+use base::col::{BTreeMap, String, ToString, Vec};
 use base::errors::{Code, Error, VerboseError};
 use base::random::LCG;
 use base::rc::Rc;
-use base::time::TimeDuration;
-use base::vec;
+use base::time::{TimeDuration, TimeInstant};
+use base::{format, vec};
 
-use crate::net::{DGramSocket, DgramSocketArgs, Endpoint, IpAddr, Port, UdpSocket};
+use crate::io::Read;
+use crate::net::{
+    DGramSocket, DgramSocketArgs, Endpoint, IpAddr, Ipv4Addr, Ipv6Addr, Port, UdpSocket,
+};
 use crate::session::NetworkManager;
-use crate::vfs::{File, FileEvent, FileWaiter};
+use crate::vfs::{File, FileEvent, FileWaiter, OpenFlags, VFS};
 
-// based on http://tools.ietf.org/html/rfc1035
+// based on http://tools.ietf.org/html/rfc1035 and http://tools.ietf.org/html/rfc3596
 
 const DNS_RECURSION_DESIRED: u16 = 0x100;
+const DNS_RESPONSE: u16 = 0x8000;
+const DNS_RCODE_MASK: u16 = 0xF;
+const DNS_RCODE_NXDOMAIN: u16 = 3;
 const DNS_PORT: Port = 53;
 
+const HEADER_SIZE: usize = 12;
+// type, class, TTL, and data length
+const ANSWER_FIXED_SIZE: usize = 10;
+
 const TYPE_A: u16 = 1; // a host address
+const TYPE_CNAME: u16 = 5; // the canonical name for an alias
+const TYPE_AAAA: u16 = 28; // an IPv6 host address
 const CLASS_IN: u16 = 1; // the internet
 
-#[repr(C, packed)]
-struct DNSHeader {
-    id: u16,
-    flags: u16,
-    qd_count: u16,
-    an_count: u16,
-    ns_count: u16,
-    ar_count: u16,
+// the maximum number of aliases we follow before giving up
+const MAX_CNAME_CHAIN: usize = 8;
+// the maximum number of compression pointers we follow within a single name
+const MAX_NAME_PTRS: usize = 16;
+
+const DEF_RETRIES: u32 = 2;
+const DEF_HOSTS_FILE: &str = "/etc/hosts";
+
+/// The type of address record to query
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum RecordType {
+    /// IPv4 addresses
+    A,
+    /// IPv6 addresses
+    AAAA,
 }
 
-#[repr(C, packed)]
-struct DNSQuestionEnd {
-    ty: u16,
-    cls: u16,
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            RecordType::A => TYPE_A,
+            RecordType::AAAA => TYPE_AAAA,
+        }
+    }
+
+    fn matches(self, addr: &IpAddr) -> bool {
+        match self {
+            RecordType::A => addr.is_ipv4(),
+            RecordType::AAAA => addr.is_ipv6(),
+        }
+    }
 }
 
-#[repr(C, packed)]
-struct DNSAnswer {
-    name: u16,
-    ty: u16,
-    cls: u16,
+enum RecordData {
+    Addr(IpAddr),
+    CName(String),
+    Other,
+}
+
+struct Record {
+    name: String,
     ttl: u32,
-    length: u16,
-    // this is the data part of the answer, where we currently only support IPv4 addresses
-    ip_addr: u32,
+    data: RecordData,
 }
 
-#[derive(Default)]
+struct CacheEntry {
+    addrs: Vec<IpAddr>,
+    expires: TimeInstant,
+}
+
+/// A DNS resolver
+///
+/// Names are first looked up in the hosts file (`/etc/hosts` by default) and afterwards via DNS,
+/// asking the nameserver of the network service unless other nameservers have been added. Results
+/// from DNS are cached according to their time-to-live and aliases (CNAME records) are followed.
 pub struct DNS {
-    nameserver: IpAddr,
+    nameservers: Vec<IpAddr>,
+    retries: u32,
+    hosts_file: String,
+    hosts: Option<Vec<(String, IpAddr)>>,
+    cache: BTreeMap<(String, RecordType), CacheEntry>,
     random: LCG,
 }
 
+impl Default for DNS {
+    fn default() -> Self {
+        Self {
+            nameservers: Vec::new(),
+            retries: DEF_RETRIES,
+            hosts_file: DEF_HOSTS_FILE.to_string(),
+            hosts: None,
+            cache: BTreeMap::new(),
+            random: LCG::default(),
+        }
+    }
+}
+
 impl DNS {
+    /// Adds the given nameserver. Nameservers are asked in the order they have been added. If no
+    /// nameserver has been added, the nameserver of the network service is used.
+    pub fn add_nameserver(&mut self, addr: IpAddr) {
+        self.nameservers.push(addr);
+    }
+
+    /// Sets the number of retries per nameserver in case a query is not answered (2 by default)
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// Sets the path of the hosts file that is consulted before DNS
+    pub fn set_hosts_file(&mut self, path: &str) {
+        self.hosts_file = path.to_string();
+        self.hosts = None;
+    }
+
+    /// Removes all cached DNS responses
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
     /// Translates the given name into an IP address. If the name is already an IP address, it will
     /// simply be converted into an [`IpAddr`] object. Otherwise, the name will be solved via DNS.
     ///
-    /// The timeout specifies the maximum time to wait for the DNS response.
+    /// The timeout specifies the maximum time to wait for each DNS response.
     pub fn get_addr(
         &mut self,
         netmng: Rc<NetworkManager>,
@@ -87,150 +164,361 @@ impl DNS {
     }
 
     /// Resolves the given hostname to an IP address. Note that this method assumes that the name is
-    /// not an IP address, but an actual hostname and will therefore always use DNS to resolve the
-    /// name. Use [`get_addr`](Self::get_addr) if you don't know whether it's a hostname or an IP
-    /// address.
+    /// not an IP address, but an actual hostname and will therefore always use the hosts file or
+    /// DNS to resolve the name. Use [`get_addr`](Self::get_addr) if you don't know whether it's a
+    /// hostname or an IP address.
     ///
-    /// The timeout specifies the maximum time to wait for the DNS response.
+    /// IPv4 addresses are preferred; IPv6 addresses are only returned if the name has no IPv4
+    /// address. The timeout specifies the maximum time to wait for each DNS response.
     pub fn resolve(
         &mut self,
         netmng: Rc<NetworkManager>,
         name: &str,
         timeout: TimeDuration,
     ) -> Result<IpAddr, VerboseError> {
-        if self.nameserver == IpAddr::unspecified() {
-            self.nameserver = netmng.nameserver()?;
-        }
-
-        let name_len = name.len();
-        let total = mem::size_of::<DNSHeader>() + name_len + 2 + mem::size_of::<DNSQuestionEnd>();
-        // reserve some space for the answer as well
-        let mut buffer = vec![0u8; total.max(1024)];
-
-        let txid = self.random.get() as u16;
-
-        // safety: we are still within the allocated vector and DNSHeader has no alignment
-        // requirements
-        let mut header = unsafe { &mut *(buffer.as_mut_ptr() as *mut DNSHeader) };
-        // build DNS request message
-        header.id = txid.to_be();
-        header.flags = DNS_RECURSION_DESIRED.to_be();
-        header.qd_count = 1u16.to_be();
-        header.an_count = 0;
-        header.ns_count = 0;
-        header.ar_count = 0;
-
-        // add hostname
-        let hostname_bytes = &mut buffer[mem::size_of::<DNSHeader>()..];
-        Self::convert_hostname(hostname_bytes, name)?;
-
-        // safety: we are still within the allocated vector and DNSQuestionEnd has no alignment
-        // requirements
-        let mut qend = unsafe {
-            &mut *(buffer
-                .as_mut_ptr()
-                .add(mem::size_of::<DNSHeader>() + name_len + 2)
-                as *mut DNSQuestionEnd)
+        let addrs = match self.resolve_all(netmng.clone(), name, RecordType::A, timeout) {
+            Err(e) if e.code() == Code::NotFound => {
+                self.resolve_all(netmng, name, RecordType::AAAA, timeout)?
+            },
+            res => res?,
         };
-        qend.ty = TYPE_A.to_be();
-        qend.cls = CLASS_IN.to_be();
+        Ok(addrs[0])
+    }
+
+    /// Resolves the given hostname to all addresses of the given record type.
+    ///
+    /// The timeout specifies the maximum time to wait for each DNS response. The returned list is
+    /// never empty; if no address was found, an error with [`Code::NotFound`] is returned.
+    pub fn resolve_all(
+        &mut self,
+        netmng: Rc<NetworkManager>,
+        name: &str,
+        ty: RecordType,
+        timeout: TimeDuration,
+    ) -> Result<Vec<IpAddr>, VerboseError> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+
+        let addrs = self.lookup_hosts(&name, ty);
+        if !addrs.is_empty() {
+            return Ok(addrs);
+        }
+
+        let mut cur = name.clone();
+        let mut ttl = u32::MAX;
+        let mut aliases = 0;
+        loop {
+            if let Some(addrs) = self.lookup_cache(&cur, ty) {
+                return Ok(addrs);
+            }
+
+            let records = self.query(&netmng, &cur, ty, timeout)?;
+
+            // follow the aliases within the response as far as possible
+            let prev_aliases = aliases;
+            loop {
+                let mut addrs = Vec::new();
+                for r in records.iter().filter(|r| r.name == cur) {
+                    if let RecordData::Addr(addr) = r.data {
+                        if ty.matches(&addr) {
+                            addrs.push(addr);
+                            ttl = ttl.min(r.ttl);
+                        }
+                    }
+                }
+
+                if !addrs.is_empty() {
+                    let expires = TimeInstant::now() + TimeDuration::from_secs(ttl as u64);
+                    if cur != name {
+                        self.cache.insert((cur, ty), CacheEntry {
+                            addrs: addrs.clone(),
+                            expires,
+                        });
+                    }
+                    self.cache.insert((name, ty), CacheEntry {
+                        addrs: addrs.clone(),
+                        expires,
+                    });
+                    return Ok(addrs);
+                }
+
+                let alias = records.iter().find_map(|r| match &r.data {
+                    RecordData::CName(target) if r.name == cur => Some((target, r.ttl)),
+                    _ => None,
+                });
+                match alias {
+                    Some((target, alias_ttl)) => {
+                        aliases += 1;
+                        if aliases > MAX_CNAME_CHAIN {
+                            return Err(VerboseError::new(
+                                Code::NotFound,
+                                "Too many aliases in DNS response".to_string(),
+                            ));
+                        }
+                        ttl = ttl.min(alias_ttl);
+                        cur = target.clone();
+                    },
+                    None => break,
+                }
+            }
+
+            // if the response neither contained an address nor a new alias, we give up. otherwise
+            // we ask again for the address of the canonical name.
+            if aliases == prev_aliases {
+                return Err(VerboseError::new(
+                    Code::NotFound,
+                    format!("No {:?} record for {} in DNS response", ty, cur),
+                ));
+            }
+        }
+    }
+
+    fn lookup_cache(&mut self, name: &str, ty: RecordType) -> Option<Vec<IpAddr>> {
+        let key = (name.to_string(), ty);
+        match self.cache.get(&key) {
+            Some(e) if e.expires > TimeInstant::now() => Some(e.addrs.clone()),
+            Some(_) => {
+                self.cache.remove(&key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    fn lookup_hosts(&mut self, name: &str, ty: RecordType) -> Vec<IpAddr> {
+        if self.hosts.is_none() {
+            // a missing hosts file is not an error; we simply use DNS then
+            self.hosts = Some(Self::read_hosts(&self.hosts_file).unwrap_or_default());
+        }
+
+        self.hosts
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter(|(n, addr)| n == name && ty.matches(addr))
+            .map(|(_, addr)| *addr)
+            .collect()
+    }
+
+    fn read_hosts(path: &str) -> Result<Vec<(String, IpAddr)>, Error> {
+        let mut file = VFS::open(path, OpenFlags::R)?;
+        let content = file.read_to_string()?;
+        Ok(Self::parse_hosts(&content))
+    }
+
+    fn parse_hosts(content: &str) -> Vec<(String, IpAddr)> {
+        let mut hosts = Vec::new();
+        for line in content.lines() {
+            // strip comments
+            let line = line.split('#').next().unwrap();
+            let mut parts = line.split_whitespace();
+            let addr = match parts.next().map(IpAddr::from_str) {
+                Some(Ok(addr)) => addr,
+                _ => continue,
+            };
+            // the first name is the canonical name, all others are aliases
+            for name in parts {
+                hosts.push((name.to_ascii_lowercase(), addr));
+            }
+        }
+        hosts
+    }
 
-        // create socket
-        let mut sock = UdpSocket::new(DgramSocketArgs::new(netmng))?;
+    fn query(
+        &mut self,
+        netmng: &Rc<NetworkManager>,
+        name: &str,
+        ty: RecordType,
+        timeout: TimeDuration,
+    ) -> Result<Vec<Record>, VerboseError> {
+        if self.nameservers.is_empty() {
+            self.nameservers.push(netmng.nameserver()?);
+        }
 
-        // send over socket
-        sock.send_to(&buffer[0..total], Endpoint::new(self.nameserver, DNS_PORT))?;
+        let mut request = Vec::with_capacity(HEADER_SIZE + name.len() + 2 + 4);
+        // the transaction id is filled in for each attempt
+        request.extend_from_slice(&0u16.to_be_bytes());
+        request.extend_from_slice(&DNS_RECURSION_DESIRED.to_be_bytes());
+        // one question, no answers, no authority records, and no additional records
+        for count in &[1u16, 0, 0, 0] {
+            request.extend_from_slice(&count.to_be_bytes());
+        }
+        Self::convert_hostname(&mut request, name)?;
+        request.extend_from_slice(&ty.code().to_be_bytes());
+        request.extend_from_slice(&CLASS_IN.to_be_bytes());
 
-        // wait for the response
-        sock.set_blocking(false)?;
+        let mut sock = UdpSocket::new(DgramSocketArgs::new(netmng.clone()))?;
+        let mut buffer = vec![0u8; 1024];
         let mut waiter = FileWaiter::default();
         waiter.add(sock.fd(), FileEvent::INPUT);
-        waiter.wait_for(timeout);
 
-        // receive response
-        let len = sock.recv(&mut buffer)?;
-        if len < mem::size_of::<DNSHeader>() {
-            return Err(VerboseError::new(
-                Code::NotFound,
-                "Invalid DNS response".to_string(),
-            ));
+        for _ in 0..=self.retries {
+            for ns in self.nameservers.clone() {
+                let txid = self.random.get() as u16;
+                request[0..2].copy_from_slice(&txid.to_be_bytes());
+
+                sock.set_blocking(true)?;
+                sock.send_to(&request, Endpoint::new(ns, DNS_PORT))?;
+
+                // wait for the response
+                sock.set_blocking(false)?;
+                waiter.wait_for(timeout);
+
+                let len = match sock.recv(&mut buffer) {
+                    Ok(len) => len,
+                    // no response within the timeout; try again
+                    Err(e) if e.code() == Code::WouldBlock => continue,
+                    Err(e) => return Err(e.into()),
+                };
+
+                match Self::parse_response(&buffer[0..len], txid) {
+                    // the name does not exist; there is no point in asking again
+                    Err(e) if e.code() == Code::NotFound => {
+                        return Err(VerboseError::new(
+                            Code::NotFound,
+                            format!("Unknown host {}", name),
+                        ))
+                    },
+                    // ignore invalid responses and responses to previous requests
+                    Err(_) => continue,
+                    Ok(records) => return Ok(records),
+                }
+            }
+        }
+
+        Err(VerboseError::new(
+            Code::Timeout,
+            format!("Received no DNS response for {}", name),
+        ))
+    }
+
+    fn parse_response(msg: &[u8], txid: u16) -> Result<Vec<Record>, Error> {
+        if msg.len() < HEADER_SIZE || Self::read_u16(msg, 0)? != txid {
+            return Err(Error::new(Code::InvArgs));
+        }
+
+        let flags = Self::read_u16(msg, 2)?;
+        if (flags & DNS_RESPONSE) == 0 {
+            return Err(Error::new(Code::InvArgs));
         }
-        if u16::from_be(header.id) != txid {
-            return Err(VerboseError::new(
-                Code::NotFound,
-                "Received DNS response with wrong transaction id".to_string(),
-            ));
+        match flags & DNS_RCODE_MASK {
+            0 => {},
+            DNS_RCODE_NXDOMAIN => return Err(Error::new(Code::NotFound)),
+            _ => return Err(Error::new(Code::InvArgs)),
         }
 
-        let questions = u16::from_be(header.qd_count);
-        let answers = u16::from_be(header.an_count);
+        let questions = Self::read_u16(msg, 4)?;
+        let answers = Self::read_u16(msg, 6)?;
 
         // skip questions
-        let mut idx = mem::size_of::<DNSHeader>();
+        let mut idx = HEADER_SIZE;
         for _ in 0..questions {
-            let qlen = Self::question_length(&buffer[idx..]);
-            idx += qlen + mem::size_of::<DNSQuestionEnd>();
+            let (_, next) = Self::read_name(msg, idx)?;
+            idx = next + 4;
         }
 
         // parse answers
+        let mut records = Vec::new();
         for _ in 0..answers {
-            if idx + mem::size_of::<DNSAnswer>() > len {
-                return Err(VerboseError::new(
-                    Code::NotFound,
-                    "Invalid DNS response".to_string(),
-                ));
+            let (name, next) = Self::read_name(msg, idx)?;
+            if next + ANSWER_FIXED_SIZE > msg.len() {
+                return Err(Error::new(Code::InvArgs));
             }
 
-            // safety: we check above whether we are in bounds and DNSAnswer has no alignment req.
-            let ans = unsafe { &*(buffer.as_ptr().add(idx) as *const DNSAnswer) };
-            if u16::from_be(ans.ty) == TYPE_A
-                && u16::from_be(ans.length) == mem::size_of::<IpAddr>() as u16
-            {
-                return Ok(IpAddr::new_from_raw(u32::from_be(ans.ip_addr)));
-            }
+            let ty = Self::read_u16(msg, next)?;
+            let cls = Self::read_u16(msg, next + 2)?;
+            let ttl = Self::read_u32(msg, next + 4)?;
+            let len = Self::read_u16(msg, next + 8)? as usize;
+            let data_start = next + ANSWER_FIXED_SIZE;
+            let data = msg
+                .get(data_start..data_start + len)
+                .ok_or_else(|| Error::new(Code::InvArgs))?;
+
+            let data = match (cls, ty, len) {
+                (CLASS_IN, TYPE_A, 4) => {
+                    RecordData::Addr(IpAddr::V4(Ipv4Addr(Self::read_u32(data, 0)?)))
+                },
+                (CLASS_IN, TYPE_AAAA, 16) => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(data);
+                    RecordData::Addr(IpAddr::V6(Ipv6Addr::from_octets(octets)))
+                },
+                // the target can be compressed as well, so we need the whole message
+                (CLASS_IN, TYPE_CNAME, _) => RecordData::CName(Self::read_name(msg, data_start)?.0),
+                _ => RecordData::Other,
+            };
+
+            records.push(Record { name, ttl, data });
+            idx = data_start + len;
         }
 
-        Err(VerboseError::new(
-            Code::NotFound,
-            "No IPv4 address in DNS response".to_string(),
-        ))
+        Ok(records)
     }
 
-    fn convert_hostname(dst: &mut [u8], src: &str) -> Result<(), Error> {
-        let mut idx = src.len();
-        let mut part_length = 0i8;
+    /// Reads the (potentially compressed) name at `idx` and returns the name and the index behind
+    /// the name.
+    fn read_name(msg: &[u8], mut idx: usize) -> Result<(String, usize), Error> {
+        let mut name = String::new();
+        let mut end = None;
+        let mut ptrs = 0;
+        loop {
+            let len = *msg.get(idx).ok_or_else(|| Error::new(Code::InvArgs))? as usize;
+            if len == 0 {
+                break;
+            }
 
-        // we start with the \0 at the end
-        dst[idx + 1] = b'\0';
+            // compression pointer to a previous name in the message?
+            if (len & 0xC0) == 0xC0 {
+                ptrs += 1;
+                if ptrs > MAX_NAME_PTRS {
+                    return Err(Error::new(Code::InvArgs));
+                }
+                // the name ends in the message behind the first pointer
+                if end.is_none() {
+                    end = Some(idx + 2);
+                }
+                idx = (Self::read_u16(msg, idx)? & 0x3FFF) as usize;
+                continue;
+            }
 
-        for b in src.bytes().rev() {
-            if b == b'.' {
-                dst[idx] = part_length as u8;
-                part_length = 0;
+            let label = msg
+                .get(idx + 1..idx + 1 + len)
+                .ok_or_else(|| Error::new(Code::InvArgs))?;
+            if !name.is_empty() {
+                name.push('.');
             }
-            else {
-                dst[idx] = b;
-                part_length = part_length
-                    .checked_add(1)
-                    .ok_or_else(|| Error::new(Code::InvArgs))?;
+            for b in label {
+                name.push(b.to_ascii_lowercase() as char);
             }
-            idx -= 1;
+            idx += len + 1;
         }
 
-        dst[idx] = part_length as u8;
-        Ok(())
+        Ok((name, end.unwrap_or(idx + 1)))
     }
 
-    fn question_length(data: &[u8]) -> usize {
-        let mut total = 0;
-        let mut idx = 0;
-        while idx < data.len() && data[idx] != 0 {
-            let len = data[idx] as usize;
-            // skip this name-part
-            total += len + 1;
-            idx += len + 1;
+    fn read_u16(msg: &[u8], idx: usize) -> Result<u16, Error> {
+        match msg.get(idx..idx + 2) {
+            Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+            None => Err(Error::new(Code::InvArgs)),
+        }
+    }
+
+    fn read_u32(msg: &[u8], idx: usize) -> Result<u32, Error> {
+        match msg.get(idx..idx + 4) {
+            Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+            None => Err(Error::new(Code::InvArgs)),
         }
-        // skip zero ending, too
-        total + 1
+    }
+
+    fn convert_hostname(dst: &mut Vec<u8>, src: &str) -> Result<(), Error> {
+        for part in src.split('.') {
+            // labels are limited to 63 bytes, because the upper two bits denote a pointer
+            if part.is_empty() || part.len() > 63 {
+                return Err(Error::new(Code::InvArgs));
+            }
+            dst.push(part.len() as u8);
+            dst.extend_from_slice(part.as_bytes());
+        }
+        dst.push(b'\0');
+        Ok(())
     }
 }
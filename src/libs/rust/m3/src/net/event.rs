@@ -17,6 +17,7 @@
 use core::fmt;
 
 use crate::cap::{CapFlags, Selector};
+use crate::cell::Cell;
 use crate::com::{RGateArgs, RecvGate, SGateArgs, SendGate};
 use crate::errors::{Code, Error};
 use crate::int_enum;
@@ -27,6 +28,7 @@ use crate::net::{Endpoint, IpAddr, Port};
 use crate::rc::Rc;
 use crate::tcu::{Header, Message};
 use crate::tiles::Activity;
+use crate::time::TimeDuration;
 
 const MSG_SIZE: usize = 2048;
 const MSG_CREDITS: usize = 4;
@@ -35,9 +37,22 @@ const MSG_BUF_SIZE: usize = MSG_SIZE * MSG_CREDITS;
 const REPLY_SIZE: usize = 32;
 const REPLY_BUF_SIZE: usize = REPLY_SIZE * MSG_CREDITS;
 
+// the number of bytes in DataMessage before the data
+const DATA_HEADER_SIZE: usize = 5 * mem::size_of::<u64>();
+
 // the receive buffer slots are 2048 bytes, but we need to substract the TCU header and the other
 // fields in DataMessage.
-pub const MTU: usize = MSG_SIZE - (mem::size_of::<Header>() + 4 * mem::size_of::<u64>());
+pub const MTU: usize = MSG_SIZE - (mem::size_of::<Header>() + DATA_HEADER_SIZE);
+
+// IP addresses are transferred as the upper and lower half of their 128-bit representation
+fn addr_to_raw(addr: IpAddr) -> [u64; 2] {
+    let bits = addr.to_bits();
+    [(bits >> 64) as u64, bits as u64]
+}
+
+fn addr_from_raw(raw: [u64; 2]) -> IpAddr {
+    IpAddr::from_bits(((raw[0] as u128) << 64) | raw[1] as u128)
+}
 
 int_enum! {
     pub struct NetEventType : u64 {
@@ -51,36 +66,44 @@ int_enum! {
 #[repr(C, align(2048))]
 pub struct DataMessage {
     ty: u64,
-    pub addr: u64,
-    pub port: u64,
+    addr: [u64; 2],
+    port: u64,
     pub size: u64,
     pub data: [u8; MTU],
 }
 
+impl DataMessage {
+    /// Returns the source or destination endpoint of the data
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::new(addr_from_raw(self.addr), self.port as Port)
+    }
+}
+
 #[repr(C)]
 pub struct ConnectedMessage {
     ty: u64,
-    pub remote_addr: u64,
-    pub remote_port: u64,
+    remote_addr: [u64; 2],
+    remote_port: u64,
 }
 
 impl ConnectedMessage {
     pub fn new(endpoint: Endpoint) -> Self {
         Self {
             ty: NetEventType::CONNECTED.val,
-            remote_addr: endpoint.addr.0 as u64,
+            remote_addr: addr_to_raw(endpoint.addr),
             remote_port: endpoint.port as u64,
         }
     }
+
+    /// Returns the remote endpoint of the connection
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::new(addr_from_raw(self.remote_addr), self.remote_port as Port)
+    }
 }
 
 impl fmt::Debug for ConnectedMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "remote={}",
-            Endpoint::new(IpAddr(self.remote_addr as u32), self.remote_port as Port)
-        )
+        write!(f, "remote={}", self.endpoint())
     }
 }
 
@@ -133,6 +156,8 @@ pub struct NetEventChannel {
     rgate: RecvGate,
     rpl_gate: RecvGate,
     sgate: SendGate,
+    // the first error the other side reported in its replies, if not yet taken
+    error: Cell<Code>,
 }
 
 impl NetEventChannel {
@@ -174,6 +199,7 @@ impl NetEventChannel {
             rgate,
             rpl_gate,
             sgate,
+            error: Cell::new(Code::None),
         }))
     }
 
@@ -194,13 +220,14 @@ impl NetEventChannel {
             rgate,
             rpl_gate,
             sgate: SendGate::new_bind(caps + 1),
+            error: Cell::new(Code::None),
         }))
     }
 
-    pub fn wait_for_events(&self) {
+    pub fn wait_for_events(&self, timeout: Option<TimeDuration>) {
         // ignore errors
         Activity::own()
-            .wait_for(Some(self.rgate.ep().unwrap()), None, None)
+            .wait_for(Some(self.rgate.ep().unwrap()), None, timeout)
             .ok();
     }
 
@@ -244,7 +271,7 @@ impl NetEventChannel {
         #[allow(clippy::uninit_assumed_init)]
         let mut msg = DataMessage {
             ty: NetEventType::DATA.val,
-            addr: endpoint.addr.0 as u64,
+            addr: addr_to_raw(endpoint.addr),
             port: endpoint.port as u64,
             size: size as u64,
             // safety: data[0..size] will be initialized below; the rest will not be sent
@@ -268,7 +295,7 @@ impl NetEventChannel {
         if self.can_send()? {
             self.fetch_replies();
 
-            let msg_size = 4 * mem::size_of::<u64>() + msg.size as usize;
+            let msg_size = DATA_HEADER_SIZE + msg.size as usize;
             self.sgate
                 .send_aligned(msg as *const _ as *const u8, msg_size, &self.rpl_gate)
         }
@@ -279,9 +306,23 @@ impl NetEventChannel {
 
     pub fn fetch_replies(&self) {
         while let Some(reply) = self.rpl_gate.fetch() {
+            // replies are empty unless the event could not be handled (see NetEvent::set_error)
+            if let Some(code) = reply.as_words().first() {
+                if self.error.get() == Code::None {
+                    self.error.set(Code::from(*code as u32));
+                }
+            }
             self.rpl_gate.ack_msg(reply).unwrap();
         }
     }
+
+    /// Returns and resets the first error that has been reported in the replies to our events
+    pub fn take_error(&self) -> Result<(), Error> {
+        match self.error.replace(Code::None) {
+            Code::None => Ok(()),
+            code => Err(Error::new(code)),
+        }
+    }
 }
 
 impl Drop for NetEventChannel {
@@ -301,11 +342,23 @@ impl Drop for NetEventChannel {
 pub struct NetEvent {
     msg: &'static Message,
     channel: Rc<NetEventChannel>,
+    error: Code,
 }
 
 impl NetEvent {
     fn new(msg: &'static Message, channel: Rc<NetEventChannel>) -> Self {
-        Self { msg, channel }
+        Self {
+            msg,
+            channel,
+            error: Code::None,
+        }
+    }
+
+    /// Reports the given error to the sender of this event with the reply
+    ///
+    /// The sender notices the error asynchronously (see [`NetEventChannel::take_error`]).
+    pub fn set_error(&mut self, code: Code) {
+        self.error = code;
     }
 
     pub fn msg_type(&self) -> NetEventType {
@@ -323,8 +376,11 @@ impl NetEvent {
 
 impl Drop for NetEvent {
     fn drop(&mut self) {
-        // reply empty message; ignore failures here
-        let reply = MsgBuf::borrow_def();
+        // reply empty message or the error code; ignore failures here
+        let mut reply = MsgBuf::borrow_def();
+        if self.error != Code::None {
+            reply.set(self.error as u64);
+        }
         self.channel.rgate.reply(&reply, self.msg).ok();
     }
 }
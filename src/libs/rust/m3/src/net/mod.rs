@@ -16,7 +16,7 @@
  */
 
 use base::errors::{Code, Error};
-use base::serialize::{Deserialize, Serialize};
+use base::serialize::{Deserialize, Deserializer, Serialize, Serializer};
 
 mod dataqueue;
 pub use self::dataqueue::DataQueue;
@@ -33,12 +33,12 @@ pub use self::event::{
 mod socket;
 pub(crate) use self::socket::Socket;
 pub use self::socket::{
-    DGramSocket, DgramSocketArgs, RawSocket, RawSocketArgs, SocketArgs, State, StreamSocket,
-    StreamSocketArgs, TcpSocket, UdpSocket,
+    DGramSocket, DgramSocketArgs, RawSocket, RawSocketArgs, SocketArgs, SocketOpt, State,
+    StreamSocket, StreamSocketArgs, TcpSocket, UdpSocket,
 };
 
 mod dns;
-pub use dns::DNS;
+pub use dns::{RecordType, DNS};
 
 /// A socket descriptor
 pub type Sd = usize;
@@ -61,35 +61,45 @@ pub const INBAND_DATA_CREDITS: usize = 4;
 pub const INBAND_DATA_BUF_SIZE: usize = INBAND_DATA_SIZE * INBAND_DATA_CREDITS;
 pub const MAX_NETDATA_SIZE: usize = 1024;
 
-/// Represents an internet protocol (IP) address
+/// Represents an internet protocol version 4 (IPv4) address
 #[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
-pub struct IpAddr(pub u32);
+pub struct Ipv4Addr(pub u32);
 
-impl IpAddr {
-    /// Creates an IP address from given 4 bytes
-    pub fn new(v0: u8, v1: u8, v2: u8, v3: u8) -> Self {
-        IpAddr(u32::from_be_bytes([v0, v1, v2, v3]))
+impl Ipv4Addr {
+    /// Creates an IPv4 address from given 4 bytes
+    pub const fn new(v0: u8, v1: u8, v2: u8, v3: u8) -> Self {
+        Ipv4Addr(u32::from_be_bytes([v0, v1, v2, v3]))
     }
 
-    /// Creates an IP address from given raw value
-    pub fn new_from_raw(val: u32) -> Self {
-        IpAddr(val)
+    /// Creates an unspecified IPv4 address
+    pub const fn unspecified() -> Self {
+        Ipv4Addr(0)
     }
 
-    /// Creates an unspecified IP address
-    pub fn unspecified() -> Self {
-        IpAddr::new(0, 0, 0, 0)
+    /// Returns the 4 bytes of this address in network byte order
+    pub fn octets(&self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+
+    /// Returns true if this is the limited broadcast address (`255.255.255.255`)
+    pub fn is_broadcast(&self) -> bool {
+        self.0 == 0xFFFF_FFFF
+    }
+
+    /// Returns true if this is a multicast address (`224.0.0.0/4`)
+    pub fn is_multicast(&self) -> bool {
+        (self.0 >> 28) == 0xE
     }
 }
 
-impl core::fmt::Display for IpAddr {
+impl core::fmt::Display for Ipv4Addr {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let [b0, b1, b2, b3] = self.0.to_be_bytes();
+        let [b0, b1, b2, b3] = self.octets();
         write!(f, "{}.{}.{}.{}", b0, b1, b2, b3)
     }
 }
 
-impl core::str::FromStr for IpAddr {
+impl core::str::FromStr for Ipv4Addr {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -109,7 +119,290 @@ impl core::str::FromStr for IpAddr {
     }
 }
 
-/// Represents an TCP/UDP endpoint consisting of an IP address and a port
+/// Represents an internet protocol version 6 (IPv6) address, consisting of eight 16-bit segments
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub struct Ipv6Addr(pub [u16; 8]);
+
+impl Ipv6Addr {
+    /// Creates an IPv6 address from given 8 segments
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(
+        s0: u16,
+        s1: u16,
+        s2: u16,
+        s3: u16,
+        s4: u16,
+        s5: u16,
+        s6: u16,
+        s7: u16,
+    ) -> Self {
+        Ipv6Addr([s0, s1, s2, s3, s4, s5, s6, s7])
+    }
+
+    /// Creates an unspecified IPv6 address (`::`)
+    pub const fn unspecified() -> Self {
+        Ipv6Addr([0; 8])
+    }
+
+    /// Creates an IPv6 address from given 16 bytes in network byte order
+    pub fn from_octets(bytes: [u8; 16]) -> Self {
+        let mut segs = [0u16; 8];
+        for (i, seg) in segs.iter_mut().enumerate() {
+            *seg = u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]]);
+        }
+        Ipv6Addr(segs)
+    }
+
+    /// Returns the 16 bytes of this address in network byte order
+    pub fn octets(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        for (i, seg) in self.0.iter().enumerate() {
+            bytes[i * 2..i * 2 + 2].copy_from_slice(&seg.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Returns true if this is a link-local address (`fe80::/10`)
+    pub fn is_link_local(&self) -> bool {
+        (self.0[0] & 0xffc0) == 0xfe80
+    }
+
+    /// Returns true if this is a multicast address (`ff00::/8`)
+    pub fn is_multicast(&self) -> bool {
+        (self.0[0] & 0xff00) == 0xff00
+    }
+}
+
+impl core::fmt::Display for Ipv6Addr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let write_segs = |f: &mut core::fmt::Formatter<'_>, segs: &[u16]| {
+            for (i, seg) in segs.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ":")?;
+                }
+                write!(f, "{:x}", seg)?;
+            }
+            Ok(())
+        };
+
+        // abbreviate the longest run of at least two zero segments with "::" (RFC 5952)
+        let (mut start, mut len) = (0, 0);
+        let mut i = 0;
+        while i < self.0.len() {
+            let run = self.0[i..].iter().take_while(|s| **s == 0).count();
+            if run > len {
+                start = i;
+                len = run;
+            }
+            i += run.max(1);
+        }
+
+        if len < 2 {
+            write_segs(f, &self.0)
+        }
+        else {
+            write_segs(f, &self.0[..start])?;
+            write!(f, "::")?;
+            write_segs(f, &self.0[start + len..])
+        }
+    }
+}
+
+impl core::str::FromStr for Ipv6Addr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // parses the colon-separated segments in `s` into `segs` and returns their number
+        let parse_segs = |s: &str, segs: &mut [u16; 8]| {
+            if s.is_empty() {
+                return Ok(0);
+            }
+
+            let mut num = 0;
+            for part in s.split(':') {
+                if num == segs.len() || part.is_empty() || part.len() > 4 {
+                    return Err(Error::new(Code::InvArgs));
+                }
+                segs[num] = u16::from_str_radix(part, 16).map_err(|_| Error::new(Code::InvArgs))?;
+                num += 1;
+            }
+            Ok(num)
+        };
+
+        let mut segs = [0u16; 8];
+        match s.find("::") {
+            Some(pos) => {
+                let mut tail = [0u16; 8];
+                let head_num = parse_segs(&s[..pos], &mut segs)?;
+                let tail_num = parse_segs(&s[pos + 2..], &mut tail)?;
+                // "::" stands for at least one zero segment
+                if head_num + tail_num > 7 {
+                    return Err(Error::new(Code::InvArgs));
+                }
+                segs[8 - tail_num..].copy_from_slice(&tail[..tail_num]);
+            },
+            None => {
+                if parse_segs(s, &mut segs)? != 8 {
+                    return Err(Error::new(Code::InvArgs));
+                }
+            },
+        }
+        Ok(Ipv6Addr(segs))
+    }
+}
+
+/// Represents an internet protocol (IP) address, which is either an IPv4 or an IPv6 address
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum IpAddr {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+}
+
+impl IpAddr {
+    /// Creates an IPv4 address from given 4 bytes
+    pub const fn new(v0: u8, v1: u8, v2: u8, v3: u8) -> Self {
+        IpAddr::V4(Ipv4Addr::new(v0, v1, v2, v3))
+    }
+
+    /// Creates an IPv4 address from given raw value
+    pub const fn new_from_raw(val: u32) -> Self {
+        IpAddr::V4(Ipv4Addr(val))
+    }
+
+    /// Creates an IPv6 address from given 8 segments
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new_v6(
+        s0: u16,
+        s1: u16,
+        s2: u16,
+        s3: u16,
+        s4: u16,
+        s5: u16,
+        s6: u16,
+        s7: u16,
+    ) -> Self {
+        IpAddr::V6(Ipv6Addr::new(s0, s1, s2, s3, s4, s5, s6, s7))
+    }
+
+    /// Creates an unspecified IPv4 address
+    pub const fn unspecified() -> Self {
+        IpAddr::V4(Ipv4Addr::unspecified())
+    }
+
+    /// Returns true if this is the unspecified IPv4 or IPv6 address
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            IpAddr::V4(a) => *a == Ipv4Addr::unspecified(),
+            IpAddr::V6(a) => *a == Ipv6Addr::unspecified(),
+        }
+    }
+
+    /// Returns true if this is the IPv4 limited broadcast address
+    pub fn is_broadcast(&self) -> bool {
+        matches!(self, IpAddr::V4(a) if a.is_broadcast())
+    }
+
+    /// Returns true if this is an IPv4 or IPv6 multicast address
+    pub fn is_multicast(&self) -> bool {
+        match self {
+            IpAddr::V4(a) => a.is_multicast(),
+            IpAddr::V6(a) => a.is_multicast(),
+        }
+    }
+
+    /// Returns true if this is an IPv4 address
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, IpAddr::V4(_))
+    }
+
+    /// Returns true if this is an IPv6 address
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, IpAddr::V6(_))
+    }
+
+    /// Returns the 128-bit representation of this address that is used to exchange addresses
+    /// with the network service. IPv4 addresses are represented as IPv4-mapped IPv6 addresses
+    /// (`::ffff:a.b.c.d`).
+    pub fn to_bits(&self) -> u128 {
+        match self {
+            IpAddr::V4(a) => (0xffff << 32) | a.0 as u128,
+            IpAddr::V6(a) => u128::from_be_bytes(a.octets()),
+        }
+    }
+
+    /// Creates an address from the given 128-bit representation (see [`to_bits`](IpAddr::to_bits))
+    pub fn from_bits(bits: u128) -> Self {
+        if (bits >> 32) == 0xffff {
+            IpAddr::V4(Ipv4Addr(bits as u32))
+        }
+        else {
+            IpAddr::V6(Ipv6Addr::from_octets(bits.to_be_bytes()))
+        }
+    }
+}
+
+impl Default for IpAddr {
+    fn default() -> Self {
+        Self::unspecified()
+    }
+}
+
+impl From<Ipv4Addr> for IpAddr {
+    fn from(addr: Ipv4Addr) -> Self {
+        IpAddr::V4(addr)
+    }
+}
+
+impl From<Ipv6Addr> for IpAddr {
+    fn from(addr: Ipv6Addr) -> Self {
+        IpAddr::V6(addr)
+    }
+}
+
+impl core::fmt::Display for IpAddr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IpAddr::V4(a) => write!(f, "{}", a),
+            IpAddr::V6(a) => write!(f, "{}", a),
+        }
+    }
+}
+
+impl core::str::FromStr for IpAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(':') {
+            Ipv6Addr::from_str(s).map(IpAddr::V6)
+        }
+        else {
+            Ipv4Addr::from_str(s).map(IpAddr::V4)
+        }
+    }
+}
+
+// addresses are transferred as two u64 values (upper and lower half of `to_bits`)
+impl Serialize for IpAddr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let bits = self.to_bits();
+        ((bits >> 64) as u64, bits as u64).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpAddr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (hi, lo) = <(u64, u64)>::deserialize(deserializer)?;
+        Ok(Self::from_bits(((hi as u128) << 64) | lo as u128))
+    }
+}
+
+/// Represents an TCP/UDP endpoint consisting of an IPv4 or IPv6 address and a port
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct Endpoint {
     pub addr: IpAddr,
@@ -133,7 +426,10 @@ impl Endpoint {
 
 impl core::fmt::Display for Endpoint {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{}:{}", self.addr, self.port)
+        match self.addr {
+            IpAddr::V4(a) => write!(f, "{}:{}", a, self.port),
+            IpAddr::V6(a) => write!(f, "[{}]:{}", a, self.port),
+        }
     }
 }
 
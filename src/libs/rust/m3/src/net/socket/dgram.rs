@@ -14,7 +14,7 @@
  */
 
 use crate::errors::Error;
-use crate::net::{socket::State, Endpoint, Port};
+use crate::net::{socket::State, Endpoint, IpAddr, Port, SocketOpt};
 
 /// Trait for all data-gram sockets, like UDP.
 pub trait DGramSocket {
@@ -78,5 +78,29 @@ pub trait DGramSocket {
     ///
     /// If the socket has not been bound so far, bind(0) will be called to bind it to an unused
     /// ephemeral port.
+    ///
+    /// Sending to the broadcast address fails with `Code::NoPerm` unless
+    /// [`SocketOpt::BROADCAST`] has been enabled. Packets are sent asynchronously. Thus, if the
+    /// packet filter of the network service denies a packet, the next send fails with
+    /// `Code::NoPerm`.
     fn send_to(&mut self, data: &[u8], endpoint: Endpoint) -> Result<(), Error>;
+
+    /// Joins the given IPv4 multicast group
+    ///
+    /// Afterwards, packets sent to the group are received via this socket, provided that they are
+    /// sent to the port the socket is bound to. The membership ends with
+    /// [`leave_group`](DGramSocket::leave_group) or when the socket is closed.
+    fn join_group(&mut self, addr: IpAddr) -> Result<(), Error>;
+
+    /// Leaves the given multicast group
+    fn leave_group(&mut self, addr: IpAddr) -> Result<(), Error>;
+
+    /// Sets the given option to given value
+    ///
+    /// See [`SocketOpt`] for the available options and the representation of their values. Note
+    /// that most options only apply to TCP sockets.
+    fn set_option(&mut self, opt: SocketOpt, value: u64) -> Result<(), Error>;
+
+    /// Returns the current value of the given option
+    fn get_option(&self, opt: SocketOpt) -> Result<u64, Error>;
 }
@@ -16,13 +16,16 @@
  */
 
 use crate::errors::{Code, Error};
+use crate::int_enum;
 use crate::llog;
 use crate::net::dataqueue::DataQueue;
 use crate::net::{
-    event, log_net, Endpoint, IpAddr, NetEvent, NetEventChannel, NetEventType, NetLogEvent, Port,
-    Sd, SocketType, MTU,
+    event, log_net, Endpoint, NetEvent, NetEventChannel, NetEventType, NetLogEvent, Sd, SocketType,
+    MTU,
 };
 use crate::rc::Rc;
+use crate::session::NetworkManager;
+use crate::time::{TimeDuration, TimeInstant};
 use crate::vfs::FileEvent;
 
 mod dgram;
@@ -57,6 +60,35 @@ impl Default for SocketArgs {
     }
 }
 
+int_enum! {
+    /// The options that can be changed via `set_option` and retrieved via `get_option`
+    ///
+    /// All values are represented as `u64`. Durations are specified in nanoseconds, whereas a
+    /// duration of zero disables the corresponding feature.
+    pub struct SocketOpt : u64 {
+        /// Disables Nagle's algorithm if non-zero (TCP only; default: 1)
+        const NO_DELAY      = 0;
+        /// The interval for keep-alive packets on idle connections (TCP only; default: 0)
+        const KEEP_ALIVE    = 1;
+        /// The time after which the connection is aborted if the remote side does not respond
+        /// (TCP only; default: 0)
+        const TIMEOUT       = 2;
+        /// The maximum time blocking operations wait for events (default: 0)
+        const RECV_TIMEOUT  = 3;
+        /// The time a close waits for a graceful shutdown before the connection is aborted
+        /// (TCP only; default: 0)
+        const LINGER        = 4;
+        /// The size of the receive buffer in bytes; can only be changed in state
+        /// [`Closed`](State::Closed) (TCP only)
+        const RECV_BUF      = 5;
+        /// The size of the send buffer in bytes; can only be changed in state
+        /// [`Closed`](State::Closed) (TCP only)
+        const SEND_BUF      = 6;
+        /// Allows to send to broadcast addresses if non-zero (UDP only; default: 0)
+        const BROADCAST     = 7;
+    }
+}
+
 /// The states sockets can be in
 #[derive(Eq, Debug, PartialEq, Clone, Copy)]
 pub enum State {
@@ -89,6 +121,11 @@ pub(crate) struct Socket {
 
     channel: Rc<NetEventChannel>,
     recv_queue: DataQueue,
+
+    // the number of established connections a listening socket can accept
+    acceptable: usize,
+    // the maximum time to wait for events in blocking operations
+    recv_timeout: Option<TimeDuration>,
 }
 
 impl Socket {
@@ -105,6 +142,9 @@ impl Socket {
 
             channel,
             recv_queue: DataQueue::default(),
+
+            acceptable: 0,
+            recv_timeout: None,
         }
     }
 
@@ -124,10 +164,39 @@ impl Socket {
         self.blocking = blocking;
     }
 
+    pub fn set_option(
+        &mut self,
+        nm: &NetworkManager,
+        opt: SocketOpt,
+        value: u64,
+    ) -> Result<(), Error> {
+        match opt {
+            // the receive timeout is handled locally
+            SocketOpt::RECV_TIMEOUT => {
+                self.recv_timeout = match value {
+                    0 => None,
+                    n => Some(TimeDuration::from_nanos(n)),
+                };
+                Ok(())
+            },
+            _ => nm.set_option(self.sd, opt, value),
+        }
+    }
+
+    pub fn get_option(&self, nm: &NetworkManager, opt: SocketOpt) -> Result<u64, Error> {
+        match opt {
+            SocketOpt::RECV_TIMEOUT => {
+                Ok(self.recv_timeout.map(|t| t.as_nanos() as u64).unwrap_or(0))
+            },
+            _ => nm.get_option(self.sd, opt),
+        }
+    }
+
     pub fn disconnect(&mut self) {
         self.local_ep = None;
         self.remote_ep = None;
         self.state = State::Closed;
+        self.acceptable = 0;
     }
 
     pub fn has_data(&self) -> bool {
@@ -162,6 +231,10 @@ impl Socket {
             return Err(Error::new(Code::OutOfBounds));
         }
 
+        // report the errors of previously sent packets (e.g., denied by the packet filter)
+        self.fetch_replies();
+        self.channel.take_error()?;
+
         let msg = self
             .channel
             .build_data_message(endpoint, data.len(), |buf| {
@@ -195,7 +268,8 @@ impl Socket {
     pub fn has_events(&mut self, events: FileEvent) -> bool {
         self.fetch_replies();
 
-        (events.contains(FileEvent::INPUT) && (self.process_events() || self.has_data()))
+        (events.contains(FileEvent::INPUT)
+            && (self.process_events() || self.has_data() || self.acceptable > 0))
             || (events.contains(FileEvent::OUTPUT) && self.can_send())
     }
 
@@ -238,13 +312,25 @@ impl Socket {
     }
 
     fn wait_for_events(&mut self, ignore_remote_closes: bool) -> Result<(), Error> {
+        let deadline = self.recv_timeout.map(|t| TimeInstant::now() + t);
         while !self.process_events() {
             if !ignore_remote_closes && self.state == State::RemoteClosed {
                 return Err(Error::new(Code::SocketClosed));
             }
 
+            let timeout = match deadline {
+                Some(end) => {
+                    let now = TimeInstant::now();
+                    if now >= end {
+                        return Err(Error::new(Code::Timeout));
+                    }
+                    Some(end - now)
+                },
+                None => None,
+            };
+
             log_net(NetLogEvent::StartedWaiting, self.sd, 0);
-            self.channel.wait_for_events();
+            self.channel.wait_for_events(timeout);
             log_net(NetLogEvent::StoppedWaiting, self.sd, 0);
         }
         Ok(())
@@ -276,7 +362,7 @@ impl Socket {
                         "socket {}: received data with {}b from {}",
                         self.sd,
                         _msg.size,
-                        Endpoint::new(IpAddr(_msg.addr as u32), _msg.port as Port)
+                        _msg.endpoint()
                     );
                     self.recv_queue.append(event, 0);
                 }
@@ -284,15 +370,18 @@ impl Socket {
 
             NetEventType::CONNECTED => {
                 let msg = event.msg::<event::ConnectedMessage>();
-                let ep = Endpoint::new(IpAddr(msg.remote_addr as u32), msg.remote_port as Port);
-                log_net(
-                    NetLogEvent::RecvConnected,
-                    self.sd,
-                    msg.remote_port as usize,
-                );
-                llog!(NET, "socket {}: connected to {}", self.sd, ep);
-                self.state = State::Connected;
-                self.remote_ep = Some(ep);
+                let ep = msg.endpoint();
+                log_net(NetLogEvent::RecvConnected, self.sd, ep.port as usize);
+                // for listening sockets, the connection is established on a new socket
+                if self.state == State::Listening {
+                    llog!(NET, "socket {}: connection from {} is ready", self.sd, ep);
+                    self.acceptable += 1;
+                }
+                else {
+                    llog!(NET, "socket {}: connected to {}", self.sd, ep);
+                    self.state = State::Connected;
+                    self.remote_ep = Some(ep);
+                }
             },
 
             NetEventType::CLOSED => {
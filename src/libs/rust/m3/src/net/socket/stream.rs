@@ -14,7 +14,8 @@
  */
 
 use crate::errors::Error;
-use crate::net::{socket::State, Endpoint, Port};
+use crate::net::{socket::State, Endpoint, Port, SocketOpt, TcpSocket};
+use crate::vfs::FileRef;
 
 /// Trait for all stream sockets, like TCP.
 pub trait StreamSocket {
@@ -31,20 +32,23 @@ pub trait StreamSocket {
     /// Returns the remote endpoint
     ///
     /// The remote endpoint is only `Some`, if the socket is currently connected (achieved either
-    /// via [`connect`](StreamSocket::connect) or [`accept`](StreamSocket::accept)). Otherwise, the
-    /// remote endpoint is `None`.
+    /// via [`connect`](StreamSocket::connect) or by being returned from
+    /// [`accept`](StreamSocket::accept)). Otherwise, the remote endpoint is `None`.
     fn remote_endpoint(&self) -> Option<Endpoint>;
 
     /// Puts this socket into listen mode on the given port.
     ///
     /// In listen mode, remote connections can be accepted. See [`accept`](StreamSocket::accept).
     /// Note that in contrast to conventional TCP/IP stacks, [`listen`](StreamSocket::listen) is a
-    /// combination of the traditional `bind` and `listen`.
+    /// combination of the traditional `bind` and `listen`. The number of connections that can be
+    /// pending at the same time is configured via
+    /// [`StreamSocketArgs::backlog`](crate::net::StreamSocketArgs::backlog).
     ///
     /// Listing on this port requires that the used session has permission for this port. This is
     /// controlled with the "tcp=..." argument in the session argument of M³'s config files.
     ///
-    /// Returns an error if the socket is not in state [`Closed`](State::Closed).
+    /// Returns an error if the socket is not in state [`Closed`](State::Closed). A socket can only
+    /// be put into listen mode once.
     fn listen(&mut self, port: Port) -> Result<(), Error>;
 
     /// Connects this socket to the given remote endpoint.
@@ -52,11 +56,15 @@ pub trait StreamSocket {
 
     /// Accepts a remote connection on this socket
     ///
-    /// The socket has to be put into listen mode first. Note that in contrast to conventional
-    /// TCP/IP stacks, accept does not yield a new socket, but uses this socket for the accepted
-    /// connection. Thus, to support multiple connections to the same port, put multiple sockets in
-    /// listen mode on this port and call accept on each of them.
-    fn accept(&mut self) -> Result<Endpoint, Error>;
+    /// The socket has to be put into listen mode first. The accepted connection is returned as a
+    /// new socket together with the remote endpoint, whereas this socket stays in listen mode.
+    ///
+    /// In non-blocking mode, [`WouldBlock`](crate::errors::Code::WouldBlock) is returned if no
+    /// connection is ready yet. In this case, the socket reports [`FileEvent::INPUT`] as soon as a
+    /// connection can be accepted.
+    ///
+    /// [`FileEvent::INPUT`]: crate::vfs::FileEvent::INPUT
+    fn accept(&mut self) -> Result<(FileRef<TcpSocket>, Endpoint), Error>;
 
     /// Returns whether data can currently be received from the socket
     ///
@@ -101,4 +109,12 @@ pub trait StreamSocket {
     /// guaranteed that all data has already been transmitted. Use [`close`](StreamSocket::close) if
     /// that is important.
     fn abort(&mut self) -> Result<(), Error>;
+
+    /// Sets the given option to given value
+    ///
+    /// See [`SocketOpt`] for the available options and the representation of their values.
+    fn set_option(&mut self, opt: SocketOpt, value: u64) -> Result<(), Error>;
+
+    /// Returns the current value of the given option
+    fn get_option(&self, opt: SocketOpt) -> Result<u64, Error>;
 }
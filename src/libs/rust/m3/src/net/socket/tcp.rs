@@ -24,7 +24,7 @@ use crate::io;
 use crate::net::{
     event, log_net,
     socket::{Socket, SocketArgs, State, StreamSocket},
-    Endpoint, Port, NetLogEvent, SocketType,
+    Endpoint, NetLogEvent, Port, SocketOpt, SocketType,
 };
 use crate::rc::Rc;
 use crate::session::{HashInput, HashOutput, NetworkManager};
@@ -35,6 +35,7 @@ use crate::vfs::{self, Fd, File, FileEvent, FileRef, INV_FD};
 pub struct StreamSocketArgs {
     nm: Rc<NetworkManager>,
     args: SocketArgs,
+    backlog: usize,
 }
 
 impl StreamSocketArgs {
@@ -43,6 +44,7 @@ impl StreamSocketArgs {
         Self {
             nm,
             args: SocketArgs::default(),
+            backlog: 1,
         }
     }
 
@@ -57,6 +59,15 @@ impl StreamSocketArgs {
         self.args.sbuf_size = size;
         self
     }
+
+    /// Sets the number of connections that can be established concurrently, but have not been
+    /// accepted yet, if the socket is put into listen mode (default: 1)
+    ///
+    /// Each of these connections requires the same buffer space as the socket itself.
+    pub fn backlog(mut self, num: usize) -> Self {
+        self.backlog = num;
+        self
+    }
 }
 
 /// Represents a stream socket using the transmission control protocol (TCP)
@@ -64,6 +75,7 @@ pub struct TcpSocket {
     fd: Fd,
     socket: Socket,
     nm: Rc<NetworkManager>,
+    backlog: usize,
 }
 
 impl TcpSocket {
@@ -78,6 +90,7 @@ impl TcpSocket {
             socket: args.nm.create(SocketType::Stream, None, &args.args)?,
             nm: args.nm,
             fd: INV_FD,
+            backlog: args.backlog,
         });
         let fd = Activity::own().files().add(sock)?;
         Ok(FileRef::new_owned(fd))
@@ -102,7 +115,11 @@ impl StreamSocket for TcpSocket {
             return Err(Error::new(Code::InvState));
         }
 
-        let addr = self.nm.listen(self.socket.sd(), port)?;
+        if self.backlog == 0 {
+            return Err(Error::new(Code::InvArgs));
+        }
+
+        let addr = self.nm.listen(self.socket.sd(), port, self.backlog)?;
         self.socket.local_ep = Some(Endpoint::new(addr, port));
         self.socket.state = State::Listening;
         Ok(())
@@ -144,31 +161,41 @@ impl StreamSocket for TcpSocket {
         }
     }
 
-    fn accept(&mut self) -> Result<Endpoint, Error> {
-        if self.state() == State::Connected {
-            return Ok(self.remote_endpoint().unwrap());
-        }
-        if self.state() == State::Connecting {
-            return Err(Error::new(Code::AlreadyInProgress));
-        }
+    fn accept(&mut self) -> Result<(FileRef<TcpSocket>, Endpoint), Error> {
         if self.state() != State::Listening {
             return Err(Error::new(Code::InvState));
         }
 
-        self.socket.state = State::Connecting;
-        while self.state() == State::Connecting {
+        while self.socket.acceptable == 0 {
             if !self.is_blocking() {
-                return Err(Error::new(Code::InProgress));
+                if !self.socket.process_events() {
+                    return Err(Error::new(Code::WouldBlock));
+                }
+            }
+            else {
+                self.socket.wait_for_events(false)?;
             }
-            self.socket.wait_for_events(false)?;
-        }
 
-        if self.state() != State::Connected {
-            Err(Error::new(Code::ConnectionFailed))
-        }
-        else {
-            Ok(self.remote_endpoint().unwrap())
+            if self.state() != State::Listening {
+                return Err(Error::new(Code::ConnectionFailed));
+            }
         }
+
+        let (mut socket, local_ep, ep) = self.nm.accept(self.socket.sd())?;
+        self.socket.acceptable -= 1;
+
+        socket.state = State::Connected;
+        socket.local_ep = Some(local_ep);
+        socket.remote_ep = Some(ep);
+
+        let sock = Box::new(TcpSocket {
+            socket,
+            nm: self.nm.clone(),
+            fd: INV_FD,
+            backlog: self.backlog,
+        });
+        let fd = Activity::own().files().add(sock)?;
+        Ok((FileRef::new_owned(fd), ep))
     }
 
     fn has_data(&self) -> bool {
@@ -264,6 +291,14 @@ impl StreamSocket for TcpSocket {
         self.socket.disconnect();
         Ok(())
     }
+
+    fn set_option(&mut self, opt: SocketOpt, value: u64) -> Result<(), Error> {
+        self.socket.set_option(&self.nm, opt, value)
+    }
+
+    fn get_option(&self, opt: SocketOpt) -> Result<u64, Error> {
+        self.socket.get_option(&self.nm, opt)
+    }
 }
 
 impl File for TcpSocket {
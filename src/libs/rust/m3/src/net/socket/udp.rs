@@ -24,7 +24,7 @@ use crate::io;
 use crate::net::{
     log_net,
     socket::{DGramSocket, Socket, SocketArgs, State},
-    Endpoint, Port, NetLogEvent, SocketType,
+    Endpoint, IpAddr, NetLogEvent, Port, SocketOpt, SocketType,
 };
 use crate::rc::Rc;
 use crate::session::{HashInput, HashOutput, NetworkManager};
@@ -66,6 +66,7 @@ pub struct UdpSocket {
     fd: Fd,
     socket: Socket,
     nm: Rc<NetworkManager>,
+    broadcast: bool,
 }
 
 impl UdpSocket {
@@ -80,6 +81,7 @@ impl UdpSocket {
             socket: args.nm.create(SocketType::Dgram, None, &args.args)?,
             nm: args.nm,
             fd: INV_FD,
+            broadcast: false,
         });
         let fd = Activity::own().files().add(sock)?;
         Ok(FileRef::new_owned(fd))
@@ -148,9 +150,35 @@ impl DGramSocket for UdpSocket {
             self.bind(0)?;
         }
 
+        // the network service only knows the subnet broadcast addresses and silently drops such
+        // packets without permission, so that we check at least the limited broadcast here
+        if endpoint.addr.is_broadcast() && !self.broadcast {
+            return Err(Error::new(Code::NoPerm));
+        }
+
         log_net(NetLogEvent::SubmitData, self.socket.sd(), data.len());
         self.socket.send(data, endpoint)
     }
+
+    fn join_group(&mut self, addr: IpAddr) -> Result<(), Error> {
+        self.nm.join_group(self.socket.sd(), addr)
+    }
+
+    fn leave_group(&mut self, addr: IpAddr) -> Result<(), Error> {
+        self.nm.leave_group(self.socket.sd(), addr)
+    }
+
+    fn set_option(&mut self, opt: SocketOpt, value: u64) -> Result<(), Error> {
+        self.socket.set_option(&self.nm, opt, value)?;
+        if opt == SocketOpt::BROADCAST {
+            self.broadcast = value != 0;
+        }
+        Ok(())
+    }
+
+    fn get_option(&self, opt: SocketOpt) -> Result<u64, Error> {
+        self.socket.get_option(&self.nm, opt)
+    }
 }
 
 impl File for UdpSocket {
@@ -56,6 +56,14 @@ impl ClientSession {
         }
     }
 
+    /// Binds a new `ClientSession` to given selector and revokes the capability on drop.
+    pub fn new_owned_bind(sel: Selector) -> Self {
+        ClientSession {
+            cap: Capability::new(sel, CapFlags::empty()),
+            close: false,
+        }
+    }
+
     /// Returns the capability selector.
     pub fn sel(&self) -> Selector {
         self.cap.sel()
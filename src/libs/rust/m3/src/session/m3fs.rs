@@ -22,17 +22,19 @@ use core::fmt;
 use crate::boxed::Box;
 use crate::cap::Selector;
 use crate::cell::RefCell;
-use crate::col::Vec;
+use crate::col::{String, ToString, Vec};
 use crate::com::{recv_result, RecvGate, SendGate, EP};
 use crate::errors::Error;
 use crate::goff;
 use crate::kif;
+use crate::quota::Quota;
 use crate::rc::Rc;
-use crate::serialize::{M3Deserializer, M3Serializer, VecSink};
+use crate::serialize::{Bytes, M3Deserializer, M3Serializer, VecSink};
 use crate::session::ClientSession;
 use crate::tiles::{Activity, ChildActivity};
 use crate::vfs::{
-    FSHandle, FSOperation, File, FileInfo, FileMode, FileSystem, GenericFile, OpenFlags,
+    FSHandle, FSOperation, File, FileInfo, FileMode, FileSystem, GenericFile, GroupId, OpenFlags,
+    UserId,
 };
 
 struct CachedEP {
@@ -41,6 +43,85 @@ struct CachedEP {
     file: Option<usize>,
 }
 
+/// The arguments for sessions that are derived from an existing session via [`M3FS::derive`].
+///
+/// By default, the derived session has the same credentials, access rights, and root directory as
+/// the session it is derived from. The derived session has its own quota, which is unlimited by
+/// default, but all allocations are also charged to the quota of the original session.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeriveArgs {
+    creds: Option<(UserId, GroupId)>,
+    readonly: bool,
+    root: String,
+    blocks: usize,
+    inodes: usize,
+}
+
+impl Default for DeriveArgs {
+    fn default() -> Self {
+        Self {
+            creds: None,
+            readonly: false,
+            root: String::new(),
+            blocks: usize::MAX,
+            inodes: usize::MAX,
+        }
+    }
+}
+
+impl DeriveArgs {
+    /// Uses the user id `uid` and group id `gid` for the session, which requires user id 0 for
+    /// the original session.
+    pub fn creds(mut self, uid: UserId, gid: GroupId) -> Self {
+        self.creds = Some((uid, gid));
+        self
+    }
+
+    /// Restricts the session to read-only access, if `readonly` is true.
+    pub fn readonly(mut self, readonly: bool) -> Self {
+        self.readonly = readonly;
+        self
+    }
+
+    /// Confines the session to the directory `root`, given relative to the root of the original
+    /// session.
+    pub fn root(mut self, root: &str) -> Self {
+        self.root = root.to_string();
+        self
+    }
+
+    /// Limits the session to allocate at most `blocks` blocks and `inodes` inodes.
+    pub fn quota(mut self, blocks: usize, inodes: usize) -> Self {
+        self.blocks = blocks;
+        self.inodes = inodes;
+        self
+    }
+}
+
+/// The result of a consistency check of the file system via [`M3FS::check`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CheckReport {
+    /// The number of blocks that are marked as used, but are not used by any inode
+    pub leaked_blocks: usize,
+    /// The number of inodes that are marked as used, but are not reachable
+    pub leaked_inodes: usize,
+    /// The number of blocks that are used by an inode, but are marked as free
+    pub unmarked_blocks: usize,
+    /// The number of inodes that are reachable, but are marked as free
+    pub unmarked_inodes: usize,
+    /// The number of blocks that are used more than once
+    pub crosslinked_blocks: usize,
+    /// The number of invalid block or inode numbers in inodes, extents, and directories
+    pub invalid_refs: usize,
+}
+
+impl CheckReport {
+    /// Returns true if no inconsistencies have been found
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// Represents a session at m3fs.
 pub struct M3FS {
     id: usize,
@@ -85,6 +166,80 @@ impl M3FS {
         )
     }
 
+    /// Creates a new session at the same m3fs server with the properties given by `args`.
+    ///
+    /// The derived session can only have less rights than this session: it cannot be writable if
+    /// this session is read-only, its root directory is always within the root directory of this
+    /// session, and only sessions with user id 0 can derive sessions with other credentials than
+    /// their own.
+    pub fn derive(&self, id: usize, args: &DeriveArgs) -> Result<FSHandle, Error> {
+        let sels = Activity::own().alloc_sels(2);
+
+        let crd = kif::CapRngDesc::new(kif::CapType::OBJECT, sels + 0, 1);
+        self.sess.obtain_for(
+            Activity::own().sel(),
+            crd,
+            |os| {
+                let (uid, gid) = args.creds.unwrap_or((0, 0));
+                os.push(FSOperation::DERIVE);
+                os.push(args.creds.is_some());
+                os.push(uid);
+                os.push(gid);
+                os.push(args.readonly);
+                os.push(args.blocks);
+                os.push(args.inodes);
+            },
+            |_| Ok(()),
+        )?;
+        let sess = ClientSession::new_owned_bind(sels + 0);
+
+        let crd = kif::CapRngDesc::new(kif::CapType::OBJECT, sels + 1, 1);
+        sess.obtain_for(
+            Activity::own().sel(),
+            crd,
+            |os| os.push(FSOperation::GET_SGATE),
+            |_| Ok(()),
+        )?;
+        let sgate = SendGate::new_bind(sels + 1);
+
+        // the root is set afterwards, because it might not fit into the exchange arguments
+        if !args.root.is_empty() {
+            send_recv_res!(&sgate, RecvGate::def(), FSOperation::CHROOT, &args.root)?;
+        }
+
+        Ok(Self::create(id, sess, sgate))
+    }
+
+    /// Returns the block quota and the inode quota of this session.
+    ///
+    /// The total budget is `usize::MAX` for unlimited quotas. The remaining budget is also limited by
+    /// the quotas of the sessions that this session has been derived from.
+    pub fn quota(&self) -> Result<(Quota<usize>, Quota<usize>), Error> {
+        let mut reply = send_recv_res!(&self.sgate, RecvGate::def(), FSOperation::GET_QUOTA)?;
+        let id = reply.pop()?;
+        let blocks = Quota::new(id, reply.pop()?, reply.pop()?);
+        let inodes = Quota::new(id, reply.pop()?, reply.pop()?);
+        Ok((blocks, inodes))
+    }
+
+    /// Checks the consistency of the file system while it is in use.
+    ///
+    /// The check compares the block and inode bitmaps against the blocks and inodes that are
+    /// actually reachable from the root directory. If `repair` is true, the bitmaps are fixed
+    /// afterwards. Cross-linked blocks and invalid references are only reported. The check
+    /// requires root credentials and, for repairs, a writable session.
+    pub fn check(&self, repair: bool) -> Result<CheckReport, Error> {
+        let mut reply = send_recv_res!(&self.sgate, RecvGate::def(), FSOperation::CHECK, repair)?;
+        Ok(CheckReport {
+            leaked_blocks: reply.pop()?,
+            leaked_inodes: reply.pop()?,
+            unmarked_blocks: reply.pop()?,
+            unmarked_inodes: reply.pop()?,
+            crosslinked_blocks: reply.pop()?,
+            invalid_refs: reply.pop()?,
+        })
+    }
+
     /// Returns a reference to the underlying [`ClientSession`]
     pub fn sess(&self) -> &ClientSession {
         &self.sess
@@ -218,6 +373,121 @@ impl FileSystem for M3FS {
         .map(|_| ())
     }
 
+    fn reflink(&self, old_path: &str, new_path: &str) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::REFLINK,
+            old_path,
+            new_path
+        )
+        .map(|_| ())
+    }
+
+    fn symlink(&self, target: &str, path: &str) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::SYMLINK,
+            target,
+            path
+        )
+        .map(|_| ())
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, Error> {
+        let mut reply = send_recv_res!(&self.sgate, RecvGate::def(), FSOperation::READLINK, path)?;
+        reply.pop::<&str>().map(|s| s.to_string())
+    }
+
+    fn chmod(&self, path: &str, mode: FileMode) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::CHMOD,
+            path,
+            mode.bits()
+        )
+        .map(|_| ())
+    }
+
+    fn chown(&self, path: &str, uid: UserId, gid: GroupId) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::CHOWN,
+            path,
+            uid,
+            gid
+        )
+        .map(|_| ())
+    }
+
+    fn utime(&self, path: &str, atime: u32, mtime: u32) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::UTIME,
+            path,
+            atime,
+            mtime
+        )
+        .map(|_| ())
+    }
+
+    fn getxattr(&self, path: &str, name: &str) -> Result<Vec<u8>, Error> {
+        let mut reply = send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::GETXATTR,
+            path,
+            name
+        )?;
+        reply.pop::<&[u8]>().map(|v| v.to_vec())
+    }
+
+    fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::SETXATTR,
+            path,
+            name,
+            Bytes(value)
+        )
+        .map(|_| ())
+    }
+
+    fn listxattr(&self, path: &str) -> Result<Vec<String>, Error> {
+        // the names might not fit into a single reply, so that we fetch them one by one
+        let mut names = Vec::new();
+        loop {
+            let mut reply = send_recv_res!(
+                &self.sgate,
+                RecvGate::def(),
+                FSOperation::LISTXATTR,
+                path,
+                names.len()
+            )?;
+            let total: usize = reply.pop()?;
+            if names.len() >= total {
+                break Ok(names);
+            }
+            names.push(reply.pop::<&str>()?.to_string());
+        }
+    }
+
+    fn removexattr(&self, path: &str, name: &str) -> Result<(), Error> {
+        send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            FSOperation::REMOVEXATTR,
+            path,
+            name
+        )
+        .map(|_| ())
+    }
+
     fn fs_type(&self) -> u8 {
         b'M'
     }
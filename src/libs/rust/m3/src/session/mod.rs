@@ -31,8 +31,8 @@ mod srvsession;
 pub use self::clisession::ClientSession;
 pub use self::disk::{BlockNo, BlockRange, Disk, DiskOperation};
 pub use self::hash::{HashInput, HashOp, HashOutput, HashSession};
-pub use self::m3fs::M3FS;
-pub use self::netmng::{NetworkManager, NetworkOp};
+pub use self::m3fs::{CheckReport, DeriveArgs, M3FS};
+pub use self::netmng::{FilterRuleStats, IfaceStats, NetworkManager, NetworkOp, SocketStats};
 pub use self::pager::{MapFlags, Pager, PagerOp};
 pub use self::pipe::{Pipe, PipeOperation, Pipes};
 pub use self::resmng::{ResMng, ResMngActInfo, ResMngActInfoResult, ResMngOperation};
@@ -16,9 +16,12 @@
 
 use base::int_enum;
 
+use crate::col::{String, ToString, Vec};
 use crate::com::{RecvGate, SendGate};
 use crate::errors::Error;
-use crate::net::{Endpoint, IpAddr, NetEventChannel, Port, Sd, Socket, SocketArgs, SocketType};
+use crate::net::{
+    Endpoint, IpAddr, NetEventChannel, Port, Sd, Socket, SocketArgs, SocketOpt, SocketType,
+};
 use crate::rc::Rc;
 use crate::session::ClientSession;
 use crate::vfs::GenFileOp;
@@ -35,18 +38,85 @@ int_enum! {
         const COMMIT        = GenFileOp::COMMIT.val;
         const TRUNCATE      = GenFileOp::TRUNCATE.val;
         // TODO what about GenericFile::CLOSE?
-        const BIND          = 15;
-        const LISTEN        = 16;
-        const CONNECT       = 17;
-        const ABORT         = 18;
-        const CREATE        = 19;
-        const GET_IP        = 20;
-        const GET_NAMESRV   = 21;
-        const GET_SGATE     = 22;
-        const OPEN_FILE     = 23;
+        const BIND          = 16;
+        const LISTEN        = 17;
+        const CONNECT       = 18;
+        const ABORT         = 19;
+        const CREATE        = 20;
+        const GET_IP        = 21;
+        const GET_NAMESRV   = 22;
+        const GET_SGATE     = 23;
+        const OPEN_FILE     = 24;
+        const ACCEPT        = 25;
+        const SET_OPTION    = 26;
+        const GET_OPTION    = 27;
+        const JOIN_GROUP    = 28;
+        const LEAVE_GROUP   = 29;
+        const FILTER_STATS  = 30;
+        const IFACE_STATS   = 31;
+        const SOCKET_STATS  = 32;
+        const CAPTURE_START = 33;
+        const CAPTURE_STOP  = 34;
     }
 }
 
+/// A packet filter rule of the network service along with its counters
+#[derive(Clone, Debug)]
+pub struct FilterRuleStats {
+    /// The session the rule belongs to or `None` for the global rules
+    pub session: Option<u64>,
+    /// The rule in the syntax used to configure it
+    pub rule: String,
+    /// The number of packets that matched the rule
+    pub packets: u64,
+    /// The number of bytes of all packets that matched the rule
+    pub bytes: u64,
+}
+
+/// The frame counters of the network interface
+#[derive(Clone, Debug, Default)]
+pub struct IfaceStats {
+    /// The number of received frames
+    pub rx_packets: u64,
+    /// The number of bytes of all received frames
+    pub rx_bytes: u64,
+    /// The number of received frames that could not be processed
+    pub rx_errors: u64,
+    /// The number of transmitted frames
+    pub tx_packets: u64,
+    /// The number of bytes of all transmitted frames
+    pub tx_bytes: u64,
+    /// The number of frames that could not be transmitted
+    pub tx_errors: u64,
+}
+
+/// The state and counters of a socket at the network service
+#[derive(Clone, Debug)]
+pub struct SocketStats {
+    /// The session the socket belongs to
+    pub session: u64,
+    /// The socket descriptor within the session
+    pub sd: Sd,
+    /// The socket type
+    pub ty: SocketType,
+    /// The socket state (for TCP sockets, the state of the TCP state machine)
+    pub state: String,
+    /// The local endpoint
+    pub local: Endpoint,
+    /// The remote endpoint, if known
+    pub remote: Endpoint,
+    /// The number of packets passed to the client
+    pub rx_packets: u64,
+    /// The number of bytes passed to the client
+    pub rx_bytes: u64,
+    /// The number of packets sent on behalf of the client
+    pub tx_packets: u64,
+    /// The number of bytes sent on behalf of the client
+    pub tx_bytes: u64,
+    /// The number of packets dropped by the packet filter
+    pub drops: u64,
+}
+
 /// Represents a session at the network service, allowing to create and use sockets
 ///
 /// To exchange events and data with the server, the [`NetEventChannel`] is used, which allows to
@@ -74,11 +144,131 @@ impl NetworkManager {
         }))
     }
 
-    /// Returns the local IP address
+    /// Returns the local IPv4 address
     pub fn ip_addr(&self) -> Result<IpAddr, Error> {
         let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::GET_IP)?;
-        let addr = IpAddr(reply.pop::<u32>()?);
-        Ok(addr)
+        // the IPv4 address always comes first
+        let _count = reply.pop::<usize>()?;
+        reply.pop::<IpAddr>()
+    }
+
+    /// Returns all local IP addresses, starting with the IPv4 address, followed by the IPv6
+    /// addresses (if IPv6 is enabled)
+    pub fn ip_addrs(&self) -> Result<Vec<IpAddr>, Error> {
+        let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::GET_IP)?;
+        let count = reply.pop::<usize>()?;
+        let mut addrs = Vec::with_capacity(count);
+        for _ in 0..count {
+            addrs.push(reply.pop::<IpAddr>()?);
+        }
+        Ok(addrs)
+    }
+
+    /// Returns the nameserver of the network service
+    ///
+    /// Fails with `Code::NotSup` if the network service has no nameserver.
+    pub fn nameserver(&self) -> Result<IpAddr, Error> {
+        let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::GET_NAMESRV)?;
+        reply.pop::<IpAddr>()
+    }
+
+    /// Returns all packet filter rules with their counters, starting with the global rules
+    ///
+    /// This requires the `admin=yes` session argument; otherwise, it fails with `Code::NoPerm`.
+    pub fn filter_stats(&self) -> Result<Vec<FilterRuleStats>, Error> {
+        let mut rules = Vec::new();
+        loop {
+            let mut reply = send_recv_res!(
+                &self.metagate,
+                RecvGate::def(),
+                NetworkOp::FILTER_STATS,
+                rules.len()
+            )?;
+            let total = reply.pop::<usize>()?;
+            if rules.len() >= total {
+                break Ok(rules);
+            }
+
+            let global = reply.pop::<bool>()?;
+            let session = reply.pop::<u64>()?;
+            rules.push(FilterRuleStats {
+                session: if global { None } else { Some(session) },
+                rule: reply.pop::<&str>()?.to_string(),
+                packets: reply.pop::<u64>()?,
+                bytes: reply.pop::<u64>()?,
+            });
+        }
+    }
+
+    /// Starts to capture the sent and received frames into the file at `path` in the pcap format
+    ///
+    /// The file is opened by the network service within its own mounts. The frames can be
+    /// restricted by `rules`, which are packet filter rules separated by semicolons: a frame is
+    /// captured if the first matching rule allows it or if no rule matches. An active capture is
+    /// replaced. This requires the `admin=yes` session argument.
+    pub fn start_capture(&self, path: &str, rules: &str) -> Result<(), Error> {
+        send_recv_res!(
+            &self.metagate,
+            RecvGate::def(),
+            NetworkOp::CAPTURE_START,
+            path,
+            rules
+        )
+        .map(|_| ())
+    }
+
+    /// Stops the current capture and returns the number of captured frames
+    ///
+    /// This requires the `admin=yes` session argument.
+    pub fn stop_capture(&self) -> Result<u64, Error> {
+        let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::CAPTURE_STOP)?;
+        reply.pop()
+    }
+
+    /// Returns the frame counters of the network interface
+    pub fn iface_stats(&self) -> Result<IfaceStats, Error> {
+        let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::IFACE_STATS)?;
+        Ok(IfaceStats {
+            rx_packets: reply.pop()?,
+            rx_bytes: reply.pop()?,
+            rx_errors: reply.pop()?,
+            tx_packets: reply.pop()?,
+            tx_bytes: reply.pop()?,
+            tx_errors: reply.pop()?,
+        })
+    }
+
+    /// Returns the state and counters of all sockets of this session
+    ///
+    /// With the `admin=yes` session argument, the sockets of all sessions are returned.
+    pub fn socket_stats(&self) -> Result<Vec<SocketStats>, Error> {
+        let mut sockets = Vec::new();
+        loop {
+            let mut reply = send_recv_res!(
+                &self.metagate,
+                RecvGate::def(),
+                NetworkOp::SOCKET_STATS,
+                sockets.len()
+            )?;
+            let total = reply.pop::<usize>()?;
+            if sockets.len() >= total {
+                break Ok(sockets);
+            }
+
+            sockets.push(SocketStats {
+                session: reply.pop()?,
+                sd: reply.pop()?,
+                ty: SocketType::from_usize(reply.pop()?),
+                state: reply.pop::<&str>()?.to_string(),
+                local: Endpoint::new(reply.pop()?, reply.pop()?),
+                remote: Endpoint::new(reply.pop()?, reply.pop()?),
+                rx_packets: reply.pop()?,
+                rx_bytes: reply.pop()?,
+                tx_packets: reply.pop()?,
+                tx_bytes: reply.pop()?,
+                drops: reply.pop()?,
+            });
+        }
     }
 
     pub(crate) fn create(
@@ -109,24 +299,49 @@ impl NetworkManager {
         Ok(Socket::new(sd, ty, chan))
     }
 
-    pub(crate) fn nameserver(&self) -> Result<IpAddr, Error> {
-        let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::GET_NAMESRV)?;
-        let addr = IpAddr(reply.pop::<u32>()?);
-        Ok(addr)
-    }
-
     pub(crate) fn bind(&self, sd: Sd, port: Port) -> Result<(IpAddr, Port), Error> {
         let mut reply = send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::BIND, sd, port)?;
-        let addr = IpAddr(reply.pop::<u32>()?);
+        let addr = reply.pop::<IpAddr>()?;
         let port = reply.pop::<Port>()?;
         Ok((addr, port))
     }
 
-    pub(crate) fn listen(&self, sd: Sd, port: Port) -> Result<IpAddr, Error> {
-        let mut reply =
-            send_recv_res!(&self.metagate, RecvGate::def(), NetworkOp::LISTEN, sd, port)?;
-        let addr = IpAddr(reply.pop::<u32>()?);
-        Ok(addr)
+    pub(crate) fn listen(&self, sd: Sd, port: Port, backlog: usize) -> Result<IpAddr, Error> {
+        let mut reply = send_recv_res!(
+            &self.metagate,
+            RecvGate::def(),
+            NetworkOp::LISTEN,
+            sd,
+            port,
+            backlog
+        )?;
+        reply.pop::<IpAddr>()
+    }
+
+    pub(crate) fn accept(&self, sd: Sd) -> Result<(Socket, Endpoint, Endpoint), Error> {
+        let mut nsd = 0;
+        let mut local_ep = Endpoint::unspecified();
+        let mut remote_ep = Endpoint::unspecified();
+        let crd = self.client_session.obtain(
+            2,
+            |sink| {
+                sink.push(NetworkOp::ACCEPT);
+                sink.push(sd);
+            },
+            |source| {
+                nsd = source.pop()?;
+                local_ep = Endpoint::new(source.pop()?, source.pop()?);
+                remote_ep = Endpoint::new(source.pop()?, source.pop()?);
+                Ok(())
+            },
+        )?;
+
+        let chan = NetEventChannel::new_client(crd.start())?;
+        Ok((
+            Socket::new(nsd, SocketType::Stream, chan),
+            local_ep,
+            remote_ep,
+        ))
     }
 
     pub(crate) fn connect(&self, sd: Sd, endpoint: Endpoint) -> Result<Endpoint, Error> {
@@ -135,12 +350,57 @@ impl NetworkManager {
             RecvGate::def(),
             NetworkOp::CONNECT,
             sd,
-            endpoint.addr.0,
+            endpoint.addr,
             endpoint.port
         )?;
-        let addr = reply.pop::<u32>()?;
+        let addr = reply.pop::<IpAddr>()?;
         let port = reply.pop::<Port>()?;
-        Ok(Endpoint::new(IpAddr(addr), port))
+        Ok(Endpoint::new(addr, port))
+    }
+
+    pub(crate) fn set_option(&self, sd: Sd, opt: SocketOpt, value: u64) -> Result<(), Error> {
+        send_recv_res!(
+            &self.metagate,
+            RecvGate::def(),
+            NetworkOp::SET_OPTION,
+            sd,
+            opt,
+            value
+        )
+        .map(|_| ())
+    }
+
+    pub(crate) fn get_option(&self, sd: Sd, opt: SocketOpt) -> Result<u64, Error> {
+        let mut reply = send_recv_res!(
+            &self.metagate,
+            RecvGate::def(),
+            NetworkOp::GET_OPTION,
+            sd,
+            opt
+        )?;
+        reply.pop::<u64>()
+    }
+
+    pub(crate) fn join_group(&self, sd: Sd, addr: IpAddr) -> Result<(), Error> {
+        send_recv_res!(
+            &self.metagate,
+            RecvGate::def(),
+            NetworkOp::JOIN_GROUP,
+            sd,
+            addr
+        )
+        .map(|_| ())
+    }
+
+    pub(crate) fn leave_group(&self, sd: Sd, addr: IpAddr) -> Result<(), Error> {
+        send_recv_res!(
+            &self.metagate,
+            RecvGate::def(),
+            NetworkOp::LEAVE_GROUP,
+            sd,
+            addr
+        )
+        .map(|_| ())
     }
 
     pub(crate) fn abort(&self, sd: Sd, remove: bool) -> Result<(), Error> {
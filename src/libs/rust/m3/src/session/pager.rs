@@ -46,25 +46,35 @@ int_enum! {
     /// The pager's operations
     pub struct PagerOp : u32 {
         /// A page fault
-        const PAGEFAULT = 0x0;
+        const PAGEFAULT  = 0x0;
         /// Initializes the pager session
-        const INIT      = 0x1;
+        const INIT       = 0x1;
         /// Adds a child activity to the pager session
-        const ADD_CHILD = 0x2;
+        const ADD_CHILD  = 0x2;
         /// Adds a new send gate to the pager session
-        const ADD_SGATE = 0x3;
+        const ADD_SGATE  = 0x3;
         /// Clone the address space of a child activity (see `ADD_CHILD`) from the parent
-        const CLONE     = 0x4;
+        const CLONE      = 0x4;
         /// Add a new mapping with anonymous memory
-        const MAP_ANON  = 0x5;
+        const MAP_ANON   = 0x5;
         /// Add a new data space mapping (e.g., a file)
-        const MAP_DS    = 0x6;
+        const MAP_DS     = 0x6;
         /// Add a new mapping for a given memory capability
-        const MAP_MEM   = 0x7;
+        const MAP_MEM    = 0x7;
         /// Remove an existing mapping
-        const UNMAP     = 0x8;
+        const UNMAP      = 0x8;
         /// Close the pager session
-        const CLOSE     = 0x9;
+        const CLOSE      = 0x9;
+        /// Change the permissions of a part of the address space
+        const PROTECT    = 0xA;
+        /// Drop the memory of a part of the address space
+        const DONTNEED   = 0xB;
+        /// Populate a part of the address space in advance
+        const WILLNEED   = 0xC;
+        /// Add a new mapping for a named shared-memory object
+        const MAP_SHM    = 0xD;
+        /// Remove the name of a shared-memory object
+        const UNLINK_SHM = 0xE;
     }
 }
 
@@ -81,6 +91,8 @@ bitflags! {
         const UNINIT  = 0x4000;
         /// Do not use a large page, even if possible
         const NOLPAGE = 0x8000;
+        /// Use large pages (requires an anonymous mapping with large-page-aligned address and size)
+        const LARGE   = 0x10000;
     }
 }
 
@@ -273,10 +285,91 @@ impl Pager {
         Ok(res)
     }
 
+    /// Maps `len` bytes of the shared-memory object `name` to virtual address `addr` with
+    /// permissions `prot`.
+    ///
+    /// If `create` is true, a new object with `len` bytes is created, which fails if the name
+    /// exists already. The memory is charged to the creator and `prot` is the maximum for all
+    /// other mappings. Otherwise, the existing object is mapped, which needs to have at least `len`
+    /// bytes and fails with `Code::NoPerm` if `prot` exceeds the permissions of the creator. The
+    /// object is accessible for all activities of this pager until it is unlinked via
+    /// [`unlink_shm`](Pager::unlink_shm) or its creator exits.
+    pub fn map_shm(
+        &self,
+        name: &str,
+        addr: goff,
+        len: usize,
+        prot: kif::Perm,
+        create: bool,
+    ) -> Result<goff, Error> {
+        let mut reply = send_recv_res!(
+            &self.req_sgate,
+            RecvGate::def(),
+            PagerOp::MAP_SHM,
+            addr,
+            len,
+            prot.bits(),
+            create,
+            name
+        )?;
+        reply.pop()
+    }
+
+    /// Removes the name of the shared-memory object `name`.
+    ///
+    /// The memory is free'd as soon as it is not mapped anymore.
+    pub fn unlink_shm(&self, name: &str) -> Result<(), Error> {
+        send_recv_res!(&self.req_sgate, RecvGate::def(), PagerOp::UNLINK_SHM, name).map(|_| ())
+    }
+
     /// Unaps the mapping at virtual address `addr`.
     pub fn unmap(&self, addr: goff) -> Result<(), Error> {
         send_recv_res!(&self.req_sgate, RecvGate::def(), PagerOp::UNMAP, addr).map(|_| ())
     }
+
+    /// Changes the permissions of the `len` bytes at virtual address `addr` to `prot`.
+    ///
+    /// The range can span multiple mappings. An empty `prot` makes the range inaccessible (e.g.,
+    /// for guard pages). File mappings cannot get more permissions than they were mapped with.
+    pub fn protect(&self, addr: goff, len: usize, prot: kif::Perm) -> Result<(), Error> {
+        send_recv_res!(
+            &self.req_sgate,
+            RecvGate::def(),
+            PagerOp::PROTECT,
+            addr,
+            len,
+            prot.bits()
+        )
+        .map(|_| ())
+    }
+
+    /// Tells the pager that the `len` bytes at virtual address `addr` are not needed anymore.
+    ///
+    /// The memory is given back and the next access yields zeros for anonymous memory and the
+    /// file's content for file mappings.
+    pub fn dont_need(&self, addr: goff, len: usize) -> Result<(), Error> {
+        send_recv_res!(
+            &self.req_sgate,
+            RecvGate::def(),
+            PagerOp::DONTNEED,
+            addr,
+            len
+        )
+        .map(|_| ())
+    }
+
+    /// Tells the pager that the `len` bytes at virtual address `addr` will be needed soon, so that
+    /// it maps them in advance to avoid the page faults.
+    pub fn will_need(&self, addr: goff, len: usize) -> Result<(), Error> {
+        send_recv_res!(
+            &self.req_sgate,
+            RecvGate::def(),
+            PagerOp::WILLNEED,
+            addr,
+            len
+        )
+        .map(|_| ())
+    }
 }
 
 impl Drop for Pager {
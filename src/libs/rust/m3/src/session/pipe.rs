@@ -33,7 +33,7 @@ pub struct Pipes {
 int_enum! {
     /// The pipe operations.
     pub struct PipeOperation : u64 {
-        const OPEN_PIPE     = GenFileOp::REQ_NOTIFY.val + 1;
+        const OPEN_PIPE     = GenFileOp::FALLOCATE.val + 1;
         const OPEN_CHAN     = Self::OPEN_PIPE.val + 1;
         const SET_MEM       = Self::OPEN_CHAN.val + 1;
         const CLOSE_PIPE    = Self::SET_MEM.val + 1;
@@ -219,7 +219,9 @@ pub fn create_map(
 
 /// Creates a new activity on tile `tile` with given name at the selector range `dst`.
 ///
-/// The argument `kmem` defines the kernel memory to assign to the activity.
+/// The argument `kmem` defines the kernel memory to assign to the activity. The argument `prio`
+/// defines the scheduling priority, which cannot be higher than the priority of the own activity.
+/// If `None`, the default priority is used or the own priority, if lower.
 ///
 /// On success, the function returns the activity id (for debugging purposes) and EP id of the first
 /// standard EP.
@@ -229,6 +231,7 @@ pub fn create_activity(
     name: &str,
     tile: Selector,
     kmem: Selector,
+    prio: Option<kif::tilemux::Priority>,
 ) -> Result<(ActId, EpId), Error> {
     let mut buf = SYSC_BUF.borrow_mut();
     build_vmsg!(
@@ -238,7 +241,8 @@ pub fn create_activity(
             dst,
             name,
             tile,
-            kmem
+            kmem,
+            prio
         }
     );
 
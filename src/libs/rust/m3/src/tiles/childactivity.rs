@@ -53,6 +53,7 @@ pub struct ActivityArgs<'n> {
     pager: Option<Pager>,
     kmem: Option<Rc<KMem>>,
     rmng: Option<ResMng>,
+    prio: Option<kif::tilemux::Priority>,
 }
 
 impl<'n> ActivityArgs<'n> {
@@ -63,6 +64,7 @@ impl<'n> ActivityArgs<'n> {
             pager: None,
             kmem: None,
             rmng: None,
+            prio: None,
         }
     }
 
@@ -85,6 +87,13 @@ impl<'n> ActivityArgs<'n> {
         self.kmem = Some(kmem);
         self
     }
+
+    /// Sets the scheduling priority of the activity, which cannot be higher than the priority of
+    /// the own activity. By default, the default priority is used or the own priority, if lower.
+    pub fn prio(mut self, prio: kif::tilemux::Priority) -> Self {
+        self.prio = Some(prio);
+        self
+    }
 }
 
 impl ChildActivity {
@@ -128,7 +137,7 @@ impl ChildActivity {
         act.pager = if let Some(mut pg) = pager {
             // now create activity, which implicitly obtains the gate cap from us
             let (id, eps_start) =
-                syscalls::create_activity(sel, args.name, tile.sel(), act.kmem().sel())?;
+                syscalls::create_activity(sel, args.name, tile.sel(), act.kmem().sel(), args.prio)?;
             act.id = id;
             act.eps_start = eps_start;
 
@@ -138,7 +147,7 @@ impl ChildActivity {
         }
         else {
             let (id, eps_start) =
-                syscalls::create_activity(sel, args.name, tile.sel(), act.kmem().sel())?;
+                syscalls::create_activity(sel, args.name, tile.sel(), act.kmem().sel(), args.prio)?;
             act.id = id;
             act.eps_start = eps_start;
             None
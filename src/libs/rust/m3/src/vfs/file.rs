@@ -31,7 +31,7 @@ use crate::kif;
 use crate::serialize::{Deserialize, M3Serializer, Serialize, VecSink};
 use crate::session::{HashInput, HashOutput, MapFlags, Pager};
 use crate::tiles::ChildActivity;
-use crate::vfs::{BlockId, DevId, Fd, INodeId};
+use crate::vfs::{BlockId, DevId, Fd, GroupId, INodeId, UserId};
 
 int_enum! {
     /// The different seek modes.
@@ -111,6 +111,7 @@ bitflags! {
 
         const FILE_DEF  = Self::IFREG.bits | 0o0644;
         const DIR_DEF   = Self::IFDIR.bits;
+        const LINK_DEF  = Self::IFLNK.bits | 0o0777;
         const PERM      = 0o777;
     }
 }
@@ -149,6 +150,8 @@ pub struct FileInfo {
     pub devno: DevId,
     pub inode: INodeId,
     pub mode: FileMode,
+    pub uid: UserId,
+    pub gid: GroupId,
     pub links: u32,
     pub size: usize,
     pub lastaccess: u32,
@@ -159,6 +162,15 @@ pub struct FileInfo {
     pub firstblock: BlockId,
 }
 
+bitflags! {
+    /// The modes for [`File::fallocate`].
+    pub struct FAllocMode : u64 {
+        /// Deallocates the range instead of allocating it. The range reads as zeros afterwards and
+        /// the file size does not change.
+        const PUNCH_HOLE    = 1;
+    }
+}
+
 bitflags! {
     pub struct FileEvent : u64 {
         const INPUT         = 1;
@@ -206,6 +218,12 @@ pub trait File: Read + Write + Seek + Map + Debug + HashInput + HashOutput {
         Err(Error::new(Code::NotSup))
     }
 
+    /// Allocates the range from `off` to `off + len`, extending the file if necessary, or punches a
+    /// hole into it, depending on `mode`.
+    fn fallocate(&mut self, _off: usize, _len: usize, _mode: FAllocMode) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+
     /// Returns the type of the file implementation used for serialization.
     fn file_type(&self) -> u8;
     /// Delegates this file to `act`.
@@ -30,7 +30,7 @@ use crate::net::{DGramSocket, StreamSocket};
 use crate::serialize::{M3Serializer, VecSink};
 use crate::session::{HashInput, HashOutput, HashSession, MapFlags, Pager};
 use crate::tiles::{Activity, ChildActivity};
-use crate::vfs::{Fd, File, FileEvent, FileTable, Map, Seek, SeekMode};
+use crate::vfs::{FAllocMode, Fd, File, FileEvent, FileTable, Map, Seek, SeekMode};
 
 /// A file reference provides access to a file of type `T`.
 ///
@@ -130,6 +130,14 @@ impl<T: ?Sized + 'static> File for FileRef<T> {
         self.borrow().stat()
     }
 
+    fn truncate(&mut self, length: usize) -> Result<(), Error> {
+        self.borrow().truncate(length)
+    }
+
+    fn fallocate(&mut self, off: usize, len: usize, mode: FAllocMode) -> Result<(), Error> {
+        self.borrow().fallocate(off, len, mode)
+    }
+
     fn delegate(&self, act: &ChildActivity) -> Result<Selector, Error> {
         self.borrow().delegate(act)
     }
@@ -243,6 +251,22 @@ impl<T: 'static + DGramSocket> DGramSocket for FileRef<T> {
     fn send_to(&mut self, data: &[u8], endpoint: crate::net::Endpoint) -> Result<(), Error> {
         self.borrow_as().send_to(data, endpoint)
     }
+
+    fn join_group(&mut self, addr: crate::net::IpAddr) -> Result<(), Error> {
+        self.borrow_as().join_group(addr)
+    }
+
+    fn leave_group(&mut self, addr: crate::net::IpAddr) -> Result<(), Error> {
+        self.borrow_as().leave_group(addr)
+    }
+
+    fn set_option(&mut self, opt: crate::net::SocketOpt, value: u64) -> Result<(), Error> {
+        self.borrow_as().set_option(opt, value)
+    }
+
+    fn get_option(&self, opt: crate::net::SocketOpt) -> Result<u64, Error> {
+        self.borrow_as().get_option(opt)
+    }
 }
 
 impl<T: 'static + StreamSocket> StreamSocket for FileRef<T> {
@@ -266,7 +290,7 @@ impl<T: 'static + StreamSocket> StreamSocket for FileRef<T> {
         self.borrow_as().connect(endpoint)
     }
 
-    fn accept(&mut self) -> Result<crate::net::Endpoint, Error> {
+    fn accept(&mut self) -> Result<(FileRef<crate::net::TcpSocket>, crate::net::Endpoint), Error> {
         self.borrow_as().accept()
     }
 
@@ -289,6 +313,14 @@ impl<T: 'static + StreamSocket> StreamSocket for FileRef<T> {
     fn abort(&mut self) -> Result<(), Error> {
         self.borrow_as().abort()
     }
+
+    fn set_option(&mut self, opt: crate::net::SocketOpt, value: u64) -> Result<(), Error> {
+        self.borrow_as().set_option(opt, value)
+    }
+
+    fn get_option(&self, opt: crate::net::SocketOpt) -> Result<u64, Error> {
+        self.borrow_as().get_option(opt)
+    }
 }
 
 impl<T: ?Sized> fmt::Debug for FileRef<T> {
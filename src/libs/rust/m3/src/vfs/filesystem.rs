@@ -21,26 +21,41 @@ use core::fmt;
 
 use crate::boxed::Box;
 use crate::cap::Selector;
+use crate::col::{String, Vec};
 use crate::errors::Error;
 use crate::int_enum;
 use crate::serialize::{M3Serializer, VecSink};
 use crate::tiles::ChildActivity;
-use crate::vfs::{File, FileInfo, FileMode, OpenFlags};
+use crate::vfs::{File, FileInfo, FileMode, GroupId, OpenFlags, UserId};
 
 int_enum! {
     /// The file system operations.
     pub struct FSOperation : u64 {
-        const STAT          = 15;
-        const MKDIR         = 16;
-        const RMDIR         = 17;
-        const LINK          = 18;
-        const UNLINK        = 19;
-        const RENAME        = 20;
-        const OPEN          = 21;
-        const GET_SGATE     = 22;
-        const GET_MEM       = 23;
-        const DEL_EP        = 24;
-        const OPEN_PRIV     = 25;
+        const STAT          = 16;
+        const MKDIR         = 17;
+        const RMDIR         = 18;
+        const LINK          = 19;
+        const UNLINK        = 20;
+        const RENAME        = 21;
+        const OPEN          = 22;
+        const GET_SGATE     = 23;
+        const GET_MEM       = 24;
+        const DEL_EP        = 25;
+        const OPEN_PRIV     = 26;
+        const SYMLINK       = 27;
+        const READLINK      = 28;
+        const CHMOD         = 29;
+        const CHOWN         = 30;
+        const UTIME         = 31;
+        const DERIVE        = 32;
+        const CHROOT        = 33;
+        const GET_QUOTA     = 34;
+        const GETXATTR      = 35;
+        const SETXATTR      = 36;
+        const LISTXATTR     = 37;
+        const REMOVEXATTR   = 38;
+        const CHECK         = 39;
+        const REFLINK       = 40;
     }
 }
 
@@ -72,6 +87,29 @@ pub trait FileSystem: fmt::Debug {
     fn unlink(&self, path: &str) -> Result<(), Error>;
     /// Renames `new_path` to `old_path`.
     fn rename(&self, old_path: &str, new_path: &str) -> Result<(), Error>;
+    /// Creates `new_path` as a copy-on-write clone of the file `old_path`.
+    fn reflink(&self, old_path: &str, new_path: &str) -> Result<(), Error>;
+
+    /// Creates a symbolic link at `path` that points to `target`.
+    fn symlink(&self, target: &str, path: &str) -> Result<(), Error>;
+    /// Returns the target of the symbolic link at `path`.
+    fn readlink(&self, path: &str) -> Result<String, Error>;
+
+    /// Changes the permissions of the file at `path` to `mode`.
+    fn chmod(&self, path: &str, mode: FileMode) -> Result<(), Error>;
+    /// Changes the owner of the file at `path` to `uid` and `gid`.
+    fn chown(&self, path: &str, uid: UserId, gid: GroupId) -> Result<(), Error>;
+    /// Sets the last access and modification time of the file at `path`.
+    fn utime(&self, path: &str, atime: u32, mtime: u32) -> Result<(), Error>;
+
+    /// Returns the value of the extended attribute `name` of the file at `path`.
+    fn getxattr(&self, path: &str, name: &str) -> Result<Vec<u8>, Error>;
+    /// Sets the extended attribute `name` of the file at `path` to `value`.
+    fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), Error>;
+    /// Returns the names of all extended attributes of the file at `path`.
+    fn listxattr(&self, path: &str) -> Result<Vec<String>, Error>;
+    /// Removes the extended attribute `name` of the file at `path`.
+    fn removexattr(&self, path: &str, name: &str) -> Result<(), Error>;
 
     /// Returns the type of the file system implementation used for serialization.
     fn fs_type(&self) -> u8;
@@ -37,7 +37,9 @@ use crate::serialize::{M3Deserializer, M3Serializer, VecSink};
 use crate::session::{ClientSession, HashInput, HashOutput, HashSession, MapFlags, Pager};
 use crate::tcu::EpId;
 use crate::tiles::{Activity, ChildActivity};
-use crate::vfs::{filetable, Fd, File, FileEvent, FileInfo, Map, OpenFlags, Seek, SeekMode};
+use crate::vfs::{
+    filetable, FAllocMode, Fd, File, FileEvent, FileInfo, Map, OpenFlags, Seek, SeekMode,
+};
 
 int_enum! {
     /// The operations for [`GenericFile`].
@@ -57,6 +59,7 @@ int_enum! {
         const SET_DEST      = 12;
         const ENABLE_NOTIFY = 13;
         const REQ_NOTIFY    = 14;
+        const FALLOCATE     = 15;
     }
 }
 
@@ -429,6 +432,26 @@ impl File for GenericFile {
         Ok(())
     }
 
+    fn fallocate(&mut self, off: usize, len: usize, mode: FAllocMode) -> Result<(), Error> {
+        self.submit(false)?;
+
+        let mut reply = send_recv_res!(
+            &self.sgate,
+            RecvGate::def(),
+            GenFileOp::FALLOCATE,
+            self.file_id(),
+            off,
+            len,
+            mode.bits()
+        )?;
+        // the extents might have changed, so that we need to start again at the current position
+        self.goff = reply.pop()?;
+        self.off = reply.pop()?;
+        self.pos = 0;
+        self.len = 0;
+        Ok(())
+    }
+
     fn file_type(&self) -> u8 {
         b'F'
     }
@@ -505,7 +528,7 @@ impl Seek for GenericFile {
         self.off = reply.pop()?;
         self.pos = 0;
         self.len = 0;
-        Ok(self.goff + off)
+        Ok(self.goff + self.off)
     }
 }
 
@@ -37,10 +37,16 @@ pub type DevId = u8;
 pub type INodeId = u32;
 /// A block ID
 pub type BlockId = u32;
+/// A user ID
+pub type UserId = u32;
+/// A group ID
+pub type GroupId = u32;
 
 pub use self::bufio::{BufReader, BufWriter};
 pub use self::dir::{read_dir, DirEntry, ReadDir};
-pub use self::file::{File, FileEvent, FileInfo, FileMode, Map, OpenFlags, Seek, SeekMode};
+pub use self::file::{
+    FAllocMode, File, FileEvent, FileInfo, FileMode, Map, OpenFlags, Seek, SeekMode,
+};
 pub use self::fileref::FileRef;
 pub use self::filesystem::{FSOperation, FileSystem};
 pub(crate) use self::filetable::INV_FD;
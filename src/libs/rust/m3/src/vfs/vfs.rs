@@ -17,13 +17,15 @@
  */
 
 use crate::borrow::StringRef;
-use crate::col::{String, ToString};
+use crate::col::{String, ToString, Vec};
 use crate::env;
 use crate::errors::{Code, Error};
 use crate::rc::Rc;
 use crate::session::M3FS;
 use crate::tiles::Activity;
-use crate::vfs::{FSHandle, File, FileInfo, FileMode, FileRef, GenericFile, OpenFlags};
+use crate::vfs::{
+    FSHandle, File, FileInfo, FileMode, FileRef, GenericFile, GroupId, OpenFlags, UserId,
+};
 
 /// Mounts the file system of type `fstype` at `path`, creating a session at `service`.
 pub fn mount(path: &str, fstype: &str, service: &str) -> Result<(), Error> {
@@ -177,3 +179,74 @@ pub fn rename(old: &str, new: &str) -> Result<(), Error> {
     let res = fs1.borrow().rename(&old[pos1..], &new[pos2..]);
     res
 }
+
+/// Creates `new` as a copy-on-write clone of the file `old`.
+///
+/// The clone shares all data with `old` until one of them is written. Fails with [`Code::InUse`] if
+/// `old` is currently open for writing.
+pub fn reflink(old: &str, new: &str) -> Result<(), Error> {
+    let mut old = StringRef::Borrowed(old);
+    let (fs1, pos1) = Activity::own().mounts().resolve(&mut old)?;
+    let mut new = StringRef::Borrowed(new);
+    let (fs2, pos2) = Activity::own().mounts().resolve(&mut new)?;
+    if !Rc::ptr_eq(&fs1, &fs2) {
+        return Err(Error::new(Code::XfsLink));
+    }
+    #[allow(clippy::let_and_return)] // is required because of fs1.borrow()'s lifetime
+    let res = fs1.borrow().reflink(&old[pos1..], &new[pos2..]);
+    res
+}
+
+/// Creates a symbolic link at `path` that points to `target`.
+///
+/// The target is not interpreted by the VFS, but only by the file system that holds the link when
+/// resolving paths. Thus, absolute targets refer to the root of this file system.
+pub fn symlink(target: &str, path: &str) -> Result<(), Error> {
+    with_path(path, |fs, fs_path| fs.borrow().symlink(target, fs_path))
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub fn readlink(path: &str) -> Result<String, Error> {
+    with_path(path, |fs, fs_path| fs.borrow().readlink(fs_path))
+}
+
+/// Changes the permissions of the file at `path` to `mode`.
+pub fn chmod(path: &str, mode: FileMode) -> Result<(), Error> {
+    with_path(path, |fs, fs_path| fs.borrow().chmod(fs_path, mode))
+}
+
+/// Changes the owner of the file at `path` to `uid` and `gid`.
+pub fn chown(path: &str, uid: UserId, gid: GroupId) -> Result<(), Error> {
+    with_path(path, |fs, fs_path| fs.borrow().chown(fs_path, uid, gid))
+}
+
+/// Sets the last access time of the file at `path` to `atime` and the last modification time to
+/// `mtime`.
+pub fn utime(path: &str, atime: u32, mtime: u32) -> Result<(), Error> {
+    with_path(path, |fs, fs_path| fs.borrow().utime(fs_path, atime, mtime))
+}
+
+/// Returns the value of the extended attribute `name` of the file at `path`.
+pub fn getxattr(path: &str, name: &str) -> Result<Vec<u8>, Error> {
+    with_path(path, |fs, fs_path| fs.borrow().getxattr(fs_path, name))
+}
+
+/// Sets the extended attribute `name` of the file at `path` to `value`.
+///
+/// Names and values are at most 255 bytes long and all attributes of a file need to fit into a
+/// single block of the file system. Values are arbitrary bytes.
+pub fn setxattr(path: &str, name: &str, value: &[u8]) -> Result<(), Error> {
+    with_path(path, |fs, fs_path| {
+        fs.borrow().setxattr(fs_path, name, value)
+    })
+}
+
+/// Returns the names of all extended attributes of the file at `path`.
+pub fn listxattr(path: &str) -> Result<Vec<String>, Error> {
+    with_path(path, |fs, fs_path| fs.borrow().listxattr(fs_path))
+}
+
+/// Removes the extended attribute `name` of the file at `path`.
+pub fn removexattr(path: &str, name: &str) -> Result<(), Error> {
+    with_path(path, |fs, fs_path| fs.borrow().removexattr(fs_path, name))
+}
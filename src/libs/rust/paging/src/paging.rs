@@ -42,6 +42,7 @@ use base::log;
 use base::math;
 use base::mem::{size_of, GlobAddr};
 use base::tcu::TCU;
+use core::cmp;
 use core::fmt;
 
 use arch::{LEVEL_BITS, LEVEL_CNT, LEVEL_MASK};
@@ -124,9 +125,13 @@ impl<A: Allocator> AddrSpace<A> {
 
             let pte_flags = MMUFlags::from_bits_truncate(pte);
             if pte_flags.is_leaf(lvl) || pte_flags.perms_missing(perm) {
-                let res = pte_to_phys(pte);
-                let flags = MMUFlags::from_bits_truncate(pte);
-                return res | to_page_flags(lvl, flags).bits();
+                let mut res = pte_to_phys(pte);
+                // for large pages, return the address of the small page within the large page
+                if lvl > 0 && pte_flags.is_leaf(lvl) {
+                    let page_size = 1 << (cfg::PAGE_BITS + lvl * LEVEL_BITS);
+                    res += (virt & (page_size - 1) & !cfg::PAGE_MASK) as Phys;
+                }
+                return res | to_page_flags(lvl, pte_flags).bits();
             }
         }
         unreachable!();
@@ -188,16 +193,18 @@ impl<A: Allocator> AddrSpace<A> {
             // safety: as above
             let mut pte = unsafe { *(pte_addr as *const MMUPTE) };
 
+            // does the range cover the complete large page?
+            let covers_lpage = level == 1
+                && math::is_aligned(*virt, cfg::LPAGE_SIZE)
+                && *pages * cfg::PAGE_SIZE >= cfg::LPAGE_SIZE;
+
             let is_leaf = if perm.has_empty_perm() {
-                MMUFlags::from_bits_truncate(pte).is_leaf(level)
+                MMUFlags::from_bits_truncate(pte).is_leaf(level) && (level == 0 || covers_lpage)
             }
             else {
                 level == 0
                 // can we use a large page?
-                || (level == 1
-                    && math::is_aligned(*virt, cfg::LPAGE_SIZE)
-                    && math::is_aligned(*phys, cfg::LPAGE_SIZE as MMUPTE)
-                    && *pages * cfg::PAGE_SIZE >= cfg::LPAGE_SIZE)
+                || (covers_lpage && math::is_aligned(*phys, cfg::LPAGE_SIZE as MMUPTE))
             };
 
             if is_leaf {
@@ -219,7 +226,10 @@ impl<A: Allocator> AddrSpace<A> {
 
                 let invalidate = arch::needs_invalidate(new_flags, old_flags);
                 if invalidate {
-                    TCU::invalidate_page(self.id as u16, *virt);
+                    // the TCU's TLB holds an entry per small page
+                    for off in (0..psize).step_by(cfg::PAGE_SIZE) {
+                        TCU::invalidate_page(self.id as u16, *virt + off);
+                    }
                     // flush single page for leaf PTEs and complete TLB for higher-level PTEs
                     if level == 0 {
                         arch::invalidate_page(self.id, *virt);
@@ -245,11 +255,27 @@ impl<A: Allocator> AddrSpace<A> {
                 *phys += psize as MMUPTE;
             }
             else {
+                // an unmapped large page does not refer to a PT either
+                let pte_flags = MMUFlags::from_bits_truncate(pte);
+                let no_pt = pte == 0 || (pte_flags.is_leaf(level) && pte_flags.has_empty_perm());
+
                 // unmapping non-existing PTs is a noop
-                if !(pte == 0 && perm.has_empty_perm()) {
-                    if pte == 0 {
+                if no_pt && perm.has_empty_perm() {
+                    // skip the range of this PTE
+                    let psize = 1 << (cfg::PAGE_BITS + level * LEVEL_BITS);
+                    let skip = cmp::min(*pages, (psize - (*virt & (psize - 1))) / cfg::PAGE_SIZE);
+                    *pages -= skip;
+                    *virt += skip * cfg::PAGE_SIZE;
+                    *phys += (skip * cfg::PAGE_SIZE) as MMUPTE;
+                }
+                else {
+                    if no_pt {
                         pte = self.create_pt(*virt, pte_addr, level)?;
                     }
+                    // we change only a part of a large page
+                    else if pte_flags.is_leaf(level) {
+                        pte = self.split_lpage(*virt, pte_addr, pte, level)?;
+                    }
 
                     self.map_pages_rec(virt, phys, pages, perm, pte, level - 1)?;
                 }
@@ -285,6 +311,46 @@ impl<A: Allocator> AddrSpace<A> {
         Ok(pte)
     }
 
+    fn split_lpage(
+        &mut self,
+        virt: usize,
+        pte_addr: usize,
+        pte: MMUPTE,
+        level: usize,
+    ) -> Result<MMUPTE, Error> {
+        // determine the permissions of the large page, but without the large-page flag
+        let flags = to_page_flags(level, MMUFlags::from_bits_truncate(pte)) & !PageFlags::L;
+        let perm = arch::to_mmu_perms(flags);
+
+        // map the same memory with the next smaller pages in a new page table
+        let frame = self.alloc.allocate_pt()?;
+        let psize: usize = 1 << (cfg::PAGE_BITS + (level - 1) * LEVEL_BITS);
+        let mut phys = pte_to_phys(pte);
+        let mut small_pte_addr = self.alloc.translate_pt(frame);
+        for _ in 0..1 << LEVEL_BITS {
+            // safety: as above
+            unsafe { *(small_pte_addr as *mut MMUPTE) = build_pte(phys, perm, level - 1, true) };
+            small_pte_addr += size_of::<MMUPTE>();
+            phys += psize as Phys;
+        }
+
+        // replace the large page with the page table
+        let new_pte = build_pte(frame, MMUFlags::empty(), level, false);
+        // safety: as above
+        unsafe { *(pte_addr as *mut MMUPTE) = new_pte };
+        arch::invalidate_tlb();
+
+        log!(
+            crate::LOG_MAP_DETAIL,
+            "Activity{}: split large page at 0x{:0>16x} into PT @ {:#x}",
+            self.id,
+            math::round_dn(virt, cfg::LPAGE_SIZE),
+            frame,
+        );
+
+        Ok(new_pte)
+    }
+
     fn clear_pt(pt_virt: usize) {
         unsafe { libc::memset(pt_virt as *mut _, 0, cfg::PAGE_SIZE) };
     }
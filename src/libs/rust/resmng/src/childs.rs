@@ -214,11 +214,36 @@ pub trait Child {
         let alloc = self.mem().pool.borrow_mut().allocate(size)?;
         let mem_sel = self.mem().pool.borrow().mem_cap(alloc.slice_id());
         let mgate = MemGate::new_bind(mem_sel).derive(alloc.addr(), alloc.size() as usize, perm)?;
-        // TODO this memory is currently only free'd on child exit
+        // this memory is free'd via free_local or on child exit
         self.add_mem(alloc, None);
         Ok(mgate)
     }
 
+    fn free_local(&mut self, mgate: MemGate) -> Result<(), Error> {
+        let (addr, size) = mgate.region()?;
+        log!(
+            crate::LOG_MEM,
+            "{}: free_local(addr={:?}, size={:#x})",
+            self.name(),
+            addr,
+            size
+        );
+
+        let idx = {
+            let pool = self.mem().pool.borrow();
+            self.res()
+                .mem
+                .iter()
+                .position(|(sel, alloc)| sel.is_none() && pool.addr_of(alloc) == addr)
+        }
+        .ok_or_else(|| Error::new(Code::InvArgs))?;
+
+        // revoke the memory gate before the memory is handed out again
+        drop(mgate);
+        self.remove_mem_by_idx(idx);
+        Ok(())
+    }
+
     fn alloc_mem(&mut self, dst_sel: Selector, size: goff, perm: Perm) -> Result<(), Error> {
         log!(
             crate::LOG_MEM,
@@ -21,6 +21,7 @@ use m3::goff;
 use m3::kif;
 use m3::rc::Rc;
 use m3::tcu::Label;
+use m3::vfs::{GroupId, UserId};
 
 use crate::parser;
 use crate::tiles;
@@ -76,11 +77,26 @@ impl PhysMemDesc {
 pub struct MountDesc {
     fs: String,
     path: String,
+    creds: Option<(UserId, GroupId)>,
+    readonly: bool,
+    root: String,
 }
 
 impl MountDesc {
-    pub(crate) fn new(fs: String, path: String) -> Self {
-        Self { fs, path }
+    pub(crate) fn new(
+        fs: String,
+        path: String,
+        creds: Option<(UserId, GroupId)>,
+        readonly: bool,
+        root: String,
+    ) -> Self {
+        Self {
+            fs,
+            path,
+            creds,
+            readonly,
+            root,
+        }
     }
 
     pub fn fs(&self) -> &String {
@@ -90,6 +106,21 @@ impl MountDesc {
     pub fn path(&self) -> &String {
         &self.path
     }
+
+    /// Returns the user and group id to access the file system with, if specified
+    pub fn creds(&self) -> Option<(UserId, GroupId)> {
+        self.creds
+    }
+
+    /// Returns true if the file system should be mounted read-only
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Returns the directory within the file system that should be mounted (empty for the root)
+    pub fn root(&self) -> &String {
+        &self.root
+    }
 }
 
 #[derive(Default)]
@@ -372,6 +403,7 @@ pub struct AppConfig {
     pub(crate) user_mem: Option<usize>,
     pub(crate) kern_mem: Option<usize>,
     pub(crate) time: Option<u64>,
+    pub(crate) prio: Option<kif::tilemux::Priority>,
     pub(crate) pts: Option<usize>,
     pub(crate) serial: Option<SerialDesc>,
     pub(crate) domains: Vec<Domain>,
@@ -416,6 +448,10 @@ impl AppConfig {
         self.eps
     }
 
+    pub fn prio(&self) -> Option<kif::tilemux::Priority> {
+        self.prio
+    }
+
     pub fn user_mem(&self) -> Option<usize> {
         self.user_mem
     }
@@ -679,6 +715,9 @@ impl AppConfig {
         if let Some(t) = self.time {
             writeln!(f, "{:0w$}TimeSlice[{} ns],", "", t, w = layer + 2)?;
         }
+        if let Some(p) = self.prio {
+            writeln!(f, "{:0w$}Priority[{}],", "", p, w = layer + 2)?;
+        }
         if let Some(n) = self.pts {
             writeln!(f, "{:0w$}PageTables[{}],", "", n, w = layer + 2)?;
         }
@@ -760,10 +799,13 @@ impl AppConfig {
         for m in &self.mounts {
             writeln!(
                 f,
-                "{:0w$}Mount[fs='{}', path='{}'],",
+                "{:0w$}Mount[fs='{}', path='{}', creds={:?}, readonly={}, root='{}'],",
                 "",
                 m.fs,
                 m.path,
+                m.creds,
+                m.readonly,
+                m.root,
                 w = layer + 2
             )?;
         }
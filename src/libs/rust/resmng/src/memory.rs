@@ -311,6 +311,10 @@ impl MemPool {
         self.slices[idx].mem.gate.sel()
     }
 
+    pub fn addr_of(&self, alloc: &Allocation) -> GlobAddr {
+        self.slices[alloc.slice_id].mem.addr + alloc.addr
+    }
+
     pub fn add(&mut self, s: MemSlice) {
         self.slices.push(s)
     }
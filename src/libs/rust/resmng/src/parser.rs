@@ -172,6 +172,12 @@ fn parse_app(p: &mut ConfigParser, start: usize) -> Result<config::AppConfig, Er
                 "usermem" => app.user_mem = Some(parse::size(&v)?),
                 "kernmem" => app.kern_mem = Some(parse::size(&v)?),
                 "time" => app.time = Some(parse::time(&v)?),
+                "prio" => match parse::int(&v)? {
+                    p if (p as usize) < kif::tilemux::PRIO_COUNT => {
+                        app.prio = Some(p as kif::tilemux::Priority)
+                    },
+                    _ => return Err(Error::new(Code::InvArgs)),
+                },
                 "pagetables" => app.pts = Some(parse::int(&v)? as usize),
                 "eps" => app.eps = Some(parse::int(&v)? as u32),
                 "daemon" => app.daemon = parse::bool(&v)?,
@@ -317,6 +323,10 @@ fn parse_domain(p: &mut ConfigParser) -> Result<config::Domain, Error> {
 fn parse_mount(p: &mut ConfigParser) -> Result<config::MountDesc, Error> {
     let mut fs = String::new();
     let mut path = String::new();
+    let mut uid = None;
+    let mut gid = None;
+    let mut readonly = false;
+    let mut root = String::new();
     loop {
         match p.parse_arg()? {
             None => break,
@@ -330,11 +340,22 @@ fn parse_mount(p: &mut ConfigParser) -> Result<config::MountDesc, Error> {
                         path = format!("{}/", v);
                     }
                 },
+                "uid" => uid = Some(parse::int(&v)? as u32),
+                "gid" => gid = Some(parse::int(&v)? as u32),
+                "readonly" => readonly = parse::bool(&v)?,
+                "root" => root = v,
                 _ => return Err(Error::new(Code::InvArgs)),
             },
         }
     }
-    Ok(config::MountDesc::new(fs, path))
+
+    // credentials need to be specified completely or not at all
+    let creds = match (uid, gid) {
+        (Some(uid), Some(gid)) => Some((uid, gid)),
+        (None, None) => None,
+        _ => return Err(Error::new(Code::InvArgs)),
+    };
+    Ok(config::MountDesc::new(fs, path, creds, readonly, root))
 }
 
 fn parse_physmem(p: &mut ConfigParser) -> Result<config::PhysMemDesc, Error> {
@@ -29,6 +29,9 @@ use thread::Event;
 
 pub const PRDT_SIZE: usize = 8;
 
+// the transfer buffer slot behind the ones for the meta buffer, used for unbuffered accesses
+const RAW_SLOT: usize = crate::buf::META_BUFFER_SIZE;
+
 pub struct DiskBackend {
     blocksize: usize,
     disk: Disk,
@@ -163,6 +166,22 @@ impl Backend for DiskBackend {
         Ok(())
     }
 
+    fn read_block(&self, dst: &mut [u8], bno: BlockNo) -> Result<(), Error> {
+        let off = RAW_SLOT * (self.blocksize + PRDT_SIZE);
+        self.disk
+            .read(0, BlockRange::new(bno), self.blocksize, Some(off as u64))?;
+        self.metabuf
+            .read_bytes(dst.as_mut_ptr(), self.blocksize, off as u64)
+    }
+
+    fn write_block(&self, src: &[u8], bno: BlockNo) -> Result<(), Error> {
+        let off = RAW_SLOT * (self.blocksize + PRDT_SIZE);
+        self.metabuf
+            .write_bytes(src.as_ptr(), self.blocksize, off as u64)?;
+        self.disk
+            .write(0, BlockRange::new(bno), self.blocksize, Some(off as u64))
+    }
+
     fn load_sb(&mut self) -> Result<SuperBlock, Error> {
         let tmp = MemGate::new(512 + PRDT_SIZE, Perm::RW)?;
         // use a separate MemGate for the disk service, because both have to activate the gate,
@@ -172,9 +191,10 @@ impl Backend for DiskBackend {
         self.disk.read(0, BlockRange::new(0), 512, None)?;
         let super_block = tmp.read_obj::<SuperBlock>(0)?;
 
-        // use separate transfer buffer for each entry to allow parallel disk requests
+        // use separate transfer buffer for each entry to allow parallel disk requests (plus one
+        // for unbuffered accesses)
         self.blocksize = super_block.block_size as usize;
-        let size = (self.blocksize + PRDT_SIZE) * crate::buf::META_BUFFER_SIZE;
+        let size = (self.blocksize + PRDT_SIZE) * (crate::buf::META_BUFFER_SIZE + 1);
         self.metabuf = MemGate::new(size, Perm::RW)?;
         // separate MemGate for the same reason as above
         self.metabuf_disk = self.metabuf.derive(0, size, Perm::RW)?;
@@ -119,6 +119,21 @@ impl Backend for MemBackend {
         Ok(())
     }
 
+    fn read_block(&self, dst: &mut [u8], bno: BlockNo) -> Result<(), Error> {
+        self.mem.read_bytes(
+            dst.as_mut_ptr(),
+            self.blocksize,
+            (bno as usize * self.blocksize) as u64,
+        )
+    }
+
+    fn write_block(&self, src: &[u8], bno: BlockNo) -> Result<(), Error> {
+        self.mem.write(
+            &src[..self.blocksize],
+            (bno as usize * self.blocksize) as u64,
+        )
+    }
+
     fn load_sb(&mut self) -> Result<SuperBlock, Error> {
         let block = self.mem.read_obj::<SuperBlock>(0)?;
         self.blocksize = block.block_size as usize;
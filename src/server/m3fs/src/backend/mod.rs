@@ -69,6 +69,12 @@ pub trait Backend {
 
     fn clear_extent(&self, ext: Extent) -> Result<(), Error>;
 
+    /// Reads block `bno` into `dst`, bypassing the buffers (used for the journal)
+    fn read_block(&self, dst: &mut [u8], bno: BlockNo) -> Result<(), Error>;
+
+    /// Writes `src` to block `bno`, bypassing the buffers (used for the journal)
+    fn write_block(&self, src: &[u8], bno: BlockNo) -> Result<(), Error>;
+
     fn load_sb(&mut self) -> Result<SuperBlock, Error>;
 
     fn store_sb(&self, super_block: &SuperBlock) -> Result<(), Error>;
@@ -293,6 +293,16 @@ impl FileBuffer {
         Ok(load_size * self.block_size)
     }
 
+    /// Writes back all dirty entries, but keeps them in the buffer
+    pub fn write_back(&mut self) -> Result<(), Error> {
+        for b in self.lru.iter_mut() {
+            if !b.locked {
+                b.flush()?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
         while let Some(mut b) = self.lru.pop_front() {
             self.entries.remove(&b.blocks);
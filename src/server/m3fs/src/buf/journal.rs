@@ -0,0 +1,190 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use crate::backend::Backend;
+use crate::buf::MetaBufferBlock;
+use crate::data::{BlockNo, JournalBlockKind, JournalHeader, SuperBlock};
+
+use m3::col::Vec;
+use m3::errors::Error;
+
+/// The write-ahead journal for metadata blocks
+///
+/// The journal consists of a header block, followed by the area for a single transaction. A
+/// transaction consists of a descriptor block listing the block numbers, the copies of the blocks
+/// and a commit block. Before the blocks are written to their final location, the transaction is
+/// committed to the journal. Afterwards, the header is updated to the next sequence number, which
+/// invalidates the transaction. Thus, a transaction in the journal whose sequence number matches
+/// the header's sequence number has been committed, but not (completely) checkpointed and is
+/// therefore replayed at startup.
+pub struct Journal {
+    start: BlockNo,
+    blocks: BlockNo,
+    seq: u32,
+    buf: Vec<u8>,
+}
+
+impl Journal {
+    /// Opens the journal of the given file system and replays a committed transaction, if any
+    pub fn new(backend: &dyn Backend, sb: &SuperBlock) -> Result<Self, Error> {
+        let mut buf = vec![0u8; sb.block_size as usize];
+        let start = sb.first_journal_block();
+        backend.read_block(&mut buf, start)?;
+
+        // an uninitialized journal (all zeros) starts with sequence number 0
+        let hdr = JournalHeader::from_buffer(&buf);
+        let seq = if hdr.is(JournalBlockKind::Header, hdr.seq) {
+            hdr.seq
+        }
+        else {
+            0
+        };
+
+        let mut journal = Self {
+            start,
+            blocks: sb.journal_blocks,
+            seq,
+            buf,
+        };
+        log!(
+            crate::LOG_JOURNAL,
+            "journal: found journal at {} with {} blocks and seq {}",
+            journal.start,
+            journal.blocks,
+            journal.seq
+        );
+
+        journal.replay(backend)?;
+        Ok(journal)
+    }
+
+    /// Returns the maximum number of blocks per transaction
+    pub fn capacity(&self) -> usize {
+        let max = JournalHeader::max_desc_entries(self.buf.len());
+        max.min(self.blocks as usize - 3)
+    }
+
+    /// Writes the given blocks as a single transaction into the journal.
+    ///
+    /// Afterwards, the blocks can be written to their final location and the transaction needs to
+    /// be completed via [`Journal::checkpoint`].
+    pub fn commit(&mut self, blocks: &[&MetaBufferBlock]) -> Result<(), Error> {
+        assert!(blocks.len() <= self.capacity());
+
+        let bnos: Vec<BlockNo> = blocks.iter().map(|b| b.blockno()).collect();
+        let count = bnos.len() as u32;
+        let checksum = JournalHeader::checksum(self.seq, &bnos);
+        log!(
+            crate::LOG_JOURNAL,
+            "journal: committing transaction {} with blocks {:?}",
+            self.seq,
+            bnos
+        );
+
+        let backend = crate::backend_mut();
+
+        // first the descriptor
+        self.buf.fill(0);
+        JournalHeader::from_buffer_mut(&mut self.buf).init(
+            JournalBlockKind::Descriptor,
+            self.seq,
+            count,
+            checksum,
+        );
+        JournalHeader::desc_entries_mut(&mut self.buf, bnos.len()).copy_from_slice(&bnos);
+        backend.write_block(&self.buf, self.start + 1)?;
+
+        // now the block contents
+        for (i, b) in blocks.iter().enumerate() {
+            backend.write_block(b.data(), self.start + 2 + i as BlockNo)?;
+        }
+
+        // the commit block makes the transaction valid
+        self.buf.fill(0);
+        JournalHeader::from_buffer_mut(&mut self.buf).init(
+            JournalBlockKind::Commit,
+            self.seq,
+            count,
+            checksum,
+        );
+        backend.write_block(&self.buf, self.start + 2 + count)
+    }
+
+    /// Completes the current transaction after all its blocks have been written to their final
+    /// location
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        self.seq = self.seq.wrapping_add(1);
+        self.write_header(&**crate::backend_mut())
+    }
+
+    fn write_header(&mut self, backend: &dyn Backend) -> Result<(), Error> {
+        self.buf.fill(0);
+        JournalHeader::from_buffer_mut(&mut self.buf).init(
+            JournalBlockKind::Header,
+            self.seq,
+            0,
+            0,
+        );
+        backend.write_block(&self.buf, self.start)
+    }
+
+    fn replay(&mut self, backend: &dyn Backend) -> Result<(), Error> {
+        // is there a descriptor for the current transaction?
+        backend.read_block(&mut self.buf, self.start + 1)?;
+        let desc = JournalHeader::from_buffer(&self.buf);
+        if !desc.is(JournalBlockKind::Descriptor, self.seq) || desc.count as usize > self.capacity()
+        {
+            return Ok(());
+        }
+
+        let count = desc.count;
+        let checksum = desc.checksum;
+        let bnos = JournalHeader::desc_entries(&self.buf, count as usize).to_vec();
+        if checksum != JournalHeader::checksum(self.seq, &bnos) {
+            return Ok(());
+        }
+
+        // has the transaction been committed?
+        backend.read_block(&mut self.buf, self.start + 2 + count)?;
+        let commit = JournalHeader::from_buffer(&self.buf);
+        if !commit.is(JournalBlockKind::Commit, self.seq)
+            || commit.count != count
+            || commit.checksum != checksum
+        {
+            log!(
+                crate::LOG_JOURNAL,
+                "journal: discarding incomplete transaction {}",
+                self.seq
+            );
+            return Ok(());
+        }
+
+        log!(
+            crate::LOG_JOURNAL,
+            "journal: replaying transaction {} with blocks {:?}",
+            self.seq,
+            bnos
+        );
+
+        let mut data = vec![0u8; self.buf.len()];
+        for (i, bno) in bnos.iter().enumerate() {
+            backend.read_block(&mut data, self.start + 2 + i as BlockNo)?;
+            backend.write_block(&data, *bno)?;
+        }
+
+        self.seq = self.seq.wrapping_add(1);
+        self.write_header(backend)
+    }
+}
@@ -15,6 +15,7 @@
  * General Public License version 2 for more details.
  */
 
+use crate::buf::Journal;
 use crate::data::BlockNo;
 
 use core::ops::{Deref, DerefMut};
@@ -22,7 +23,7 @@ use core::ptr::NonNull;
 
 use m3::boxed::Box;
 use m3::col::{BoxList, Treap, Vec};
-use m3::errors::Error;
+use m3::errors::{Code, Error};
 
 use thread::Event;
 
@@ -153,10 +154,14 @@ pub struct MetaBuffer {
     ids: Treap<BlockNo, usize>,
     // contains pointers to the MetaBufferBlock objects, indexed by their id
     blocks: Vec<NonNull<MetaBufferBlock>>,
+    // the journal that dirty blocks are committed to before writing them back (if enabled)
+    journal: Option<Journal>,
+    // whether the current request needed more blocks than the buffer could provide
+    overflow: bool,
 }
 
 impl MetaBuffer {
-    pub fn new(blocksize: usize) -> Self {
+    pub fn new(blocksize: usize, journal: Option<Journal>) -> Self {
         let mut blocks = Vec::with_capacity(META_BUFFER_SIZE);
         let mut lru = BoxList::new();
         for i in 0..META_BUFFER_SIZE {
@@ -172,9 +177,16 @@ impl MetaBuffer {
             ids: Treap::new(),
             blocks,
             lru,
+            journal,
+            overflow: false,
         }
     }
 
+    /// Returns whether dirty blocks are written back via the journal
+    pub fn journaled(&self) -> bool {
+        self.journal.is_some()
+    }
+
     fn bno_to_id(&self, bno: BlockNo) -> Option<usize> {
         self.ids.get(&bno).copied()
     }
@@ -218,17 +230,23 @@ impl MetaBuffer {
             }
         }
 
-        // find first unused head
-        let mut use_block = None;
-        for lru_element in self.lru.iter() {
-            if lru_element.links == 0 {
-                use_block = Some(lru_element.id);
-                break;
-            }
-        }
+        // find first unused head; with a journal, dirty blocks stay until the end of the request
+        let journaled = self.journaled();
+        let use_block = match self
+            .lru
+            .iter()
+            .find(|b| b.links == 0 && !(journaled && b.dirty))
+        {
+            Some(b) => b.id,
+            None => {
+                log!(crate::LOG_BUFFER, "metabuffer: no free block for <{}>", bno);
+                self.overflow = true;
+                return Err(Error::new(Code::NoSpace));
+            },
+        };
 
         let block = unsafe {
-            let block = &mut (*self.blocks[use_block.unwrap()].as_ptr());
+            let block = &mut (*self.blocks[use_block].as_ptr());
             self.lru.move_to_back(block);
             block
         };
@@ -236,7 +254,7 @@ impl MetaBuffer {
         // flush if there is still a block present with the given bno.
         if block.bno != 0 {
             self.ids.remove(&block.bno);
-            block.flush()?;
+            self.flush_block(block)?;
         }
 
         // use this block
@@ -257,11 +275,92 @@ impl MetaBuffer {
         Ok(MetaBufferBlockRef::new(block.id))
     }
 
+    /// Writes back the given block. With a journal, dirty blocks can only be written as part of
+    /// the transaction at the end of the request (see [`MetaBuffer::end_request`]).
+    pub fn flush_block(&mut self, block: &mut MetaBufferBlock) -> Result<(), Error> {
+        if self.journaled() {
+            Ok(())
+        }
+        else {
+            block.flush()
+        }
+    }
+
+    /// Ends the current request. With a journal, all blocks the request has changed are committed
+    /// as a single transaction. If the request did not fit into the buffer, its changes are
+    /// discarded instead and false is returned.
+    pub fn end_request(&mut self) -> Result<bool, Error> {
+        if !self.journaled() {
+            return Ok(true);
+        }
+
+        if self.overflow {
+            self.overflow = false;
+            self.discard();
+            return Ok(false);
+        }
+
+        self.flush_journaled()
+    }
+
+    fn discard(&mut self) {
+        log!(
+            crate::LOG_BUFFER,
+            "metabuffer: discarding changes of incomplete request"
+        );
+        for block_ptr in &mut self.blocks {
+            let block = unsafe { &mut (*block_ptr.as_ptr()) };
+            if block.dirty {
+                // forget the block, so that it is loaded from the backend on the next use
+                assert!(block.links == 0);
+                self.ids.remove(&block.bno);
+                block.bno = 0;
+                block.dirty = false;
+            }
+        }
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
+        if self.journaled() {
+            return self.flush_journaled().map(|_| ());
+        }
+
         for block_ptr in &mut self.blocks {
             let block = unsafe { &mut (*block_ptr.as_ptr()) };
             block.flush()?;
         }
         Ok(())
     }
+
+    fn flush_journaled(&mut self) -> Result<bool, Error> {
+        let dirty = self
+            .blocks
+            .iter()
+            .filter(|b| unsafe { (*b.as_ptr()).dirty })
+            .copied()
+            .collect::<Vec<_>>();
+        if dirty.is_empty() {
+            return Ok(true);
+        }
+
+        // write back the file data first, so that committed metadata never refers to stale data
+        crate::file_buffer_mut().write_back()?;
+
+        // the journal can hold the complete buffer (see M3FSRequestHandler::new), so that all
+        // changes always form a single transaction
+        let journal = self.journal.as_mut().unwrap();
+        let blocks = dirty
+            .iter()
+            .map(|b| unsafe { &*b.as_ptr() })
+            .collect::<Vec<_>>();
+        journal.commit(&blocks)?;
+
+        for block_ptr in &dirty {
+            let block = unsafe { &mut (*block_ptr.as_ptr()) };
+            block.flush()?;
+        }
+
+        journal.checkpoint()?;
+        Ok(true)
+    }
 }
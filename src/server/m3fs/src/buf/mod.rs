@@ -16,7 +16,9 @@
  */
 
 mod file_buffer;
+mod journal;
 mod meta_buffer;
 
 pub use file_buffer::{FileBuffer, LoadLimit};
+pub use journal::Journal;
 pub use meta_buffer::{MetaBuffer, MetaBufferBlock, MetaBufferBlockRef, META_BUFFER_SIZE};
@@ -29,6 +29,8 @@ pub struct Allocator {
     total: u32,
     blocks: u32,
     blocksize: usize,
+    // the first block of the reference-count table, if items can be shared
+    refs: Option<u32>,
 }
 
 impl Allocator {
@@ -40,6 +42,7 @@ impl Allocator {
         total: u32,
         blocks: u32,
         blocksize: usize,
+        refs: Option<u32>,
     ) -> Self {
         let alloc = Allocator {
             name,
@@ -49,6 +52,7 @@ impl Allocator {
             total,
             blocks,
             blocksize,
+            refs,
         };
         log!(crate::LOG_ALLOC, "Created {:#?}", alloc);
         alloc
@@ -62,6 +66,20 @@ impl Allocator {
         self.free
     }
 
+    /// Sets the number of free items and the first free item after the bitmap has been changed
+    /// externally (e.g., by a repair)
+    pub fn set_free(&mut self, free: u32, first_free: u32) {
+        log!(
+            crate::LOG_ALLOC,
+            "allocator[{}]::set_free(free={}, first_free={})",
+            self.name,
+            free,
+            first_free
+        );
+        self.free = free;
+        self.first_free = first_free;
+    }
+
     pub fn alloc(&mut self, count: Option<&mut usize>) -> Result<u32, Error> {
         let mut tmp_count = 1;
         let count = count.unwrap_or(&mut tmp_count);
@@ -185,7 +203,87 @@ impl Allocator {
         Ok(start)
     }
 
-    pub fn free(&mut self, mut start: usize, mut count: usize) -> Result<(), Error> {
+    /// Returns true if items can be shared
+    pub fn shareable(&self) -> bool {
+        self.refs.is_some()
+    }
+
+    /// Returns the number of additional references to item `no`
+    pub fn refs(&self, no: usize) -> Result<u8, Error> {
+        match self.refs {
+            Some(first) => {
+                let block =
+                    crate::meta_buffer_mut().get_block(first + (no / self.blocksize) as u32)?;
+                Ok(block.data()[no % self.blocksize])
+            },
+            None => Ok(0),
+        }
+    }
+
+    /// Sets the number of additional references to item `no` to `refs`
+    pub fn set_refs(&mut self, no: usize, refs: u8) -> Result<(), Error> {
+        let first = self.refs.ok_or_else(|| Error::new(Code::NotSup))?;
+        let mut block = crate::meta_buffer_mut().get_block(first + (no / self.blocksize) as u32)?;
+        block.mark_dirty();
+        block.data_mut()[no % self.blocksize] = refs;
+        Ok(())
+    }
+
+    /// Adds a reference to the items `start..start + count`, which need to be allocated
+    ///
+    /// Fails with [`Code::NoSpace`] if one of the items has the maximum number of references.
+    pub fn share(&mut self, start: usize, count: usize) -> Result<(), Error> {
+        log!(
+            crate::LOG_ALLOC,
+            "allocator[{}]::share(start={}, count={})",
+            self.name,
+            start,
+            count
+        );
+
+        for no in start..start + count {
+            if self.refs(no)? == u8::MAX {
+                return Err(Error::new(Code::NoSpace));
+            }
+        }
+        for no in start..start + count {
+            let refs = self.refs(no)?;
+            self.set_refs(no, refs + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Drops a reference to the items `start..start + count` and frees all items that are not
+    /// referenced anymore.
+    ///
+    /// Returns the number of freed items.
+    pub fn free(&mut self, start: usize, count: usize) -> Result<usize, Error> {
+        if self.refs.is_none() {
+            self.free_range(start, count)?;
+            return Ok(count);
+        }
+
+        // free the unshared items in runs and drop a reference to the others
+        let mut freed = 0;
+        let mut run = start;
+        for no in start..start + count {
+            let refs = self.refs(no)?;
+            if refs > 0 {
+                self.free_range(run, no - run)?;
+                freed += no - run;
+                self.set_refs(no, refs - 1)?;
+                run = no + 1;
+            }
+        }
+        self.free_range(run, start + count - run)?;
+        Ok(freed + start + count - run)
+    }
+
+    fn free_range(&mut self, mut start: usize, mut count: usize) -> Result<(), Error> {
+        if count == 0 {
+            return Ok(());
+        }
+
         log!(
             crate::LOG_ALLOC,
             "allocator[{}]::free(start={}, count={})",
@@ -27,6 +27,9 @@ use m3::math;
 use m3::mem::size_of;
 
 /// On-disk representation of directory entries.
+///
+/// Entries with an empty name are unused and only denote free space. They are left behind if the
+/// only entry of a block is removed and are used for empty buckets in hashed directories.
 #[repr(align(4), C)]
 pub struct DirEntry {
     pub nodeno: InodeNo,
@@ -77,6 +80,24 @@ impl DirEntry {
         }
     }
 
+    /// Returns the hash of the given name, which determines its bucket in hashed directories
+    pub fn hash(name: &str) -> u32 {
+        // FNV-1a
+        name.bytes()
+            .fold(0x811c_9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x0100_0193))
+    }
+
+    /// Returns true if this entry is unused
+    pub fn is_empty(&self) -> bool {
+        self.name_length == 0
+    }
+
+    /// Turns this entry into an unused entry that spans the same space
+    pub fn clear(&mut self) {
+        self.nodeno = 0;
+        self.name_length = 0;
+    }
+
     /// Returns the size of this entry when stored on disk. Includes the static size of the struct
     /// as well as the str. buffer size.
     pub fn size(&self) -> usize {
@@ -48,6 +48,9 @@ impl fmt::Debug for ExtPos {
 }
 
 /// Represents an extent as stored on disk
+///
+/// Extents that start at block 0 (the superblock) are holes in sparse files. They do not occupy any
+/// blocks, but cover `length` blocks of the file that read as zeros.
 #[derive(Clone, Copy, Debug)]
 #[repr(C, align(8))]
 pub struct Extent {
@@ -60,6 +63,16 @@ impl Extent {
         Self { start, length }
     }
 
+    /// Creates a hole of `length` blocks
+    pub fn hole(length: u32) -> Self {
+        Self::new(0, length)
+    }
+
+    /// Returns true if this extent is a hole
+    pub fn is_hole(&self) -> bool {
+        self.start == 0 && self.length > 0
+    }
+
     pub fn block_range(&self) -> core::ops::Range<BlockNo> {
         core::ops::Range {
             start: self.start,
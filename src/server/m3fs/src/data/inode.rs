@@ -16,7 +16,8 @@
 
 use crate::buf::MetaBufferBlockRef;
 use crate::data::{
-    BlockNo, Dev, Extent, ExtentCache, ExtentRef, InodeNo, Time, INODE_DIR_COUNT, NUM_INODE_BYTES,
+    BlockNo, Dev, Extent, ExtentCache, ExtentRef, GroupId, InodeNo, Time, UserId, INODE_DIR_COUNT,
+    NUM_INODE_BYTES,
 };
 use crate::ops::inodes;
 
@@ -46,6 +47,14 @@ pub struct INode {
     pub direct: [Extent; INODE_DIR_COUNT], // direct entries
     pub indirect: BlockNo,                 // location of the indirect block if != 0,
     pub dindirect: BlockNo,                // location of double indirect block if != 0
+
+    pub uid: UserId,
+    pub gid: GroupId,
+    pub xattr: BlockNo, // location of the extended-attribute block if != 0
+    // the quota that is charged for this inode and the mount the quota belongs to
+    pub quota_mount: u32,
+    pub quota: u32,
+    _reserved: [u32; 11],
 }
 
 impl Clone for INode {
@@ -67,6 +76,13 @@ impl Clone for INode {
             direct: self.direct,
             indirect: self.indirect,
             dindirect: self.dindirect,
+
+            uid: self.uid,
+            gid: self.gid,
+            xattr: self.xattr,
+            quota_mount: self.quota_mount,
+            quota: self.quota,
+            _reserved: [0; 11],
         }
     }
 }
@@ -88,6 +104,12 @@ impl INode {
         }; INODE_DIR_COUNT];
         self.indirect = 0;
         self.dindirect = 0;
+
+        self.uid = 0;
+        self.gid = 0;
+        self.xattr = 0;
+        self.quota_mount = 0;
+        self.quota = 0;
     }
 
     pub fn to_file_info(&self) -> FileInfo {
@@ -95,6 +117,8 @@ impl INode {
             devno: self.devno,
             inode: self.inode,
             mode: self.mode,
+            uid: self.uid,
+            gid: self.gid,
             links: self.links as u32,
             size: self.size as usize,
             lastaccess: self.lastaccess,
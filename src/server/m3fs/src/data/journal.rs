@@ -0,0 +1,110 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use crate::data::BlockNo;
+
+use m3::mem::size_of;
+
+pub const JOURNAL_MAGIC: u32 = 0x4D33_4A4E;
+
+/// The different kinds of journal-internal blocks
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum JournalBlockKind {
+    /// The first block of the journal, denoting the sequence number of the next transaction
+    Header     = 1,
+    /// The first block of a transaction, listing the block numbers of the journaled blocks
+    Descriptor = 2,
+    /// The last block of a transaction, marking the transaction as complete
+    Commit     = 3,
+}
+
+/// The on-disk header of all journal-internal blocks (header, descriptors and commits)
+///
+/// Descriptors are followed by `count` block numbers in the same block.
+#[derive(Debug)]
+#[repr(C)]
+pub struct JournalHeader {
+    pub magic: u32,
+    pub kind: u32,
+    pub seq: u32,
+    pub count: u32,
+    pub checksum: u32,
+}
+
+impl JournalHeader {
+    /// Returns a reference to the journal header at the beginning of the given block
+    pub fn from_buffer(block_data: &[u8]) -> &Self {
+        assert!(block_data.len() >= size_of::<Self>());
+        // safety: the block is large enough and the header consists of u32 fields only
+        unsafe { &*(block_data.as_ptr() as *const Self) }
+    }
+
+    /// Returns a mutable reference to the journal header at the beginning of the given block
+    pub fn from_buffer_mut(block_data: &mut [u8]) -> &mut Self {
+        assert!(block_data.len() >= size_of::<Self>());
+        // safety: see above
+        unsafe { &mut *(block_data.as_mut_ptr() as *mut Self) }
+    }
+
+    /// Returns true if this header is valid and of the given kind and sequence number
+    pub fn is(&self, kind: JournalBlockKind, seq: u32) -> bool {
+        self.magic == JOURNAL_MAGIC && self.kind == kind as u32 && self.seq == seq
+    }
+
+    /// Initializes this header with given values
+    pub fn init(&mut self, kind: JournalBlockKind, seq: u32, count: u32, checksum: u32) {
+        self.magic = JOURNAL_MAGIC;
+        self.kind = kind as u32;
+        self.seq = seq;
+        self.count = count;
+        self.checksum = checksum;
+    }
+
+    /// Returns the maximum number of block numbers a descriptor can hold
+    pub fn max_desc_entries(block_size: usize) -> usize {
+        (block_size - size_of::<Self>()) / size_of::<BlockNo>()
+    }
+
+    /// Returns the slice of block numbers behind the descriptor in the given block
+    pub fn desc_entries(block_data: &[u8], count: usize) -> &[BlockNo] {
+        assert!(count <= Self::max_desc_entries(block_data.len()));
+        // safety: the block is large enough for `count` entries, which are u32's
+        unsafe {
+            let start = block_data.as_ptr().add(size_of::<Self>()) as *const BlockNo;
+            core::slice::from_raw_parts(start, count)
+        }
+    }
+
+    /// Returns the mutable slice of block numbers behind the descriptor in the given block
+    pub fn desc_entries_mut(block_data: &mut [u8], count: usize) -> &mut [BlockNo] {
+        assert!(count <= Self::max_desc_entries(block_data.len()));
+        // safety: see above
+        unsafe {
+            let start = block_data.as_mut_ptr().add(size_of::<Self>()) as *mut BlockNo;
+            core::slice::from_raw_parts_mut(start, count)
+        }
+    }
+
+    /// Calculates the checksum for a transaction with given sequence number and blocks
+    pub fn checksum(seq: u32, blocks: &[BlockNo]) -> u32 {
+        blocks
+            .iter()
+            .enumerate()
+            .fold(1 + seq.wrapping_mul(3), |sum, (i, b)| {
+                sum.wrapping_add(b.wrapping_mul(i as u32 * 2 + 5))
+            })
+    }
+}
@@ -19,22 +19,29 @@ mod bitmap;
 mod direntry;
 mod extent;
 mod inode;
+mod journal;
 mod superblock;
+mod xattr;
 
 pub use allocator::Allocator;
+pub use bitmap::Bitmap;
 pub use direntry::{DirEntry, DirEntryIterator};
 pub use extent::{ExtPos, Extent, ExtentCache, ExtentRef};
 pub use inode::INodeRef;
+pub use journal::{JournalBlockKind, JournalHeader};
 pub use superblock::SuperBlock;
+pub use xattr::{write_xattrs, XAttrIterator, MAX_XATTR_LEN};
 
 pub type BlockNo = m3::session::BlockNo;
 pub type BlockRange = m3::session::BlockRange;
 pub type Dev = u8;
 pub type InodeNo = u32;
 pub type Time = u32;
+pub type UserId = m3::vfs::UserId;
+pub type GroupId = m3::vfs::GroupId;
 
 pub const INODE_DIR_COUNT: usize = 3;
 pub const MAX_BLOCK_SIZE: u32 = 4096;
-pub const NUM_INODE_BYTES: usize = 64;
+pub const NUM_INODE_BYTES: usize = 128;
 pub const NUM_EXT_BYTES: usize = 8;
 pub const DIR_ENTRY_LEN: usize = 12;
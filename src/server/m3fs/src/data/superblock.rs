@@ -28,9 +28,18 @@ pub struct SuperBlock {
     pub first_free_inode: u32,
     pub first_free_block: u32,
     pub checksum: u32,
+    pub journal_blocks: u32,
+    pub features: u32,
+    // the number of times the file system has been mounted, which identifies the quotas of a mount
+    pub mounts: u32,
 }
 
 impl SuperBlock {
+    /// Directories consist of 2^n blocks, each holding the entries whose names hash to it
+    pub const FEAT_HASHED_DIRS: u32 = 1;
+    /// Data blocks can be shared between files, which is tracked in a reference-count table
+    pub const FEAT_REFLINK: u32 = 2;
+
     pub fn get_checksum(&self) -> u32 {
         1 + self.block_size * 2
             + self.total_inodes * 3
@@ -39,6 +48,18 @@ impl SuperBlock {
             + self.free_blocks * 11
             + self.first_free_inode * 13
             + self.first_free_block * 17
+            + self.journal_blocks * 19
+            + self.features * 23
+    }
+
+    /// Returns true if directories are hashed
+    pub fn hashed_dirs(&self) -> bool {
+        (self.features & Self::FEAT_HASHED_DIRS) != 0
+    }
+
+    /// Returns true if data blocks can be shared between files
+    pub fn reflink(&self) -> bool {
+        (self.features & Self::FEAT_REFLINK) != 0
     }
 
     pub fn first_inodebm_block(&self) -> BlockNo {
@@ -61,6 +82,32 @@ impl SuperBlock {
         self.first_blockbm_block() + self.blockbm_blocks()
     }
 
+    pub fn inode_blocks(&self) -> BlockNo {
+        (self.total_inodes * NUM_INODE_BYTES as u32 + self.block_size - 1) / self.block_size
+    }
+
+    pub fn first_journal_block(&self) -> BlockNo {
+        self.first_inode_block() + self.inode_blocks()
+    }
+
+    pub fn first_refcnt_block(&self) -> BlockNo {
+        self.first_journal_block() + self.journal_blocks
+    }
+
+    /// The reference-count table holds one byte per block with the number of additional references
+    pub fn refcnt_blocks(&self) -> BlockNo {
+        if self.reflink() {
+            (self.total_blocks + self.block_size - 1) / self.block_size
+        }
+        else {
+            0
+        }
+    }
+
+    pub fn first_data_block(&self) -> BlockNo {
+        self.first_refcnt_block() + self.refcnt_blocks()
+    }
+
     pub fn extents_per_block(&self) -> usize {
         self.block_size as usize / NUM_EXT_BYTES
     }
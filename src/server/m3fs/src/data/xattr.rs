@@ -0,0 +1,87 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use m3::errors::{Code, Error};
+
+/// The size of the header of an extended-attribute entry (name and value length)
+pub const XATTR_ENTRY_LEN: usize = 2;
+/// The maximum length of the name and the value of an extended attribute
+pub const MAX_XATTR_LEN: usize = u8::MAX as usize;
+
+/// Iterates over the extended attributes stored in a block
+///
+/// Each entry consists of the length of the name and the value (one byte each), followed by the
+/// name and the value. The list is terminated by an entry with an empty name or the end of the
+/// block. The iterator stops at entries that exceed the block, in case the image is corrupt.
+pub struct XAttrIterator<'e> {
+    block_data: &'e [u8],
+    off: usize,
+}
+
+impl<'e> XAttrIterator<'e> {
+    pub fn from_block(block_data: &'e [u8]) -> Self {
+        XAttrIterator { block_data, off: 0 }
+    }
+}
+
+impl<'e> Iterator for XAttrIterator<'e> {
+    type Item = (&'e [u8], &'e [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.block_data.get(self.off..)?;
+        if data.len() < XATTR_ENTRY_LEN || data[0] == 0 {
+            return None;
+        }
+
+        let (nlen, vlen) = (data[0] as usize, data[1] as usize);
+        let end = XATTR_ENTRY_LEN + nlen + vlen;
+        if end > data.len() {
+            return None;
+        }
+
+        self.off += end;
+        let name = &data[XATTR_ENTRY_LEN..XATTR_ENTRY_LEN + nlen];
+        Some((name, &data[XATTR_ENTRY_LEN + nlen..end]))
+    }
+}
+
+/// Writes the given extended attributes into `block_data` and zeros the rest of the block
+///
+/// Fails with [`Code::NoSpace`] if the attributes do not fit into the block.
+pub fn write_xattrs<'a, I>(block_data: &mut [u8], attrs: I) -> Result<(), Error>
+where
+    I: Iterator<Item = (&'a [u8], &'a [u8])>,
+{
+    let mut off = 0;
+    for (name, value) in attrs {
+        assert!(!name.is_empty() && name.len() <= MAX_XATTR_LEN && value.len() <= MAX_XATTR_LEN);
+        let end = off + XATTR_ENTRY_LEN + name.len() + value.len();
+        if end > block_data.len() {
+            return Err(Error::new(Code::NoSpace));
+        }
+
+        block_data[off] = name.len() as u8;
+        block_data[off + 1] = value.len() as u8;
+        let data_off = off + XATTR_ENTRY_LEN;
+        block_data[data_off..data_off + name.len()].copy_from_slice(name);
+        block_data[data_off + name.len()..end].copy_from_slice(value);
+        off = end;
+    }
+
+    for b in &mut block_data[off..] {
+        *b = 0;
+    }
+    Ok(())
+}
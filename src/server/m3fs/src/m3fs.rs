@@ -19,6 +19,15 @@
 #[macro_use]
 extern crate m3;
 
+/// Commits the changes of the current request (see [`end_request`]) and replies the given values
+/// afterwards, so that clients are only told about changes that have been committed
+macro_rules! commit_and_reply {
+    ( $is:expr, $( $args:expr ),* ) => ({
+        crate::end_request()?;
+        reply_vmsg!($is, $( $args ),*)
+    });
+}
+
 mod backend;
 mod buf;
 mod data;
@@ -26,8 +35,12 @@ mod ops;
 mod sess;
 
 use crate::backend::{Backend, DiskBackend, MemBackend};
-use crate::buf::{FileBuffer, MetaBuffer};
-use crate::data::{Allocator, SuperBlock};
+use crate::buf::{FileBuffer, Journal, MetaBuffer, META_BUFFER_SIZE};
+use crate::data::{Allocator, Bitmap, BlockNo, SuperBlock};
+use crate::ops::check;
+use crate::ops::perms::Creds;
+use crate::ops::quota::Quota;
+use crate::ops::sparse::Zeros;
 use crate::sess::{FSSession, M3FSSession, MetaSession, OpenFiles};
 
 use base::cell::LazyStaticUnsafeCell;
@@ -44,6 +57,7 @@ use m3::{
         server_loop, CapExchange, Handler, RequestHandler, Server, SessId, SessionContainer,
         DEF_MAX_CLIENTS,
     },
+    session::ServerSession,
     tcu::Label,
     tiles::Activity,
     vfs::{FSOperation, GenFileOp},
@@ -58,10 +72,16 @@ pub const LOG_DIRS: bool = false;
 pub const LOG_INODES: bool = false;
 pub const LOG_LINKS: bool = false;
 pub const LOG_FIND: bool = false;
+pub const LOG_JOURNAL: bool = false;
+pub const LOG_PERMS: bool = false;
+pub const LOG_XATTRS: bool = false;
+pub const LOG_CHECK: bool = false;
+pub const LOG_REFLINK: bool = false;
 
 // Server constants
 const FS_IMG_OFFSET: goff = 0;
-const MSG_SIZE: usize = 128;
+// large enough for SETXATTR requests, which carry a path, a name, and a value
+const MSG_SIZE: usize = 512;
 
 // The global request handler
 static REQHDL: LazyReadOnlyCell<RequestHandler> = LazyReadOnlyCell::default();
@@ -76,6 +96,7 @@ static BA: LazyStaticRefCell<Allocator> = LazyStaticRefCell::default();
 static IA: LazyStaticRefCell<Allocator> = LazyStaticRefCell::default();
 static SETTINGS: LazyReadOnlyCell<FsSettings> = LazyReadOnlyCell::default();
 static BACKEND: LazyStaticRefCell<Box<dyn Backend>> = LazyStaticRefCell::default();
+static ZEROS: LazyStaticRefCell<Zeros> = LazyStaticRefCell::default();
 
 fn superblock() -> Ref<'static, SuperBlock> {
     SB.borrow()
@@ -105,6 +126,9 @@ fn settings() -> &'static FsSettings {
 fn backend_mut() -> RefMut<'static, Box<dyn Backend>> {
     BACKEND.borrow_mut()
 }
+fn zeros() -> Ref<'static, Zeros> {
+    ZEROS.borrow()
+}
 
 fn flush_buffer() -> Result<(), Error> {
     crate::meta_buffer_mut().flush()?;
@@ -120,6 +144,53 @@ fn flush_buffer() -> Result<(), Error> {
     crate::backend_mut().store_sb(&*sb)
 }
 
+/// Ends the current request by committing its metadata changes (see [`MetaBuffer::end_request`]).
+/// If the changes had to be discarded, the request fails with [`Code::NoSpace`].
+fn end_request() -> Result<(), Error> {
+    if crate::meta_buffer_mut().end_request()? {
+        ops::quota::commit();
+    }
+    else {
+        // the charges and releases of the request have been discarded as well
+        ops::quota::rollback();
+
+        // the allocators might have used the discarded bitmap blocks; rebuild them from the disk
+        let sb = crate::superblock();
+        let backend = crate::backend_mut();
+        let bs = sb.block_size as usize;
+        let (free, first) = count_free(&**backend, sb.first_inodebm_block(), sb.total_inodes, bs)?;
+        crate::inodes_mut().set_free(free, first);
+        let (free, first) = count_free(&**backend, sb.first_blockbm_block(), sb.total_blocks, bs)?;
+        crate::blocks_mut().set_free(free, first);
+        return Err(Error::new(Code::NoSpace));
+    }
+    Ok(())
+}
+
+/// Determines the number of free bits and the first free bit of the bitmap starting at `first_bno`
+fn count_free(
+    backend: &dyn Backend,
+    first_bno: BlockNo,
+    total: u32,
+    block_size: usize,
+) -> Result<(u32, u32), Error> {
+    let mut buf = vec![0u8; block_size];
+    let mut free = 0;
+    let mut first_free = None;
+    let bits_per_block = (block_size * 8) as u32;
+    for (i, start) in (0..total).step_by(bits_per_block as usize).enumerate() {
+        backend.read_block(&mut buf, first_bno + i as BlockNo)?;
+        let bitmap = Bitmap::from_bytes(&mut buf);
+        for bit in 0..bits_per_block.min(total - start) {
+            if !bitmap.is_bit_set(bit as usize) {
+                free += 1;
+                first_free.get_or_insert(start + bit);
+            }
+        }
+    }
+    Ok((free, first_free.unwrap_or(total)))
+}
+
 int_enum! {
     pub struct M3FSOperation : u64 {
         const STAT          = GenFileOp::STAT.val;
@@ -136,6 +207,7 @@ int_enum! {
         const SET_DEST      = GenFileOp::SET_DEST.val;
         const ENABLE_NOTIFY = GenFileOp::ENABLE_NOTIFY.val;
         const REQ_NOTIFY    = GenFileOp::REQ_NOTIFY.val;
+        const FALLOCATE     = GenFileOp::FALLOCATE.val;
         const OPEN          = FSOperation::OPEN.val;
         const FSTAT         = FSOperation::STAT.val;
         const MKDIR         = FSOperation::MKDIR.val;
@@ -147,6 +219,20 @@ int_enum! {
         const GET_SGATE     = FSOperation::GET_SGATE.val;
         const DEL_EP        = FSOperation::DEL_EP.val;
         const OPEN_PRIV     = FSOperation::OPEN_PRIV.val;
+        const SYMLINK       = FSOperation::SYMLINK.val;
+        const READLINK      = FSOperation::READLINK.val;
+        const CHMOD         = FSOperation::CHMOD.val;
+        const CHOWN         = FSOperation::CHOWN.val;
+        const UTIME         = FSOperation::UTIME.val;
+        const DERIVE        = FSOperation::DERIVE.val;
+        const CHROOT        = FSOperation::CHROOT.val;
+        const GET_QUOTA     = FSOperation::GET_QUOTA.val;
+        const GETXATTR      = FSOperation::GETXATTR.val;
+        const SETXATTR      = FSOperation::SETXATTR.val;
+        const LISTXATTR     = FSOperation::LISTXATTR.val;
+        const REMOVEXATTR   = FSOperation::REMOVEXATTR.val;
+        const CHECK         = FSOperation::CHECK.val;
+        const REFLINK       = FSOperation::REFLINK.val;
     }
 }
 
@@ -160,9 +246,52 @@ impl M3FSRequestHandler {
         // init thread manager, otherwise the waiting within the file and meta buffer impl. panics.
         thread::init();
 
-        let sb = backend.load_sb().expect("Unable to load super block");
+        let mut sb = backend.load_sb().expect("Unable to load super block");
         log!(crate::LOG_DEF, "Loaded {:#?}", sb);
 
+        // journaling is only worth it if the metadata survives a restart
+        let journal = if sb.journal_blocks > 0 && settings().backend == "disk" {
+            // replay the last transaction, if necessary, before we use any metadata
+            let journal = Journal::new(&*backend, &sb)?;
+
+            // all changes of a request are committed as one transaction, which therefore needs to
+            // be able to hold all blocks of the meta buffer
+            if journal.capacity() < META_BUFFER_SIZE {
+                log!(
+                    crate::LOG_DEF,
+                    "Journal holds {} blocks, but needs {}",
+                    journal.capacity(),
+                    META_BUFFER_SIZE
+                );
+                return Err(Error::new(Code::InvArgs));
+            }
+
+            // the counters in the superblock are not journaled; determine them from the bitmaps
+            let (free, first) = count_free(
+                &*backend,
+                sb.first_inodebm_block(),
+                sb.total_inodes,
+                sb.block_size as usize,
+            )?;
+            sb.update_inodebm(free, first);
+            let (free, first) = count_free(
+                &*backend,
+                sb.first_blockbm_block(),
+                sb.total_blocks,
+                sb.block_size as usize,
+            )?;
+            sb.update_blockbm(free, first);
+            Some(journal)
+        }
+        else {
+            None
+        };
+
+        // identify the quotas of this mount (see ops::quota::owner)
+        sb.mounts = sb.mounts.wrapping_add(1);
+        sb.checksum = sb.get_checksum();
+        backend.store_sb(&sb)?;
+
         BA.set(Allocator::new(
             String::from("Block"),
             sb.first_blockbm_block(),
@@ -171,6 +300,12 @@ impl M3FSRequestHandler {
             sb.total_blocks,
             sb.blockbm_blocks(),
             sb.block_size as usize,
+            if sb.reflink() {
+                Some(sb.first_refcnt_block())
+            }
+            else {
+                None
+            },
         ));
         IA.set(Allocator::new(
             String::from("INodes"),
@@ -180,13 +315,15 @@ impl M3FSRequestHandler {
             sb.total_inodes,
             sb.inodebm_block(),
             sb.block_size as usize,
+            None,
         ));
 
         // safety: we pass in a newly constructed MetaBuffer and have not initialized MB before
         unsafe {
-            MB.set(MetaBuffer::new(sb.block_size as usize));
+            MB.set(MetaBuffer::new(sb.block_size as usize, journal));
         }
         FB.set(FileBuffer::new(sb.block_size as usize));
+        ZEROS.set(Zeros::new(sb.block_size as usize)?);
         SB.set(sb);
 
         BACKEND.set(backend);
@@ -207,13 +344,14 @@ impl M3FSRequestHandler {
             M3FSOperation::NEXT_OUT => self.exec_on_sess(input, |sess, is| sess.next_out(is)),
             M3FSOperation::COMMIT => self.exec_on_sess(input, |sess, is| sess.commit(is)),
             M3FSOperation::TRUNCATE => self.exec_on_sess(input, |sess, is| sess.truncate(is)),
+            M3FSOperation::FALLOCATE => self.exec_on_sess(input, |sess, is| sess.fallocate(is)),
             M3FSOperation::CLOSE => match self.exec_on_sess(input, |sess, is| sess.close(is)) {
-                Ok(true) => {
+                Ok(true) => crate::end_request().and_then(|_| {
                     // get session id, then notify caller that we closed, finally close self
                     let sid = input.label() as SessId;
                     input.reply_error(Code::None).ok();
                     self.close_session(sid, input.rgate())
-                },
+                }),
                 Ok(false) => Ok(()),
                 Err(e) => Err(e),
             },
@@ -228,12 +366,37 @@ impl M3FSRequestHandler {
             M3FSOperation::RENAME => self.exec_on_sess(input, |sess, is| sess.rename(is)),
             M3FSOperation::SYNC => self.exec_on_sess(input, |sess, is| sess.sync(is)),
             M3FSOperation::OPEN_PRIV => self.exec_on_sess(input, |sess, is| sess.open_priv(is)),
+            M3FSOperation::SYMLINK => self.exec_on_sess(input, |sess, is| sess.symlink(is)),
+            M3FSOperation::READLINK => self.exec_on_sess(input, |sess, is| sess.readlink(is)),
+            M3FSOperation::CHMOD => self.exec_on_sess(input, |sess, is| sess.chmod(is)),
+            M3FSOperation::CHOWN => self.exec_on_sess(input, |sess, is| sess.chown(is)),
+            M3FSOperation::UTIME => self.exec_on_sess(input, |sess, is| sess.utime(is)),
+            M3FSOperation::CHROOT => self.exec_on_sess(input, |sess, is| sess.chroot(is)),
+            M3FSOperation::GET_QUOTA => self.exec_on_sess(input, |sess, is| sess.get_quota(is)),
+            M3FSOperation::GETXATTR => self.exec_on_sess(input, |sess, is| sess.getxattr(is)),
+            M3FSOperation::SETXATTR => self.exec_on_sess(input, |sess, is| sess.setxattr(is)),
+            M3FSOperation::LISTXATTR => self.exec_on_sess(input, |sess, is| sess.listxattr(is)),
+            M3FSOperation::REMOVEXATTR => self.exec_on_sess(input, |sess, is| sess.removexattr(is)),
+            M3FSOperation::CHECK => self.check(input),
+            M3FSOperation::REFLINK => self.exec_on_sess(input, |sess, is| sess.reflink(is)),
             _ => Err(Error::new(Code::InvArgs)),
         };
 
+        // successful requests have been committed before the reply; this commits the changes of
+        // failed requests and the changes after the reply (e.g., on close)
+        let commit = crate::end_request();
         if let Err(ref e) = res {
             input.reply_error(e.code()).ok();
         }
+        else if let Err(ref e) = commit {
+            log!(
+                LOG_DEF,
+                "[{}] fs::handle(op={}): unable to commit: {:?}",
+                input.label(),
+                op,
+                e.code()
+            );
+        }
 
         log!(
             LOG_DEF,
@@ -245,6 +408,57 @@ impl M3FSRequestHandler {
         Ok(())
     }
 
+    fn check(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let sid = is.label() as SessId;
+        let repair: bool = is.pop()?;
+
+        match self.sessions.get(sid) {
+            Some(FSSession::Meta(meta)) => {
+                if !meta.creds().is_root() {
+                    return Err(Error::new(Code::NoPerm));
+                }
+                if repair && meta.readonly() {
+                    return Err(Error::new(Code::ReadOnly));
+                }
+            },
+            _ => return Err(Error::new(Code::InvArgs)),
+        }
+
+        // the extents that have been allocated for appends are not part of the inodes yet
+        let mut pending = Vec::new();
+        self.sessions.for_each(|s| {
+            if let FSSession::File(f) = s {
+                if let Some(ext) = f.append_extent() {
+                    pending.push((f.ino(), ext));
+                }
+            }
+        });
+
+        let report = check::check(&pending, repair)?;
+        if repair {
+            crate::flush_buffer()?;
+        }
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] fs::check(repair={}) -> {:?}",
+            sid,
+            repair,
+            report
+        );
+
+        commit_and_reply!(
+            is,
+            Code::None as u32,
+            report.leaked_blocks,
+            report.leaked_inodes,
+            report.unmarked_blocks,
+            report.unmarked_inodes,
+            report.crosslinked_blocks,
+            report.invalid_refs
+        )
+    }
+
     fn exec_on_sess<F, R>(&mut self, is: &mut GateIStream<'_>, function: F) -> Result<R, Error>
     where
         F: Fn(&mut FSSession, &mut GateIStream<'_>) -> Result<R, Error>,
@@ -327,25 +541,62 @@ impl Handler<FSSession> for M3FSRequestHandler {
         srv_sel: Selector,
         arg: &str,
     ) -> Result<(Selector, SessId), Error> {
-        // get max number of files
+        // get max number of files, the credentials (root by default), the access restrictions, and
+        // the quota (unlimited by default)
         let mut max_files: usize = 16;
-        if arg.len() > 6 && &arg[..6] == "files=" {
-            max_files = arg[6..].parse().map_err(|_| Error::new(Code::InvArgs))?;
+        let mut creds = Creds::default();
+        let mut readonly = false;
+        let mut root = None;
+        let mut max_blocks = Quota::UNLIMITED;
+        let mut max_inodes = Quota::UNLIMITED;
+        for a in arg.split_whitespace() {
+            if let Some(files) = a.strip_prefix("files=") {
+                max_files = files.parse().map_err(|_| Error::new(Code::InvArgs))?;
+            }
+            else if let Some(blocks) = a.strip_prefix("blocks=") {
+                max_blocks = blocks.parse().map_err(|_| Error::new(Code::InvArgs))?;
+            }
+            else if let Some(inodes) = a.strip_prefix("inodes=") {
+                max_inodes = inodes.parse().map_err(|_| Error::new(Code::InvArgs))?;
+            }
+            else if let Some(uid) = a.strip_prefix("uid=") {
+                creds.uid = uid.parse().map_err(|_| Error::new(Code::InvArgs))?;
+            }
+            else if let Some(gid) = a.strip_prefix("gid=") {
+                creds.gid = gid.parse().map_err(|_| Error::new(Code::InvArgs))?;
+            }
+            else if let Some(ro) = a.strip_prefix("readonly=") {
+                readonly = m3::parse::bool(ro)?;
+            }
+            else if let Some(path) = a.strip_prefix("root=") {
+                root = Some(path);
+            }
+            else {
+                return Err(Error::new(Code::InvArgs));
+            }
+        }
+
+        // the root directory needs to be accessible with the given credentials
+        if let Some(path) = root {
+            creds.root_dir = ops::dirs::search_dir(path, &creds)?;
         }
 
         // get the id this session would belong to.
         let sessid = self.sessions.next_id()?;
 
         self.sessions.add_next(crt, srv_sel, true, |sess| {
+            creds.quota = Quota::new(None, max_blocks, max_inodes);
             log!(
                 crate::LOG_SESSION,
-                "[{}] creating session(crt={}, max_files={})",
+                "[{}] creating session(crt={}, max_files={}, creds={:?}, readonly={})",
                 sess.ident(),
                 crt,
-                max_files
+                max_files,
+                creds,
+                readonly
             );
             Ok(FSSession::Meta(MetaSession::new(
-                sess, sessid, crt, max_files,
+                sess, sessid, crt, max_files, creds, readonly,
             )))
         })
     }
@@ -374,9 +625,57 @@ impl Handler<FSSession> for M3FSRequestHandler {
                     let file_session =
                         meta.open_file(sel, crt, data, next_sess_id, REQHDL.get().recv_gate())?;
 
+                    // the file might have been created or truncated; commit that before the
+                    // client gets the session
+                    crate::end_request()?;
+
                     self.sessions
                         .add(crt, next_sess_id, FSSession::File(file_session))
                 },
+                M3FSOperation::DERIVE => {
+                    let args = data.in_args();
+                    let set_creds: bool = args.pop()?;
+                    let uid = args.pop()?;
+                    let gid = args.pop()?;
+                    let readonly = meta.readonly() || args.pop::<bool>()?;
+                    let max_blocks = args.pop()?;
+                    let max_inodes = args.pop()?;
+
+                    // the derived session starts at the same root; only root can hand out
+                    // sessions with different credentials
+                    let mut creds = meta.creds().clone();
+                    if set_creds && (uid != creds.uid || gid != creds.gid) {
+                        if !creds.is_root() {
+                            return Err(Error::new(Code::NoPerm));
+                        }
+                        creds.uid = uid;
+                        creds.gid = gid;
+                    }
+
+                    log!(
+                        crate::LOG_SESSION,
+                        "[{}] meta::derive(creds={:?}, readonly={}) -> sid={}",
+                        sid,
+                        creds,
+                        readonly,
+                        next_sess_id
+                    );
+
+                    let sess = ServerSession::new(sel, crt, next_sess_id as u64, true)?;
+                    data.out_caps(m3::kif::CapRngDesc::new(
+                        m3::kif::CapType::OBJECT,
+                        sess.sel(),
+                        1,
+                    ));
+
+                    // the derived session has its own quota, but is still bound to ours
+                    creds.quota = Quota::new(Some(creds.quota.clone()), max_blocks, max_inodes);
+
+                    let max_files = meta.max_files();
+                    let nmeta =
+                        MetaSession::new(sess, next_sess_id, crt, max_files, creds, readonly);
+                    self.sessions.add(crt, next_sess_id, FSSession::Meta(nmeta))
+                },
                 _ => Err(Error::new(Code::InvArgs)),
             },
 
@@ -467,6 +766,7 @@ pub struct FsSettings {
     max_load: usize,
     max_clients: usize,
     clear: bool,
+    check: bool,
     selector: Option<Selector>,
     fs_offset: goff,
 }
@@ -481,6 +781,7 @@ impl core::default::Default for FsSettings {
             max_load: 128,
             max_clients: DEF_MAX_CLIENTS,
             clear: false,
+            check: false,
             selector: None,
             fs_offset: FS_IMG_OFFSET,
         }
@@ -489,7 +790,7 @@ impl core::default::Default for FsSettings {
 
 fn usage() -> ! {
     println!(
-        "Usage: {} [-n <name>] [-s <sel>] [-e <blocks>] [-c] [-b <blocks>]",
+        "Usage: {} [-n <name>] [-s <sel>] [-e <blocks>] [-c] [-f] [-b <blocks>]",
         env::args().next().unwrap()
     );
     println!("       [-o <offset>] [-m <clients>] (disk|mem <fssize>)");
@@ -498,6 +799,7 @@ fn usage() -> ! {
     println!("  -s: don't create service, use selectors <sel>..<sel+1>");
     println!("  -e: the number of blocks to extend files when appending");
     println!("  -c: clear allocated blocks");
+    println!("  -f: check the file system and repair the bitmaps at startup");
     println!("  -b: the maximum number of blocks loaded from the disk");
     println!("  -o: the file system offset in DRAM");
     println!("  -m: the maximum number of clients (receive slots)");
@@ -541,6 +843,10 @@ fn parse_args() -> Result<FsSettings, String> {
                 settings.clear = true;
                 i -= 1; // argument has no value
             },
+            "-f" => {
+                settings.check = true;
+                i -= 1; // argument has no value
+            },
             _ => break,
         }
         // move forward 2 by default, since most arguments have a value
@@ -585,6 +891,14 @@ pub fn main() -> i32 {
             .expect("Failed to create m3fs handler based on disk backend")
     };
 
+    if SETTINGS.get().check {
+        let report = check::check(&[], true).expect("File system check failed");
+        if !report.is_clean() {
+            println!("m3fs: repaired inconsistent file system: {:?}", report);
+        }
+        crate::flush_buffer().expect("Unable to write back repaired file system");
+    }
+
     // create new server for file system and pass on selector to handler
     let serv =
         Server::new(&SETTINGS.get().name, &mut hdl).expect("Could not create service 'm3fs'");
@@ -599,6 +913,8 @@ pub fn main() -> i32 {
     server_loop(|| {
         // handle message that is given to the server
         serv.handle_ctrl_chan(&mut hdl)?;
+        // commit the remaining changes of failed requests, which have already been answered
+        end_request().ok();
         REQHDL.get().handle(|op, is| hdl.handle(op, is))
     })
     .ok();
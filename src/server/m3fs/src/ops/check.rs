@@ -0,0 +1,457 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use crate::data::{
+    Bitmap, BlockNo, DirEntryIterator, Extent, ExtentCache, INodeRef, InodeNo, INODE_DIR_COUNT,
+};
+use crate::ops::inodes;
+
+use m3::col::Vec;
+use m3::errors::Error;
+use m3::session::CheckReport;
+
+/// Walks the file system and compares the block and inode bitmaps with the blocks and inodes that
+/// are actually in use.
+///
+/// Besides everything that is reachable from the root directory, inodes that have been removed but
+/// are still open and the extents in `pending` are considered to be in use. The latter are the
+/// extents that sessions have allocated for appends, but not yet added to the inode.
+///
+/// If `repair` is true, leaked blocks and inodes are freed and used blocks and inodes that are not
+/// marked as such are marked used. Cross-linked blocks and invalid references are only reported,
+/// because repairing them would require to decide which inode loses its data.
+///
+/// If blocks can be shared, a block may be used once more than it has additional references.
+/// References that exceed the actual uses are counted as leaked and dropped by a repair, whereas
+/// uses that exceed the references are counted as cross-linked and turned into references.
+pub fn check(pending: &[(InodeNo, Extent)], repair: bool) -> Result<CheckReport, Error> {
+    log!(
+        crate::LOG_CHECK,
+        "check::check(pending={}, repair={})",
+        pending.len(),
+        repair
+    );
+
+    let mut chk = Checker::new();
+
+    chk.walk(0)?;
+    for (ino, ext) in pending {
+        chk.use_extent(*ino, ext);
+    }
+    for ino in chk.open_orphans()? {
+        chk.walk(ino)?;
+    }
+
+    chk.compare_inodes(repair)?;
+    chk.compare_blocks(repair)?;
+    chk.compare_refs(repair)?;
+
+    log!(crate::LOG_CHECK, "check::check() -> {:?}", chk.report);
+    Ok(chk.report)
+}
+
+struct Checker {
+    // the blocks and inodes that we have found to be in use
+    blocks: Vec<u8>,
+    inodes: Vec<u8>,
+    // the number of additional uses of each block, if blocks can be shared
+    shared: Vec<u8>,
+    report: CheckReport,
+}
+
+impl Checker {
+    fn new() -> Self {
+        let sb = crate::superblock();
+        let mut blocks = vec![0; (sb.total_blocks as usize + 7) / 8];
+        let inodes = vec![0; (sb.total_inodes as usize + 7) / 8];
+
+        // the superblock, the bitmaps, the inodes, and the journal are always in use
+        let mut bm = Bitmap::from_bytes(&mut blocks);
+        for bno in 0..sb.first_data_block() {
+            bm.set_bit(bno as usize);
+        }
+
+        let shared = if crate::blocks_mut().shareable() {
+            vec![0; sb.total_blocks as usize]
+        }
+        else {
+            Vec::new()
+        };
+
+        Self {
+            blocks,
+            inodes,
+            shared,
+            report: CheckReport::default(),
+        }
+    }
+
+    /// Marks block `bno` as used by `ino` and returns false if the block number is invalid
+    fn use_block(&mut self, ino: InodeNo, bno: BlockNo) -> bool {
+        let sb = crate::superblock();
+        if bno < sb.first_data_block() || bno >= sb.total_blocks {
+            log!(
+                crate::LOG_CHECK,
+                "check: inode {} refers to invalid block {}",
+                ino,
+                bno
+            );
+            self.report.invalid_refs += 1;
+            return false;
+        }
+
+        let mut bm = Bitmap::from_bytes(&mut self.blocks);
+        if bm.is_bit_set(bno as usize) && !self.shared.is_empty() {
+            // whether the block is cross-linked is decided by its references (see compare_refs)
+            self.shared[bno as usize] = self.shared[bno as usize].saturating_add(1);
+        }
+        else if bm.is_bit_set(bno as usize) {
+            log!(
+                crate::LOG_CHECK,
+                "check: block {} of inode {} is used more than once",
+                bno,
+                ino
+            );
+            self.report.crosslinked_blocks += 1;
+        }
+        bm.set_bit(bno as usize);
+        true
+    }
+
+    /// Marks all blocks of `ext` as used by `ino` and returns false if the extent is invalid
+    fn use_extent(&mut self, ino: InodeNo, ext: &Extent) -> bool {
+        // holes do not occupy any blocks
+        if ext.is_hole() {
+            return true;
+        }
+
+        if ext.start == 0 || ext.length == 0 {
+            log!(
+                crate::LOG_CHECK,
+                "check: inode {} has empty extent {:?}",
+                ino,
+                ext
+            );
+            self.report.invalid_refs += 1;
+            return false;
+        }
+
+        ext.block_range()
+            .fold(true, |valid, bno| self.use_block(ino, bno) && valid)
+    }
+
+    /// Marks the indirect block `bno` of `ino` as used and loads `count` extents from it
+    fn load_extents(
+        &mut self,
+        ino: InodeNo,
+        bno: BlockNo,
+        count: usize,
+        exts: &mut Vec<Extent>,
+    ) -> Result<(), Error> {
+        if bno == 0 {
+            log!(
+                crate::LOG_CHECK,
+                "check: inode {} is missing an indirect block",
+                ino
+            );
+            self.report.invalid_refs += 1;
+        }
+        else if self.use_block(ino, bno) {
+            let cache = ExtentCache::from_buffer(crate::meta_buffer_mut().get_block(bno)?);
+            for i in 0..count {
+                exts.push(cache[i]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects all extents of `inode` and marks the indirect blocks as used
+    fn extents(&mut self, inode: &INodeRef) -> Result<Vec<Extent>, Error> {
+        let per_block = crate::superblock().extents_per_block();
+        let count = inode.extents as usize;
+
+        let mut exts = inode.direct[..count.min(INODE_DIR_COUNT)].to_vec();
+
+        if count > INODE_DIR_COUNT {
+            let indir = (count - INODE_DIR_COUNT).min(per_block);
+            self.load_extents(inode.inode, inode.indirect, indir, &mut exts)?;
+        }
+
+        if count > INODE_DIR_COUNT + per_block {
+            let mut rem = count - (INODE_DIR_COUNT + per_block);
+            let mut ptrs = Vec::new();
+            let dindir = (rem + per_block - 1) / per_block;
+            self.load_extents(inode.inode, inode.dindirect, dindir, &mut ptrs)?;
+
+            for ptr in ptrs {
+                let indir = rem.min(per_block);
+                self.load_extents(inode.inode, ptr.start, indir, &mut exts)?;
+                rem -= indir;
+            }
+        }
+
+        Ok(exts)
+    }
+
+    /// Marks `root` and everything that is reachable from it as used
+    fn walk(&mut self, root: InodeNo) -> Result<(), Error> {
+        let total_inodes = crate::superblock().total_inodes;
+
+        // don't use recursion here to not depend on the depth of the directory hierarchy
+        let mut todo = vec![root];
+        while let Some(ino) = todo.pop() {
+            if ino >= total_inodes {
+                log!(crate::LOG_CHECK, "check: found invalid inode {}", ino);
+                self.report.invalid_refs += 1;
+                continue;
+            }
+
+            // hard links lead to the same inode multiple times
+            let mut bm = Bitmap::from_bytes(&mut self.inodes);
+            if bm.is_bit_set(ino as usize) {
+                continue;
+            }
+            bm.set_bit(ino as usize);
+
+            let inode = inodes::get(ino)?;
+            if inode.inode != ino {
+                log!(
+                    crate::LOG_CHECK,
+                    "check: inode {} says that its number is {}",
+                    ino,
+                    inode.inode
+                );
+                self.report.invalid_refs += 1;
+                continue;
+            }
+
+            for ext in self.extents(&inode)? {
+                if self.use_extent(ino, &ext) && inode.mode.is_dir() {
+                    for bno in ext.block_range() {
+                        self.read_dir(ino, bno, &mut todo)?;
+                    }
+                }
+            }
+
+            if inode.xattr != 0 {
+                self.use_block(ino, inode.xattr);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds all inodes that are referenced by the directory block `bno` of `ino` to `todo`
+    fn read_dir(
+        &mut self,
+        ino: InodeNo,
+        bno: BlockNo,
+        todo: &mut Vec<InodeNo>,
+    ) -> Result<(), Error> {
+        let block = crate::meta_buffer_mut().get_block(bno)?;
+        let entries = DirEntryIterator::from_block(block.data());
+        while let Some(e) = entries.next() {
+            // don't loop endlessly on corrupt directories
+            if e.next == 0 {
+                log!(
+                    crate::LOG_CHECK,
+                    "check: directory {} has a corrupt entry in block {}",
+                    ino,
+                    bno
+                );
+                self.report.invalid_refs += 1;
+                break;
+            }
+
+            if !e.is_empty() && e.name() != "." && e.name() != ".." {
+                todo.push(e.nodeno);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns all inodes that have not been reached, but are marked as used and still open
+    fn open_orphans(&mut self) -> Result<Vec<InodeNo>, Error> {
+        let (first, total) = {
+            let sb = crate::superblock();
+            (sb.first_inodebm_block(), sb.total_inodes)
+        };
+
+        let mut orphans = Vec::new();
+        let used = &mut self.inodes;
+        for_each_bit(first, total, |ino, marked, bit| {
+            if marked.is_bit_set(bit)
+                && !Bitmap::from_bytes(&mut used[..]).is_bit_set(ino as usize)
+                && crate::open_files_mut().get_file_mut(ino).is_some()
+            {
+                orphans.push(ino);
+            }
+            false
+        })?;
+        Ok(orphans)
+    }
+
+    fn compare_inodes(&mut self, repair: bool) -> Result<(), Error> {
+        let (first, total) = {
+            let sb = crate::superblock();
+            (sb.first_inodebm_block(), sb.total_inodes)
+        };
+
+        let diff = compare("inode", first, total, &mut self.inodes, repair)?;
+        self.report.leaked_inodes = diff.leaked;
+        self.report.unmarked_inodes = diff.unmarked;
+        if repair {
+            crate::inodes_mut().set_free(diff.free, diff.first_free);
+        }
+        Ok(())
+    }
+
+    fn compare_blocks(&mut self, repair: bool) -> Result<(), Error> {
+        let (first, total) = {
+            let sb = crate::superblock();
+            (sb.first_blockbm_block(), sb.total_blocks)
+        };
+
+        let diff = compare("block", first, total, &mut self.blocks, repair)?;
+        self.report.leaked_blocks = diff.leaked;
+        self.report.unmarked_blocks = diff.unmarked;
+        if repair {
+            crate::blocks_mut().set_free(diff.free, diff.first_free);
+        }
+        Ok(())
+    }
+
+    fn compare_refs(&mut self, repair: bool) -> Result<(), Error> {
+        let mut blocks = crate::blocks_mut();
+        for (bno, uses) in self.shared.iter().enumerate() {
+            let refs = blocks.refs(bno)?;
+            if refs == *uses {
+                continue;
+            }
+
+            log!(
+                crate::LOG_CHECK,
+                "check: block {} has {} additional references, but {} additional uses",
+                bno,
+                refs,
+                uses
+            );
+            if refs > *uses {
+                self.report.leaked_blocks += 1;
+            }
+            else {
+                self.report.crosslinked_blocks += 1;
+            }
+
+            if repair {
+                blocks.set_refs(bno, *uses)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The differences between an on-disk bitmap and the actually used items
+struct Diff {
+    leaked: usize,
+    unmarked: usize,
+    // the number of free items and the first free item after the (optional) repair
+    free: u32,
+    first_free: u32,
+}
+
+/// Compares the on-disk bitmap with `total` bits starting at block `first` with `used` and makes
+/// the bitmap equal to `used` if `repair` is true.
+fn compare(
+    name: &str,
+    first: BlockNo,
+    total: u32,
+    used: &mut [u8],
+    repair: bool,
+) -> Result<Diff, Error> {
+    let mut diff = Diff {
+        leaked: 0,
+        unmarked: 0,
+        free: 0,
+        first_free: total,
+    };
+
+    for_each_bit(first, total, |no, marked, bit| {
+        let is_used = Bitmap::from_bytes(&mut used[..]).is_bit_set(no as usize);
+        let mut changed = false;
+        if marked.is_bit_set(bit) != is_used {
+            if is_used {
+                log!(
+                    crate::LOG_CHECK,
+                    "check: {} {} is used, but marked free",
+                    name,
+                    no
+                );
+                diff.unmarked += 1;
+            }
+            else {
+                log!(
+                    crate::LOG_CHECK,
+                    "check: {} {} is unused, but marked used",
+                    name,
+                    no
+                );
+                diff.leaked += 1;
+            }
+
+            if repair {
+                if is_used {
+                    marked.set_bit(bit);
+                }
+                else {
+                    marked.unset_bit(bit);
+                }
+                changed = true;
+            }
+        }
+
+        if !marked.is_bit_set(bit) {
+            diff.free += 1;
+            diff.first_free = diff.first_free.min(no);
+        }
+        changed
+    })?;
+
+    Ok(diff)
+}
+
+/// Calls `func` for all bits of the on-disk bitmap with `total` bits starting at block `first`.
+///
+/// `func` receives the number of the bit within the entire bitmap, the bitmap of the current block,
+/// and the number of the bit within that block. It returns true if it has changed the bitmap.
+fn for_each_bit<F>(first: BlockNo, total: u32, mut func: F) -> Result<(), Error>
+where
+    F: FnMut(u32, &mut Bitmap<'_>, usize) -> bool,
+{
+    let bits_per_block = crate::superblock().block_size * 8;
+    for (i, start) in (0..total).step_by(bits_per_block as usize).enumerate() {
+        let mut block = crate::meta_buffer_mut().get_block(first + i as BlockNo)?;
+
+        let mut changed = false;
+        let mut bitmap = Bitmap::from_bytes(block.data_mut());
+        for bit in 0..bits_per_block.min(total - start) {
+            changed |= func(start + bit, &mut bitmap, bit as usize);
+        }
+
+        if changed {
+            block.mark_dirty();
+        }
+    }
+    Ok(())
+}
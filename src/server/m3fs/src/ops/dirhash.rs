@@ -0,0 +1,165 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+//! Hashed directories
+//!
+//! If enabled in the superblock, directories consist of 2^n blocks, called buckets, and each entry
+//! is stored in the bucket its name hashes to. Thus, lookups only need to consider a single block.
+//! If a bucket is full, the number of buckets is doubled and the entries of each bucket are split
+//! between the bucket and its new sibling. Directories never shrink, and blocks behind the last
+//! bucket are left unused.
+
+use crate::buf::{MetaBufferBlock, MetaBufferBlockRef};
+use crate::data::{BlockNo, DirEntry, DirEntryIterator, INodeRef, InodeNo};
+use crate::ops::inodes;
+use crate::ops::links;
+use crate::ops::quota::Quota;
+
+use m3::col::{String, ToString, Vec};
+use m3::errors::{Code, Error};
+
+/// The maximum number of times the directory is grown to make room for a single entry
+const MAX_GROW: usize = 4;
+
+/// Returns true if directories are hashed
+pub fn enabled() -> bool {
+    crate::superblock().hashed_dirs()
+}
+
+/// Returns the number of buckets of the given directory
+pub fn buckets(dir: &INodeRef) -> u32 {
+    let blocks = (dir.size / crate::superblock().block_size as u64) as u32;
+    match blocks {
+        0 => 0,
+        n => (n + 1).next_power_of_two() / 2,
+    }
+}
+
+/// Returns the block number of the block with index `idx` in the given directory
+fn block_no(dir: &INodeRef, mut idx: u32) -> Option<BlockNo> {
+    for ext in dir.extent_iter() {
+        if idx < ext.length {
+            return Some(ext.start + idx);
+        }
+        idx -= ext.length;
+    }
+    None
+}
+
+/// Returns the block of the bucket that an entry with given name belongs to, if any
+pub fn bucket(dir: &INodeRef, name: &str) -> Result<Option<MetaBufferBlockRef>, Error> {
+    let buckets = buckets(dir);
+    if buckets == 0 {
+        return Ok(None);
+    }
+
+    let idx = DirEntry::hash(name) & (buckets - 1);
+    match block_no(dir, idx) {
+        Some(bno) => crate::meta_buffer_mut().get_block(bno).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Inserts an entry with given name pointing to `ino` into the given directory, growing the
+/// directory if the bucket is full. New blocks are charged as described for
+/// [`inodes::create_extent`].
+pub fn insert(dir: &INodeRef, name: &str, ino: InodeNo, quota: &Quota) -> Result<(), Error> {
+    for i in 0..=MAX_GROW {
+        if let Some(mut block) = bucket(dir, name)? {
+            if links::insert_into(&mut block, name, ino) {
+                return Ok(());
+            }
+        }
+
+        if i < MAX_GROW {
+            grow(dir, quota)?;
+        }
+    }
+
+    log!(
+        crate::LOG_LINKS,
+        "dirhash: no space for {} in directory {} with {} buckets",
+        name,
+        dir.inode,
+        buckets(dir)
+    );
+    Err(Error::new(Code::NoSpace))
+}
+
+/// Doubles the number of buckets of the given directory and redistributes the entries
+fn grow(dir: &INodeRef, quota: &Quota) -> Result<(), Error> {
+    let block_size = crate::superblock().block_size;
+    let old = buckets(dir);
+    let new = if old == 0 { 1 } else { old * 2 };
+
+    log!(
+        crate::LOG_LINKS,
+        "dirhash::grow(dir={}, buckets={} -> {})",
+        dir.inode,
+        old,
+        new
+    );
+
+    // allocate the missing blocks
+    let mut blocks = (dir.size / block_size as u64) as u32;
+    let mut indir = None;
+    while blocks < new {
+        let ext = inodes::get_extent(dir, dir.extents as usize, &mut indir, true)?;
+        let ext_range = inodes::create_extent(dir, true, new - blocks, quota)?;
+        *ext.as_mut() = ext_range;
+        blocks += ext_range.length;
+    }
+
+    if old == 0 {
+        let mut block = crate::meta_buffer_mut().get_block(block_no(dir, 0).unwrap())?;
+        fill(&mut block, &[]);
+        return Ok(());
+    }
+
+    // split each bucket into itself and its new sibling
+    for idx in 0..old {
+        let mut block = crate::meta_buffer_mut().get_block(block_no(dir, idx).unwrap())?;
+        let (stay, moved): (Vec<(String, InodeNo)>, Vec<(String, InodeNo)>) = {
+            let entries = DirEntryIterator::from_block(block.data());
+            let mut all = Vec::new();
+            while let Some(e) = entries.next() {
+                if !e.is_empty() {
+                    all.push((e.name().to_string(), e.nodeno));
+                }
+            }
+            all.into_iter()
+                .partition(|(name, _)| (DirEntry::hash(name) & (new - 1)) == idx)
+        };
+
+        let mut sibling = crate::meta_buffer_mut().get_block(block_no(dir, idx + old).unwrap())?;
+        fill(&mut block, &stay);
+        fill(&mut sibling, &moved);
+    }
+    Ok(())
+}
+
+/// Overwrites the given block with the given entries, which are known to fit
+fn fill(block: &mut MetaBufferBlock, entries: &[(String, InodeNo)]) {
+    block.overwrite_zero();
+
+    // start with an empty entry spanning the whole block, which is reused for the first entry
+    let entry = DirEntry::from_buffer_mut(block, 0);
+    entry.next = crate::superblock().block_size;
+
+    for (name, ino) in entries {
+        let ok = links::insert_into(block, name, *ino);
+        assert!(ok);
+    }
+}
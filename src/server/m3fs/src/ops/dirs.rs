@@ -14,11 +14,18 @@
  * General Public License version 2 for more details.
  */
 
-use crate::data::{DirEntry, DirEntryIterator, INodeRef, InodeNo};
+use crate::data::{DirEntry, DirEntryIterator, GroupId, INodeRef, InodeNo, Time, UserId};
+use crate::ops::perms::{self, Creds};
+use crate::ops::quota::Quota;
 use crate::ops::{inodes, links};
 
+use m3::borrow::StringRef;
+use m3::col::{String, ToString};
 use m3::errors::{Code, Error};
-use m3::vfs::FileMode;
+use m3::vfs::{FileMode, OpenFlags};
+
+/// The maximum number of symbolic links that are followed during a single path lookup
+const MAX_LINK_DEPTH: usize = 8;
 
 /// Returns the directory and filename part of the given path.
 ///
@@ -53,24 +60,26 @@ fn find_entry(inode: &INodeRef, name: &str) -> Result<InodeNo, Error> {
         name
     );
 
-    for ext in inode.extent_iter() {
-        for block in ext.block_iter() {
-            let entry_iter = DirEntryIterator::from_block(block.data());
-            while let Some(entry) = entry_iter.next() {
-                log!(crate::LOG_FIND, "  considering {}", entry.name());
-                if entry.name() == name {
-                    return Ok(entry.nodeno);
-                }
+    let res = links::find_in_blocks(inode, name, |block| {
+        let entry_iter = DirEntryIterator::from_block(block.data());
+        while let Some(entry) = entry_iter.next() {
+            log!(crate::LOG_FIND, "  considering {}", entry.name());
+            if !entry.is_empty() && entry.name() == name {
+                return Ok(Some(entry.nodeno));
             }
         }
-    }
+        Ok(None)
+    })?;
 
-    Err(Error::new(Code::NoSuchFile))
+    res.ok_or_else(|| Error::new(Code::NoSuchFile))
 }
 
 /// Searches for the given path, optionally creates a new file, and returns the inode number.
-pub fn search(path: &str, create: bool) -> Result<InodeNo, Error> {
-    let ino = do_search(path, create);
+///
+/// Symbolic links are followed, including a symbolic link in the last path component. All
+/// directories on the way need to be searchable with the credentials `creds`.
+pub fn search(path: &str, create: bool, creds: &Creds) -> Result<InodeNo, Error> {
+    let ino = do_search(path, create, true, creds);
     log!(
         crate::LOG_DIRS,
         "dirs::search(path={}, create={}) -> {:?}",
@@ -81,76 +90,129 @@ pub fn search(path: &str, create: bool) -> Result<InodeNo, Error> {
     ino
 }
 
-fn do_search(mut path: &str, create: bool) -> Result<InodeNo, Error> {
-    // remove all leading /
-    while path.starts_with('/') {
-        path = &path[1..];
-    }
+/// Searches for the given path and returns the inode number.
+///
+/// In contrast to [`search`], a symbolic link in the last path component is not followed.
+pub fn search_nofollow(path: &str, creds: &Creds) -> Result<InodeNo, Error> {
+    let ino = do_search(path, false, false, creds);
+    log!(
+        crate::LOG_DIRS,
+        "dirs::search_nofollow(path={}) -> {:?}",
+        path,
+        ino.as_ref().map_err(|e| e.code()),
+    );
+    ino
+}
 
-    // root inode?
-    if path.is_empty() {
-        return Ok(0);
-    }
+fn do_search(path: &str, create: bool, follow: bool, creds: &Creds) -> Result<InodeNo, Error> {
+    // the path is only copied if we have to follow a symbolic link
+    let mut path = StringRef::Borrowed(path);
+    let mut pos = 0;
+    let mut links = 0;
+
+    // start at the root inode of the session
+    let mut dir = inodes::get(creds.root_dir)?;
+
+    loop {
+        // remove all leading /
+        while path[pos..].starts_with('/') {
+            pos += 1;
+        }
 
-    // start at root inode with search
-    let mut ino = 0;
+        // root inode or end of path reached?
+        if path[pos..].is_empty() {
+            return Ok(dir.inode);
+        }
 
-    let (filename, inode) = loop {
-        // get directory inode
-        let inode = inodes::get(ino)?;
+        // we need to be allowed to search the directory
+        if dir.mode.is_dir() {
+            perms::check(&dir, creds, OpenFlags::X)?;
+        }
 
         // find directory entry
-        let next_end = path.find('/').unwrap_or(path.len());
-        let filename = &path[..next_end];
-        let next_ino = find_entry(&inode, filename);
+        let next_end = path[pos..].find('/').map(|e| pos + e).unwrap_or(path.len());
+        let filename = &path[pos..next_end];
+        let next_ino = find_entry(&dir, filename);
 
         // walk to next path component start
-        let mut end = &path[next_end..];
-        while end.starts_with('/') {
-            end = &end[1..];
+        let mut end = next_end;
+        while path[end..].starts_with('/') {
+            end += 1;
+        }
+        let last = end == path.len();
+
+        // the root directory cannot be left via ".."
+        if filename == ".." && dir.inode == creds.root_dir {
+            if last {
+                return Ok(dir.inode);
+            }
+            pos = end;
+            continue;
         }
 
         match next_ino {
             Ok(nodeno) => {
+                let next_inode = inodes::get(nodeno)?;
+                if next_inode.mode.is_link() && (follow || !last) {
+                    links += 1;
+                    if links > MAX_LINK_DEPTH {
+                        return Err(Error::new(Code::LinkLoop));
+                    }
+
+                    // continue with the link target, followed by the remaining path
+                    let target = read_target(&next_inode)?;
+                    // absolute targets start at the root; relative ones at the link's directory
+                    if target.starts_with('/') {
+                        dir = inodes::get(creds.root_dir)?;
+                    }
+                    let new_path = format!("{}/{}", target, &path[end..]);
+                    path.set(new_path);
+                    pos = 0;
+                    continue;
+                }
+
                 // if path is now empty, finish searching
-                if end.is_empty() {
+                if last {
                     return Ok(nodeno);
                 }
                 // continue with this directory
-                ino = nodeno;
+                dir = next_inode;
             },
             Err(e) if e.code() == Code::NoSuchFile => {
                 // cannot create new file if it's not the last path component
-                if !end.is_empty() {
+                if !last || !create {
                     return Err(Error::new(Code::NoSuchFile));
                 }
 
-                // not found, maybe we want to create it
-                break (filename, inode);
+                // not found, but we want to create it
+                perms::check(&dir, creds, OpenFlags::W)?;
+                let new_inode = inodes::create(FileMode::FILE_DEF, creds)?;
+                if let Err(e) = links::create(&dir, filename, &new_inode, &creds.quota) {
+                    crate::open_files_mut().delete_file(new_inode.inode).ok();
+                    return Err(e);
+                };
+                return Ok(new_inode.inode);
             },
             Err(e) => return Err(e),
         }
 
         // to next path component
-        path = end;
-    };
-
-    if create {
-        // create inode and put link into directory
-        let new_inode = inodes::create(FileMode::FILE_DEF)?;
-        if let Err(e) = links::create(&inode, filename, &new_inode) {
-            crate::open_files_mut().delete_file(new_inode.inode).ok();
-            return Err(e);
-        };
-        return Ok(new_inode.inode);
+        pos = end;
     }
+}
 
-    Err(Error::new(Code::NoSuchFile))
+/// Searches for the directory at given path and returns its inode number
+pub fn search_dir(path: &str, creds: &Creds) -> Result<InodeNo, Error> {
+    let ino = search(path, false, creds)?;
+    if !inodes::get(ino)?.mode.is_dir() {
+        return Err(Error::new(Code::IsNoDir));
+    }
+    Ok(ino)
 }
 
 /// Creates a new directory with given mode at given path
-pub fn create(path: &str, mode: FileMode) -> Result<(), Error> {
-    let res = do_create(path, mode);
+pub fn create(path: &str, mode: FileMode, creds: &Creds) -> Result<(), Error> {
+    let res = do_create(path, mode, creds);
     log!(
         crate::LOG_DIRS,
         "dirs::create(path={}, mode={:o}) -> {:?}",
@@ -161,35 +223,36 @@ pub fn create(path: &str, mode: FileMode) -> Result<(), Error> {
     res
 }
 
-fn do_create(path: &str, mode: FileMode) -> Result<(), Error> {
+fn do_create(path: &str, mode: FileMode, creds: &Creds) -> Result<(), Error> {
     let (dir, name) = split_path(path);
 
     // get parent directory
-    let parent_ino = search(dir, false)?;
+    let parent_ino = search(dir, false, creds)?;
 
     // ensure that the entry doesn't exist
-    if search(path, false).is_ok() {
+    if search_nofollow(path, creds).is_ok() {
         return Err(Error::new(Code::Exists));
     }
 
     let parinode = inodes::get(parent_ino)?;
-    if let Ok(dirino) = inodes::create(FileMode::DIR_DEF | mode) {
+    perms::check(&parinode, creds, OpenFlags::W)?;
+    if let Ok(dirino) = inodes::create(FileMode::DIR_DEF | mode, creds) {
         // create directory itself
-        if let Err(e) = links::create(&parinode, name, &dirino) {
+        if let Err(e) = links::create(&parinode, name, &dirino, &creds.quota) {
             crate::open_files_mut().delete_file(dirino.inode).ok();
             return Err(e);
         }
 
         // create "." link
-        if let Err(e) = links::create(&dirino, ".", &dirino) {
-            links::remove(&parinode, name, false).unwrap();
+        if let Err(e) = links::create(&dirino, ".", &dirino, &creds.quota) {
+            links::remove(&parinode, name, false)?;
             return Err(e);
         }
 
         // create ".." link
-        if let Err(e) = links::create(&dirino, "..", &parinode) {
-            links::remove(&dirino, ".", false).unwrap();
-            links::remove(&parinode, name, false).unwrap();
+        if let Err(e) = links::create(&dirino, "..", &parinode, &creds.quota) {
+            links::remove(&dirino, ".", false)?;
+            links::remove(&parinode, name, false)?;
             return Err(e);
         }
 
@@ -201,12 +264,12 @@ fn do_create(path: &str, mode: FileMode) -> Result<(), Error> {
 }
 
 /// Removes the directory at given path if it is empty
-pub fn remove(path: &str) -> Result<(), Error> {
+pub fn remove(path: &str, creds: &Creds) -> Result<(), Error> {
     log!(crate::LOG_DIRS, "dirs::remove(path={})", path);
 
-    let ino = search(path, false)?;
+    let ino = search_nofollow(path, creds)?;
     // cannot remove root directory
-    if ino == 0 {
+    if ino == creds.root_dir {
         return Err(Error::new(Code::InvArgs));
     }
 
@@ -221,7 +284,7 @@ pub fn remove(path: &str) -> Result<(), Error> {
         for block in ext.block_iter() {
             let entry_iter = DirEntryIterator::from_block(block.data());
             while let Some(entry) = entry_iter.next() {
-                if entry.name() != "." && entry.name() != ".." {
+                if !entry.is_empty() && entry.name() != "." && entry.name() != ".." {
                     return Err(Error::new(Code::DirNotEmpty));
                 }
             }
@@ -231,17 +294,17 @@ pub fn remove(path: &str) -> Result<(), Error> {
     // hardlinks to directories are not possible, thus we always have 2 ( . and ..)
     assert!(inode.links == 2, "expected 2 links, found {}", inode.links);
 
-    let parent_inode = unlink(path, false)?;
+    let parent_inode = unlink(path, false, creds)?;
 
-    // we have already removed the entry; if something fails now we're screwed
-    inodes::decrease_links(&parent_inode).unwrap();
-    inodes::decrease_links(&inode).unwrap();
+    // we have already removed the entry; if something fails now, the journal discards the request
+    inodes::decrease_links(&parent_inode)?;
+    inodes::decrease_links(&inode)?;
 
     Ok(())
 }
 
 /// Creates a link at `new_path` to `old_path`
-pub fn link(old_path: &str, new_path: &str) -> Result<(), Error> {
+pub fn link(old_path: &str, new_path: &str, creds: &Creds) -> Result<(), Error> {
     log!(
         crate::LOG_DIRS,
         "dirs::link(old_path={}, new_path={})",
@@ -249,7 +312,7 @@ pub fn link(old_path: &str, new_path: &str) -> Result<(), Error> {
         new_path
     );
 
-    let old_ino = search(old_path, false)?;
+    let old_ino = search_nofollow(old_path, creds)?;
 
     // it can't be a directory
     let old_inode = inodes::get(old_ino)?;
@@ -259,15 +322,16 @@ pub fn link(old_path: &str, new_path: &str) -> Result<(), Error> {
 
     let (dir, name) = split_path(new_path);
 
-    let base_ino = search(dir, false)?;
+    let base_ino = search(dir, false, creds)?;
     let base_inode = inodes::get(base_ino)?;
+    perms::check(&base_inode, creds, OpenFlags::W)?;
 
     // the destination cannot already exist
     if find_entry(&base_inode, name).is_ok() {
         return Err(Error::new(Code::Exists));
     }
 
-    links::create(&base_inode, name, &old_inode)
+    links::create(&base_inode, name, &old_inode, &creds.quota)
 }
 
 /// Removes the directory entry at given path
@@ -275,7 +339,7 @@ pub fn link(old_path: &str, new_path: &str) -> Result<(), Error> {
 /// If `deny_dir` is true and the path points to a directory, the call fails.
 ///
 /// Returns the directory inode
-pub fn unlink(path: &str, deny_dir: bool) -> Result<INodeRef, Error> {
+pub fn unlink(path: &str, deny_dir: bool, creds: &Creds) -> Result<INodeRef, Error> {
     log!(
         crate::LOG_DIRS,
         "dirs::unlink(path={}, deny_dir={})",
@@ -289,14 +353,17 @@ pub fn unlink(path: &str, deny_dir: bool) -> Result<INodeRef, Error> {
         return Err(Error::new(Code::InvArgs));
     }
 
-    let par_ino = search(dir, false)?;
+    let par_ino = search(dir, false, creds)?;
     let par_inode = inodes::get(par_ino)?;
 
+    let ino = find_entry(&par_inode, name)?;
+    perms::check_remove(&par_inode, &inodes::get(ino)?, creds)?;
+
     links::remove(&par_inode, name, deny_dir).map(|_| par_inode)
 }
 
 /// Renames `old_path` to `new_path`
-pub fn rename(old_path: &str, new_path: &str) -> Result<(), Error> {
+pub fn rename(old_path: &str, new_path: &str, creds: &Creds) -> Result<(), Error> {
     log!(
         crate::LOG_DIRS,
         "dirs::rename(old_path={}, new_path={})",
@@ -310,7 +377,7 @@ pub fn rename(old_path: &str, new_path: &str) -> Result<(), Error> {
     if old_name.is_empty() || old_name == "." || old_name == ".." {
         return Err(Error::new(Code::InvArgs));
     }
-    let old_dir_ino = search(old_dir, false)?;
+    let old_dir_ino = search(old_dir, false, creds)?;
     let old_dir_inode = inodes::get(old_dir_ino)?;
 
     // get old inode to link to
@@ -321,6 +388,7 @@ pub fn rename(old_path: &str, new_path: &str) -> Result<(), Error> {
     if old_inode.mode.is_dir() {
         return Err(Error::new(Code::IsDir));
     }
+    perms::check_remove(&old_dir_inode, &old_inode, creds)?;
 
     // find new path
     let (new_dir, new_name) = split_path(new_path);
@@ -328,53 +396,207 @@ pub fn rename(old_path: &str, new_path: &str) -> Result<(), Error> {
     if new_name.is_empty() || new_name == "." || new_name == ".." {
         return Err(Error::new(Code::InvArgs));
     }
-    let new_dir_ino = search(new_dir, false)?;
+    let new_dir_ino = search(new_dir, false, creds)?;
     let new_dir_inode = inodes::get(new_dir_ino)?;
 
-    // search for the entry in the new directory and change link to new inode if found
-    let mut prev_ino = None;
-    'search_loop: for ext in new_dir_inode.extent_iter() {
-        for mut block in ext.block_iter() {
-            let mut off = 0;
-            let end = crate::superblock().block_size as usize;
-            while off < end {
-                // TODO marking all blocks dirty here is suboptimal
-                let entry = DirEntry::from_buffer_mut(&mut block, off);
-                if entry.name() == new_name {
-                    // both link to the same inode? nothing to do
-                    if entry.nodeno == old_ino {
-                        return Ok(());
-                    }
-
-                    // remember original link
-                    prev_ino = Some(entry.nodeno);
-
-                    // set new inode
-                    entry.nodeno = old_ino;
-                    break 'search_loop;
-                }
+    // replacing an existing entry is subject to the same rules as removing it
+    match find_entry(&new_dir_inode, new_name) {
+        Ok(prev_ino) => perms::check_remove(&new_dir_inode, &inodes::get(prev_ino)?, creds)?,
+        Err(_) => perms::check(&new_dir_inode, creds, OpenFlags::W)?,
+    }
 
-                off += entry.next as usize;
+    // search for the entry in the new directory and change link to new inode if found
+    let prev_ino = links::find_in_blocks(&new_dir_inode, new_name, |block| {
+        let mut off = 0;
+        let end = crate::superblock().block_size as usize;
+        while off < end {
+            // TODO marking all blocks dirty here is suboptimal
+            let entry = DirEntry::from_buffer_mut(block, off);
+            if !entry.is_empty() && entry.name() == new_name {
+                // remember original link and set new inode
+                let prev = entry.nodeno;
+                entry.nodeno = old_ino;
+                return Ok(Some(prev));
             }
+
+            off += entry.next as usize;
         }
+        Ok(None)
+    })?;
+
+    // both link to the same inode? nothing to do
+    if prev_ino == Some(old_ino) {
+        return Ok(());
     }
 
-    // point of no return: we have changed the DirEntry; for simplicity, we assume here that
-    // everything works fine, because we cannot undo that operation safely since that could fail as
-    // well.
+    // point of no return: we have changed the DirEntry; we cannot undo that operation safely since
+    // that could fail as well. With a journal, the changes of the failed request are discarded.
 
     if let Some(prev_ino) = prev_ino {
-        let prev_inode = inodes::get(prev_ino).unwrap();
-        inodes::decrease_links(&prev_inode).unwrap();
+        let prev_inode = inodes::get(prev_ino)?;
+        inodes::decrease_links(&prev_inode)?;
 
         // increase links for the old_inode, because we will increase it in links::create below as
         // well and if we don't links::remove might delete the inode.
         old_inode.as_mut().links += 1;
     }
     else {
-        links::create(&new_dir_inode, new_name, &old_inode).unwrap();
+        // nothing has been changed yet, so that we can simply fail here (e.g., due to the quota)
+        links::create(&new_dir_inode, new_name, &old_inode, &creds.quota)?;
+    }
+
+    links::remove(&old_dir_inode, old_name, true)
+}
+
+/// Creates a symbolic link at `path` that points to `target`
+pub fn symlink(target: &str, path: &str, creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_DIRS,
+        "dirs::symlink(target={}, path={})",
+        target,
+        path
+    );
+
+    // the target is stored in a single block
+    if target.is_empty() || target.len() > crate::superblock().block_size as usize {
+        return Err(Error::new(Code::InvArgs));
+    }
+
+    let (dir, name) = split_path(path);
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Error::new(Code::InvArgs));
+    }
+
+    let base_ino = search(dir, false, creds)?;
+    let base_inode = inodes::get(base_ino)?;
+    perms::check(&base_inode, creds, OpenFlags::W)?;
+
+    // the destination cannot already exist
+    if find_entry(&base_inode, name).is_ok() {
+        return Err(Error::new(Code::Exists));
+    }
+
+    let inode = inodes::create(FileMode::LINK_DEF, creds)?;
+    if let Err(e) = write_target(&inode, target, &creds.quota) {
+        crate::open_files_mut().delete_file(inode.inode).ok();
+        return Err(e);
     }
 
-    links::remove(&old_dir_inode, old_name, true).unwrap();
+    if let Err(e) = links::create(&base_inode, name, &inode, &creds.quota) {
+        crate::open_files_mut().delete_file(inode.inode).ok();
+        return Err(e);
+    }
     Ok(())
 }
+
+/// Returns the target of the symbolic link at `path`
+pub fn readlink(path: &str, creds: &Creds) -> Result<String, Error> {
+    log!(crate::LOG_DIRS, "dirs::readlink(path={})", path);
+
+    let ino = search_nofollow(path, creds)?;
+    let inode = inodes::get(ino)?;
+    if !inode.mode.is_link() {
+        return Err(Error::new(Code::InvArgs));
+    }
+
+    read_target(&inode)
+}
+
+/// Changes the permission bits of the file at `path` to `mode`
+///
+/// Only the owner of the file is allowed to do that.
+pub fn chmod(path: &str, mode: FileMode, creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_DIRS,
+        "dirs::chmod(path={}, mode={:o})",
+        path,
+        mode
+    );
+
+    let ino = search(path, false, creds)?;
+    let inode = inodes::get(ino)?;
+    if !creds.owns(&inode) {
+        return Err(Error::new(Code::NoPerm));
+    }
+
+    let changeable = FileMode::PERM | FileMode::ISUID | FileMode::ISGID | FileMode::ISSTICKY;
+    inode.as_mut().mode = (inode.mode & !changeable) | (mode & changeable);
+    Ok(())
+}
+
+/// Changes the owner of the file at `path` to `uid` and `gid`
+///
+/// Only root can change the owner arbitrarily. The owner of the file can only change the group to
+/// its own group.
+pub fn chown(path: &str, uid: UserId, gid: GroupId, creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_DIRS,
+        "dirs::chown(path={}, uid={}, gid={})",
+        path,
+        uid,
+        gid
+    );
+
+    let ino = search(path, false, creds)?;
+    let inode = inodes::get(ino)?;
+    if !creds.is_root()
+        && (creds.uid != inode.uid || uid != inode.uid || (gid != inode.gid && gid != creds.gid))
+    {
+        return Err(Error::new(Code::NoPerm));
+    }
+
+    inode.as_mut().uid = uid;
+    inode.as_mut().gid = gid;
+    Ok(())
+}
+
+/// Sets the last access time of the file at `path` to `atime` and the last modification time to
+/// `mtime`
+///
+/// Only the owner of the file is allowed to do that.
+pub fn utime(path: &str, atime: Time, mtime: Time, creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_DIRS,
+        "dirs::utime(path={}, atime={}, mtime={})",
+        path,
+        atime,
+        mtime
+    );
+
+    let ino = search(path, false, creds)?;
+    let inode = inodes::get(ino)?;
+    if !creds.owns(&inode) {
+        return Err(Error::new(Code::NoPerm));
+    }
+
+    inode.as_mut().lastaccess = atime;
+    inode.as_mut().lastmod = mtime;
+    Ok(())
+}
+
+fn write_target(inode: &INodeRef, target: &str, quota: &Quota) -> Result<(), Error> {
+    let mut indir = None;
+    let ext = inodes::get_extent(inode, 0, &mut indir, true)?;
+    *ext.as_mut() = inodes::create_extent(inode, true, 1, quota)?;
+
+    let mut block = crate::meta_buffer_mut().get_block(ext.start)?;
+    block.data_mut()[..target.len()].copy_from_slice(target.as_bytes());
+    block.mark_dirty();
+
+    inode.as_mut().size = target.len() as u64;
+    Ok(())
+}
+
+fn read_target(inode: &INodeRef) -> Result<String, Error> {
+    let mut indir = None;
+    let ext = inodes::get_extent(inode, 0, &mut indir, false)?;
+    if ext.length == 0 {
+        return Err(Error::new(Code::InvArgs));
+    }
+
+    let block = crate::meta_buffer_mut().get_block(ext.start)?;
+    let len = (inode.size as usize).min(block.data().len());
+    let target =
+        core::str::from_utf8(&block.data()[..len]).map_err(|_| Error::new(Code::Utf8Error))?;
+    Ok(target.to_string())
+}
@@ -16,33 +16,54 @@
 
 use crate::buf::LoadLimit;
 use crate::data::{
-    ExtPos, Extent, ExtentCache, ExtentRef, INodeRef, InodeNo, INODE_DIR_COUNT, NUM_EXT_BYTES,
-    NUM_INODE_BYTES,
+    BlockNo, ExtPos, Extent, ExtentCache, ExtentRef, INodeRef, InodeNo, INODE_DIR_COUNT,
+    NUM_EXT_BYTES, NUM_INODE_BYTES,
 };
+use crate::ops::perms::Creds;
+use crate::ops::quota::{self, Quota};
+use crate::ops::xattrs;
 
 use m3::{
     cap::Selector,
     com::Perm,
     errors::{Code, Error},
-    math,
+    goff, math, syscalls,
+    tiles::Activity,
     vfs::{FileMode, SeekMode},
 };
 
-/// Creates a new inode with given mode and returns its INodeRef
-pub fn create(mode: FileMode) -> Result<INodeRef, Error> {
-    log!(crate::LOG_INODES, "inodes::create(mode={:o})", mode);
+/// Creates a new inode with given mode, owned by `creds`, and returns its INodeRef
+pub fn create(mode: FileMode, creds: &Creds) -> Result<INodeRef, Error> {
+    log!(
+        crate::LOG_INODES,
+        "inodes::create(mode={:o}, uid={}, gid={})",
+        mode,
+        creds.uid,
+        creds.gid
+    );
 
-    let ino = crate::inodes_mut().alloc(None)?;
+    creds.quota.charge_inodes(1)?;
+    let ino = match crate::inodes_mut().alloc(None) {
+        Ok(ino) => ino,
+        Err(e) => {
+            creds.quota.release_inodes(1);
+            return Err(e);
+        },
+    };
     let inode = get(ino)?;
     // reset inode
     inode.as_mut().reset();
     inode.as_mut().inode = ino;
     inode.as_mut().devno = 0; // TODO
     inode.as_mut().mode = mode;
+    inode.as_mut().uid = creds.uid;
+    inode.as_mut().gid = creds.gid;
+    inode.as_mut().quota_mount = crate::superblock().mounts;
+    inode.as_mut().quota = creds.quota.id();
     Ok(inode)
 }
 
-/// Decreases the number of links for the given inode and deletes it, if there are no links anymore
+/// Decreases the number of links for the given inode and deletes it, if there are no links anymore.
 pub fn decrease_links(inode: &INodeRef) -> Result<(), Error> {
     inode.as_mut().links -= 1;
     if inode.links == 0 {
@@ -52,14 +73,17 @@ pub fn decrease_links(inode: &INodeRef) -> Result<(), Error> {
     Ok(())
 }
 
-/// Frees the inode with given number
+/// Frees the inode with given number and credits the space to the quota that owns it
 pub fn free(inode_no: InodeNo) -> Result<(), Error> {
     log!(crate::LOG_INODES, "inodes::free(inode_no={})", inode_no);
 
     let ino = get(inode_no)?;
     let inodeno = ino.inode as usize;
     truncate(&ino, &ExtPos::new(0, 0))?;
-    crate::inodes_mut().free(inodeno, 1)
+    xattrs::free(&ino)?;
+    crate::inodes_mut().free(inodeno, 1)?;
+    quota::release_inode(&ino);
+    Ok(())
 }
 
 /// Loads an INodeRef for given inode number
@@ -169,7 +193,24 @@ pub fn get_extent_mem(
     let blocksize = crate::superblock().block_size;
     let mut extlen = (ext.length * blocksize) as usize;
 
-    let mut bytes = crate::backend_mut().get_filedata(*ext, start.off, perms, sel, Some(limit))?;
+    let mut bytes = if ext.is_hole() {
+        // holes are backed by read-only zeros; the client will ask again for the rest
+        let zeros = crate::zeros();
+        let rem = extlen - math::round_dn(start.off, blocksize as usize);
+        let bytes = rem.min(zeros.size());
+        syscalls::derive_mem(
+            Activity::own().sel(),
+            sel,
+            zeros.mem().sel(),
+            0,
+            bytes as goff,
+            Perm::R,
+        )?;
+        bytes
+    }
+    else {
+        crate::backend_mut().get_filedata(*ext, start.off, perms, sel, Some(limit))?
+    };
 
     // stop at file end
     if (start.ext == (inode.extents - 1) as usize)
@@ -191,13 +232,14 @@ pub fn get_extent_mem(
 ///
 /// `pos` denotes the position where to append to, `sel` the selector to use for the MemGate, `perm`
 /// the permissions for the MemGate, and `accessed` denotes the number of times we already accessed
-/// this file.
+/// this file. New blocks are charged to `quota`.
 pub fn req_append(
     inode: &INodeRef,
     pos: &ExtPos,
     sel: Selector,
     perm: Perm,
     limit: &mut LoadLimit,
+    quota: &Quota,
 ) -> Result<(usize, usize, Option<Extent>), Error> {
     let num_extents = inode.extents;
 
@@ -209,16 +251,22 @@ pub fn req_append(
         num_extents
     );
 
-    if pos.ext < inode.extents as usize {
-        let mut indir = None;
-        let ext = get_extent(inode, pos.ext, &mut indir, false)?;
+    let mut indir = None;
+    let last = if pos.ext < inode.extents as usize {
+        Some(get_extent(inode, pos.ext, &mut indir, false)?)
+    }
+    else {
+        None
+    };
 
+    // holes are never continued, but followed by a new extent
+    if let Some(ext) = last.filter(|e| !e.is_hole()) {
         let extlen = (ext.length * crate::superblock().block_size) as usize;
         let bytes = crate::backend_mut().get_filedata(*ext, pos.off, perm, sel, Some(limit))?;
         Ok((bytes, extlen, None))
     }
     else {
-        let ext = create_extent(None, crate::settings().extend as u32)?;
+        let ext = create_extent(inode, false, crate::settings().extend as u32, quota)?;
 
         // this is a new extent we don't have to load it
         let load = if crate::settings().clear {
@@ -252,7 +300,14 @@ pub fn append_extent(inode: &INodeRef, next: Extent) -> Result<bool, Error> {
     // try to load existing inode
     let ext = if inode.extents > 0 {
         let ext = get_extent(inode, (inode.extents - 1) as usize, &mut indir, false)?;
-        if ext.start + ext.length != next.start {
+        // holes can only be merged with holes and blocks only with adjacent blocks
+        let mergeable = if ext.is_hole() {
+            next.is_hole()
+        }
+        else {
+            !next.is_hole() && ext.start + ext.length == next.start
+        };
+        if !mergeable {
             None
         }
         else {
@@ -317,7 +372,7 @@ pub fn get_extent(
                     return Err(Error::new(Code::NotFound));
                 }
                 // alloc block for indirect extents and put in inode
-                let indirect_block = crate::blocks_mut().alloc(None)?;
+                let indirect_block = alloc_block(inode, None)?;
                 inode.as_mut().indirect = indirect_block;
                 created = true;
             }
@@ -346,7 +401,7 @@ pub fn get_extent(
             if !create {
                 return Err(Error::new(Code::NotFound));
             }
-            let dindirect_block = crate::blocks_mut().alloc(None)?;
+            let dindirect_block = alloc_block(inode, None)?;
             inode.as_mut().dindirect = dindirect_block;
             created = true;
         }
@@ -369,7 +424,7 @@ pub fn get_extent(
             (extent / crate::superblock().extents_per_block()) * NUM_EXT_BYTES,
         );
         if ptr.length == 0 {
-            ptr.as_mut().start = crate::blocks_mut().alloc(None)?;
+            ptr.as_mut().start = alloc_block(inode, None)?;
             ptr.as_mut().length = 1;
             created = true;
         }
@@ -436,7 +491,7 @@ fn change_extent(
         // we assume that we only delete extents at the end; thus, if its the first, we can remove
         // the indirect block as well.
         if remove && extent == 0 {
-            crate::blocks_mut().free(inode.indirect as usize, 1)?;
+            free_block(inode, inode.indirect)?;
             inode.as_mut().indirect = 0;
         }
 
@@ -460,14 +515,14 @@ fn change_extent(
         if remove {
             // Is first block in dind block
             if ext_loc == 0 {
-                crate::blocks_mut().free(ptr.start as usize, 1)?;
+                free_block(inode, ptr.start)?;
                 ptr.as_mut().start = 0;
                 ptr.as_mut().length = 0;
             }
 
             // for the double-indirect too
             if extent == 0 {
-                crate::blocks_mut().free(inode.dindirect as usize, 1)?;
+                free_block(inode, inode.dindirect)?;
                 inode.as_mut().dindirect = 0;
             }
         }
@@ -479,14 +534,52 @@ fn change_extent(
     Err(Error::new(Code::NotFound))
 }
 
-/// Creates a new extent for given inode with given number of blocks
+/// Allocates a block for the metadata of `inode` (e.g., extents or extended attributes) and charges
+/// it to the quota that owns `inode` or adopts it (see [`quota::adopt`])
+pub fn alloc_block(inode: &INodeRef, adopter: Option<&Quota>) -> Result<BlockNo, Error> {
+    quota::charge_blocks(inode, adopter, 1)?;
+    crate::blocks_mut().alloc(None).map_err(|e| {
+        quota::release_blocks(inode, 1);
+        e
+    })
+}
+
+/// Frees the metadata block `bno` of `inode` and credits it to the quota that owns `inode`
+pub fn free_block(inode: &INodeRef, bno: BlockNo) -> Result<(), Error> {
+    let freed = crate::blocks_mut().free(bno as usize, 1)?;
+    quota::release_blocks(inode, freed);
+    Ok(())
+}
+
+/// Creates a new extent for given inode with given number of blocks and adds it to the extent
+/// count and the size of `inode` if `attach` is true
 ///
-/// `accessed` denotes the number of times we already accessed this file.
+/// The blocks are charged to the quota that owns `inode` or `quota` if there is no owner (see
+/// [`quota::adopt`]). If the quota does not suffice for all blocks, the extent is shortened
+/// accordingly.
 ///
 /// Returns the created extent
-pub fn create_extent(inode: Option<&INodeRef>, blocks: u32) -> Result<Extent, Error> {
-    let mut count = blocks as usize;
-    let start = crate::blocks_mut().alloc(Some(&mut count))?;
+pub fn create_extent(
+    inode: &INodeRef,
+    attach: bool,
+    blocks: u32,
+    quota: &Quota,
+) -> Result<Extent, Error> {
+    quota::adopt(inode, quota)?;
+    let req = (blocks as usize).min(quota::avail_blocks(inode)).max(1);
+    quota::charge_blocks(inode, Some(quota), req)?;
+
+    let mut count = req;
+    let start = match crate::blocks_mut().alloc(Some(&mut count)) {
+        Ok(start) => start,
+        Err(e) => {
+            quota::release_blocks(inode, req);
+            return Err(e);
+        },
+    };
+    // the allocator might have found less blocks than requested
+    quota::release_blocks(inode, req - count);
+
     let ext = Extent::new(start, count as u32);
 
     let blocksize = crate::superblock().block_size;
@@ -494,17 +587,47 @@ pub fn create_extent(inode: Option<&INodeRef>, blocks: u32) -> Result<Extent, Er
         crate::backend_mut().clear_extent(ext)?;
     }
 
-    if let Some(ino) = inode {
-        let old_size = ino.size;
-        ino.as_mut().extents += 1;
-        ino.as_mut().size = (old_size + blocksize as u64 - 1) & !(blocksize as u64 - 1);
-        ino.as_mut().size += (count * blocksize as usize) as u64;
+    if attach {
+        let old_size = inode.size;
+        inode.as_mut().extents += 1;
+        inode.as_mut().size = (old_size + blocksize as u64 - 1) & !(blocksize as u64 - 1);
+        inode.as_mut().size += (count * blocksize as usize) as u64;
     }
 
     Ok(ext)
 }
 
-/// Truncates the given inode until the given position.
+/// Returns the number of blocks that `inode` occupies, including the blocks for indirect extents and
+/// extended attributes
+pub fn count_blocks(inode: &INodeRef) -> Result<usize, Error> {
+    let per_block = crate::superblock().extents_per_block();
+    let count = inode.extents as usize;
+
+    let mut blocks = 0;
+    let mut indir = None;
+    for i in 0..count {
+        let ext = get_extent(inode, i, &mut indir, false)?;
+        if !ext.is_hole() {
+            blocks += ext.length as usize;
+        }
+    }
+
+    if inode.indirect != 0 {
+        blocks += 1;
+    }
+    if inode.dindirect != 0 {
+        // the double indirect block and the indirect blocks it refers to
+        let rem = count.saturating_sub(INODE_DIR_COUNT + per_block);
+        blocks += 1 + (rem + per_block - 1) / per_block;
+    }
+    if inode.xattr != 0 {
+        blocks += 1;
+    }
+    Ok(blocks)
+}
+
+/// Truncates the given inode until the given position and credits the freed blocks to the quota
+/// that owns the inode.
 pub fn truncate(inode: &INodeRef, pos: &ExtPos) -> Result<(), Error> {
     log!(
         crate::LOG_INODES,
@@ -523,7 +646,11 @@ pub fn truncate(inode: &INodeRef, pos: &ExtPos) -> Result<(), Error> {
         let mut i = iextents - 1;
         while i > pos.ext {
             let ext = change_extent(inode, i, &mut indir, true)?;
-            crate::blocks_mut().free(ext.start as usize, ext.length as usize)?;
+            if !ext.is_hole() {
+                // shared blocks are only freed with their last reference
+                let freed = crate::blocks_mut().free(ext.start as usize, ext.length as usize)?;
+                quota::release_blocks(inode, freed);
+            }
             inode.as_mut().extents -= 1;
             inode.as_mut().size -= (ext.length * blocksize) as u64;
             ext.as_mut().start = 0;
@@ -551,9 +678,11 @@ pub fn truncate(inode: &INodeRef, pos: &ExtPos) -> Result<(), Error> {
                     diff
                 };
                 let blocks = bdiff / blocksize as usize;
-                if blocks > 0 {
+                if blocks > 0 && !ext.is_hole() {
                     // free all of these blocks
-                    crate::blocks_mut().free((ext.start + ext.length) as usize - blocks, blocks)?;
+                    let freed = crate::blocks_mut()
+                        .free((ext.start + ext.length) as usize - blocks, blocks)?;
+                    quota::release_blocks(inode, freed);
                 }
                 inode.as_mut().size -= diff as u64;
                 ext.as_mut().length = (ext.length as usize - blocks) as u32;
@@ -579,7 +708,7 @@ pub fn sync_metadata(inode: &INodeRef) -> Result<(), Error> {
     for ext in inode.extent_iter() {
         for mut block in ext.block_iter() {
             crate::backend_mut().sync_meta(&mut block)?;
-            block.flush()?;
+            crate::meta_buffer_mut().flush_block(&mut block)?;
         }
     }
     Ok(())
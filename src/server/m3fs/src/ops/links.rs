@@ -14,15 +14,19 @@
  * General Public License version 2 for more details.
  */
 
-use crate::data::{DirEntry, INodeRef, DIR_ENTRY_LEN};
+use crate::buf::MetaBufferBlock;
+use crate::data::{DirEntry, INodeRef, InodeNo, DIR_ENTRY_LEN};
+use crate::ops::dirhash;
 use crate::ops::inodes;
+use crate::ops::quota::Quota;
 
 use m3::errors::{Code, Error};
 
 /// Creates a link in directory `dir` with given name pointing to `inode`.
 ///
-/// Assumes that no entry with given name already exists!
-pub fn create(dir: &INodeRef, name: &str, inode: &INodeRef) -> Result<(), Error> {
+/// Assumes that no entry with given name already exists! If the directory needs to be extended,
+/// the new block is charged as described for [`inodes::create_extent`].
+pub fn create(dir: &INodeRef, name: &str, inode: &INodeRef, quota: &Quota) -> Result<(), Error> {
     log!(
         crate::LOG_LINKS,
         "links::create(dir={}, name={}, inode={})",
@@ -31,64 +35,85 @@ pub fn create(dir: &INodeRef, name: &str, inode: &INodeRef) -> Result<(), Error>
         inode.inode,
     );
 
-    let mut created = false;
-    let new_entry_size = DIR_ENTRY_LEN + name.len();
-
-    'search_loop: for ext in dir.extent_iter() {
-        for mut block in ext.block_iter() {
-            let mut off = 0;
-            let end = crate::superblock().block_size as usize;
-            while off < end {
-                let entry = DirEntry::from_buffer_mut(&mut block, off);
-
-                let rem = entry.next - entry.size() as u32;
-                if rem >= new_entry_size as u32 {
-                    // change current entry
-                    entry.next = entry.size() as u32;
-                    let entry_next = entry.next;
-
-                    // create new entry behind it
-                    let new_entry =
-                        DirEntry::from_buffer_mut(&mut block, off + entry_next as usize);
-
-                    new_entry.set_name(name);
-                    new_entry.nodeno = inode.inode;
-                    new_entry.next = rem;
-
+    if dirhash::enabled() {
+        dirhash::insert(dir, name, inode.inode, quota)?;
+    }
+    else {
+        let mut created = false;
+        'search_loop: for ext in dir.extent_iter() {
+            for mut block in ext.block_iter() {
+                if insert_into(&mut block, name, inode.inode) {
                     created = true;
                     break 'search_loop;
                 }
-
-                off += entry.next as usize;
             }
         }
-    }
 
-    // no suitable space found; extend directory
-    if !created {
-        let mut indir = None;
-        let ext = inodes::get_extent(dir, dir.extents as usize, &mut indir, true)?;
-
-        // insert one block extent
-        let ext_range = inodes::create_extent(Some(dir), 1)?;
-        *ext.as_mut() = ext_range;
-
-        // put entry at the beginning of the block
-        let start = ext.start;
-        let mut block = crate::meta_buffer_mut().get_block(start)?;
-        let new_entry = DirEntry::from_buffer_mut(&mut block, 0);
-        new_entry.set_name(name);
-        new_entry.nodeno = inode.inode;
-        new_entry.next = crate::superblock().block_size;
+        // no suitable space found; extend directory
+        if !created {
+            let mut indir = None;
+            let ext = inodes::get_extent(dir, dir.extents as usize, &mut indir, true)?;
+
+            // insert one block extent
+            let ext_range = inodes::create_extent(dir, true, 1, quota)?;
+            *ext.as_mut() = ext_range;
+
+            // put entry at the beginning of the block
+            let start = ext.start;
+            let mut block = crate::meta_buffer_mut().get_block(start)?;
+            let new_entry = DirEntry::from_buffer_mut(&mut block, 0);
+            new_entry.set_name(name);
+            new_entry.nodeno = inode.inode;
+            new_entry.next = crate::superblock().block_size;
+        }
     }
 
     inode.as_mut().links += 1;
     Ok(())
 }
 
+/// Inserts an entry with given name pointing to `ino` into the given directory block.
+///
+/// Returns false if there is not enough space left in the block.
+pub fn insert_into(block: &mut MetaBufferBlock, name: &str, ino: InodeNo) -> bool {
+    let new_entry_size = (DIR_ENTRY_LEN + name.len()) as u32;
+
+    let mut off = 0;
+    let end = crate::superblock().block_size as usize;
+    while off < end {
+        let entry = DirEntry::from_buffer_mut(block, off);
+
+        // reuse empty entries if they are large enough
+        if entry.is_empty() && entry.next >= new_entry_size {
+            entry.set_name(name);
+            entry.nodeno = ino;
+            return true;
+        }
+
+        let rem = entry.next - entry.size() as u32;
+        if rem >= new_entry_size {
+            // change current entry
+            entry.next = entry.size() as u32;
+            let entry_next = entry.next;
+
+            // create new entry behind it
+            let new_entry = DirEntry::from_buffer_mut(block, off + entry_next as usize);
+
+            new_entry.set_name(name);
+            new_entry.nodeno = ino;
+            new_entry.next = rem;
+            return true;
+        }
+
+        off += entry.next as usize;
+    }
+    false
+}
+
 /// Removes the link with given name from `dir`
 ///
-/// If `deny_dir` is true, the function fails if the link points to a directory.
+/// If `deny_dir` is true, the function fails if the link points to a directory. If the inode is
+/// deleted, its space is credited to the quota that owns it.
 pub fn remove(dir: &INodeRef, name: &str, deny_dir: bool) -> Result<(), Error> {
     log!(
         crate::LOG_LINKS,
@@ -98,60 +123,94 @@ pub fn remove(dir: &INodeRef, name: &str, deny_dir: bool) -> Result<(), Error> {
         deny_dir
     );
 
-    for ext in dir.extent_iter() {
-        for mut block in ext.block_iter() {
-            let mut prev_off = 0;
-            let mut off = 0;
-            let end = crate::superblock().block_size as usize;
-            while off < end {
-                // TODO marking all blocks dirty here is suboptimal
-                let entry = DirEntry::from_buffer_mut(&mut block, off);
-
-                if entry.name() == name {
-                    // if we're not removing a dir, we're coming from unlink(). in this case,
-                    // directories are not allowed
-                    let inode = inodes::get(entry.nodeno)?;
-                    if deny_dir && inode.mode.is_dir() {
-                        return Err(Error::new(Code::IsDir));
-                    }
-
-                    let entry_next = entry.next;
-
-                    // remove entry by skipping over it
-                    if off > 0 {
-                        let mut prev = DirEntry::from_buffer_mut(&mut block, prev_off);
-                        prev.next += entry_next;
-                    }
-                    // copy the next entry back, if there is any
-                    else {
-                        let next_off = off + entry_next as usize;
-                        if next_off < end {
-                            let (cur_entry, next_entry) = DirEntry::two_from_buffer_mut(
-                                &mut block,
-                                off,
-                                off + entry_next as usize,
-                            );
-
-                            let dist = cur_entry.next;
-                            cur_entry.next = next_entry.next;
-                            cur_entry.nodeno = next_entry.nodeno;
-
-                            cur_entry.set_name(next_entry.name());
-                            cur_entry.next = dist + next_entry.next;
-                        }
-                    }
-
-                    // reduce links and free if necessary
-                    inodes::decrease_links(&inode)?;
-
-                    return Ok(());
-                }
+    match find_in_blocks(dir, name, |block| remove_from(block, name, deny_dir))? {
+        Some(()) => Ok(()),
+        None => Err(Error::new(Code::NoSuchFile)),
+    }
+}
+
+/// Removes the entry with given name from the given directory block, if it exists.
+fn remove_from(
+    block: &mut MetaBufferBlock,
+    name: &str,
+    deny_dir: bool,
+) -> Result<Option<()>, Error> {
+    let mut prev_off = 0;
+    let mut off = 0;
+    let end = crate::superblock().block_size as usize;
+    while off < end {
+        // TODO marking all blocks dirty here is suboptimal
+        let entry = DirEntry::from_buffer_mut(block, off);
+
+        if !entry.is_empty() && entry.name() == name {
+            // if we're not removing a dir, we're coming from unlink(). in this case,
+            // directories are not allowed
+            let inode = inodes::get(entry.nodeno)?;
+            if deny_dir && inode.mode.is_dir() {
+                return Err(Error::new(Code::IsDir));
+            }
+
+            let entry_next = entry.next;
 
-                prev_off = off;
-                off += entry.next as usize;
+            // remove entry by skipping over it
+            if off > 0 {
+                let prev = DirEntry::from_buffer_mut(block, prev_off);
+                prev.next += entry_next;
             }
+            // copy the next entry back, if there is any
+            else {
+                let next_off = off + entry_next as usize;
+                if next_off < end {
+                    let (cur_entry, next_entry) =
+                        DirEntry::two_from_buffer_mut(block, off, off + entry_next as usize);
+
+                    let dist = cur_entry.next;
+                    cur_entry.next = next_entry.next;
+                    cur_entry.nodeno = next_entry.nodeno;
+
+                    cur_entry.set_name(next_entry.name());
+                    cur_entry.next = dist + next_entry.next;
+                }
+                // otherwise, leave an empty entry behind that spans the whole block
+                else {
+                    entry.clear();
+                }
+            }
+
+            // reduce links and free if necessary
+            inodes::decrease_links(&inode)?;
+
+            return Ok(Some(()));
         }
+
+        prev_off = off;
+        off += entry.next as usize;
+    }
+    Ok(None)
+}
+
+/// Calls `func` for the blocks of directory `dir` that may contain an entry with given name until
+/// `func` returns a result.
+///
+/// In hashed directories, only the bucket of `name` is considered, whereas all blocks are
+/// considered otherwise.
+pub fn find_in_blocks<R, F>(dir: &INodeRef, name: &str, mut func: F) -> Result<Option<R>, Error>
+where
+    F: FnMut(&mut MetaBufferBlock) -> Result<Option<R>, Error>,
+{
+    if dirhash::enabled() {
+        return match dirhash::bucket(dir, name)? {
+            Some(mut block) => func(&mut block),
+            None => Ok(None),
+        };
     }
 
-    Err(Error::new(Code::NoSuchFile))
+    for ext in dir.extent_iter() {
+        for mut block in ext.block_iter() {
+            if let Some(res) = func(&mut block)? {
+                return Ok(Some(res));
+            }
+        }
+    }
+    Ok(None)
 }
@@ -14,6 +14,13 @@
  * General Public License version 2 for more details.
  */
 
+pub mod check;
+pub mod dirhash;
 pub mod dirs;
 pub mod inodes;
 pub mod links;
+pub mod perms;
+pub mod quota;
+pub mod reflink;
+pub mod sparse;
+pub mod xattrs;
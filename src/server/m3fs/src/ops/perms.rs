@@ -0,0 +1,102 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use crate::data::{GroupId, INodeRef, InodeNo, UserId};
+use crate::ops::quota::Quota;
+
+use m3::errors::{Code, Error};
+use m3::rc::Rc;
+use m3::vfs::{FileMode, OpenFlags};
+
+/// The credentials of a session that are used for permission checks
+///
+/// Besides the user and group id, the credentials contain the directory that acts as the root
+/// directory for all path lookups, which cannot be left, and the quota that all allocations are
+/// charged to.
+#[derive(Clone, Debug, Default)]
+pub struct Creds {
+    pub uid: UserId,
+    pub gid: GroupId,
+    pub root_dir: InodeNo,
+    pub quota: Rc<Quota>,
+}
+
+impl Creds {
+    pub fn new(uid: UserId, gid: GroupId, root_dir: InodeNo, quota: Rc<Quota>) -> Self {
+        Self {
+            uid,
+            gid,
+            root_dir,
+            quota,
+        }
+    }
+
+    /// Returns true if these are the credentials of the superuser, which bypasses all checks
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Returns true if these credentials own the given inode
+    pub fn owns(&self, inode: &INodeRef) -> bool {
+        self.is_root() || self.uid == inode.uid
+    }
+}
+
+/// Checks whether `creds` have the permissions `perm` (a combination of R, W, and X) for `inode`
+pub fn check(inode: &INodeRef, creds: &Creds, perm: OpenFlags) -> Result<(), Error> {
+    if creds.is_root() {
+        return Ok(());
+    }
+
+    let (r, w, x) = if creds.uid == inode.uid {
+        (FileMode::IRUSR, FileMode::IWUSR, FileMode::IXUSR)
+    }
+    else if creds.gid == inode.gid {
+        (FileMode::IRGRP, FileMode::IWGRP, FileMode::IXGRP)
+    }
+    else {
+        (FileMode::IROTH, FileMode::IWOTH, FileMode::IXOTH)
+    };
+
+    if (perm.contains(OpenFlags::R) && !inode.mode.contains(r))
+        || (perm.contains(OpenFlags::W) && !inode.mode.contains(w))
+        || (perm.contains(OpenFlags::X) && !inode.mode.contains(x))
+    {
+        log!(
+            crate::LOG_PERMS,
+            "perms: {:?} denied for inode {} (mode={:o}, uid={}, gid={}) and {:?}",
+            perm,
+            inode.inode,
+            inode.mode,
+            inode.uid,
+            inode.gid,
+            creds,
+        );
+        return Err(Error::new(Code::NoPerm));
+    }
+    Ok(())
+}
+
+/// Checks whether `creds` may remove or replace the entry for `inode` in directory `dir`.
+///
+/// Besides write access to the directory, this requires ownership of the directory or the entry if
+/// the sticky bit is set for the directory.
+pub fn check_remove(dir: &INodeRef, inode: &INodeRef, creds: &Creds) -> Result<(), Error> {
+    check(dir, creds, OpenFlags::W)?;
+    if dir.mode.contains(FileMode::ISSTICKY) && !creds.owns(dir) && !creds.owns(inode) {
+        return Err(Error::new(Code::NoPerm));
+    }
+    Ok(())
+}
@@ -0,0 +1,327 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use crate::data::INodeRef;
+use crate::ops::inodes;
+
+use m3::cell::{Cell, StaticCell, StaticRefCell};
+use m3::col::Vec;
+use m3::errors::{Code, Error};
+use m3::rc::Rc;
+
+/// The id of a quota, which is unique within one mount of the file system
+pub type QuotaId = u32;
+
+// the next quota id; 0 denotes inodes without owner
+static NEXT_ID: StaticCell<QuotaId> = StaticCell::new(1);
+// the quotas that belong to a session or are still charged for inodes
+static QUOTAS: StaticRefCell<Vec<Rc<Quota>>> = StaticRefCell::new(Vec::new());
+// the quotas and their usage (blocks, inodes) at the end of the last committed request
+static COMMITTED: StaticRefCell<Vec<(Rc<Quota>, usize, usize)>> = StaticRefCell::new(Vec::new());
+
+/// The limit for a single resource (blocks or inodes) and its current usage
+#[derive(Debug)]
+struct Limit {
+    max: usize,
+    used: Cell<usize>,
+}
+
+impl Limit {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: Cell::new(0),
+        }
+    }
+
+    fn avail(&self) -> usize {
+        self.max.saturating_sub(self.used.get())
+    }
+
+    fn charge(&self, count: usize) {
+        self.used.set(self.used.get() + count);
+    }
+
+    fn release(&self, count: usize) {
+        self.used.set(self.used.get().saturating_sub(count));
+    }
+}
+
+/// Returns the quota that owns `inode`, if it exists
+///
+/// The owner is stored in the inode together with the mount it has been created in, because the
+/// quotas are not persistent.
+pub fn owner(inode: &INodeRef) -> Option<Rc<Quota>> {
+    if inode.quota_mount == crate::superblock().mounts {
+        Quota::get(inode.quota)
+    }
+    else {
+        None
+    }
+}
+
+/// Returns the quota that owns `inode`. If the owner does not exist (anymore), `inode` is adopted by
+/// `adopter`, which is charged for the inode and all its existing blocks. Fails with
+/// [`Code::NoSpace`] if these exceed the quota of `adopter`.
+pub fn adopt(inode: &INodeRef, adopter: &Quota) -> Result<Option<Rc<Quota>>, Error> {
+    if let Some(q) = owner(inode) {
+        return Ok(Some(q));
+    }
+
+    // the placeholder quota never owns inodes
+    let adopter = match Quota::get(adopter.id()) {
+        Some(q) => q,
+        None => return Ok(None),
+    };
+
+    let blocks = inodes::count_blocks(inode)?;
+    adopter.charge_inodes(1)?;
+    if let Err(e) = adopter.charge_blocks(blocks) {
+        adopter.release_inodes(1);
+        return Err(e);
+    }
+
+    log!(
+        crate::LOG_ALLOC,
+        "quota[{}]: adopted inode {} with {} blocks",
+        adopter.id(),
+        inode.inode,
+        blocks
+    );
+
+    inode.as_mut().quota_mount = crate::superblock().mounts;
+    inode.as_mut().quota = adopter.id();
+    Ok(Some(adopter))
+}
+
+/// Remembers the current usage of all quotas as the usage of the last committed request
+pub fn commit() {
+    let quotas = QUOTAS.borrow();
+    let mut committed = COMMITTED.borrow_mut();
+    committed.clear();
+    committed.extend(
+        quotas
+            .iter()
+            .map(|q| (q.clone(), q.blocks.used.get(), q.inodes.used.get())),
+    );
+}
+
+/// Restores the usage of all quotas of the last committed request, because the changes of the
+/// current request have been discarded
+pub fn rollback() {
+    let mut quotas = QUOTAS.borrow_mut();
+    let committed = COMMITTED.borrow();
+
+    // quotas that have been created since then have not been charged for committed changes
+    for q in quotas.iter() {
+        q.blocks.used.set(0);
+        q.inodes.used.set(0);
+    }
+
+    for (q, blocks, inodes) in committed.iter() {
+        q.blocks.used.set(*blocks);
+        q.inodes.used.set(*inodes);
+        // quotas that have been removed since then might be charged again
+        if !quotas.iter().any(|o| o.id == q.id) {
+            quotas.push(q.clone());
+        }
+    }
+    quotas.retain(|q| !q.unused());
+}
+
+/// Returns the number of blocks that can still be allocated for `inode`
+pub fn avail_blocks(inode: &INodeRef) -> usize {
+    owner(inode)
+        .map(|q| q.avail_blocks())
+        .unwrap_or(Quota::UNLIMITED)
+}
+
+/// Charges `count` blocks for `inode` to the quota that owns it or adopts it (see [`adopt`])
+pub fn charge_blocks(inode: &INodeRef, adopter: Option<&Quota>, count: usize) -> Result<(), Error> {
+    let quota = match adopter {
+        Some(a) => adopt(inode, a)?,
+        None => owner(inode),
+    };
+    match quota {
+        Some(q) => q.charge_blocks(count),
+        None => Ok(()),
+    }
+}
+
+/// Credits `count` blocks of `inode` to the quota that owns it
+pub fn release_blocks(inode: &INodeRef, count: usize) {
+    if let Some(q) = owner(inode) {
+        q.release_blocks(count);
+    }
+}
+
+/// Credits `inode` itself to the quota that owns it
+pub fn release_inode(inode: &INodeRef) {
+    if let Some(q) = owner(inode) {
+        q.release_inodes(1);
+    }
+}
+
+/// The number of blocks and inodes a session can allocate
+///
+/// Quotas are nested: the quota of a derived session has the quota of the session it has been
+/// derived from as its parent. Allocations are charged to the complete chain, so that a session
+/// cannot exceed the limits of its parents.
+///
+/// Each inode is owned by the quota of the session that created it. The owner is charged for the
+/// inode and all its blocks, including indirect blocks and the block for extended attributes,
+/// independent of the session that performs an allocation or a release. Quotas stay alive after
+/// their session has been closed until all their inodes have been removed.
+///
+/// The usage is not persistent. Inodes from a previous mount are therefore adopted by the quota of
+/// the next session that allocates blocks for them, which is charged for the inode and its existing
+/// blocks at that point.
+#[derive(Debug)]
+pub struct Quota {
+    id: QuotaId,
+    parent: Option<Rc<Quota>>,
+    blocks: Limit,
+    inodes: Limit,
+    closed: Cell<bool>,
+}
+
+impl Quota {
+    /// A quota without a limit
+    pub const UNLIMITED: usize = usize::MAX;
+
+    /// Creates a new quota with at most `blocks` blocks and `inodes` inodes
+    pub fn new(parent: Option<Rc<Quota>>, blocks: usize, inodes: usize) -> Rc<Self> {
+        let id = NEXT_ID.get();
+        NEXT_ID.set(id + 1);
+        let quota = Rc::new(Self {
+            id,
+            parent,
+            blocks: Limit::new(blocks),
+            inodes: Limit::new(inodes),
+            closed: Cell::new(false),
+        });
+        QUOTAS.borrow_mut().push(quota.clone());
+        quota
+    }
+
+    /// Returns the quota with given id, if it exists
+    pub fn get(id: QuotaId) -> Option<Rc<Self>> {
+        QUOTAS.borrow().iter().find(|q| q.id == id).cloned()
+    }
+
+    /// Returns the id of this quota
+    pub fn id(&self) -> QuotaId {
+        self.id
+    }
+
+    /// Marks this quota as closed, because its session is gone. It is removed as soon as it is not
+    /// charged for anything anymore.
+    pub fn close(&self) {
+        self.closed.set(true);
+        self.collect();
+    }
+
+    fn collect(&self) {
+        // parents are kept alive by their children, but are charged for them as well
+        for q in self.chain() {
+            if q.unused() {
+                QUOTAS.borrow_mut().retain(|o| o.id != q.id);
+            }
+        }
+    }
+
+    fn unused(&self) -> bool {
+        self.closed.get() && self.blocks.used.get() == 0 && self.inodes.used.get() == 0
+    }
+
+    /// Returns the maximum and the currently used number of blocks of this quota
+    pub fn blocks(&self) -> (usize, usize) {
+        (self.blocks.max, self.blocks.used.get())
+    }
+
+    /// Returns the maximum and the currently used number of inodes of this quota
+    pub fn inodes(&self) -> (usize, usize) {
+        (self.inodes.max, self.inodes.used.get())
+    }
+
+    fn chain(&self) -> impl Iterator<Item = &Quota> {
+        core::iter::successors(Some(self), |q| q.parent.as_deref())
+    }
+
+    /// Returns the number of blocks that can still be allocated, considering all parents
+    pub fn avail_blocks(&self) -> usize {
+        self.chain().map(|q| q.blocks.avail()).min().unwrap()
+    }
+
+    /// Returns the number of inodes that can still be allocated, considering all parents
+    pub fn avail_inodes(&self) -> usize {
+        self.chain().map(|q| q.inodes.avail()).min().unwrap()
+    }
+
+    /// Charges `count` blocks to this quota or fails with [`Code::NoSpace`] if they exceed it
+    pub fn charge_blocks(&self, count: usize) -> Result<(), Error> {
+        if count > self.avail_blocks() {
+            log!(
+                crate::LOG_ALLOC,
+                "quota[{}]: allocation of {} blocks exceeds quota",
+                self.id,
+                count
+            );
+            return Err(Error::new(Code::NoSpace));
+        }
+        self.chain().for_each(|q| q.blocks.charge(count));
+        Ok(())
+    }
+
+    /// Credits `count` blocks to this quota
+    pub fn release_blocks(&self, count: usize) {
+        self.chain().for_each(|q| q.blocks.release(count));
+        self.collect();
+    }
+
+    /// Charges `count` inodes to this quota or fails with [`Code::NoSpace`] if they exceed it
+    pub fn charge_inodes(&self, count: usize) -> Result<(), Error> {
+        if count > self.avail_inodes() {
+            log!(
+                crate::LOG_ALLOC,
+                "quota[{}]: allocation of {} inodes exceeds quota",
+                self.id,
+                count
+            );
+            return Err(Error::new(Code::NoSpace));
+        }
+        self.chain().for_each(|q| q.inodes.charge(count));
+        Ok(())
+    }
+
+    /// Credits `count` inodes to this quota
+    pub fn release_inodes(&self, count: usize) {
+        self.chain().for_each(|q| q.inodes.release(count));
+        self.collect();
+    }
+}
+
+impl Default for Quota {
+    // a placeholder that is not registered and thus never owns inodes
+    fn default() -> Self {
+        Self {
+            id: 0,
+            parent: None,
+            blocks: Limit::new(Self::UNLIMITED),
+            inodes: Limit::new(Self::UNLIMITED),
+            closed: Cell::new(true),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+//! Copy-on-write clones of files
+//!
+//! A clone shares all blocks with the original file. The block allocator counts the additional
+//! references to each block, so that a block is only freed with its last reference. Before a shared
+//! block is written, it is copied and the writer gets the copy.
+
+use crate::buf::LoadLimit;
+use crate::data::{BlockNo, ExtPos, Extent, INodeRef};
+use crate::ops::perms::{self, Creds};
+use crate::ops::quota::{self, Quota};
+use crate::ops::{dirs, inodes, sparse};
+
+use m3::col::Vec;
+use m3::com::{MemGate, Perm};
+use m3::errors::{Code, Error};
+use m3::tiles::Activity;
+use m3::vfs::OpenFlags;
+
+/// Creates the file `dst_path` as a clone of the file `src_path`
+///
+/// Fails with [`Code::InUse`] if `src_path` is open for writing.
+pub fn clone_file(src_path: &str, dst_path: &str, creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_REFLINK,
+        "reflink::clone_file(src_path={}, dst_path={})",
+        src_path,
+        dst_path
+    );
+
+    if !crate::blocks_mut().shareable() {
+        return Err(Error::new(Code::NotSup));
+    }
+
+    let src = inodes::get(dirs::search(src_path, false, creds)?)?;
+    if src.mode.is_dir() {
+        return Err(Error::new(Code::IsDir));
+    }
+    perms::check(&src, creds, OpenFlags::R)?;
+
+    // the extents are not final while an append is in progress and writers might still have access
+    // to the blocks that would be shared afterwards
+    if let Some(file) = crate::open_files_mut().get_file_mut(src.inode) {
+        if file.appending() || file.writers() > 0 {
+            return Err(Error::new(Code::InUse));
+        }
+    }
+
+    if dirs::search_nofollow(dst_path, creds).is_ok() {
+        return Err(Error::new(Code::Exists));
+    }
+    let dst = inodes::get(dirs::search(dst_path, true, creds)?)?;
+    dst.as_mut().mode = src.mode;
+
+    if let Err(e) = share_extents(&src, &dst) {
+        // removing the clone drops the references we have added so far
+        dirs::unlink(dst_path, true, creds)?;
+        return Err(e);
+    }
+    dst.as_mut().size = src.size;
+    Ok(())
+}
+
+fn share_extents(src: &INodeRef, dst: &INodeRef) -> Result<(), Error> {
+    let blocksize = crate::superblock().block_size as u64;
+    let mut indir = None;
+    for i in 0..src.extents as usize {
+        let ext = *inodes::get_extent(src, i, &mut indir, false)?;
+        if !ext.is_hole() {
+            crate::blocks_mut().share(ext.start as usize, ext.length as usize)?;
+        }
+        inodes::append_extent(dst, ext)?;
+        // keep the size consistent with the extents in case we need to remove the clone again
+        dst.as_mut().size += ext.length as u64 * blocksize;
+    }
+    Ok(())
+}
+
+/// Copies up to `count` shared blocks of `inode`, starting with the block that contains `pos`, so
+/// that the memory for `pos` can be handed out for writing. The copies are charged as described for
+/// [`inodes::create_extent`].
+///
+/// Returns the position that corresponds to `pos` afterwards.
+pub fn unshare(inode: &INodeRef, pos: &ExtPos, count: u32, quota: &Quota) -> Result<ExtPos, Error> {
+    if !crate::blocks_mut().shareable() || pos.ext >= inode.extents as usize {
+        return Ok(*pos);
+    }
+
+    let blocksize = crate::superblock().block_size as usize;
+    let ext = *inodes::get_extent(inode, pos.ext, &mut None, false)?;
+    let first = (pos.off / blocksize) as u32;
+    if ext.is_hole() || first >= ext.length {
+        return Ok(*pos);
+    }
+
+    // determine the shared blocks at and behind `pos`
+    let shared = {
+        let blocks = crate::blocks_mut();
+        let mut shared = Vec::new();
+        for i in first..ext.length {
+            shared.push(blocks.refs((ext.start + i) as usize)? > 0);
+        }
+        shared
+    };
+    let unshared = shared.iter().take_while(|s| !**s).count() as u32;
+    if first + unshared == ext.length {
+        return Ok(*pos);
+    }
+
+    // the memory for `pos` reaches until the end of the extent; thus, split it before shared blocks
+    if unshared > 0 {
+        let split = first + unshared;
+        sparse::replace_extent(inode, pos.ext, &[
+            Extent::new(ext.start, split),
+            Extent::new(ext.start + split, ext.length - split),
+        ])?;
+        return Ok(*pos);
+    }
+
+    let run = shared.iter().take_while(|s| **s).count() as u32;
+    let copy = inodes::create_extent(inode, false, count.min(run), quota)?;
+
+    log!(
+        crate::LOG_REFLINK,
+        "reflink::unshare(inode={}, pos={:?}, ext={:?}) -> {:?}",
+        inode.inode,
+        pos,
+        ext,
+        copy
+    );
+
+    copy_blocks(ext.start + first, copy)?;
+    let freed = crate::blocks_mut().free((ext.start + first) as usize, copy.length as usize)?;
+    quota::release_blocks(inode, freed);
+
+    let rest = first + copy.length;
+    sparse::replace_extent(inode, pos.ext, &[
+        Extent::new(ext.start, first),
+        copy,
+        Extent::new(ext.start + rest, ext.length - rest),
+    ])?;
+
+    let ext_idx = if first > 0 { pos.ext + 1 } else { pos.ext };
+    let ext_off = pos.off - first as usize * blocksize;
+    Ok(ExtPos::new(ext_idx, ext_off))
+}
+
+/// Copies the blocks starting at `src` into the blocks of `dst`
+fn copy_blocks(src: BlockNo, dst: Extent) -> Result<(), Error> {
+    let blocksize = crate::superblock().block_size as usize;
+    let mut buf = vec![0u8; blocksize];
+
+    for i in 0..dst.length {
+        let src_sel = Activity::own().alloc_sel();
+        crate::backend_mut().get_filedata(
+            Extent::new(src + i, 1),
+            0,
+            Perm::R,
+            src_sel,
+            Some(&mut LoadLimit::new()),
+        )?;
+        // the capabilities are revoked on drop
+        let src_mem = MemGate::new_owned_bind(src_sel);
+        src_mem.read(&mut buf, 0)?;
+
+        // the destination is overwritten completely, so that it does not need to be loaded
+        let dst_sel = Activity::own().alloc_sel();
+        crate::backend_mut().get_filedata(
+            Extent::new(dst.start + i, 1),
+            0,
+            Perm::RW,
+            dst_sel,
+            None,
+        )?;
+        let dst_mem = MemGate::new_owned_bind(dst_sel);
+        dst_mem.write(&buf, 0)?;
+    }
+    Ok(())
+}
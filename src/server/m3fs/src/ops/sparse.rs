@@ -0,0 +1,291 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+//! Sparse files
+//!
+//! Files can contain holes, which are extents that do not occupy any blocks (see
+//! [`Extent::is_hole`]). Holes read as zeros and get blocks as soon as they are written. To keep
+//! appends simple, only whole blocks become holes: if the file size is not block aligned, the last
+//! block is always backed by a block.
+
+use crate::buf::LoadLimit;
+use crate::data::{ExtPos, Extent, INodeRef};
+use crate::ops::quota::{self, Quota};
+use crate::ops::{inodes, reflink};
+
+use m3::col::Vec;
+use m3::com::{MemGate, Perm};
+use m3::errors::Error;
+use m3::goff;
+use m3::math;
+use m3::tiles::Activity;
+use m3::vfs::SeekMode;
+
+/// The number of blocks of zeros that are handed out for a hole at once
+const ZERO_BLOCKS: usize = 16;
+
+/// The memory that is handed out to clients to read holes
+pub struct Zeros {
+    mem: MemGate,
+    size: usize,
+}
+
+impl Zeros {
+    pub fn new(block_size: usize) -> Result<Self, Error> {
+        let size = ZERO_BLOCKS * block_size;
+        let mem = MemGate::new(size, Perm::RW)?;
+        let zeros = vec![0u8; block_size];
+        for i in 0..ZERO_BLOCKS {
+            mem.write(&zeros, (i * block_size) as goff)?;
+        }
+        Ok(Self { mem, size })
+    }
+
+    pub fn mem(&self) -> &MemGate {
+        &self.mem
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Extends `inode` to `size` bytes by appending a hole and charges the potentially required new
+/// last block to the owner of `inode` or `quota` (see [`inodes::create_extent`]).
+pub fn extend(inode: &INodeRef, size: usize, quota: &Quota) -> Result<(), Error> {
+    let blocksize = crate::superblock().block_size as usize;
+    let old = inode.size as usize;
+    if size <= old {
+        return Ok(());
+    }
+
+    log!(
+        crate::LOG_INODES,
+        "sparse::extend(inode={}, size={} -> {})",
+        inode.inode,
+        old,
+        size
+    );
+
+    // the rest of the current last block might contain stale data
+    zero(inode, old, size.min(math::round_up(old, blocksize)), quota)?;
+
+    let old_blocks = math::round_up(old, blocksize) / blocksize;
+    let new_blocks = math::round_up(size, blocksize) / blocksize;
+    let full_blocks = size / blocksize;
+    if full_blocks > old_blocks {
+        inodes::append_extent(inode, Extent::hole((full_blocks - old_blocks) as u32))?;
+    }
+    if new_blocks > full_blocks.max(old_blocks) {
+        let ext = alloc_zeroed(inode, 1, quota)?;
+        inodes::append_extent(inode, ext)?;
+    }
+
+    inode.as_mut().size = size as u64;
+    Ok(())
+}
+
+/// Allocates up to `count` blocks for the hole at `pos`, starting with the block that contains
+/// `pos`, and charges them to the owner of `inode` or `quota` (see [`inodes::create_extent`]).
+///
+/// Returns the position of `pos` within the new extent and the number of allocated blocks.
+pub fn fill_hole(
+    inode: &INodeRef,
+    pos: &ExtPos,
+    count: u32,
+    quota: &Quota,
+) -> Result<(ExtPos, u32), Error> {
+    let blocksize = crate::superblock().block_size as usize;
+    let hole = *inodes::get_extent(inode, pos.ext, &mut None, false)?;
+    assert!(hole.is_hole());
+
+    let first = (pos.off / blocksize) as u32;
+    let ext = alloc_zeroed(inode, count.min(hole.length - first), quota)?;
+
+    log!(
+        crate::LOG_INODES,
+        "sparse::fill_hole(inode={}, pos={:?}, hole={:?}) -> {:?}",
+        inode.inode,
+        pos,
+        hole,
+        ext
+    );
+
+    replace_extent(inode, pos.ext, &[
+        Extent::hole(first),
+        ext,
+        Extent::hole(hole.length - first - ext.length),
+    ])?;
+
+    let ext_idx = if first > 0 { pos.ext + 1 } else { pos.ext };
+    let ext_off = pos.off - first as usize * blocksize;
+    Ok((ExtPos::new(ext_idx, ext_off), ext.length))
+}
+
+/// Ensures that the range from `off` to `off + len` of `inode` is backed by blocks, extending the
+/// file if necessary. New blocks are charged as described for [`inodes::create_extent`].
+pub fn allocate(inode: &INodeRef, off: usize, len: usize, quota: &Quota) -> Result<(), Error> {
+    log!(
+        crate::LOG_INODES,
+        "sparse::allocate(inode={}, off={}, len={})",
+        inode.inode,
+        off,
+        len
+    );
+
+    let blocksize = crate::superblock().block_size as usize;
+    let end = off + len;
+    extend(inode, end, quota)?;
+
+    let mut blk = off / blocksize;
+    let last = math::round_up(end, blocksize) / blocksize;
+    while blk < last {
+        let (_, pos) = inodes::get_seek_pos(inode, blk * blocksize, SeekMode::SET)?;
+        let ext = *inodes::get_extent(inode, pos.ext, &mut None, false)?;
+        let count = (ext.length as usize - pos.off / blocksize).min(last - blk);
+        if ext.is_hole() {
+            let (_, filled) = fill_hole(inode, &pos, count as u32, quota)?;
+            blk += filled as usize;
+        }
+        else {
+            blk += count;
+        }
+    }
+    Ok(())
+}
+
+/// Punches a hole into `inode` from `off` to `off + len` and credits the freed blocks to the owner of
+/// `inode`. Blocks that need to be copied before they are zeroed are charged as described for
+/// [`inodes::create_extent`].
+///
+/// Blocks that are completely within the range are freed, whereas the other parts of the range are
+/// zeroed. The file size does not change.
+pub fn punch_hole(inode: &INodeRef, off: usize, len: usize, quota: &Quota) -> Result<(), Error> {
+    log!(
+        crate::LOG_INODES,
+        "sparse::punch_hole(inode={}, off={}, len={})",
+        inode.inode,
+        off,
+        len
+    );
+
+    let blocksize = crate::superblock().block_size as usize;
+    let end = off.saturating_add(len).min(inode.size as usize);
+    if off >= end {
+        return Ok(());
+    }
+
+    // a partially used last block is never freed (see above)
+    let first = math::round_up(off, blocksize) / blocksize;
+    let last = end / blocksize;
+    if first >= last {
+        return zero(inode, off, end, quota);
+    }
+
+    zero(inode, off, first * blocksize, quota)?;
+    zero(inode, last * blocksize, end, quota)?;
+
+    let mut idx = 0;
+    let mut blk = 0;
+    while idx < inode.extents as usize && blk < last {
+        let ext = *inodes::get_extent(inode, idx, &mut None, false)?;
+        let ext_blk = blk;
+        let start = first.max(ext_blk);
+        let end = last.min(ext_blk + ext.length as usize);
+        blk += ext.length as usize;
+
+        if start >= end || ext.is_hole() {
+            idx += 1;
+            continue;
+        }
+
+        // free the blocks and replace them by a hole
+        let (start, end) = ((start - ext_blk) as u32, (end - ext_blk) as u32);
+        let freed =
+            crate::blocks_mut().free((ext.start + start) as usize, (end - start) as usize)?;
+        quota::release_blocks(inode, freed);
+
+        idx += replace_extent(inode, idx, &[
+            Extent::new(ext.start, start),
+            Extent::hole(end - start),
+            Extent::new(ext.start + end, ext.length - end),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Allocates `count` blocks for `inode` that are filled with zeros. The allocator might provide less
+/// blocks.
+fn alloc_zeroed(inode: &INodeRef, count: u32, quota: &Quota) -> Result<Extent, Error> {
+    let ext = inodes::create_extent(inode, false, count, quota)?;
+    // if enabled, create_extent has cleared the extent already
+    if !crate::settings().clear {
+        crate::backend_mut().clear_extent(ext)?;
+    }
+    Ok(ext)
+}
+
+/// Replaces the extent with index `idx` of `inode` by the non-empty extents in `exts`
+///
+/// All following extents are moved backwards accordingly. Returns the number of inserted extents.
+pub fn replace_extent(inode: &INodeRef, idx: usize, exts: &[Extent]) -> Result<usize, Error> {
+    let exts = exts.iter().filter(|e| e.length > 0).collect::<Vec<_>>();
+    let count = inode.extents as usize;
+    let mut indir = None;
+
+    if exts.len() > 1 {
+        for i in (idx + 1..count).rev() {
+            let ext = *inodes::get_extent(inode, i, &mut indir, false)?;
+            *inodes::get_extent(inode, i + exts.len() - 1, &mut indir, true)?.as_mut() = ext;
+        }
+    }
+    for (i, ext) in exts.iter().enumerate() {
+        *inodes::get_extent(inode, idx + i, &mut indir, true)?.as_mut() = **ext;
+    }
+
+    inode.as_mut().extents += exts.len() as u32 - 1;
+    Ok(exts.len())
+}
+
+/// Zeros the bytes from `start` to `end` of `inode`, skipping holes. Shared blocks are copied
+/// before and the copies are charged to `quota`.
+fn zero(inode: &INodeRef, mut start: usize, end: usize, quota: &Quota) -> Result<(), Error> {
+    let blocksize = crate::superblock().block_size as usize;
+    let zeros = vec![0u8; blocksize];
+
+    while start < end {
+        let amount = (math::round_dn(start, blocksize) + blocksize).min(end) - start;
+
+        let (_, pos) = inodes::get_seek_pos(inode, start, SeekMode::SET)?;
+        let pos = reflink::unshare(inode, &pos, 1, quota)?;
+        let ext = *inodes::get_extent(inode, pos.ext, &mut None, false)?;
+        if !ext.is_hole() {
+            let sel = Activity::own().alloc_sel();
+            crate::backend_mut().get_filedata(
+                ext,
+                pos.off,
+                Perm::RW,
+                sel,
+                Some(&mut LoadLimit::new()),
+            )?;
+            // the capability is revoked on drop
+            let mem = MemGate::new_owned_bind(sel);
+            mem.write(&zeros[..amount], (pos.off % blocksize) as goff)?;
+        }
+
+        start += amount;
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use crate::data::{write_xattrs, INodeRef, XAttrIterator, MAX_XATTR_LEN};
+use crate::ops::perms::{self, Creds};
+use crate::ops::quota::Quota;
+use crate::ops::{dirs, inodes};
+
+use m3::col::{String, ToString, Vec};
+use m3::errors::{Code, Error};
+use m3::vfs::OpenFlags;
+
+/// Loads the extended attributes of given inode
+fn load(inode: &INodeRef) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    if inode.xattr == 0 {
+        return Ok(Vec::new());
+    }
+
+    let block = crate::meta_buffer_mut().get_block(inode.xattr)?;
+    Ok(XAttrIterator::from_block(block.data())
+        .map(|(n, v)| (String::from_utf8_lossy(n).into_owned(), v.to_vec()))
+        .collect())
+}
+
+/// Stores the given extended attributes for given inode, allocating or freeing the block as needed
+///
+/// A new block is charged as described for [`inodes::alloc_block`], adopting the inode for `quota`.
+fn store(inode: &INodeRef, attrs: &[(String, Vec<u8>)], quota: &Quota) -> Result<(), Error> {
+    if attrs.is_empty() {
+        return free(inode);
+    }
+
+    let mut new_block = false;
+    if inode.xattr == 0 {
+        inode.as_mut().xattr = inodes::alloc_block(inode, Some(quota))?;
+        new_block = true;
+    }
+
+    let mut block = crate::meta_buffer_mut().get_block(inode.xattr)?;
+    let iter = attrs.iter().map(|(n, v)| (n.as_bytes(), v.as_slice()));
+    let res = write_xattrs(block.data_mut(), iter);
+    block.mark_dirty();
+    drop(block);
+
+    // don't keep an empty block around if the attributes did not fit
+    if res.is_err() && new_block {
+        free(inode)?;
+    }
+    res
+}
+
+/// Frees the extended-attribute block of given inode, if any, and credits it to the quota that owns
+/// the inode
+pub fn free(inode: &INodeRef) -> Result<(), Error> {
+    if inode.xattr != 0 {
+        inodes::free_block(inode, inode.xattr)?;
+        inode.as_mut().xattr = 0;
+    }
+    Ok(())
+}
+
+/// Returns the value of the extended attribute `name` of the file at `path`
+pub fn get(path: &str, name: &str, creds: &Creds) -> Result<Vec<u8>, Error> {
+    log!(
+        crate::LOG_XATTRS,
+        "xattrs::get(path={}, name={})",
+        path,
+        name
+    );
+
+    let inode = inodes::get(dirs::search(path, false, creds)?)?;
+    perms::check(&inode, creds, OpenFlags::R)?;
+
+    load(&inode)?
+        .into_iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v)
+        .ok_or_else(|| Error::new(Code::NotFound))
+}
+
+/// Returns the names of all extended attributes of the file at `path`
+pub fn list(path: &str, creds: &Creds) -> Result<Vec<String>, Error> {
+    log!(crate::LOG_XATTRS, "xattrs::list(path={})", path);
+
+    let inode = inodes::get(dirs::search(path, false, creds)?)?;
+    perms::check(&inode, creds, OpenFlags::R)?;
+
+    Ok(load(&inode)?.into_iter().map(|(n, _)| n).collect())
+}
+
+/// Sets the extended attribute `name` of the file at `path` to `value`
+///
+/// All attributes of a file are stored in a single block. Thus, this fails with
+/// [`Code::NoSpace`] if the attributes of the file would exceed a block.
+pub fn set(path: &str, name: &str, value: &[u8], creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_XATTRS,
+        "xattrs::set(path={}, name={}, len={})",
+        path,
+        name,
+        value.len()
+    );
+
+    if name.is_empty() || name.len() > MAX_XATTR_LEN || value.len() > MAX_XATTR_LEN {
+        return Err(Error::new(Code::InvArgs));
+    }
+
+    let inode = inodes::get(dirs::search(path, false, creds)?)?;
+    perms::check(&inode, creds, OpenFlags::W)?;
+
+    let mut attrs = load(&inode)?;
+    match attrs.iter_mut().find(|(n, _)| n == name) {
+        Some((_, v)) => *v = value.to_vec(),
+        None => attrs.push((name.to_string(), value.to_vec())),
+    }
+    store(&inode, &attrs, &creds.quota)
+}
+
+/// Removes the extended attribute `name` of the file at `path`
+pub fn remove(path: &str, name: &str, creds: &Creds) -> Result<(), Error> {
+    log!(
+        crate::LOG_XATTRS,
+        "xattrs::remove(path={}, name={})",
+        path,
+        name
+    );
+
+    let inode = inodes::get(dirs::search(path, false, creds)?)?;
+    perms::check(&inode, creds, OpenFlags::W)?;
+
+    let mut attrs = load(&inode)?;
+    let idx = attrs
+        .iter()
+        .position(|(n, _)| n == name)
+        .ok_or_else(|| Error::new(Code::NotFound))?;
+    attrs.remove(idx);
+    store(&inode, &attrs, &creds.quota)
+}
@@ -17,7 +17,8 @@
 
 use crate::buf::LoadLimit;
 use crate::data::{ExtPos, Extent, INodeRef, InodeNo};
-use crate::ops::inodes;
+use crate::ops::quota::{self, Quota};
+use crate::ops::{inodes, reflink, sparse};
 use crate::sess::M3FSSession;
 
 use m3::{
@@ -26,10 +27,11 @@ use m3::{
     com::{GateIStream, RecvGate, SendGate},
     errors::{Code, Error},
     kif::{CapRngDesc, CapType, Perm, INVALID_SEL},
+    rc::Rc,
     server::{CapExchange, SessId},
     session::ServerSession,
     syscalls, tcu,
-    vfs::{OpenFlags, SeekMode},
+    vfs::{FAllocMode, OpenFlags, SeekMode},
 };
 
 struct Entry {
@@ -85,6 +87,9 @@ pub struct FileSession {
     filename: String,
     ino: InodeNo,
 
+    // the quota that adopts the file for allocations if it has no owner (see quota::adopt)
+    quota: Rc<Quota>,
+
     // session information
     sess_sel: Selector,
     sess_creator: usize,
@@ -108,6 +113,7 @@ impl FileSession {
         oflags: OpenFlags,
         ino: InodeNo,
         rgate: Option<&RecvGate>,
+        quota: Rc<Quota>,
     ) -> Result<Self, Error> {
         // the server session for this file
         let sess_sel = if srv_sel == m3::kif::INVALID_SEL {
@@ -165,6 +171,8 @@ impl FileSession {
             filename: filename.to_string(),
             ino,
 
+            quota,
+
             sess_sel,
             sess_creator: crt,
             session_id: file_sess_id,
@@ -175,7 +183,7 @@ impl FileSession {
             _server_session,
         };
 
-        crate::open_files_mut().add_sess(ino);
+        crate::open_files_mut().add_sess(ino, oflags);
 
         Ok(fsess)
     }
@@ -205,6 +213,7 @@ impl FileSession {
             self.oflags,
             self.ino,
             Some(rgate),
+            self.quota.clone(),
         )?;
 
         self.child_sessions.push(sid);
@@ -229,6 +238,7 @@ impl FileSession {
 
         // determine extent from byte offset
         let (_, extpos) = inodes::get_seek_pos(&inode, offset as usize, SeekMode::SET)?;
+        let extpos = self.prepare_write(&inode, extpos)?;
 
         let sel = m3::tiles::Activity::own().alloc_sel();
         let (len, _) = inodes::get_extent_mem(
@@ -257,6 +267,31 @@ impl FileSession {
         Ok(())
     }
 
+    /// Allocates blocks for the hole at `pos` or copies the shared blocks at `pos`, if the file is
+    /// opened for writing.
+    ///
+    /// Returns the position that corresponds to `pos` afterwards.
+    fn prepare_write(&self, inode: &INodeRef, pos: ExtPos) -> Result<ExtPos, Error> {
+        if !self.oflags.contains(OpenFlags::W) || pos.ext >= inode.extents as usize {
+            return Ok(pos);
+        }
+
+        // TODO the extent positions of other sessions for this file might change, but clients are
+        // currently not prepared for that (as for truncate)!
+        let extend = crate::settings().extend as u32;
+        let ext = *inodes::get_extent(inode, pos.ext, &mut None, false)?;
+        // at the end of the extent, nothing is handed out for writing
+        if pos.off >= (ext.length * crate::superblock().block_size) as usize {
+            Ok(pos)
+        }
+        else if ext.is_hole() {
+            sparse::fill_hole(inode, &pos, extend, &self.quota).map(|(pos, _)| pos)
+        }
+        else {
+            reflink::unshare(inode, &pos, extend, &self.quota)
+        }
+    }
+
     fn revoke_cap(&mut self) {
         if self.cur_sel != m3::kif::INVALID_SEL {
             m3::tiles::Activity::own()
@@ -277,6 +312,11 @@ impl FileSession {
         self.ino
     }
 
+    /// Returns the extent that has been allocated for an append, but not yet added to the inode
+    pub fn append_extent(&self) -> Option<Extent> {
+        self.append_ext
+    }
+
     pub fn meta_sess(&self) -> SessId {
         self.meta_sess_id
     }
@@ -324,7 +364,7 @@ impl FileSession {
         let mut sel = m3::tiles::Activity::own().alloc_sel();
 
         // do we need to append to the file?
-        let (len, extlen) = if out && (self.next_fileoff as u64 == inode.size) {
+        let (len, extlen) = if out && (self.next_fileoff as u64 >= inode.size) {
             let mut files = crate::open_files_mut();
             let open_file = files.get_file_mut(self.ino).unwrap();
 
@@ -337,6 +377,14 @@ impl FileSession {
                 return Err(Error::new(Code::Exists));
             }
 
+            // writing behind the end turns the gap into a hole
+            if self.next_fileoff as u64 > inode.size {
+                sparse::extend(&inode, self.next_fileoff, &self.quota)?;
+                let (fileoff, extpos) = inodes::get_seek_pos(&inode, 0, SeekMode::END)?;
+                self.next_fileoff = fileoff;
+                self.next_pos = extpos;
+            }
+
             // continue in last extent, if there is space
             if (self.next_pos.ext > 0)
                 && (self.next_fileoff as u64 == inode.size)
@@ -347,12 +395,16 @@ impl FileSession {
                 self.next_pos = extpos;
             }
 
+            // the last extent is continued, so that its blocks must not be shared
+            self.next_pos = self.prepare_write(&inode, self.next_pos)?;
+
             let (len, extlen, new_ext) = inodes::req_append(
                 &inode,
                 &self.next_pos,
                 sel,
                 Perm::from(self.oflags),
                 &mut self.load_limit,
+                &self.quota,
             )?;
 
             self.appending = true;
@@ -362,6 +414,11 @@ impl FileSession {
             (len, extlen)
         }
         else {
+            // writing into holes or shared blocks requires blocks of our own
+            if out {
+                self.next_pos = self.prepare_write(&inode, self.next_pos)?;
+            }
+
             // get next mem_cap
             let res = inodes::get_extent_mem(
                 &inode,
@@ -413,7 +470,7 @@ impl FileSession {
             self.cur_bytes
         );
 
-        reply_vmsg!(is, Code::None as u32, capoff, self.cur_bytes)?;
+        commit_and_reply!(is, Code::None as u32, capoff, self.cur_bytes)?;
 
         self.revoke_cap();
         self.cur_sel = sel;
@@ -443,7 +500,14 @@ impl FileSession {
         self.next_pos = extpos;
         self.next_fileoff = pos;
 
-        reply_vmsg!(stream, Code::None as u32, pos - extpos.off, extpos.off)
+        // seeking behind the end is allowed; reads hit EOF and writes create a hole in between
+        if whence == SeekMode::SET && off > pos {
+            self.next_pos = ExtPos::new(inode.extents as usize, 0);
+            self.next_fileoff = off;
+            return commit_and_reply!(stream, Code::None as u32, off, 0);
+        }
+
+        commit_and_reply!(stream, Code::None as u32, pos - extpos.off, extpos.off)
     }
 
     pub fn file_stat(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -457,9 +521,7 @@ impl FileSession {
         let inode = inodes::get(self.ino)?;
         let info = inode.to_file_info();
 
-        let mut reply = m3::mem::MsgBuf::borrow_def();
-        build_vmsg!(reply, Code::None, info);
-        stream.reply(&reply)
+        commit_and_reply!(stream, Code::None as u32, info)
     }
 
     pub fn file_path(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -470,7 +532,7 @@ impl FileSession {
             self.filename
         );
 
-        reply_vmsg!(stream, Code::None as u32, self.filename)
+        commit_and_reply!(stream, Code::None as u32, self.filename)
     }
 
     pub fn file_truncate(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -486,8 +548,12 @@ impl FileSession {
 
         let inode = inodes::get(self.ino)?;
 
+        // truncating to a larger size appends a hole
         if off as u64 > inode.size {
-            return Err(Error::new(Code::InvArgs));
+            if !self.oflags.contains(OpenFlags::W) {
+                return Err(Error::new(Code::NoPerm));
+            }
+            sparse::extend(&inode, off, &self.quota)?;
         }
 
         let (fileoff, extpos) = inodes::get_seek_pos(&inode, off, SeekMode::SET)?;
@@ -504,7 +570,57 @@ impl FileSession {
         // prepared for that!
         self.revoke_cap();
 
-        reply_vmsg!(stream, Code::None as u32, fileoff - extpos.off, extpos.off)
+        commit_and_reply!(stream, Code::None as u32, fileoff - extpos.off, extpos.off)
+    }
+
+    pub fn file_fallocate(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let off: usize = stream.pop()?;
+        let len: usize = stream.pop()?;
+        let mode = FAllocMode::from_bits_truncate(stream.pop()?);
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] file::fallocate(path={}, off={}, len={}, mode={:?})",
+            self.session_id,
+            self.filename,
+            off,
+            len,
+            mode
+        );
+
+        if !self.oflags.contains(OpenFlags::W) {
+            return Err(Error::new(Code::NoPerm));
+        }
+        if len == 0 || off.checked_add(len).is_none() {
+            return Err(Error::new(Code::InvArgs));
+        }
+        // the extents must not change during an append
+        if crate::open_files_mut()
+            .get_file_mut(self.ino)
+            .unwrap()
+            .appending()
+        {
+            return Err(Error::new(Code::Exists));
+        }
+
+        let inode = inodes::get(self.ino)?;
+        if mode.contains(FAllocMode::PUNCH_HOLE) {
+            sparse::punch_hole(&inode, off, len, &self.quota)?;
+        }
+        else {
+            sparse::allocate(&inode, off, len, &self.quota)?;
+        }
+
+        // the extents might have changed; start again at the current file position
+        let (fileoff, extpos) = inodes::get_seek_pos(&inode, self.next_fileoff, SeekMode::SET)?;
+        self.next_fileoff = fileoff;
+        self.next_pos = extpos;
+
+        // revoke the current to remove the client's access to the changed parts
+        // TODO we need to revoke the access from others as well (see truncate)
+        self.revoke_cap();
+
+        commit_and_reply!(stream, Code::None as u32, fileoff - extpos.off, extpos.off)
     }
 
     pub fn file_commit(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -547,7 +663,7 @@ impl FileSession {
             Err(e)
         }
         else {
-            stream.reply_error(Code::None)
+            commit_and_reply!(stream, Code::None as u32)
         }
     }
 
@@ -585,6 +701,7 @@ impl FileSession {
                     append_ext.start as usize + blocks,
                     old_len as usize - blocks,
                 )?;
+                quota::release_blocks(inode, old_len as usize - blocks);
             }
 
             self.cur_extlen = blocks * blocksize;
@@ -620,7 +737,7 @@ impl FileSession {
         log!(crate::LOG_SESSION, "[{}] file::sync()", self.session_id,);
 
         crate::flush_buffer()?;
-        stream.reply_error(Code::None)
+        commit_and_reply!(stream, Code::None as u32)
     }
 }
 
@@ -638,10 +755,14 @@ impl Drop for FileSession {
             crate::blocks_mut()
                 .free(ext.start as usize, ext.length as usize)
                 .unwrap();
+            let inode = inodes::get(self.ino).unwrap();
+            quota::release_blocks(&inode, ext.length as usize);
         }
 
         // remove session from open_files and from its meta session
-        crate::open_files_mut().remove_session(self.ino).unwrap();
+        crate::open_files_mut()
+            .remove_session(self.ino, self.oflags)
+            .unwrap();
 
         // revoke caps if needed
         self.revoke_cap();
@@ -692,6 +813,11 @@ impl M3FSSession for FileSession {
         self.file_truncate(stream)
     }
 
+    fn fallocate(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let _: usize = stream.pop()?;
+        self.file_fallocate(stream)
+    }
+
     fn mkdir(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
         Err(Error::new(Code::NotSup))
     }
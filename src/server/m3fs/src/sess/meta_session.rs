@@ -14,8 +14,9 @@
  * General Public License version 2 for more details.
  */
 
-use crate::data::ExtPos;
-use crate::ops::{dirs, inodes};
+use crate::data::{ExtPos, GroupId, UserId};
+use crate::ops::perms::{self, Creds};
+use crate::ops::{dirs, inodes, reflink, xattrs};
 use crate::sess::{FileSession, M3FSSession};
 
 use m3::{
@@ -24,6 +25,7 @@ use m3::{
     col::{Treap, Vec},
     com::{GateIStream, RecvGate, SGateArgs, SendGate},
     errors::{Code, Error},
+    serialize::Bytes,
     server::CapExchange,
     server::SessId,
     session::ServerSession,
@@ -43,6 +45,8 @@ pub struct MetaSession {
     priv_eps: Vec<Selector>,
     creator: usize,
     session_id: SessId,
+    creds: Creds,
+    readonly: bool,
 }
 
 impl MetaSession {
@@ -51,6 +55,8 @@ impl MetaSession {
         session_id: SessId,
         crt: usize,
         max_files: usize,
+        creds: Creds,
+        readonly: bool,
     ) -> Self {
         MetaSession {
             _server_session,
@@ -62,6 +68,29 @@ impl MetaSession {
             priv_eps: Vec::new(),
             creator: crt,
             session_id,
+            creds,
+            readonly,
+        }
+    }
+
+    pub fn creds(&self) -> &Creds {
+        &self.creds
+    }
+
+    pub fn max_files(&self) -> usize {
+        self.max_files
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.readonly {
+            Err(Error::new(Code::ReadOnly))
+        }
+        else {
+            Ok(())
         }
     }
 
@@ -156,20 +185,24 @@ impl MetaSession {
             return Err(Error::new(Code::NoSpace));
         }
 
-        let ino = dirs::search(path, flags.contains(OpenFlags::CREATE))?;
+        let modifying = OpenFlags::W | OpenFlags::TRUNC | OpenFlags::APPEND | OpenFlags::CREATE;
+        if flags.intersects(modifying) {
+            self.check_writable()?;
+        }
+
+        let ino = dirs::search(path, flags.contains(OpenFlags::CREATE), &self.creds)?;
         let inode = inodes::get(ino)?;
-        let inode_mode = inode.mode;
 
-        if (flags.contains(OpenFlags::W) && !inode_mode.contains(FileMode::IWUSR))
-            || (flags.contains(OpenFlags::R) && !inode_mode.contains(FileMode::IRUSR))
-        {
+        if let Err(e) = perms::check(&inode, &self.creds, flags & OpenFlags::RWX) {
             log!(
                 crate::LOG_SESSION,
-                "insufficient permissions: flags={:o}, mode={:o}",
+                "insufficient permissions: flags={:o}, mode={:o}, uid={}, gid={}",
                 flags,
                 inode.mode,
+                inode.uid,
+                inode.gid,
             );
-            return Err(Error::new(Code::NoPerm));
+            return Err(e);
         }
 
         // only determine the current size, if we're writing and the file isn't empty
@@ -193,6 +226,7 @@ impl MetaSession {
             flags,
             inode.inode,
             rgate,
+            self.creds.quota.clone(),
         )
     }
 
@@ -213,6 +247,7 @@ impl Drop for MetaSession {
         for g in self.sgates.iter_mut() {
             g.deactivate();
         }
+        self.creds.quota.close();
     }
 }
 
@@ -263,14 +298,12 @@ impl M3FSSession for MetaSession {
             path
         );
 
-        let ino = dirs::search(path, false)?;
+        let ino = dirs::search(path, false, &self.creds)?;
         let inode = inodes::get(ino)?;
 
         let info = inode.to_file_info();
 
-        let mut reply = m3::mem::MsgBuf::borrow_def();
-        build_vmsg!(reply, Code::None, info);
-        stream.reply(&reply)
+        commit_and_reply!(stream, Code::None as u32, info)
     }
 
     fn mkdir(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -285,9 +318,10 @@ impl M3FSSession for MetaSession {
             mode
         );
 
-        dirs::create(path, mode)?;
+        self.check_writable()?;
+        dirs::create(path, mode, &self.creds)?;
 
-        stream.reply_error(Code::None)
+        commit_and_reply!(stream, Code::None as u32)
     }
 
     fn rmdir(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -300,9 +334,10 @@ impl M3FSSession for MetaSession {
             path
         );
 
-        dirs::remove(path)?;
+        self.check_writable()?;
+        dirs::remove(path, &self.creds)?;
 
-        stream.reply_error(Code::None)
+        commit_and_reply!(stream, Code::None as u32)
     }
 
     fn link(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -317,9 +352,28 @@ impl M3FSSession for MetaSession {
             new_path
         );
 
-        dirs::link(old_path, new_path)?;
+        self.check_writable()?;
+        dirs::link(old_path, new_path, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn reflink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let old_path: &str = stream.pop()?;
+        let new_path: &str = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::reflink(old_path={}, new_path: {})",
+            self.session_id,
+            old_path,
+            new_path
+        );
+
+        self.check_writable()?;
+        reflink::clone_file(old_path, new_path, &self.creds)?;
 
-        stream.reply_error(Code::None)
+        commit_and_reply!(stream, Code::None as u32)
     }
 
     fn unlink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -332,9 +386,10 @@ impl M3FSSession for MetaSession {
             path
         );
 
-        dirs::unlink(path, true)?;
+        self.check_writable()?;
+        dirs::unlink(path, true, &self.creds)?;
 
-        stream.reply_error(Code::None)
+        commit_and_reply!(stream, Code::None as u32)
     }
 
     fn rename(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -349,9 +404,210 @@ impl M3FSSession for MetaSession {
             new_path
         );
 
-        dirs::rename(old_path, new_path)?;
+        self.check_writable()?;
+        dirs::rename(old_path, new_path, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn symlink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let target: &str = stream.pop()?;
+        let path: &str = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::symlink(target={}, path: {})",
+            self.session_id,
+            target,
+            path
+        );
+
+        self.check_writable()?;
+        dirs::symlink(target, path, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn readlink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::readlink(path={})",
+            self.session_id,
+            path
+        );
+
+        let target = dirs::readlink(path, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32, target)
+    }
+
+    fn chmod(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let mode = FileMode::from_bits_truncate(stream.pop::<u16>()?);
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::chmod(path={}, mode={:o})",
+            self.session_id,
+            path,
+            mode
+        );
+
+        self.check_writable()?;
+        dirs::chmod(path, mode, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn chown(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let uid: UserId = stream.pop()?;
+        let gid: GroupId = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::chown(path={}, uid={}, gid={})",
+            self.session_id,
+            path,
+            uid,
+            gid
+        );
+
+        self.check_writable()?;
+        dirs::chown(path, uid, gid, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn utime(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let atime: u32 = stream.pop()?;
+        let mtime: u32 = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::utime(path={}, atime={}, mtime={})",
+            self.session_id,
+            path,
+            atime,
+            mtime
+        );
+
+        self.check_writable()?;
+        dirs::utime(path, atime, mtime, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn chroot(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::chroot(path={})",
+            self.session_id,
+            path
+        );
+
+        // the path is resolved relative to the current root, so that it can only be narrowed
+        self.creds.root_dir = dirs::search_dir(path, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn get_quota(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::get_quota()",
+            self.session_id
+        );
+
+        // the remaining budget is also limited by the quotas we have been derived from
+        let quota = &self.creds.quota;
+        commit_and_reply!(
+            stream,
+            Code::None as u32,
+            quota.id(),
+            quota.blocks().0,
+            quota.avail_blocks(),
+            quota.inodes().0,
+            quota.avail_inodes()
+        )
+    }
+
+    fn getxattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let name: &str = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::getxattr(path={}, name={})",
+            self.session_id,
+            path,
+            name
+        );
+
+        let value = xattrs::get(path, name, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32, Bytes(&value))
+    }
+
+    fn setxattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let name: &str = stream.pop()?;
+        let value: &[u8] = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::setxattr(path={}, name={}, len={})",
+            self.session_id,
+            path,
+            name,
+            value.len()
+        );
+
+        self.check_writable()?;
+        xattrs::set(path, name, value, &self.creds)?;
+
+        commit_and_reply!(stream, Code::None as u32)
+    }
+
+    fn listxattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let idx: usize = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::listxattr(path={}, idx={})",
+            self.session_id,
+            path,
+            idx
+        );
+
+        let names = xattrs::list(path, &self.creds)?;
+        let name = names.get(idx).map(|n| n.as_str()).unwrap_or("");
+
+        commit_and_reply!(stream, Code::None as u32, names.len(), name)
+    }
+
+    fn removexattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = stream.pop()?;
+        let name: &str = stream.pop()?;
+
+        log!(
+            crate::LOG_SESSION,
+            "[{}] meta::removexattr(path={}, name={})",
+            self.session_id,
+            path,
+            name
+        );
+
+        self.check_writable()?;
+        xattrs::remove(path, name, &self.creds)?;
 
-        stream.reply_error(Code::None)
+        commit_and_reply!(stream, Code::None as u32)
     }
 
     fn open_priv(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -388,7 +644,7 @@ impl M3FSSession for MetaSession {
         self.priv_files.insert(id, session);
         self.priv_file_count += 1;
 
-        reply_vmsg!(stream, 0, id)
+        commit_and_reply!(stream, 0, id)
     }
 
     fn close(&mut self, stream: &mut GateIStream<'_>) -> Result<bool, Error> {
@@ -396,7 +652,7 @@ impl M3FSSession for MetaSession {
 
         if self.priv_files.remove(&fid).is_some() {
             self.priv_file_count -= 1;
-            stream.reply_error(Code::None)?;
+            commit_and_reply!(stream, Code::None as u32)?;
         }
         else {
             stream.reply_error(Code::InvArgs)?;
@@ -94,6 +94,13 @@ impl M3FSSession for FSSession {
         }
     }
 
+    fn fallocate(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.fallocate(stream),
+            FSSession::File(f) => f.fallocate(stream),
+        }
+    }
+
     fn mkdir(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
         match self {
             FSSession::Meta(m) => m.mkdir(stream),
@@ -129,6 +136,90 @@ impl M3FSSession for FSSession {
         }
     }
 
+    fn symlink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.symlink(stream),
+            FSSession::File(f) => f.symlink(stream),
+        }
+    }
+
+    fn readlink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.readlink(stream),
+            FSSession::File(f) => f.readlink(stream),
+        }
+    }
+
+    fn chmod(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.chmod(stream),
+            FSSession::File(f) => f.chmod(stream),
+        }
+    }
+
+    fn chown(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.chown(stream),
+            FSSession::File(f) => f.chown(stream),
+        }
+    }
+
+    fn utime(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.utime(stream),
+            FSSession::File(f) => f.utime(stream),
+        }
+    }
+
+    fn chroot(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.chroot(stream),
+            FSSession::File(f) => f.chroot(stream),
+        }
+    }
+
+    fn get_quota(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.get_quota(stream),
+            FSSession::File(f) => f.get_quota(stream),
+        }
+    }
+
+    fn getxattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.getxattr(stream),
+            FSSession::File(f) => f.getxattr(stream),
+        }
+    }
+
+    fn setxattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.setxattr(stream),
+            FSSession::File(f) => f.setxattr(stream),
+        }
+    }
+
+    fn listxattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.listxattr(stream),
+            FSSession::File(f) => f.listxattr(stream),
+        }
+    }
+
+    fn removexattr(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.removexattr(stream),
+            FSSession::File(f) => f.removexattr(stream),
+        }
+    }
+
+    fn reflink(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            FSSession::Meta(m) => m.reflink(stream),
+            FSSession::File(f) => f.reflink(stream),
+        }
+    }
+
     fn sync(&mut self, stream: &mut GateIStream<'_>) -> Result<(), Error> {
         match self {
             FSSession::Meta(m) => m.sync(stream),
@@ -179,6 +270,9 @@ pub trait M3FSSession {
     fn truncate(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
         Err(Error::new(Code::NotSup))
     }
+    fn fallocate(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
     fn mkdir(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
         Err(Error::new(Code::NotSup))
     }
@@ -194,6 +288,42 @@ pub trait M3FSSession {
     fn rename(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
         Err(Error::new(Code::NotSup))
     }
+    fn symlink(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn readlink(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn chmod(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn chown(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn utime(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn chroot(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn get_quota(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn getxattr(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn setxattr(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn listxattr(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn removexattr(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
+    fn reflink(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
+        Err(Error::new(Code::NotSup))
+    }
     fn sync(&mut self, _stream: &mut GateIStream<'_>) -> Result<(), Error> {
         Err(Error::new(Code::NotSup))
     }
@@ -19,11 +19,13 @@ use crate::ops::inodes;
 
 use m3::col::Treap;
 use m3::errors::Error;
+use m3::vfs::OpenFlags;
 
 pub struct OpenFile {
     appending: bool,
     deleted: bool,
     refs: usize,
+    writers: usize,
 }
 
 impl OpenFile {
@@ -31,7 +33,8 @@ impl OpenFile {
         OpenFile {
             appending: false,
             deleted: false,
-            refs: 1,
+            refs: 0,
+            writers: 0,
         }
     }
 
@@ -39,6 +42,11 @@ impl OpenFile {
         self.appending
     }
 
+    /// Returns the number of sessions that have the file open for writing
+    pub fn writers(&self) -> usize {
+        self.writers
+    }
+
     pub fn set_appending(&mut self, new: bool) {
         self.appending = new;
     }
@@ -70,22 +78,28 @@ impl OpenFiles {
         Ok(())
     }
 
-    pub fn add_sess(&mut self, ino: InodeNo) {
+    pub fn add_sess(&mut self, ino: InodeNo, flags: OpenFlags) {
         // add reference to OpenFile instance or create new one
-        if let Some(file) = self.get_file_mut(ino) {
-            file.refs += 1;
-        }
-        else {
+        if self.get_file_mut(ino).is_none() {
             self.files.insert(ino, OpenFile::new());
         }
+
+        let file = self.get_file_mut(ino).unwrap();
+        file.refs += 1;
+        if flags.contains(OpenFlags::W) {
+            file.writers += 1;
+        }
     }
 
-    pub fn remove_session(&mut self, ino: InodeNo) -> Result<(), Error> {
+    pub fn remove_session(&mut self, ino: InodeNo, flags: OpenFlags) -> Result<(), Error> {
         let file = self.get_file_mut(ino).unwrap();
 
         // dereference OpenFile instance
         assert!(file.refs > 0);
         file.refs -= 1;
+        if flags.contains(OpenFlags::W) {
+            file.writers -= 1;
+        }
 
         // are there sessions left using the file?
         if file.refs == 0 {
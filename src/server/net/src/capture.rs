@@ -0,0 +1,193 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+// captures the sent and received frames into a file in the pcap format, which can be opened with
+// Wireshark or tcpdump.
+
+use core::str::FromStr;
+
+use m3::cell::StaticRefCell;
+use m3::errors::Error;
+use m3::io::Write;
+use m3::log;
+use m3::net::SocketType;
+use m3::time::TimeInstant;
+use m3::vfs::{BufWriter, FileRef, GenericFile, OpenFlags, VFS};
+
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpAddress, IpEndpoint, IpProtocol, Ipv4Packet, Ipv6Packet,
+    TcpPacket, UdpPacket,
+};
+
+use crate::filter::{Direction, Filter, Packet, Rule};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION: (u16, u16) = (2, 4);
+const PCAP_SNAPLEN: u32 = 65535;
+const PCAP_LINKTYPE_ETHERNET: u32 = 1;
+
+static CAPTURE: StaticRefCell<Option<Capture>> = StaticRefCell::new(None);
+
+struct Capture {
+    file: BufWriter<FileRef<GenericFile>>,
+    filter: Filter,
+    start: TimeInstant,
+    frames: u64,
+}
+
+impl Capture {
+    fn write_header(&mut self) -> Result<(), Error> {
+        self.file.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        self.file.write_all(&PCAP_VERSION.0.to_le_bytes())?;
+        self.file.write_all(&PCAP_VERSION.1.to_le_bytes())?;
+        // time zone offset and timestamp accuracy
+        self.file.write_all(&0u32.to_le_bytes())?;
+        self.file.write_all(&0u32.to_le_bytes())?;
+        self.file.write_all(&PCAP_SNAPLEN.to_le_bytes())?;
+        self.file.write_all(&PCAP_LINKTYPE_ETHERNET.to_le_bytes())
+    }
+
+    fn write_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        // we don't know the wall-clock time, so that the timestamps start at zero
+        let time = self.start.elapsed();
+        let len = frame.len().min(PCAP_SNAPLEN as usize);
+        self.file
+            .write_all(&(time.as_secs() as u32).to_le_bytes())?;
+        self.file.write_all(&time.subsec_micros().to_le_bytes())?;
+        self.file.write_all(&(len as u32).to_le_bytes())?;
+        self.file.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.file.write_all(&frame[0..len])
+    }
+}
+
+/// Starts to capture all frames that are permitted by the given filter rules into the file at
+/// `path`, replacing a currently active capture
+///
+/// The rules are separated by semicolons and use the syntax of the packet filter (see
+/// [`Rule`](crate::filter::Rule)). Frames are captured if the first matching rule allows them or
+/// if no rule matches.
+pub fn start(path: &str, rules: &str) -> Result<(), Error> {
+    let mut filter = Filter::new();
+    for rule in rules.split(';').map(|r| r.trim()).filter(|r| !r.is_empty()) {
+        filter.add_rule(Rule::from_str(rule)?);
+    }
+
+    let file = VFS::open(path, OpenFlags::W | OpenFlags::CREATE | OpenFlags::TRUNC)?;
+    let mut capture = Capture {
+        file: BufWriter::new(file),
+        filter,
+        start: TimeInstant::now(),
+        frames: 0,
+    };
+    capture.write_header()?;
+
+    log!(crate::LOG_DEF, "Capturing frames into {}", path);
+    stop().ok();
+    CAPTURE.replace(Some(capture));
+    Ok(())
+}
+
+/// Stops the current capture and returns the number of captured frames
+pub fn stop() -> Result<u64, Error> {
+    match CAPTURE.replace(None) {
+        Some(mut c) => {
+            c.file.flush()?;
+            Ok(c.frames)
+        },
+        None => Ok(0),
+    }
+}
+
+/// Captures the given frame, if a capture is active and the filter permits it
+pub fn frame(dir: Direction, frame: &[u8]) {
+    let res = match CAPTURE.borrow_mut().as_mut() {
+        Some(c) => {
+            // don't bother to parse the frame if there are no rules
+            if !c.filter.rules().is_empty() && !c.filter.permits(&to_packet(dir, frame)) {
+                return;
+            }
+            c.frames += 1;
+            c.write_frame(frame)
+        },
+        None => return,
+    };
+
+    if let Err(e) = res {
+        log!(crate::LOG_ERR, "Unable to capture frame: {}; stopping", e);
+        stop().ok();
+    }
+}
+
+// the remote side is the source of received and the destination of sent frames
+fn remote<T>(dir: Direction, src: T, dst: T) -> T {
+    match dir {
+        Direction::In => src,
+        Direction::Out => dst,
+    }
+}
+
+// determines the properties of the given frame that are used by the filter rules
+fn to_packet(dir: Direction, frame: &[u8]) -> Packet {
+    let mut pkt = Packet {
+        dir,
+        proto: SocketType::Raw,
+        remote: IpEndpoint::UNSPECIFIED,
+        size: frame.len(),
+    };
+
+    let eth = match EthernetFrame::new_checked(frame) {
+        Ok(eth) => eth,
+        Err(_) => return pkt,
+    };
+    let (src, dst, proto, payload) = match eth.ethertype() {
+        EthernetProtocol::Ipv4 => match Ipv4Packet::new_checked(eth.payload()) {
+            Ok(ip) => (
+                IpAddress::Ipv4(ip.src_addr()),
+                IpAddress::Ipv4(ip.dst_addr()),
+                ip.protocol(),
+                ip.payload(),
+            ),
+            Err(_) => return pkt,
+        },
+        EthernetProtocol::Ipv6 => match Ipv6Packet::new_checked(eth.payload()) {
+            Ok(ip) => (
+                IpAddress::Ipv6(ip.src_addr()),
+                IpAddress::Ipv6(ip.dst_addr()),
+                ip.next_header(),
+                ip.payload(),
+            ),
+            Err(_) => return pkt,
+        },
+        _ => return pkt,
+    };
+
+    pkt.remote.addr = remote(dir, src, dst);
+    match proto {
+        IpProtocol::Tcp => {
+            pkt.proto = SocketType::Stream;
+            if let Ok(tcp) = TcpPacket::new_checked(payload) {
+                pkt.remote.port = remote(dir, tcp.src_port(), tcp.dst_port());
+            }
+        },
+        IpProtocol::Udp => {
+            pkt.proto = SocketType::Dgram;
+            if let Ok(udp) = UdpPacket::new_checked(payload) {
+                pkt.remote.port = remote(dir, udp.src_port(), udp.dst_port());
+            }
+        },
+        _ => {},
+    }
+    pkt
+}
@@ -29,18 +29,23 @@ mod inner;
 
 pub use inner::*;
 
-use smoltcp::iface::{Context, Interface, SocketHandle};
+mod monitor;
+
+pub use monitor::{IfaceStats, MonitoredDevice};
+
+use smoltcp::iface::{Context, Interface, Routes, SocketHandle};
 use smoltcp::socket::AnySocket;
 use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{IpCidr, Ipv4Address};
 
 pub enum DriverInterface<'a> {
-    Lo(Interface<'a, smoltcp::phy::Loopback>),
+    Lo(Interface<'a, MonitoredDevice<smoltcp::phy::Loopback>>),
     #[cfg(target_vendor = "gem5")]
-    Eth(Interface<'a, E1000Device>),
+    Eth(Interface<'a, MonitoredDevice<E1000Device>>),
     #[cfg(target_vendor = "hw")]
-    Eth(Interface<'a, AXIEthDevice>),
+    Eth(Interface<'a, MonitoredDevice<AXIEthDevice>>),
     #[cfg(target_vendor = "host")]
-    Eth(Interface<'a, DevFifo>),
+    Eth(Interface<'a, MonitoredDevice<DevFifo>>),
 }
 
 impl<'a> DriverInterface<'a> {
@@ -51,6 +56,17 @@ impl<'a> DriverInterface<'a> {
         }
     }
 
+    pub fn remove_socket(&mut self, handle: SocketHandle) {
+        match self {
+            Self::Lo(l) => {
+                l.remove_socket(handle);
+            },
+            Self::Eth(e) => {
+                e.remove_socket(handle);
+            },
+        }
+    }
+
     pub fn get_socket<T: AnySocket<'a>>(&mut self, handle: SocketHandle) -> &mut T {
         match self {
             Self::Lo(l) => l.get_socket(handle),
@@ -68,6 +84,49 @@ impl<'a> DriverInterface<'a> {
         }
     }
 
+    pub fn update_ip_addrs<F: FnOnce(&mut [IpCidr])>(&mut self, func: F) {
+        match self {
+            Self::Lo(l) => l.update_ip_addrs(|addrs| func(&mut addrs[..])),
+            Self::Eth(e) => e.update_ip_addrs(|addrs| func(&mut addrs[..])),
+        }
+    }
+
+    pub fn ip_addrs(&self) -> &[IpCidr] {
+        match self {
+            Self::Lo(l) => l.ip_addrs(),
+            Self::Eth(e) => e.ip_addrs(),
+        }
+    }
+
+    pub fn join_multicast_group(
+        &mut self,
+        addr: Ipv4Address,
+        timestamp: Instant,
+    ) -> smoltcp::Result<bool> {
+        match self {
+            Self::Lo(l) => l.join_multicast_group(addr, timestamp),
+            Self::Eth(e) => e.join_multicast_group(addr, timestamp),
+        }
+    }
+
+    pub fn leave_multicast_group(
+        &mut self,
+        addr: Ipv4Address,
+        timestamp: Instant,
+    ) -> smoltcp::Result<bool> {
+        match self {
+            Self::Lo(l) => l.leave_multicast_group(addr, timestamp),
+            Self::Eth(e) => e.leave_multicast_group(addr, timestamp),
+        }
+    }
+
+    pub fn routes_mut(&mut self) -> &mut Routes<'a> {
+        match self {
+            Self::Lo(l) => l.routes_mut(),
+            Self::Eth(e) => e.routes_mut(),
+        }
+    }
+
     pub fn poll(&mut self, timestamp: Instant) -> smoltcp::Result<bool> {
         match self {
             Self::Lo(l) => l.poll(timestamp),
@@ -85,7 +144,14 @@ impl<'a> DriverInterface<'a> {
     pub fn needs_poll(&self) -> bool {
         match self {
             Self::Lo(_) => false,
-            Self::Eth(e) => e.device().needs_poll(),
+            Self::Eth(e) => e.device().inner().needs_poll(),
+        }
+    }
+
+    pub fn stats(&self) -> &IfaceStats {
+        match self {
+            Self::Lo(l) => l.device().stats(),
+            Self::Eth(e) => e.device().stats(),
         }
     }
 }
@@ -0,0 +1,134 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use core::cell::Cell;
+
+use smoltcp::phy::{Device, DeviceCapabilities};
+use smoltcp::time::Instant;
+
+use crate::capture;
+use crate::filter::Direction;
+
+/// The frame counters of a network interface
+#[derive(Default)]
+pub struct IfaceStats {
+    pub rx_packets: Cell<u64>,
+    pub rx_bytes: Cell<u64>,
+    pub rx_errors: Cell<u64>,
+    pub tx_packets: Cell<u64>,
+    pub tx_bytes: Cell<u64>,
+    pub tx_errors: Cell<u64>,
+}
+
+fn inc(counter: &Cell<u64>, amount: u64) {
+    counter.set(counter.get() + amount);
+}
+
+/// A device that wraps another device, counts the received and transmitted frames, and passes them
+/// to the packet capture
+pub struct MonitoredDevice<D> {
+    inner: D,
+    stats: IfaceStats,
+}
+
+impl<D> MonitoredDevice<D> {
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            stats: IfaceStats::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> &IfaceStats {
+        &self.stats
+    }
+}
+
+impl<'a, D: Device<'a>> Device<'a> for MonitoredDevice<D> {
+    type RxToken = RxToken<'a, D::RxToken>;
+    type TxToken = TxToken<'a, D::TxToken>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let stats = &self.stats;
+        self.inner
+            .receive()
+            .map(|(rx, tx)| (RxToken { inner: rx, stats }, TxToken { inner: tx, stats }))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let stats = &self.stats;
+        self.inner.transmit().map(|tx| TxToken { inner: tx, stats })
+    }
+}
+
+pub struct RxToken<'a, T> {
+    inner: T,
+    stats: &'a IfaceStats,
+}
+
+impl<'a, T: smoltcp::phy::RxToken> smoltcp::phy::RxToken for RxToken<'a, T> {
+    fn consume<R, F>(self, timestamp: Instant, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        let stats = self.stats;
+        let res = self.inner.consume(timestamp, |buf| {
+            inc(&stats.rx_packets, 1);
+            inc(&stats.rx_bytes, buf.len() as u64);
+            capture::frame(Direction::In, buf);
+            f(buf)
+        });
+        if res.is_err() {
+            inc(&stats.rx_errors, 1);
+        }
+        res
+    }
+}
+
+pub struct TxToken<'a, T> {
+    inner: T,
+    stats: &'a IfaceStats,
+}
+
+impl<'a, T: smoltcp::phy::TxToken> smoltcp::phy::TxToken for TxToken<'a, T> {
+    fn consume<R, F>(self, timestamp: Instant, len: usize, f: F) -> smoltcp::Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> smoltcp::Result<R>,
+    {
+        let res = self.inner.consume(timestamp, len, |buf| {
+            let res = f(buf);
+            if res.is_ok() {
+                capture::frame(Direction::Out, buf);
+            }
+            res
+        });
+        match res {
+            Ok(_) => {
+                inc(&self.stats.tx_packets, 1);
+                inc(&self.stats.tx_bytes, len as u64);
+            },
+            Err(_) => inc(&self.stats.tx_errors, 1),
+        }
+        res
+    }
+}
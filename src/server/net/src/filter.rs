@@ -0,0 +1,309 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use core::fmt;
+use core::str::FromStr;
+
+use m3::cell::{Ref, StaticRefCell};
+use m3::col::Vec;
+use m3::errors::{Code, Error};
+use m3::io::Read;
+use m3::log;
+use m3::net::{Port, SocketType};
+use m3::parse;
+use m3::vfs::{OpenFlags, VFS};
+
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint};
+
+// the rules of the rules file, which apply to all sessions and are checked first
+static GLOBAL: StaticRefCell<Filter> = StaticRefCell::new(Filter::new());
+
+/// The action to take for packets that match a rule
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// The direction of a packet from the client's point of view
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Received from the network and passed to the client
+    In,
+    /// Sent by the client to the network
+    Out,
+}
+
+/// The properties of a packet that rules can match on
+pub struct Packet {
+    pub dir: Direction,
+    pub proto: SocketType,
+    pub remote: IpEndpoint,
+    pub size: usize,
+}
+
+/// A single filter rule
+///
+/// Rules are written as `<action>,<dir>,<proto>[,<addr>[,<ports>]]`, where the fields can also be
+/// separated by whitespace:
+/// - `<action>` is `allow` or `deny`,
+/// - `<dir>` is `in`, `out`, or `any`,
+/// - `<proto>` is `tcp`, `udp`, `raw`, or `any`,
+/// - `<addr>` is the remote address with an optional prefix length (`a.b.c.d/n`) or `any`,
+/// - `<ports>` is the remote port or port range (`x-y`) or `any`.
+///
+/// TCP connections are checked once when they are established: `out` applies to connects and `in`
+/// to incoming connections. Denied connects fail and denied incoming connections are aborted.
+pub struct Rule {
+    action: Action,
+    dir: Option<Direction>,
+    proto: Option<SocketType>,
+    addr: Option<IpCidr>,
+    ports: Option<(Port, Port)>,
+    packets: u64,
+    bytes: u64,
+}
+
+impl Rule {
+    /// Returns the number of packets that matched this rule
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Returns the number of bytes of all packets that matched this rule
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn matches(&self, pkt: &Packet) -> bool {
+        self.dir.map(|d| d == pkt.dir).unwrap_or(true)
+            && self.proto.map(|p| p == pkt.proto).unwrap_or(true)
+            && self
+                .addr
+                .map(|a| a.contains_addr(&pkt.remote.addr))
+                .unwrap_or(true)
+            && self
+                .ports
+                .map(|(from, to)| pkt.remote.port >= from && pkt.remote.port <= to)
+                .unwrap_or(true)
+    }
+
+    fn parse_addr(s: &str) -> Result<IpCidr, Error> {
+        if s.contains('/') {
+            return IpCidr::from_str(s).map_err(|_| Error::new(Code::InvArgs));
+        }
+
+        // a single address
+        match IpAddress::from_str(s) {
+            Ok(a @ IpAddress::Ipv4(_)) => Ok(IpCidr::new(a, 32)),
+            Ok(a @ IpAddress::Ipv6(_)) => Ok(IpCidr::new(a, 128)),
+            _ => Err(Error::new(Code::InvArgs)),
+        }
+    }
+
+    fn parse_ports(s: &str) -> Result<(Port, Port), Error> {
+        let range = match s.find('-') {
+            Some(pos) => (
+                parse::int(&s[0..pos])? as Port,
+                parse::int(&s[(pos + 1)..])? as Port,
+            ),
+            None => {
+                let port = parse::int(s)? as Port;
+                (port, port)
+            },
+        };
+        if range.0 > range.1 {
+            return Err(Error::new(Code::InvArgs));
+        }
+        Ok(range)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty());
+
+        let action = match parts.next() {
+            Some("allow") => Action::Allow,
+            Some("deny") => Action::Deny,
+            _ => return Err(Error::new(Code::InvArgs)),
+        };
+        let dir = match parts.next() {
+            Some("in") => Some(Direction::In),
+            Some("out") => Some(Direction::Out),
+            Some("any") => None,
+            _ => return Err(Error::new(Code::InvArgs)),
+        };
+        let proto = match parts.next() {
+            Some("tcp") => Some(SocketType::Stream),
+            Some("udp") => Some(SocketType::Dgram),
+            Some("raw") => Some(SocketType::Raw),
+            Some("any") => None,
+            _ => return Err(Error::new(Code::InvArgs)),
+        };
+        let addr = match parts.next() {
+            None | Some("any") => None,
+            Some(a) => Some(Self::parse_addr(a)?),
+        };
+        let ports = match parts.next() {
+            None | Some("any") => None,
+            Some(p) => Some(Self::parse_ports(p)?),
+        };
+
+        if parts.next().is_some() {
+            return Err(Error::new(Code::InvArgs));
+        }
+
+        Ok(Self {
+            action,
+            dir,
+            proto,
+            addr,
+            ports,
+            packets: 0,
+            bytes: 0,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        };
+        let dir = match self.dir {
+            Some(Direction::In) => "in",
+            Some(Direction::Out) => "out",
+            None => "any",
+        };
+        let proto = match self.proto {
+            Some(SocketType::Stream) => "tcp",
+            Some(SocketType::Dgram) => "udp",
+            Some(SocketType::Raw) => "raw",
+            _ => "any",
+        };
+        write!(f, "{},{},{},", action, dir, proto)?;
+
+        match self.addr {
+            Some(a) => write!(f, "{},", a)?,
+            None => write!(f, "any,")?,
+        }
+        match self.ports {
+            Some((from, to)) if from == to => write!(f, "{}", from),
+            Some((from, to)) => write!(f, "{}-{}", from, to),
+            None => write!(f, "any"),
+        }
+    }
+}
+
+/// An ordered list of rules, where the first matching rule decides
+pub struct Filter {
+    rules: Vec<Rule>,
+}
+
+impl Filter {
+    pub const fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    /// Returns whether the first rule that matches the given packet allows it, without accounting
+    /// the packet to this rule. Packets that do not match any rule are allowed.
+    pub fn permits(&self, pkt: &Packet) -> bool {
+        self.rules
+            .iter()
+            .find(|r| r.matches(pkt))
+            .map(|r| r.action == Action::Allow)
+            .unwrap_or(true)
+    }
+
+    /// Returns the action of the first rule that matches the given packet, if any, and accounts
+    /// the packet to this rule
+    fn check(&mut self, pkt: &Packet) -> Option<Action> {
+        let rule = self.rules.iter_mut().find(|r| r.matches(pkt))?;
+        rule.packets += 1;
+        rule.bytes += pkt.size as u64;
+        Some(rule.action)
+    }
+}
+
+/// Returns the global rules
+pub fn global_rules() -> Ref<'static, Filter> {
+    GLOBAL.borrow()
+}
+
+/// Loads the global rules from the given file, which contains one rule per line. Empty lines and
+/// everything behind a `#` are ignored.
+pub fn load_rules(path: &str) -> Result<(), Error> {
+    let mut file = VFS::open(path, OpenFlags::R)?;
+    let content = file.read_to_string()?;
+
+    let mut global = GLOBAL.borrow_mut();
+    for (no, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let rule = Rule::from_str(line).map_err(|e| {
+            log!(
+                crate::LOG_ERR,
+                "{}:{}: invalid rule '{}'",
+                path,
+                no + 1,
+                line
+            );
+            e
+        })?;
+        global.add_rule(rule);
+    }
+    Ok(())
+}
+
+/// Checks whether the given packet passes the global rules and the given session rules
+///
+/// Packets that do not match any rule are allowed.
+pub fn allows(sess: u64, rules: &mut Filter, pkt: &Packet) -> bool {
+    let action = GLOBAL
+        .borrow_mut()
+        .check(pkt)
+        .or_else(|| rules.check(pkt))
+        .unwrap_or(Action::Allow);
+
+    if action == Action::Deny {
+        log!(
+            crate::LOG_FILTER,
+            "[{}] dropping {:?} {:?} packet with {}b (remote {})",
+            sess,
+            pkt.dir,
+            pkt.proto,
+            pkt.size,
+            pkt.remote
+        );
+    }
+    action == Action::Allow
+}
@@ -19,6 +19,7 @@
 #![feature(const_ptr_offset_from)]
 #![no_std]
 
+use core::cmp;
 use core::str::FromStr;
 
 use m3::cap::Selector;
@@ -27,25 +28,32 @@ use m3::col::{BTreeMap, String, ToString, Vec};
 use m3::com::{GateIStream, RecvGate};
 use m3::errors::{Code, Error};
 use m3::math;
-use m3::net::{log_net, NetLogEvent};
+use m3::net::{log_net, IpAddr, NetLogEvent};
 use m3::rc::Rc;
 use m3::server::{CapExchange, Handler, Server, SessId, SessionContainer, DEF_MAX_CLIENTS};
 use m3::session::NetworkOp;
 use m3::tiles::Activity;
 use m3::time::{TimeDuration, TimeInstant};
 use m3::{env, reply_vmsg};
-use m3::{log, println};
+use m3::{format, log, println, vec};
 
 use smoltcp::iface::{InterfaceBuilder, NeighborCache, Routes, SocketHandle};
-use smoltcp::wire::{EthernetAddress, IpAddress, Ipv4Cidr};
+use smoltcp::wire::{
+    EthernetAddress, IpAddress, IpCidr, Ipv4Address, Ipv4Cidr, Ipv6Address, Ipv6Cidr,
+};
 
 use crate::driver::DriverInterface;
 use crate::sess::NetworkSession;
+use crate::smoltcpif::dhcp::DhcpClient;
+use crate::smoltcpif::slaac::SlaacClient;
 use crate::smoltcpif::socket::to_m3_addr;
 
+mod capture;
 mod driver;
+mod filter;
 mod ports;
 mod sess;
+mod shaper;
 mod smoltcpif;
 
 pub const LOG_ERR: bool = true;
@@ -58,13 +66,18 @@ pub const LOG_NIC_ERR: bool = true;
 pub const LOG_NIC_DETAIL: bool = false;
 pub const LOG_SMOLTCP: bool = false;
 pub const LOG_DETAIL: bool = false;
+pub const LOG_FILTER: bool = false;
 
 const MAX_SOCKETS: usize = 64;
 
 static OWN_IP: LazyStaticCell<IpAddress> = LazyStaticCell::default();
+static OWN_IPS6: StaticRefCell<Vec<IpAddress>> = StaticRefCell::new(Vec::new());
 static NAMESERVER: LazyStaticCell<IpAddress> = LazyStaticCell::default();
 static OWN_MAC: [u8; 6] = [0x00, 0x0A, 0x35, 0x03, 0x02, 0x03];
 static TIMEOUTS: StaticRefCell<Vec<(SocketHandle, TimeInstant)>> = StaticRefCell::new(Vec::new());
+// the joined multicast groups and the number of sockets that are members of each group
+static GROUPS: StaticRefCell<Vec<(Ipv4Address, usize)>> = StaticRefCell::new(Vec::new());
+static START: LazyStaticCell<TimeInstant> = LazyStaticCell::default();
 
 pub fn add_timeout(handle: SocketHandle, timeout: TimeInstant) {
     TIMEOUTS.borrow_mut().push((handle, timeout));
@@ -74,6 +87,39 @@ pub fn remove_timeout(handle: SocketHandle) {
     TIMEOUTS.borrow_mut().retain(|t| t.0 != handle);
 }
 
+/// Returns the current time in the representation of smoltcp
+pub fn smol_now() -> smoltcp::time::Instant {
+    smoltcp::time::Instant::from_millis(START.get().elapsed().as_millis() as i64)
+}
+
+/// Adds a socket to the given multicast group, joining the group if necessary
+pub fn join_group(iface: &mut DriverInterface<'_>, addr: Ipv4Address) -> Result<(), Error> {
+    let mut groups = GROUPS.borrow_mut();
+    if let Some(g) = groups.iter_mut().find(|g| g.0 == addr) {
+        g.1 += 1;
+        return Ok(());
+    }
+
+    iface.join_multicast_group(addr, smol_now()).map_err(|e| {
+        log!(LOG_ERR, "Unable to join multicast group {}: {}", addr, e);
+        Error::new(Code::InvArgs)
+    })?;
+    groups.push((addr, 1));
+    Ok(())
+}
+
+/// Removes a socket from the given multicast group, leaving the group if it was the last member
+pub fn leave_group(iface: &mut DriverInterface<'_>, addr: Ipv4Address) {
+    let mut groups = GROUPS.borrow_mut();
+    if let Some(idx) = groups.iter().position(|g| g.0 == addr) {
+        groups[idx].1 -= 1;
+        if groups[idx].1 == 0 {
+            groups.remove(idx);
+            iface.leave_multicast_group(addr, smol_now()).ok();
+        }
+    }
+}
+
 fn next_timeout() -> Option<TimeInstant> {
     TIMEOUTS
         .borrow()
@@ -91,6 +137,12 @@ struct NetHandler<'a> {
     iface: DriverInterface<'a>,
     // the receive gates for requests from clients
     rgate: Rc<RecvGate>,
+    // the DHCP client, if our address is configured dynamically
+    dhcp: Option<DhcpClient>,
+    // the SLAAC client, if our IPv6 address is configured via router advertisements
+    slaac: Option<SlaacClient>,
+    // the session that is scheduled first in the current round
+    first_sess: SessId,
 }
 
 impl NetHandler<'_> {
@@ -109,6 +161,24 @@ impl NetHandler<'_> {
                 NetworkOp::LISTEN => sess.listen(is, &mut self.iface),
                 NetworkOp::CONNECT => sess.connect(is, &mut self.iface),
                 NetworkOp::ABORT => sess.abort(is, &mut self.iface),
+                NetworkOp::SET_OPTION => sess.set_option(is, &mut self.iface),
+                NetworkOp::GET_OPTION => sess.get_option(is),
+                NetworkOp::JOIN_GROUP => sess.join_group(is, &mut self.iface),
+                NetworkOp::LEAVE_GROUP => sess.leave_group(is, &mut self.iface),
+                NetworkOp::FILTER_STATS => match sess.is_admin() {
+                    true => self.filter_stats(is),
+                    false => Err(Error::new(Code::NoPerm)),
+                },
+                NetworkOp::CAPTURE_START => match sess.is_admin() {
+                    true => Self::start_capture(is),
+                    false => Err(Error::new(Code::NoPerm)),
+                },
+                NetworkOp::CAPTURE_STOP => match sess.is_admin() {
+                    true => Self::stop_capture(is),
+                    false => Err(Error::new(Code::NoPerm)),
+                },
+                NetworkOp::IFACE_STATS => self.iface_stats(is),
+                NetworkOp::SOCKET_STATS => self.socket_stats(sess_id, is),
                 NetworkOp::GET_IP => self.get_ip(is),
                 NetworkOp::GET_NAMESRV => self.get_nameserver(is),
                 _ => Err(Error::new(Code::InvArgs)),
@@ -120,8 +190,124 @@ impl NetHandler<'_> {
     }
 
     fn get_ip(&self, is: &mut GateIStream<'_>) -> Result<(), Error> {
-        let addr = to_m3_addr(OWN_IP.get());
-        reply_vmsg!(is, Code::None as i32, addr.0)
+        // the IPv4 address comes first, followed by all IPv6 addresses
+        let addrs = core::iter::once(OWN_IP.get())
+            .chain(OWN_IPS6.borrow().iter().copied())
+            .map(to_m3_addr)
+            .collect::<Vec<IpAddr>>();
+        reply_vmsg!(is, Code::None as i32, addrs)
+    }
+
+    fn filter_stats(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let idx: usize = is.pop()?;
+
+        // the global rules come first, followed by the rules of all sessions
+        let mut rules = filter::global_rules()
+            .rules()
+            .iter()
+            .map(|r| (None, r.to_string(), r.packets(), r.bytes()))
+            .collect::<Vec<(Option<u64>, String, u64, u64)>>();
+        self.sessions.for_each(|s| {
+            if let NetworkSession::SocketSession(ss) = s {
+                let sess = ss.ident();
+                rules.extend(
+                    ss.filter()
+                        .rules()
+                        .iter()
+                        .map(|r| (Some(sess), r.to_string(), r.packets(), r.bytes())),
+                );
+            }
+        });
+
+        // the rules are transferred one at a time to not exceed the message size
+        match rules.get(idx) {
+            Some((sess, rule, packets, bytes)) => reply_vmsg!(
+                is,
+                Code::None as i32,
+                rules.len(),
+                sess.is_none(),
+                sess.unwrap_or(0),
+                rule,
+                *packets,
+                *bytes
+            ),
+            None => reply_vmsg!(is, Code::None as i32, rules.len()),
+        }
+    }
+
+    fn start_capture(is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let path: &str = is.pop()?;
+        let rules: &str = is.pop()?;
+        capture::start(path, rules)?;
+        is.reply_error(Code::None)
+    }
+
+    fn stop_capture(is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let frames = capture::stop()?;
+        reply_vmsg!(is, Code::None as i32, frames)
+    }
+
+    fn iface_stats(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let stats = self.iface.stats();
+        reply_vmsg!(
+            is,
+            Code::None as i32,
+            stats.rx_packets.get(),
+            stats.rx_bytes.get(),
+            stats.rx_errors.get(),
+            stats.tx_packets.get(),
+            stats.tx_bytes.get(),
+            stats.tx_errors.get()
+        )
+    }
+
+    fn socket_stats(&mut self, sess_id: SessId, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let idx: usize = is.pop()?;
+
+        // admin sessions see the sockets of all sessions, others only their own
+        let own = match self.sessions.get(sess_id) {
+            Some(s) if s.is_admin() => None,
+            Some(NetworkSession::SocketSession(ss)) => Some(ss.ident()),
+            _ => return Err(Error::new(Code::InvArgs)),
+        };
+
+        let mut sockets = Vec::new();
+        self.sessions.for_each(|s| {
+            if let NetworkSession::SocketSession(ss) = s {
+                let sess = ss.ident();
+                if own.map(|o| o == sess).unwrap_or(true) {
+                    sockets.extend(ss.sockets().map(|(sd, s)| (sess, sd, s.clone())));
+                }
+            }
+        });
+
+        // the sockets are transferred one at a time to not exceed the message size
+        match sockets.get(idx) {
+            Some((sess, sd, socket)) => {
+                let socket = socket.borrow();
+                let (local, remote) = socket.addresses(&mut self.iface);
+                let stats = socket.stats();
+                reply_vmsg!(
+                    is,
+                    Code::None as i32,
+                    sockets.len(),
+                    *sess,
+                    *sd,
+                    socket.socket_type() as usize,
+                    socket.state_name(&mut self.iface),
+                    local.addr,
+                    local.port,
+                    remote.addr,
+                    remote.port,
+                    stats.rx_packets,
+                    stats.rx_bytes,
+                    stats.tx_packets,
+                    stats.tx_bytes,
+                    stats.drops
+                )
+            },
+            None => reply_vmsg!(is, Code::None as i32, sockets.len()),
+        }
     }
 
     fn get_nameserver(&self, is: &mut GateIStream<'_>) -> Result<(), Error> {
@@ -130,28 +316,48 @@ impl NetHandler<'_> {
         }
 
         let addr = to_m3_addr(NAMESERVER.get());
-        reply_vmsg!(is, Code::None as i32, addr.0)
+        reply_vmsg!(is, Code::None as i32, addr)
+    }
+
+    // calls `func` for all socket sessions in round-robin order, starting with `first_sess`
+    fn for_each_socket_sess<F>(&mut self, mut func: F)
+    where
+        F: FnMut(&mut sess::SocketSession, &mut DriverInterface<'_>),
+    {
+        let count = self.sessions.capacity();
+        for i in 0..count {
+            let sid = (self.first_sess + i) % count;
+            if let Some(NetworkSession::SocketSession(ss)) = self.sessions.get_mut(sid) {
+                func(ss, &mut self.iface);
+            }
+        }
     }
 
     // processes outgoing events to clients
     fn process_outgoing(&mut self) -> bool {
-        let iface = &mut self.iface;
         let mut res = false;
-        self.sessions.for_each(|s| {
-            if let NetworkSession::SocketSession(ss) = s {
-                res |= ss.process_outgoing(iface)
-            }
-        });
+        self.for_each_socket_sess(|ss, iface| res |= ss.process_outgoing(iface));
         res
     }
 
     // processes incoming events from clients and returns whether there is still work to do
     fn process_incoming(&mut self) -> bool {
-        let iface = &mut self.iface;
+        // start a new round with the next session to not favor any session
+        self.first_sess = (self.first_sess + 1) % self.sessions.capacity();
+
         let mut res = false;
+        self.for_each_socket_sess(|ss, iface| res |= ss.process_incoming(iface));
+        res
+    }
+
+    // returns the earliest point in time at which a throttled session can continue
+    fn ready_at(&mut self) -> Option<TimeInstant> {
+        let mut res: Option<TimeInstant> = None;
         self.sessions.for_each(|s| {
             if let NetworkSession::SocketSession(ss) = s {
-                res |= ss.process_incoming(iface)
+                if let Some(ready) = ss.ready_at() {
+                    res = Some(res.map_or(ready, |r| cmp::min(r, ready)));
+                }
             }
         });
         res
@@ -231,14 +437,62 @@ pub fn own_ip() -> IpAddress {
     OWN_IP.get()
 }
 
+/// Returns our own IP address that should be used to communicate with `remote`
+pub fn own_ip_for(remote: IpAddress) -> IpAddress {
+    match remote {
+        IpAddress::Ipv6(r) => {
+            let ips = OWN_IPS6.borrow();
+            // prefer the link-local address for link-local peers and a global address otherwise
+            ips.iter()
+                .find(|a| matches!(a, IpAddress::Ipv6(l) if l.is_link_local() == r.is_link_local()))
+                .or_else(|| ips.first())
+                .copied()
+                .unwrap_or(IpAddress::Unspecified)
+        },
+        _ => OWN_IP.get(),
+    }
+}
+
+/// Builds the IPv6 interface identifier from the given MAC address according to the modified
+/// EUI-64 format (RFC 4291, appendix A)
+fn eui64_ident(mac: &[u8; 6]) -> [u8; 8] {
+    [
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xFF,
+        0xFE,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]
+}
+
+/// Returns `cidr` with its interface identifier replaced by the EUI-64 identifier of `mac`, if
+/// the identifier is zero (stateless address autoconfiguration with a statically known prefix)
+fn autoconf_ipv6(cidr: Ipv6Cidr, mac: &[u8; 6]) -> Ipv6Cidr {
+    let mut bytes = cidr.address().0;
+    if bytes[8..].iter().all(|b| *b == 0) {
+        bytes[8..].copy_from_slice(&eui64_ident(mac));
+    }
+    Ipv6Cidr::new(Ipv6Address(bytes), cidr.prefix_len())
+}
+
 #[derive(Clone, Debug)]
 pub struct NetSettings {
     driver: String,
     name: String,
     ip: smoltcp::wire::Ipv4Address,
+    dhcp: bool,
     netmask: smoltcp::wire::Ipv4Address,
-    nameserver: Option<smoltcp::wire::Ipv4Address>,
+    nameserver: Option<IpAddress>,
     gateway: Option<smoltcp::wire::Ipv4Address>,
+    ip6: Option<Ipv6Cidr>,
+    slaac: bool,
+    gateway6: Option<Ipv6Address>,
+    neighbors: usize,
+    rules: Option<String>,
+    capture: Option<String>,
     max_clients: usize,
 }
 
@@ -249,8 +503,15 @@ impl Default for NetSettings {
             name: String::default(),
             netmask: smoltcp::wire::Ipv4Address::new(255, 255, 255, 0),
             ip: smoltcp::wire::Ipv4Address::default(),
+            dhcp: false,
             nameserver: None,
             gateway: None,
+            ip6: None,
+            slaac: false,
+            gateway6: None,
+            neighbors: 8,
+            rules: None,
+            capture: None,
             max_clients: DEF_MAX_CLIENTS,
         }
     }
@@ -258,18 +519,44 @@ impl Default for NetSettings {
 
 fn usage() -> ! {
     println!(
-        "Usage: {} [-d <driver>] [-m <max-clients>] [-a <netmask>] [-n <nameserver>] [-g <gateway>] <name> <ip>",
+        concat!(
+            "Usage: {} [-d <driver>] [-m <max-clients>] [-a <netmask>] [-n <nameserver>] ",
+            "[-g <gateway>] [-6 (<ipv6>/<prefix>|auto)] [-G <ipv6-gateway>] [-N <neighbors>] ",
+            "[-f <rules>] [-c <pcap-file>] <name> (<ip>|dhcp)"
+        ),
         env::args().next().unwrap()
     );
     println!();
     println!("  -d: the driver to use (lo=loopback or default=E1000/Fifo)");
     println!("  -m: the maximum number of clients (receive slots)");
     println!("  -a: the network mask to use (default: 255.255.255.0)");
-    println!("  -n: the IP address of the DNS server");
+    println!("  -n: the IPv4 or IPv6 address of the DNS server");
     println!("  -g: the IP address of the default gateway");
+    println!("  -6: enables IPv6 with given address; a zero interface identifier is derived");
+    println!("      from the MAC address (EUI-64), as is the additional link-local address");
+    println!("      with 'auto', the address is configured via router advertisements (SLAAC)");
+    println!("  -G: the IPv6 address of the default gateway (overrides the advertised router)");
+    println!("  -N: the number of neighbor cache entries for ARP and NDISC (default: 8)");
+    println!("  -f: the file with packet filter rules that apply to all sessions");
+    println!("  -c: capture all frames into the given file in the pcap format");
+    println!();
+    println!(
+        "  With 'dhcp' instead of an IP address, the IPv4 address, the default gateway and the"
+    );
+    println!("  nameserver are obtained via DHCP, overriding -a, -g, and -n.");
+    println!(
+        "  With '-6 auto', a nameserver advertised by the router is used if there is no other."
+    );
     m3::exit(1);
 }
 
+/// Returns the value of the option at index `i` in `args`
+fn option_value<'a>(args: &[&'a str], i: usize) -> Result<&'a str, String> {
+    args.get(i + 1)
+        .copied()
+        .ok_or_else(|| format!("Missing value for option {}", args[i]))
+}
+
 fn parse_args() -> Result<NetSettings, String> {
     let mut settings = NetSettings::default();
 
@@ -296,10 +583,8 @@ fn parse_args() -> Result<NetSettings, String> {
             },
             "-n" => {
                 settings.nameserver = Some(
-                    smoltcp::wire::Ipv4Address::from_str(
-                        args.get(i + 1).expect("Failed to read nameserver!"),
-                    )
-                    .expect("Failed to parse nameserver IP!"),
+                    IpAddress::from_str(option_value(&args, i)?)
+                        .map_err(|_| String::from("Failed to parse nameserver IP"))?,
                 );
                 i += 1;
             },
@@ -312,6 +597,47 @@ fn parse_args() -> Result<NetSettings, String> {
                 );
                 i += 1;
             },
+            "-6" => {
+                match option_value(&args, i)? {
+                    "auto" => settings.slaac = true,
+                    addr => {
+                        settings.ip6 = Some(
+                            Ipv6Cidr::from_str(addr)
+                                .map_err(|_| String::from("Failed to parse IPv6 address/prefix"))?,
+                        )
+                    },
+                }
+                i += 1;
+            },
+            "-G" => {
+                settings.gateway6 = Some(
+                    Ipv6Address::from_str(option_value(&args, i)?)
+                        .map_err(|_| String::from("Failed to parse IPv6 gateway"))?,
+                );
+                i += 1;
+            },
+            "-N" => {
+                settings.neighbors = option_value(&args, i)?
+                    .parse::<usize>()
+                    .map_err(|_| String::from("Failed to parse neighbor count"))?;
+                i += 1;
+            },
+            "-f" => {
+                settings.rules = Some(
+                    args.get(i + 1)
+                        .expect("Failed to read rules file!")
+                        .to_string(),
+                );
+                i += 1;
+            },
+            "-c" => {
+                settings.capture = Some(
+                    args.get(i + 1)
+                        .expect("Failed to read capture file!")
+                        .to_string(),
+                );
+                i += 1;
+            },
             _ => break,
         }
         i += 1;
@@ -322,9 +648,13 @@ fn parse_args() -> Result<NetSettings, String> {
     }
 
     settings.name = args.get(i).expect("Failed to read name!").to_string();
-    settings.ip =
-        smoltcp::wire::Ipv4Address::from_str(args.get(i + 1).expect("Failed to read ip!"))
-            .expect("Failed to parse IP address!");
+    match *args.get(i + 1).expect("Failed to read ip!") {
+        "dhcp" => settings.dhcp = true,
+        ip => {
+            settings.ip =
+                smoltcp::wire::Ipv4Address::from_str(ip).expect("Failed to parse IP address!")
+        },
+    }
     Ok(settings)
 }
 
@@ -345,20 +675,52 @@ pub fn main() -> i32 {
 
     rgate.activate().expect("Failed to activate main rgate");
 
-    let mut neighbor_cache_entries = [None; 8];
+    // the neighbor cache is shared between ARP (IPv4) and neighbor discovery (IPv6)
+    let mut neighbor_cache_entries = vec![None; settings.neighbors];
     let neighbor_cache = NeighborCache::new(&mut neighbor_cache_entries[..]);
 
-    let ip_cidr = smoltcp::wire::IpCidr::Ipv4(
+    // with DHCP, we start with the unspecified address, which is replaced once we got a lease
+    let ip_cidr = IpCidr::Ipv4(if settings.dhcp {
+        Ipv4Cidr::new(smoltcp::wire::Ipv4Address::UNSPECIFIED, 0)
+    }
+    else {
         Ipv4Cidr::from_netmask(settings.ip, settings.netmask)
-            .expect("Invalid IP-address/netmask pair"),
-    );
+            .expect("Invalid IP-address/netmask pair")
+    });
     let ip_addr = ip_cidr.address();
     OWN_IP.set(ip_addr);
 
-    if let Some(ns) = settings.nameserver {
-        let ns_cidr =
-            Ipv4Cidr::from_netmask(ns, settings.netmask).expect("Invalid nameserver/netmask pair");
-        NAMESERVER.set(IpAddress::Ipv4(ns_cidr.address()));
+    let mut ip_addrs = vec![ip_cidr];
+    let link_local = autoconf_ipv6(
+        Ipv6Cidr::new(Ipv6Address::new(0xFE80, 0, 0, 0, 0, 0, 0, 0), 64),
+        &OWN_MAC,
+    );
+    if settings.ip6.is_some() || settings.slaac {
+        // with SLAAC, the global address is unspecified until we got a router advertisement
+        let global = match settings.ip6 {
+            Some(ip6) => autoconf_ipv6(ip6, &OWN_MAC),
+            None => Ipv6Cidr::new(Ipv6Address::UNSPECIFIED, 0),
+        };
+
+        // the global address comes last in the interface (see SlaacClient), but first for clients
+        for cidr in [link_local, global] {
+            ip_addrs.push(IpCidr::Ipv6(cidr));
+            if !cidr.address().is_unspecified() {
+                OWN_IPS6
+                    .borrow_mut()
+                    .insert(0, IpAddress::Ipv6(cidr.address()));
+            }
+        }
+    }
+
+    match settings.nameserver {
+        Some(IpAddress::Ipv4(ns)) => {
+            let ns_cidr = Ipv4Cidr::from_netmask(ns, settings.netmask)
+                .expect("Invalid nameserver/netmask pair");
+            NAMESERVER.set(IpAddress::Ipv4(ns_cidr.address()));
+        },
+        Some(ns) => NAMESERVER.set(ns),
+        None => {},
     }
 
     let mut routes = Routes::new(BTreeMap::new());
@@ -367,19 +729,36 @@ pub fn main() -> i32 {
             .add_default_ipv4_route(gw)
             .expect("Cannot add default route");
     }
+    if let Some(gw) = settings.gateway6 {
+        routes
+            .add_default_ipv6_route(gw)
+            .expect("Cannot add default IPv6 route");
+    }
 
+    if let Some(ref rules) = settings.rules {
+        filter::load_rules(rules).expect("Unable to load packet filter rules");
+    }
+
+    START.set(TimeInstant::now());
     ports::init(MAX_SOCKETS);
 
+    if let Some(ref path) = settings.capture {
+        capture::start(path, "").expect("Unable to start packet capture");
+    }
+
     let iface = if settings.driver == "lo" {
         driver::DriverInterface::Lo(
             InterfaceBuilder::new(
-                smoltcp::phy::Loopback::new(smoltcp::phy::Medium::Ethernet),
+                driver::MonitoredDevice::new(smoltcp::phy::Loopback::new(
+                    smoltcp::phy::Medium::Ethernet,
+                )),
                 Vec::with_capacity(MAX_SOCKETS),
             )
             .hardware_addr(EthernetAddress::from_bytes(&OWN_MAC).into())
             .neighbor_cache(neighbor_cache)
-            .ip_addrs([ip_cidr])
+            .ip_addrs(ip_addrs)
             .routes(routes)
+            .ipv4_multicast_groups(BTreeMap::new())
             .finalize(),
         )
     }
@@ -391,12 +770,16 @@ pub fn main() -> i32 {
         #[cfg(target_vendor = "host")]
         let device = driver::DevFifo::new(&settings.name);
         driver::DriverInterface::Eth(
-            InterfaceBuilder::new(device, Vec::with_capacity(MAX_SOCKETS))
-                .hardware_addr(EthernetAddress::from_bytes(&OWN_MAC).into())
-                .neighbor_cache(neighbor_cache)
-                .ip_addrs([ip_cidr])
-                .routes(routes)
-                .finalize(),
+            InterfaceBuilder::new(
+                driver::MonitoredDevice::new(device),
+                Vec::with_capacity(MAX_SOCKETS),
+            )
+            .hardware_addr(EthernetAddress::from_bytes(&OWN_MAC).into())
+            .neighbor_cache(neighbor_cache)
+            .ip_addrs(ip_addrs)
+            .routes(routes)
+            .ipv4_multicast_groups(BTreeMap::new())
+            .finalize(),
         )
     };
 
@@ -405,7 +788,21 @@ pub fn main() -> i32 {
         sessions: SessionContainer::new(settings.max_clients),
         iface,
         rgate: Rc::new(rgate),
+        dhcp: None,
+        slaac: None,
+        first_sess: 0,
     };
+    if settings.dhcp {
+        handler.dhcp = Some(DhcpClient::new(&mut handler.iface));
+    }
+    if settings.slaac {
+        handler.slaac = Some(SlaacClient::new(
+            &mut handler.iface,
+            link_local.address(),
+            OWN_MAC,
+            settings.gateway6.is_none(),
+        ));
+    }
 
     let serv = Server::new(&settings.name, &mut handler).expect("Failed to create server!");
     handler.sel = serv.sel();
@@ -415,20 +812,27 @@ pub fn main() -> i32 {
         concat!(
             "netrs: created service {} with {{\n",
             "  driver={},\n",
+            "  dhcp={},\n",
             "  ip={:?},\n",
             "  nameserver={:?},\n",
             "  gateway={:?},\n",
+            "  ip6={:?},\n",
+            "  slaac={},\n",
+            "  gateway6={:?},\n",
             "}}"
         ),
         settings.name,
         settings.driver,
+        settings.dhcp,
         settings.ip,
         settings.nameserver,
         settings.gateway,
+        OWN_IPS6.borrow(),
+        settings.slaac,
+        settings.gateway6,
     );
 
     let rgatec = handler.rgate.clone();
-    let start = TimeInstant::now();
 
     'outer: loop {
         let sleep_nanos = loop {
@@ -447,13 +851,22 @@ pub fn main() -> i32 {
             // receive events from clients and push data to send into smoltcp sockets
             let sends_pending = handler.process_incoming();
 
-            let cur_time = smoltcp::time::Instant::from_millis(start.elapsed().as_millis() as i64);
+            let cur_time = smol_now();
 
             // now poll smoltcp to send and receive packets
             if let Err(e) = handler.iface.poll(cur_time) {
                 log!(LOG_DETAIL, "netrs: poll failed: {}", e);
             }
 
+            // apply new or lost DHCP leases
+            if let Some(dhcp) = handler.dhcp.as_mut() {
+                dhcp.process(&mut handler.iface);
+            }
+            // apply router advertisements and solicit them at startup
+            if let Some(slaac) = handler.slaac.as_mut() {
+                slaac.process(&mut handler.iface);
+            }
+
             // check for outgoing events we have to send to clients
             let recvs_pending = handler.process_outgoing();
 
@@ -475,6 +888,12 @@ pub fn main() -> i32 {
             Some(timeout) if timeout > now && timeout - now < sleep_nanos => timeout - now,
             _ => sleep_nanos,
         };
+        // wake up as soon as the rate limit of a throttled session permits further transfers
+        let sleep_nanos = match handler.ready_at() {
+            Some(ready) if ready <= now => TimeDuration::ZERO,
+            Some(ready) if ready - now < sleep_nanos => ready - now,
+            _ => sleep_nanos,
+        };
 
         log_net(NetLogEvent::StartedWaiting, 0, 0);
         log!(LOG_DETAIL, "Sleeping for {:?}", sleep_nanos);
@@ -136,6 +136,53 @@ impl NetworkSession {
         }
     }
 
+    pub fn set_option(
+        &mut self,
+        is: &mut GateIStream<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(), Error> {
+        match self {
+            NetworkSession::FileSession(_fs) => Err(Error::new(Code::NotSup)),
+            NetworkSession::SocketSession(ss) => ss.set_option(is, iface),
+        }
+    }
+
+    pub fn get_option(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        match self {
+            NetworkSession::FileSession(_fs) => Err(Error::new(Code::NotSup)),
+            NetworkSession::SocketSession(ss) => ss.get_option(is),
+        }
+    }
+
+    pub fn join_group(
+        &mut self,
+        is: &mut GateIStream<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(), Error> {
+        match self {
+            NetworkSession::FileSession(_fs) => Err(Error::new(Code::NotSup)),
+            NetworkSession::SocketSession(ss) => ss.join_group(is, iface),
+        }
+    }
+
+    pub fn leave_group(
+        &mut self,
+        is: &mut GateIStream<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(), Error> {
+        match self {
+            NetworkSession::FileSession(_fs) => Err(Error::new(Code::NotSup)),
+            NetworkSession::SocketSession(ss) => ss.leave_group(is, iface),
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        match self {
+            NetworkSession::FileSession(_fs) => false,
+            NetworkSession::SocketSession(ss) => ss.is_admin(),
+        }
+    }
+
     pub fn close(&mut self, iface: &mut DriverInterface<'_>) -> Result<(), Error> {
         match self {
             NetworkSession::FileSession(fs) => fs.close(iface),
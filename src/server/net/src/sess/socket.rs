@@ -16,6 +16,7 @@
  */
 
 use core::cmp;
+use core::str::FromStr;
 
 use m3::cap::Selector;
 use m3::cell::RefCell;
@@ -23,27 +24,38 @@ use m3::col::Vec;
 use m3::com::{GateIStream, RecvGate, SendGate};
 use m3::errors::{Code, Error};
 use m3::kif::{CapRngDesc, CapType};
-use m3::net::{log_net, IpAddr, NetLogEvent, Port, Sd, SocketArgs, SocketType, MTU};
+use m3::net::{
+    log_net, Endpoint, IpAddr, NetLogEvent, Port, Sd, SocketArgs, SocketOpt, SocketType, MTU,
+};
 use m3::parse;
 use m3::rc::Rc;
 use m3::serialize::M3Deserializer;
 use m3::server::CapExchange;
 use m3::session::{NetworkOp, ServerSession};
 use m3::tcu;
+use m3::time::TimeInstant;
 use m3::vfs::OpenFlags;
 use m3::{log, reply_vmsg, vec};
 
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
 use crate::driver::DriverInterface;
+use crate::filter::{Direction, Filter, Rule};
 use crate::ports::{self, AnyPort};
 use crate::sess::file::FileSession;
-use crate::smoltcpif::socket::{to_m3_addr, to_m3_ep, SendNetEvent, Socket};
+use crate::shaper::Budget;
+use crate::smoltcpif::socket::{to_m3_addr, to_m3_ep, to_smol_addr, SendNetEvent, Socket};
 
 struct Settings {
     bufs: usize,
     socks: usize,
     raw: bool,
+    admin: bool,
+    // the maximum data rate per direction in bytes per second
+    rate: Option<u64>,
     tcp_ports: Vec<(Port, Port)>,
     udp_ports: Vec<(Port, Port)>,
+    filter: Filter,
 }
 
 impl Default for Settings {
@@ -52,8 +64,11 @@ impl Default for Settings {
             bufs: 64 * 1024,
             socks: 4,
             raw: false,
+            admin: false,
+            rate: None,
             tcp_ports: Vec::new(),
             udp_ports: Vec::new(),
+            filter: Filter::new(),
         }
     }
 }
@@ -93,6 +108,19 @@ fn parse_arguments(args_str: &str) -> Result<Settings, Error> {
         else if arg == "raw=yes" {
             args.raw = true;
         }
+        else if arg == "admin=yes" {
+            args.admin = true;
+        }
+        else if let Some(rate) = arg.strip_prefix("rate=") {
+            let rate = parse::size(rate)? as u64;
+            if rate == 0 {
+                return Err(Error::new(Code::InvArgs));
+            }
+            args.rate = Some(rate);
+        }
+        else if let Some(rule) = arg.strip_prefix("rule=") {
+            args.filter.add_rule(Rule::from_str(rule)?);
+        }
         else if let Some(portdesc) = arg.strip_prefix("tcp=") {
             parse_ports(portdesc, &mut args.tcp_ports)?;
         }
@@ -117,6 +145,12 @@ pub struct SocketSession {
     server_session: ServerSession,
     // sockets the client has open
     sockets: Vec<Option<Rc<RefCell<Socket>>>>,
+    // the budgets for sending data to the network and for passing received data to the client
+    tx: Budget,
+    rx: Budget,
+    // the sockets to start with in the next round of sending and receiving
+    tx_next: usize,
+    rx_next: usize,
 }
 
 impl SocketSession {
@@ -140,10 +174,41 @@ impl SocketSession {
             rgate,
             server_session,
             sockets: vec![None; settings.socks],
+            tx: Budget::new(settings.rate),
+            rx: Budget::new(settings.rate),
+            tx_next: 0,
+            rx_next: 0,
             settings,
         })
     }
 
+    pub fn ident(&self) -> u64 {
+        self.server_session.ident()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.settings.admin
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.settings.filter
+    }
+
+    /// Returns the point in time at which the rate limit allows further transfers, if throttled
+    pub fn ready_at(&mut self) -> Option<TimeInstant> {
+        match (self.tx.ready_at(), self.rx.ready_at()) {
+            (Some(tx), Some(rx)) => Some(cmp::min(tx, rx)),
+            (tx, rx) => tx.or(rx),
+        }
+    }
+
+    pub fn sockets(&self) -> impl Iterator<Item = (Sd, &Rc<RefCell<Socket>>)> {
+        self.sockets
+            .iter()
+            .enumerate()
+            .filter_map(|(sd, s)| s.as_ref().map(|s| (sd, s)))
+    }
+
     pub fn obtain(
         &mut self,
         crt: usize,
@@ -166,6 +231,17 @@ impl SocketSession {
                 xchg.out_args().push(sd);
                 Ok(())
             },
+            NetworkOp::ACCEPT => {
+                let (caps, sd, local_ep, remote_ep) = self.accept_socket(is, iface)?;
+                xchg.out_caps(caps);
+                let os = xchg.out_args();
+                os.push(sd);
+                os.push(local_ep.addr);
+                os.push(local_ep.port);
+                os.push(remote_ep.addr);
+                os.push(remote_ep.port);
+                Ok(())
+            },
             NetworkOp::OPEN_FILE => {
                 let caps = self.open_file(crt, srv_sel, is)?;
                 xchg.out_caps(caps);
@@ -291,8 +367,9 @@ impl SocketSession {
         Err(Error::new(Code::NoSpace))
     }
 
-    fn remove_socket(&mut self, sd: Sd) {
+    fn remove_socket(&mut self, sd: Sd, iface: &mut DriverInterface<'_>) {
         if let Some(s) = self.sockets[sd].take() {
+            s.borrow_mut().remove(iface);
             self.settings.bufs += s.borrow().buffer_space();
         }
     }
@@ -348,6 +425,67 @@ impl SocketSession {
         }
     }
 
+    fn accept_socket(
+        &mut self,
+        is: &mut M3Deserializer<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(CapRngDesc, Sd, Endpoint, Endpoint), Error> {
+        let sd: Sd = is.pop()?;
+
+        let res = self.do_accept(sd, iface);
+
+        log!(
+            crate::LOG_SESS,
+            "[{}] net::accept(sd={}) -> {:?}",
+            self.server_session.ident(),
+            sd,
+            res
+        );
+
+        res
+    }
+
+    fn do_accept(
+        &mut self,
+        sd: Sd,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(CapRngDesc, Sd, Endpoint, Endpoint), Error> {
+        let listener = self.get_socket(sd)?;
+        let nsd = self
+            .sockets
+            .iter()
+            .position(|s| s.is_none())
+            .ok_or_else(|| Error::new(Code::NoSpace))?;
+
+        // 2 caps for us, 2 for the client
+        let caps = m3::tiles::Activity::own().alloc_sels(4);
+
+        let socket = listener.borrow_mut().accept(nsd, caps, iface)?;
+        let (local_ep, remote_ep) = socket.endpoints(iface).unwrap();
+        self.sockets[nsd] = Some(Rc::new(RefCell::new(socket)));
+
+        // the accepted connection took its buffers with it; replace it in the backlog if we still
+        // have enough space. Otherwise, the backlog is refilled as soon as sockets are removed.
+        self.fill_backlog(&listener, iface);
+
+        Ok((
+            CapRngDesc::new(CapType::OBJECT, caps + 2, 2),
+            nsd,
+            local_ep,
+            remote_ep,
+        ))
+    }
+
+    fn fill_backlog(&mut self, sock: &Rc<RefCell<Socket>>, iface: &mut DriverInterface<'_>) {
+        let space = sock.borrow().conn_space();
+        while sock.borrow().missing_backlog() > 0 && self.settings.bufs >= space {
+            if sock.borrow_mut().add_backlog(iface).is_err() {
+                break;
+            }
+            self.settings.bufs -= space;
+        }
+    }
+
     fn can_use_port(&self, ty: SocketType, port: Port) -> bool {
         let ports = match ty {
             SocketType::Stream => &self.settings.tcp_ports,
@@ -391,10 +529,12 @@ impl SocketSession {
         };
 
         let port_no = port.number();
-        sock.borrow_mut().bind(crate::own_ip(), port, iface)?;
+        // bind to the unspecified address to receive packets for IPv4 and IPv6
+        sock.borrow_mut()
+            .bind(IpAddress::Unspecified, port, iface)?;
 
         let addr = to_m3_addr(crate::own_ip());
-        reply_vmsg!(is, Code::None as i32, addr.0, port_no)
+        reply_vmsg!(is, Code::None as i32, addr, port_no)
     }
 
     pub fn listen(
@@ -404,13 +544,15 @@ impl SocketSession {
     ) -> Result<(), Error> {
         let sd: Sd = is.pop()?;
         let port: Port = is.pop()?;
+        let backlog: usize = is.pop()?;
 
         log!(
             crate::LOG_SESS,
-            "[{}] net::listen(sd={}, port={})",
+            "[{}] net::listen(sd={}, port={}, backlog={})",
             self.server_session.ident(),
             sd,
-            port
+            port,
+            backlog
         );
 
         let sock = self.get_socket(sd)?;
@@ -418,10 +560,18 @@ impl SocketSession {
             return Err(Error::new(Code::NoPerm));
         }
 
-        sock.borrow_mut().listen(iface, crate::own_ip(), port)?;
+        // the socket's own buffers are used for the first connection in the backlog
+        let space = sock.borrow().conn_space() * backlog.saturating_sub(1);
+        if self.settings.bufs < space {
+            return Err(Error::new(Code::NoSpace));
+        }
+
+        sock.borrow_mut()
+            .listen(iface, IpAddress::Unspecified, port, backlog)?;
+        self.fill_backlog(&sock, iface);
 
         let addr = to_m3_addr(crate::own_ip());
-        reply_vmsg!(is, Code::None as i32, addr.0)
+        reply_vmsg!(is, Code::None as i32, addr)
     }
 
     pub fn connect(
@@ -430,7 +580,7 @@ impl SocketSession {
         iface: &mut DriverInterface<'_>,
     ) -> Result<(), Error> {
         let sd: Sd = is.pop()?;
-        let remote_addr = IpAddr(is.pop::<u32>()?);
+        let remote_addr: IpAddr = is.pop()?;
         let remote_port: Port = is.pop()?;
 
         let local_port = ports::alloc();
@@ -445,12 +595,18 @@ impl SocketSession {
         );
 
         let sock = self.get_socket(sd)?;
+        let remote = IpEndpoint::new(to_smol_addr(remote_addr), remote_port);
+        let sess = self.server_session.ident();
+        if !Socket::filter_conn(sess, &mut self.settings.filter, Direction::Out, remote) {
+            return Err(Error::new(Code::NoPerm));
+        }
+
         let port_no = *local_port;
         sock.borrow_mut()
             .connect(remote_addr, remote_port, local_port, iface)?;
 
-        let addr = to_m3_addr(crate::own_ip());
-        reply_vmsg!(is, Code::None as i32, addr.0, port_no)
+        let addr = to_m3_addr(crate::own_ip_for(to_smol_addr(remote_addr)));
+        reply_vmsg!(is, Code::None as i32, addr, port_no)
     }
 
     pub fn abort(
@@ -472,6 +628,117 @@ impl SocketSession {
         Ok(())
     }
 
+    pub fn set_option(
+        &mut self,
+        is: &mut GateIStream<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(), Error> {
+        let sd: Sd = is.pop()?;
+        let opt: SocketOpt = is.pop()?;
+        let value: u64 = is.pop()?;
+
+        log!(
+            crate::LOG_SESS,
+            "[{}] net::set_option(sd={}, opt={}, value={})",
+            self.server_session.ident(),
+            sd,
+            opt.val,
+            value
+        );
+
+        let sock = self.get_socket(sd)?;
+        match opt {
+            SocketOpt::RECV_BUF | SocketOpt::SEND_BUF => {
+                let (mut rbuf, mut sbuf) = {
+                    let s = sock.borrow();
+                    (
+                        s.get_option(SocketOpt::RECV_BUF)? as usize,
+                        s.get_option(SocketOpt::SEND_BUF)? as usize,
+                    )
+                };
+                if opt == SocketOpt::RECV_BUF {
+                    rbuf = value as usize;
+                }
+                else {
+                    sbuf = value as usize;
+                }
+
+                // the new buffers need to fit into our budget
+                let old_space = sock.borrow().buffer_space();
+                if rbuf + sbuf > old_space && self.settings.bufs < rbuf + sbuf - old_space {
+                    return Err(Error::new(Code::NoSpace));
+                }
+
+                sock.borrow_mut().resize_buffers(iface, rbuf, sbuf)?;
+                self.settings.bufs = self.settings.bufs + old_space - (rbuf + sbuf);
+            },
+            _ => sock.borrow_mut().set_option(iface, opt, value)?,
+        }
+
+        is.reply_error(Code::None)
+    }
+
+    pub fn get_option(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let sd: Sd = is.pop()?;
+        let opt: SocketOpt = is.pop()?;
+
+        let sock = self.get_socket(sd)?;
+        let value = sock.borrow().get_option(opt)?;
+
+        log!(
+            crate::LOG_SESS,
+            "[{}] net::get_option(sd={}, opt={}) -> {}",
+            self.server_session.ident(),
+            sd,
+            opt.val,
+            value
+        );
+
+        reply_vmsg!(is, Code::None as i32, value)
+    }
+
+    pub fn join_group(
+        &mut self,
+        is: &mut GateIStream<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(), Error> {
+        let sd: Sd = is.pop()?;
+        let addr: IpAddr = is.pop()?;
+
+        log!(
+            crate::LOG_SESS,
+            "[{}] net::join_group(sd={}, addr={})",
+            self.server_session.ident(),
+            sd,
+            addr
+        );
+
+        let sock = self.get_socket(sd)?;
+        sock.borrow_mut().join_group(iface, to_smol_addr(addr))?;
+        is.reply_error(Code::None)
+    }
+
+    pub fn leave_group(
+        &mut self,
+        is: &mut GateIStream<'_>,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<(), Error> {
+        let sd: Sd = is.pop()?;
+        let addr: IpAddr = is.pop()?;
+
+        log!(
+            crate::LOG_SESS,
+            "[{}] net::leave_group(sd={}, addr={})",
+            self.server_session.ident(),
+            sd,
+            addr
+        );
+
+        let sock = self.get_socket(sd)?;
+        sock.borrow_mut().leave_group(iface, to_smol_addr(addr))?;
+        is.reply_error(Code::None)
+    }
+
     fn do_abort(
         &mut self,
         sd: Sd,
@@ -489,31 +756,68 @@ impl SocketSession {
         let socket = self.get_socket(sd)?;
         socket.borrow_mut().abort(iface);
         if remove {
-            self.remove_socket(sd);
+            self.remove_socket(sd, iface);
+
+            // the freed buffer space might allow us to refill the backlog of listening sockets
+            for s in self.sockets.clone().iter().flatten() {
+                self.fill_backlog(s, iface);
+            }
         }
         Ok(())
     }
 
     pub fn process_incoming(&mut self, iface: &mut DriverInterface<'_>) -> bool {
         let sess = self.server_session.ident();
+        let filter = &mut self.settings.filter;
+        let budget = &mut self.tx;
         let mut needs_recheck = false;
+        let mut next = None;
+
+        budget.start_round();
 
-        // iterate over all sockets and check for events
-        'outer_loop: for idx in 0..self.sockets.len() {
+        // iterate over all sockets, starting where the last round stopped, and check for events
+        let count = self.sockets.len();
+        'outer_loop: for i in 0..count {
+            let idx = (self.tx_next + i) % count;
             if let Some(socket) = self.sockets.get(idx).unwrap() {
                 let mut sock = socket.borrow_mut();
                 let chan = sock.channel().clone();
+                let start_bytes = sock.stats().tx_bytes;
 
                 chan.fetch_replies();
 
-                if sock.process_queued_events(sess, iface) {
+                // the budget is charged with the data that has actually been sent
+                let sent = sock.stats().tx_bytes;
+                let queued = sock.process_queued_events(sess, iface);
+                budget.consume((sock.stats().tx_bytes - sent) as usize);
+                if queued {
                     needs_recheck = true;
                     continue 'outer_loop;
                 }
 
-                // receive everything in the channel
-                while let Some(event) = chan.receive_event() {
-                    if sock.process_event(sess, iface, event) {
+                // receive everything in the channel, as far as our budget permits
+                loop {
+                    if budget.exhausted() {
+                        // continue in the next round
+                        needs_recheck = true;
+                        next = Some(resume_at(idx, sock.stats().tx_bytes != start_bytes, count));
+                        break 'outer_loop;
+                    }
+                    if budget.throttled() {
+                        // continue as soon as the rate limit permits (see ready_at)
+                        next = Some(resume_at(idx, sock.stats().tx_bytes != start_bytes, count));
+                        break 'outer_loop;
+                    }
+
+                    let event = match chan.receive_event() {
+                        Some(ev) => ev,
+                        None => break,
+                    };
+
+                    let sent = sock.stats().tx_bytes;
+                    let queued = sock.process_event(sess, iface, filter, event);
+                    budget.consume((sock.stats().tx_bytes - sent) as usize);
+                    if queued {
                         needs_recheck = true;
                         continue 'outer_loop;
                     }
@@ -521,15 +825,32 @@ impl SocketSession {
             }
         }
 
+        if let Some(next) = next {
+            self.tx_next = next;
+        }
         needs_recheck
     }
 
     pub fn process_outgoing(&mut self, iface: &mut DriverInterface<'_>) -> bool {
+        let sess = self.server_session.ident();
+        let filter = &mut self.settings.filter;
+        let budget = &mut self.rx;
         let mut needs_recheck = false;
-        // iterate over all sockets and try to receive
-        for socket in self.sockets.iter().flatten() {
+        let mut next = None;
+
+        budget.start_round();
+
+        // iterate over all sockets, starting where the last round stopped, and try to receive
+        let count = self.sockets.len();
+        for i in 0..count {
+            let idx = (self.rx_next + i) % count;
+            let socket = match self.sockets.get(idx).unwrap() {
+                Some(s) => s,
+                None => continue,
+            };
             let socket_sd = socket.borrow().sd();
             let chan = socket.borrow().channel().clone();
+            let start_bytes = socket.borrow().stats().rx_bytes;
 
             loop {
                 chan.fetch_replies();
@@ -542,12 +863,12 @@ impl SocketSession {
                     break;
                 }
 
-                if let Some(event) = socket.borrow_mut().fetch_event(iface) {
+                if let Some(event) = socket.borrow_mut().fetch_event(sess, iface, filter) {
                     log!(
                         crate::LOG_DATA,
                         "[{}] socket {}: received event {:?}",
                         socket_sd,
-                        self.server_session.ident(),
+                        sess,
                         event,
                     );
 
@@ -558,7 +879,7 @@ impl SocketSession {
                             log_net(
                                 NetLogEvent::RecvConnected,
                                 socket_sd,
-                                e.remote_port as usize,
+                                e.endpoint().port as usize,
                             );
                             chan.send_event(e).unwrap()
                         },
@@ -578,44 +899,75 @@ impl SocketSession {
                     break;
                 }
 
-                let mut received = false;
-                socket.borrow_mut().receive(iface, |data, addr| {
-                    let ep = to_m3_ep(addr);
-                    let amount = cmp::min(MTU, data.len());
-
-                    log_net(NetLogEvent::FetchData, socket_sd, amount);
-                    log!(
-                        crate::LOG_DATA,
-                        "[{}] socket {}: received packet with {}b from {}",
-                        socket_sd,
-                        self.server_session.ident(),
-                        amount,
-                        ep
-                    );
+                // stop receiving data if our budget is used up, but still deliver the events of
+                // the other sockets
+                if budget.exhausted() || budget.throttled() {
+                    // continue in the next round (if exhausted) or as soon as the rate limit
+                    // permits (see ready_at)
+                    needs_recheck |= budget.exhausted();
+                    if next.is_none() {
+                        let served = socket.borrow().stats().rx_bytes != start_bytes;
+                        next = Some(resume_at(idx, served, count));
+                    }
+                    break;
+                }
 
-                    let msg = chan.build_data_message(ep, amount, |buf| {
-                        buf[0..amount].copy_from_slice(&data[0..amount]);
-                    });
+                let received_bytes = socket.borrow().stats().rx_bytes;
+                let received = socket
+                    .borrow_mut()
+                    .receive(sess, iface, filter, |data, addr| {
+                        let ep = to_m3_ep(addr);
+                        let amount = cmp::min(MTU, data.len());
 
-                    if let Err(e) = chan.send_data(&msg) {
+                        log_net(NetLogEvent::FetchData, socket_sd, amount);
                         log!(
-                            crate::LOG_ERR,
-                            "[{}] socket {}: sending received packet with {}b failed: {}",
+                            crate::LOG_DATA,
+                            "[{}] socket {}: received packet with {}b from {}",
                             socket_sd,
-                            self.server_session.ident(),
+                            sess,
                             amount,
-                            e
+                            ep
                         );
-                    }
-                    received = true;
-                    amount
-                });
+
+                        let msg = chan.build_data_message(ep, amount, |buf| {
+                            buf[0..amount].copy_from_slice(&data[0..amount]);
+                        });
+
+                        if let Err(e) = chan.send_data(&msg) {
+                            log!(
+                                crate::LOG_ERR,
+                                "[{}] socket {}: sending received packet with {}b failed: {}",
+                                socket_sd,
+                                sess,
+                                amount,
+                                e
+                            );
+                        }
+                        amount
+                    });
+                budget.consume((socket.borrow().stats().rx_bytes - received_bytes) as usize);
 
                 if !received {
                     break;
                 }
             }
         }
+
+        if let Some(next) = next {
+            self.rx_next = next;
+        }
         needs_recheck
     }
 }
+
+/// Determines the socket to start with in the next round after the budget ran out at socket
+/// `idx`: the same socket if it did not get anything in this round and the next one otherwise,
+/// so that a single busy socket cannot starve the other sockets of the session.
+fn resume_at(idx: usize, served: bool, count: usize) -> usize {
+    if served {
+        (idx + 1) % count
+    }
+    else {
+        idx
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use core::cmp;
+
+use m3::net::MTU;
+use m3::time::{TimeDuration, TimeInstant};
+
+/// The number of bytes each session can transfer per direction in one scheduling round
+///
+/// The sessions are scheduled according to deficit round robin: in every round, each session
+/// receives this quantum and can transfer data until it is used up. Since packets are not split,
+/// a session can overdraw its quantum, which is deducted from the quantum of the next round.
+pub const QUANTUM: usize = 16 * MTU;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A token bucket that limits the data rate to a given number of bytes per second
+pub struct TokenBucket {
+    rate: u64,
+    burst: i64,
+    // can become negative, because packets are not split
+    tokens: i64,
+    // the point in time up to which tokens have been added
+    last: TimeInstant,
+}
+
+impl TokenBucket {
+    /// Creates a new token bucket for `rate` bytes per second, which can store tokens for 100ms,
+    /// but at least for two packets
+    pub fn new(rate: u64) -> Self {
+        let burst = cmp::max(rate / 10, 2 * MTU as u64) as i64;
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: TimeInstant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = TimeInstant::now();
+        let elapsed = now.duration_since(self.last).as_nanos();
+        let new = elapsed * self.rate as u128 / NANOS_PER_SEC;
+        if self.tokens as i128 + new as i128 >= self.burst as i128 {
+            self.tokens = self.burst;
+            self.last = now;
+        }
+        else if new > 0 {
+            self.tokens += new as i64;
+            // only account the time for the added tokens to not lose the fractions
+            let nanos = new * NANOS_PER_SEC / self.rate as u128;
+            self.last += TimeDuration::from_nanos(nanos as u64);
+        }
+    }
+
+    /// Returns true if there are no tokens left
+    pub fn is_empty(&mut self) -> bool {
+        self.refill();
+        self.tokens <= 0
+    }
+
+    /// Removes the tokens for `bytes` from the bucket
+    pub fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as i64;
+    }
+
+    /// Returns the point in time at which tokens are available again
+    pub fn ready_at(&self) -> TimeInstant {
+        let missing = cmp::max(1 - self.tokens, 0) as u128;
+        self.last + TimeDuration::from_nanos((missing * NANOS_PER_SEC / self.rate as u128) as u64)
+    }
+}
+
+/// The transfer budget of a session for one direction
+pub struct Budget {
+    deficit: isize,
+    bucket: Option<TokenBucket>,
+}
+
+impl Budget {
+    /// Creates a new budget, limited to `rate` bytes per second, if given
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            deficit: 0,
+            bucket: rate.map(TokenBucket::new),
+        }
+    }
+
+    /// Starts a new scheduling round by adding the quantum
+    ///
+    /// A remaining quantum of the previous round is dropped, because the session apparently had
+    /// nothing more to transfer.
+    pub fn start_round(&mut self) {
+        self.deficit = cmp::min(self.deficit, 0) + QUANTUM as isize;
+    }
+
+    /// Returns true if the quantum for this round is used up
+    pub fn exhausted(&self) -> bool {
+        self.deficit <= 0
+    }
+
+    /// Returns true if the rate limit does not allow further transfers at the moment
+    pub fn throttled(&mut self) -> bool {
+        self.bucket.as_mut().map(|b| b.is_empty()).unwrap_or(false)
+    }
+
+    /// Accounts the transfer of `bytes`
+    pub fn consume(&mut self, bytes: usize) {
+        self.deficit -= bytes as isize;
+        if let Some(b) = self.bucket.as_mut() {
+            b.consume(bytes);
+        }
+    }
+
+    /// Returns the point in time at which the rate limit allows further transfers, if throttled
+    pub fn ready_at(&mut self) -> Option<TimeInstant> {
+        match self.throttled() {
+            true => self.bucket.as_ref().map(|b| b.ready_at()),
+            false => None,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use m3::log;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::{Dhcpv4Event, Dhcpv4Socket};
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr};
+
+use crate::driver::DriverInterface;
+
+/// The DHCP client that obtains our IPv4 address, default gateway and nameserver.
+///
+/// The lease is renewed by smoltcp as long as the interface is polled. Whenever the configuration
+/// changes, the interface and the addresses reported to clients are updated accordingly.
+pub struct DhcpClient {
+    socket: SocketHandle,
+}
+
+impl DhcpClient {
+    pub fn new(iface: &mut DriverInterface<'_>) -> Self {
+        Self {
+            socket: iface.add_socket(Dhcpv4Socket::new()),
+        }
+    }
+
+    /// Processes pending DHCP events; needs to be called after each poll of the interface
+    pub fn process(&mut self, iface: &mut DriverInterface<'_>) {
+        let event = iface.get_socket::<Dhcpv4Socket>(self.socket).poll();
+        match event {
+            None => {},
+
+            Some(Dhcpv4Event::Configured(config)) => {
+                log!(
+                    crate::LOG_DEF,
+                    "netrs: DHCP lease: ip={}, gateway={:?}, nameserver={:?}",
+                    config.address,
+                    config.router,
+                    config.dns_servers[0]
+                );
+
+                Self::set_ipv4_addr(iface, config.address);
+
+                if let Some(router) = config.router {
+                    iface.routes_mut().add_default_ipv4_route(router).unwrap();
+                }
+                else {
+                    iface.routes_mut().remove_default_ipv4_route();
+                }
+
+                match config.dns_servers.iter().flatten().next() {
+                    Some(ns) => crate::NAMESERVER.set(IpAddress::Ipv4(*ns)),
+                    None => crate::NAMESERVER.unset(),
+                };
+            },
+
+            Some(Dhcpv4Event::Deconfigured) => {
+                log!(crate::LOG_DEF, "netrs: DHCP lease lost");
+
+                Self::set_ipv4_addr(iface, Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
+                iface.routes_mut().remove_default_ipv4_route();
+                crate::NAMESERVER.unset();
+            },
+        }
+    }
+
+    fn set_ipv4_addr(iface: &mut DriverInterface<'_>, cidr: Ipv4Cidr) {
+        // the IPv4 address is always the first one
+        iface.update_ip_addrs(|addrs| addrs[0] = IpCidr::Ipv4(cidr));
+        crate::OWN_IP.set(IpAddress::Ipv4(cidr.address()));
+    }
+}
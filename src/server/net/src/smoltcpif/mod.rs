@@ -16,5 +16,7 @@
  * General Public License version 2 for more details.
  */
 
+pub mod dhcp;
 pub mod logger;
+pub mod slaac;
 pub mod socket;
@@ -0,0 +1,337 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+use m3::log;
+use m3::time::{TimeDuration, TimeInstant};
+use m3::vec;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::{RawPacketMetadata, RawSocket, RawSocketBuffer};
+use smoltcp::wire::{
+    Icmpv6Packet, IpAddress, IpCidr, IpProtocol, IpVersion, Ipv6Address, Ipv6Cidr, Ipv6Packet,
+    Ipv6Repr,
+};
+
+use crate::driver::DriverInterface;
+
+// ICMPv6 message types and NDISC options (RFC 4861 and RFC 8106)
+const ROUTER_SOLICIT: u8 = 133;
+const ROUTER_ADVERT: u8 = 134;
+const OPT_SOURCE_LLADDR: u8 = 1;
+const OPT_PREFIX_INFO: u8 = 3;
+const OPT_RDNSS: u8 = 25;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+// RFC 4861, section 10
+const MAX_RTR_SOLICITATIONS: usize = 3;
+const RTR_SOLICITATION_INTERVAL: TimeDuration = TimeDuration::from_secs(4);
+
+const ALL_ROUTERS: Ipv6Address =
+    Ipv6Address([0xFF, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+const INFINITE: u32 = 0xFFFF_FFFF;
+
+/// Something that has been learned from a router advertisement and expires at some point
+#[derive(Copy, Clone, Debug)]
+struct Learned<T> {
+    value: T,
+    expires: Option<TimeInstant>,
+}
+
+impl<T> Learned<T> {
+    fn new(value: T, lifetime: u32) -> Self {
+        let expires = match lifetime {
+            INFINITE => None,
+            secs => Some(TimeInstant::now() + TimeDuration::from_secs(secs as u64)),
+        };
+        Self { value, expires }
+    }
+
+    fn expired(&self, now: TimeInstant) -> bool {
+        matches!(self.expires, Some(e) if e <= now)
+    }
+}
+
+/// The stateless address autoconfiguration (SLAAC) for IPv6 according to RFC 4862.
+///
+/// The client solicits router advertisements and configures the global address from the
+/// advertised prefix and our EUI-64 interface identifier. Additionally, the advertising router is
+/// used as default gateway, unless a gateway has been configured statically, and the first
+/// advertised DNS server (RFC 8106) is used as nameserver, unless there is another one.
+/// Everything is removed again as soon as its lifetime expires.
+pub struct SlaacClient {
+    socket: SocketHandle,
+    link_local: Ipv6Address,
+    mac: [u8; 6],
+    set_gateway: bool,
+    solicitations: usize,
+    next_solicit: Option<TimeInstant>,
+    addr: Option<Learned<Ipv6Cidr>>,
+    router: Option<Learned<Ipv6Address>>,
+    nameserver: Option<Learned<Ipv6Address>>,
+}
+
+impl SlaacClient {
+    /// Creates a new client for the interface with given link-local address and MAC address.
+    ///
+    /// The global address is stored as the last address of the interface, which needs to be
+    /// reserved with an unspecified IPv6 address. If `set_gateway` is true, the default IPv6 route
+    /// is set to the advertising router.
+    pub fn new(
+        iface: &mut DriverInterface<'_>,
+        link_local: Ipv6Address,
+        mac: [u8; 6],
+        set_gateway: bool,
+    ) -> Self {
+        // we only need to receive router advertisements and to send solicitations
+        let socket = iface.add_socket(RawSocket::new(
+            IpVersion::Ipv6,
+            IpProtocol::Icmpv6,
+            RawSocketBuffer::new(vec![RawPacketMetadata::EMPTY; 4], vec![0u8; 4 * 1024]),
+            RawSocketBuffer::new(vec![RawPacketMetadata::EMPTY; 1], vec![0u8; 128]),
+        ));
+
+        Self {
+            socket,
+            link_local,
+            mac,
+            set_gateway,
+            solicitations: 0,
+            next_solicit: Some(TimeInstant::now()),
+            addr: None,
+            router: None,
+            nameserver: None,
+        }
+    }
+
+    /// Processes received router advertisements, sends router solicitations and removes expired
+    /// configurations; needs to be called after each poll of the interface
+    pub fn process(&mut self, iface: &mut DriverInterface<'_>) {
+        let now = TimeInstant::now();
+
+        loop {
+            let socket = iface.get_socket::<RawSocket<'_>>(self.socket);
+            let mut buf = [0u8; 1500];
+            let len = match socket.recv_slice(&mut buf) {
+                Ok(len) => len,
+                Err(_) => break,
+            };
+            self.handle_packet(iface, &buf[0..len]);
+        }
+
+        if matches!(self.addr, Some(a) if a.expired(now)) {
+            log!(crate::LOG_DEF, "netrs: SLAAC address expired");
+            self.set_addr(iface, None);
+        }
+        if matches!(self.router, Some(r) if r.expired(now)) {
+            log!(crate::LOG_DEF, "netrs: IPv6 router expired");
+            self.set_router(iface, None);
+        }
+        if matches!(self.nameserver, Some(n) if n.expired(now)) {
+            self.set_nameserver(None);
+        }
+
+        if let Some(next) = self.next_solicit {
+            if next <= now {
+                self.solicit(iface);
+            }
+        }
+    }
+
+    fn solicit(&mut self, iface: &mut DriverInterface<'_>) {
+        crate::remove_timeout(self.socket);
+        if self.solicitations == MAX_RTR_SOLICITATIONS {
+            self.next_solicit = None;
+            return;
+        }
+
+        // router solicitation with our link-layer address as the only option
+        let mut msg = [0u8; 16];
+        msg[0] = ROUTER_SOLICIT;
+        msg[8] = OPT_SOURCE_LLADDR;
+        msg[9] = 1;
+        msg[10..16].copy_from_slice(&self.mac);
+
+        let repr = Ipv6Repr {
+            src_addr: self.link_local,
+            dst_addr: ALL_ROUTERS,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: msg.len(),
+            hop_limit: 255,
+        };
+        let mut buf = [0u8; 40 + 16];
+        let mut packet = Ipv6Packet::new_unchecked(&mut buf[..]);
+        repr.emit(&mut packet);
+        packet.payload_mut().copy_from_slice(&msg);
+        let mut icmp = Icmpv6Packet::new_unchecked(packet.payload_mut());
+        icmp.fill_checksum(&self.link_local.into(), &ALL_ROUTERS.into());
+
+        let socket = iface.get_socket::<RawSocket<'_>>(self.socket);
+        if socket.send_slice(&buf).is_ok() {
+            self.solicitations += 1;
+        }
+
+        let next = TimeInstant::now() + RTR_SOLICITATION_INTERVAL;
+        self.next_solicit = Some(next);
+        crate::add_timeout(self.socket, next);
+    }
+
+    fn handle_packet(&mut self, iface: &mut DriverInterface<'_>, data: &[u8]) {
+        let packet = match Ipv6Packet::new_checked(data) {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        let src = packet.src_addr();
+        // router advertisements are only valid from link-local routers on our link (RFC 4861, 6.1.2)
+        if !src.is_link_local() || packet.hop_limit() != 255 {
+            return;
+        }
+
+        let icmp = match Icmpv6Packet::new_checked(packet.payload()) {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        if u8::from(icmp.msg_type()) != ROUTER_ADVERT
+            || icmp.msg_code() != 0
+            || !icmp.verify_checksum(&src.into(), &packet.dst_addr().into())
+        {
+            return;
+        }
+
+        let ra = packet.payload();
+        if ra.len() < 16 {
+            return;
+        }
+
+        // we have got an answer; stop soliciting
+        self.next_solicit = None;
+        crate::remove_timeout(self.socket);
+
+        let router_lifetime = u16::from_be_bytes([ra[6], ra[7]]);
+        log!(
+            crate::LOG_DEF,
+            "netrs: router advertisement from {} (lifetime {}s)",
+            src,
+            router_lifetime
+        );
+        match router_lifetime {
+            0 => self.set_router(iface, None),
+            secs => self.set_router(iface, Some(Learned::new(src, secs as u32))),
+        }
+
+        let mut opts = &ra[16..];
+        while opts.len() >= 8 {
+            let len = opts[1] as usize * 8;
+            if len == 0 || len > opts.len() {
+                break;
+            }
+            let opt = &opts[0..len];
+            match opt[0] {
+                OPT_PREFIX_INFO if len == 32 => self.handle_prefix(iface, opt),
+                OPT_RDNSS if len >= 24 => {
+                    let lifetime = u32::from_be_bytes([opt[4], opt[5], opt[6], opt[7]]);
+                    let ns = Ipv6Address::from_bytes(&opt[8..24]);
+                    match lifetime {
+                        0 => self.set_nameserver(None),
+                        secs => self.set_nameserver(Some(Learned::new(ns, secs))),
+                    }
+                },
+                _ => {},
+            }
+            opts = &opts[len..];
+        }
+    }
+
+    fn handle_prefix(&mut self, iface: &mut DriverInterface<'_>, opt: &[u8]) {
+        let prefix_len = opt[2];
+        let flags = opt[3];
+        let valid = u32::from_be_bytes([opt[4], opt[5], opt[6], opt[7]]);
+        let preferred = u32::from_be_bytes([opt[8], opt[9], opt[10], opt[11]]);
+        let prefix = Ipv6Address::from_bytes(&opt[16..32]);
+
+        // RFC 4862, section 5.5.3; our interface identifier has 64 bits
+        if (flags & PREFIX_FLAG_AUTONOMOUS) == 0
+            || prefix.is_link_local()
+            || preferred > valid
+            || prefix_len != 64
+        {
+            return;
+        }
+
+        let cidr = crate::autoconf_ipv6(Ipv6Cidr::new(Ipv6Address(prefix.mask(64)), 64), &self.mac);
+        match valid {
+            0 => {
+                if matches!(self.addr, Some(a) if a.value == cidr) {
+                    self.set_addr(iface, None);
+                }
+            },
+            secs => self.set_addr(iface, Some(Learned::new(cidr, secs))),
+        }
+    }
+
+    fn set_addr(&mut self, iface: &mut DriverInterface<'_>, addr: Option<Learned<Ipv6Cidr>>) {
+        let old = self.addr.map(|a| IpAddress::Ipv6(a.value.address()));
+        let new = addr.map(|a| a.value);
+        if old.is_none() {
+            if let Some(cidr) = new {
+                log!(crate::LOG_DEF, "netrs: SLAAC address {}", cidr);
+            }
+        }
+
+        let cidr = new.unwrap_or_else(|| Ipv6Cidr::new(Ipv6Address::UNSPECIFIED, 0));
+        iface.update_ip_addrs(|addrs| {
+            // the global address is always the last one (see main)
+            let last = addrs.len() - 1;
+            addrs[last] = IpCidr::Ipv6(cidr);
+        });
+
+        let mut ips = crate::OWN_IPS6.borrow_mut();
+        ips.retain(|a| Some(*a) != old);
+        if let Some(cidr) = new {
+            ips.insert(0, IpAddress::Ipv6(cidr.address()));
+        }
+        self.addr = addr;
+    }
+
+    fn set_router(
+        &mut self,
+        iface: &mut DriverInterface<'_>,
+        router: Option<Learned<Ipv6Address>>,
+    ) {
+        if self.set_gateway {
+            match router {
+                Some(r) => {
+                    iface.routes_mut().add_default_ipv6_route(r.value).unwrap();
+                },
+                None => {
+                    iface.routes_mut().remove_default_ipv6_route();
+                },
+            }
+        }
+        self.router = router;
+    }
+
+    fn set_nameserver(&mut self, ns: Option<Learned<Ipv6Address>>) {
+        let old = self.nameserver.map(|n| IpAddress::Ipv6(n.value));
+        // don't replace nameservers that have been configured otherwise
+        let ours = !crate::NAMESERVER.is_some() || Some(crate::NAMESERVER.get()) == old;
+        if ours {
+            match ns {
+                Some(n) => crate::NAMESERVER.set(IpAddress::Ipv6(n.value)),
+                None => crate::NAMESERVER.unset(),
+            }
+            self.nameserver = ns;
+        }
+    }
+}
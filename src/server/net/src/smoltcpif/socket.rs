@@ -16,12 +16,14 @@
 
 use m3::cap::Selector;
 use m3::cell::RefCell;
+use m3::col::{String, ToString, Vec, VecDeque};
 use m3::errors::{Code, Error};
 use m3::log;
 use m3::mem::size_of;
 use m3::net::{
     log_net, CloseReqMessage, ClosedMessage, ConnectedMessage, DataMessage, DataQueue, Endpoint,
-    IpAddr, NetEvent, NetEventChannel, NetEventType, Port, Sd, NetLogEvent, SocketArgs, SocketType,
+    IpAddr, Ipv6Addr, NetEvent, NetEventChannel, NetEventType, NetLogEvent, Port, Sd, SocketArgs,
+    SocketOpt, SocketType,
 };
 use m3::rc::Rc;
 use m3::time::{TimeDuration, TimeInstant};
@@ -32,27 +34,46 @@ use smoltcp::socket::{
     RawSocket, RawSocketBuffer, TcpSocket, TcpSocketBuffer, TcpState, UdpSocket, UdpSocketBuffer,
 };
 use smoltcp::storage::PacketMetadata;
+use smoltcp::time::Duration;
 use smoltcp::wire::IpVersion;
-use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv6Address};
 
 use crate::driver::DriverInterface;
+use crate::filter::{self, Direction, Filter, Packet};
 use crate::ports::{AnyPort, EphemeralPort};
 use crate::sess::FileSession;
 
 const CONNECT_TIMEOUT: TimeDuration = TimeDuration::from_secs(6);
 
+fn to_smol_duration(nanos: u64) -> Option<Duration> {
+    match nanos {
+        0 => None,
+        n => Some(Duration::from_micros(n / 1000)),
+    }
+}
+
+fn from_smol_duration(dur: Option<Duration>) -> u64 {
+    dur.map(|d| d.total_micros() * 1000).unwrap_or(0)
+}
+
+/// Converts an IpAddress from smoltcp into an M³ IpAddr.
 pub fn to_m3_addr(addr: IpAddress) -> IpAddr {
-    if addr.as_bytes().len() != 4 {
-        IpAddr::unspecified()
+    match addr {
+        IpAddress::Ipv4(a) => IpAddr::new(a.0[0], a.0[1], a.0[2], a.0[3]),
+        IpAddress::Ipv6(a) => IpAddr::V6(Ipv6Addr::from_octets(a.0)),
+        _ => IpAddr::unspecified(),
     }
-    else {
-        let bytes = addr.as_bytes();
-        IpAddr::new(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+/// Converts an M³ IpAddr into an IpAddress for smoltcp.
+pub fn to_smol_addr(addr: IpAddr) -> IpAddress {
+    match addr {
+        IpAddr::V4(a) => IpAddress::Ipv4(Ipv4Address::from_bytes(&a.octets())),
+        IpAddr::V6(a) => IpAddress::Ipv6(Ipv6Address::from_bytes(&a.octets())),
     }
 }
 
 /// Converts an IpEndpoint from smoltcp into an M³ (IpAddr, Port) tuple.
-/// Assumes that the IpEndpoint a is Ipv4 address, otherwise this will panic.
 pub fn to_m3_ep(addr: IpEndpoint) -> Endpoint {
     Endpoint::new(to_m3_addr(addr.addr), addr.port)
 }
@@ -68,9 +89,25 @@ pub enum SendNetEvent {
 pub enum State {
     Closed,
     Bound,
+    Listening,
     Connecting,
     Connected,
     RemoteClosed,
+    // a listening socket was closed, but the client has not been informed yet
+    Closing,
+}
+
+/// The packet and byte counters of a socket
+#[derive(Copy, Clone, Default)]
+pub struct SocketStats {
+    /// Packets and bytes passed to the client
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    /// Packets and bytes sent on behalf of the client
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    /// Packets dropped by the packet filter
+    pub drops: u64,
 }
 
 /// Socket abstraction that unifies the different socket types
@@ -82,6 +119,30 @@ pub struct Socket {
     connect_start: Option<TimeInstant>,
     _local_port: Option<EphemeralPort>,
     buffer_space: usize,
+    rbuf_size: usize,
+    sbuf_size: usize,
+
+    // TCP options set by the client; see SocketOpt
+    nagle: bool,
+    keep_alive: Option<Duration>,
+    timeout: Option<Duration>,
+    linger: Option<TimeDuration>,
+    // the time our side started to close the connection, if lingering is enabled
+    close_start: Option<TimeInstant>,
+
+    // UDP options: whether we may send to broadcast addresses and the joined multicast groups
+    broadcast: bool,
+    groups: Vec<Ipv4Address>,
+
+    // for listening sockets with a backlog: the endpoint we listen on, the desired backlog size,
+    // the smoltcp sockets that are still listening and the established connections that have not
+    // been accepted yet
+    listen_ep: Option<IpEndpoint>,
+    max_backlog: usize,
+    backlog: Vec<SocketHandle>,
+    ready: VecDeque<SocketHandle>,
+
+    stats: SocketStats,
 
     // communication channel to client for incoming data/close-requests and outgoing events/data
     channel: Rc<NetEventChannel>,
@@ -117,10 +178,7 @@ impl Socket {
         iface: &mut DriverInterface<'_>,
     ) -> Result<Self, Error> {
         let socket = match ty {
-            SocketType::Stream => iface.add_socket(TcpSocket::new(
-                TcpSocketBuffer::new(vec![0u8; args.rbuf_size]),
-                TcpSocketBuffer::new(vec![0u8; args.sbuf_size]),
-            )),
+            SocketType::Stream => Self::new_tcp_socket(args.rbuf_size, args.sbuf_size, iface),
             SocketType::Dgram => iface.add_socket(UdpSocket::new(
                 UdpSocketBuffer::new(vec![PacketMetadata::EMPTY; args.rbuf_slots], vec![
                     0u8;
@@ -154,6 +212,27 @@ impl Socket {
             connect_start: None,
             _local_port: None,
             buffer_space: Self::required_space(ty, args),
+            rbuf_size: args.rbuf_size,
+            sbuf_size: args.sbuf_size,
+
+            // disable Nagle's algorithm by default, because it delays sends, which at least for
+            // us reduces the achieved bandwidth in our benchmarks dramatically (factor 10).
+            // Maybe we don't transfer enough data?
+            nagle: false,
+            keep_alive: None,
+            timeout: None,
+            linger: None,
+            close_start: None,
+
+            broadcast: false,
+            groups: Vec::new(),
+
+            listen_ep: None,
+            max_backlog: 0,
+            backlog: Vec::new(),
+            ready: VecDeque::new(),
+
+            stats: SocketStats::default(),
 
             channel: NetEventChannel::new_server(caps)?,
             send_queue: DataQueue::default(),
@@ -163,6 +242,17 @@ impl Socket {
         })
     }
 
+    fn new_tcp_socket(
+        rbuf_size: usize,
+        sbuf_size: usize,
+        iface: &mut DriverInterface<'_>,
+    ) -> SocketHandle {
+        iface.add_socket(TcpSocket::new(
+            TcpSocketBuffer::new(vec![0u8; rbuf_size]),
+            TcpSocketBuffer::new(vec![0u8; sbuf_size]),
+        ))
+    }
+
     pub fn sd(&self) -> Sd {
         self.sd
     }
@@ -179,6 +269,19 @@ impl Socket {
         self.buffer_space
     }
 
+    /// Returns the buffer space that is required for one connection of this socket
+    pub fn conn_space(&self) -> usize {
+        self.rbuf_size + self.sbuf_size
+    }
+
+    /// Returns the number of connections that are missing in the backlog of this socket
+    pub fn missing_backlog(&self) -> usize {
+        match self.state {
+            State::Listening => self.max_backlog - self.backlog.len() - self.ready.len(),
+            _ => 0,
+        }
+    }
+
     pub fn recv_file(&self) -> Option<&Rc<RefCell<FileSession>>> {
         self.rfile.as_ref()
     }
@@ -195,15 +298,49 @@ impl Socket {
         self.sfile = file;
     }
 
-    pub fn fetch_event(&mut self, iface: &mut DriverInterface<'_>) -> Option<SendNetEvent> {
+    /// Returns whether the packet filter allows a connection with `remote` in given direction
+    ///
+    /// Streams are filtered once when the connection is established instead of per segment, so that
+    /// denied connections are refused rather than cut off.
+    pub fn filter_conn(sess: u64, filter: &mut Filter, dir: Direction, remote: IpEndpoint) -> bool {
+        let pkt = Packet {
+            dir,
+            proto: SocketType::Stream,
+            remote,
+            size: 0,
+        };
+        filter::allows(sess, filter, &pkt)
+    }
+
+    pub fn fetch_event(
+        &mut self,
+        sess: u64,
+        iface: &mut DriverInterface<'_>,
+        filter: &mut Filter,
+    ) -> Option<SendNetEvent> {
         match (self.ty, self.state) {
             (SocketType::Stream, State::Connecting) => {
                 let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
                 if tcp_socket.state() == TcpState::Established {
-                    // disable Nagle's algorithm, because it delays sends, which at least for us
-                    // reduces the achieved bandwidth in our benchmarks dramatically (factor 10).
-                    // Maybe we don't transfer enough data?
-                    tcp_socket.set_nagle_enabled(false);
+                    // our own connections have been filtered on connect, incoming ones are now
+                    if self.connect_start.is_none()
+                        && !Self::filter_conn(
+                            sess,
+                            filter,
+                            Direction::In,
+                            tcp_socket.remote_endpoint(),
+                        )
+                    {
+                        // refuse the connection and wait for the next one
+                        let port = tcp_socket.local_endpoint().port;
+                        tcp_socket.abort();
+                        tcp_socket
+                            .listen(IpEndpoint::new(IpAddress::Unspecified, port))
+                            .unwrap();
+                        return None;
+                    }
+
+                    self.apply_tcp_options(tcp_socket);
                     if self.connect_start.take().is_some() {
                         crate::remove_timeout(self.socket);
                     }
@@ -238,9 +375,48 @@ impl Socket {
                 }
             },
 
+            (SocketType::Stream, State::Listening) => loop {
+                let idx = self.backlog.iter().position(|h| {
+                    iface.get_socket::<TcpSocket<'_>>(*h).state() == TcpState::Established
+                })?;
+
+                let handle = self.backlog[idx];
+                let tcp_socket = iface.get_socket::<TcpSocket<'_>>(handle);
+                let remote = tcp_socket.remote_endpoint();
+                if !Self::filter_conn(sess, filter, Direction::In, remote) {
+                    // refuse the connection and keep listening with this socket
+                    tcp_socket.abort();
+                    tcp_socket.listen(self.listen_ep.unwrap()).unwrap();
+                    continue;
+                }
+
+                // move the connection to the ready queue until the client accepts it
+                self.apply_tcp_options(tcp_socket);
+                self.backlog.remove(idx);
+                self.ready.push_back(handle);
+                break Some(SendNetEvent::Connected(ConnectedMessage::new(to_m3_ep(
+                    remote,
+                ))));
+            },
+
+            (SocketType::Stream, State::Closing) => {
+                self.state = State::Closed;
+                Some(SendNetEvent::Closed(ClosedMessage::default()))
+            },
+
             (SocketType::Stream, State::Connected | State::RemoteClosed) => {
                 let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
+                // abort the connection if it could not be closed gracefully within the linger time
+                if let (Some(start), Some(linger)) = (self.close_start, self.linger) {
+                    if TimeInstant::now() >= start + linger {
+                        tcp_socket.abort();
+                    }
+                }
+
                 if !tcp_socket.is_open() {
+                    if self.close_start.take().is_some() {
+                        crate::remove_timeout(self.socket);
+                    }
                     self._local_port = None;
                     self.state = State::Closed;
                     self.send_queue.clear();
@@ -294,16 +470,25 @@ impl Socket {
         }
     }
 
+    /// Puts this socket into listen mode on given address and port.
+    ///
+    /// With a `backlog` of zero, this socket itself is used for the next connection. Otherwise,
+    /// `backlog` connections can be established on this port concurrently, which are handed out as
+    /// new sockets via [`accept`](Socket::accept). The socket's own buffers are used for the first
+    /// connection, whereas the buffers for the others have to be added via
+    /// [`add_backlog`](Socket::add_backlog).
     pub fn listen(
         &mut self,
         iface: &mut DriverInterface<'_>,
         addr: IpAddress,
         port: Port,
+        backlog: usize,
     ) -> Result<(), Error> {
         if self.ty != SocketType::Stream {
             return Err(Error::new(Code::InvArgs));
         }
-        if self.state != State::Closed {
+        // the socket's own smoltcp socket might have been handed out by accept already
+        if self.state != State::Closed || self.listen_ep.is_some() {
             return Err(Error::new(Code::InvState));
         }
 
@@ -312,7 +497,15 @@ impl Socket {
         match tcp_socket.listen(endpoint) {
             Ok(_) => {
                 self.connect_start = None;
-                self.state = State::Connecting;
+                if backlog > 0 {
+                    self.listen_ep = Some(endpoint);
+                    self.max_backlog = backlog;
+                    self.backlog.push(self.socket);
+                    self.state = State::Listening;
+                }
+                else {
+                    self.state = State::Connecting;
+                }
                 Ok(())
             },
             Err(e) => {
@@ -323,6 +516,267 @@ impl Socket {
         }
     }
 
+    /// Adds another listening smoltcp socket to the backlog of this socket.
+    ///
+    /// The caller is responsible to account for the required buffer space (see
+    /// [`conn_space`](Socket::conn_space)).
+    pub fn add_backlog(&mut self, iface: &mut DriverInterface<'_>) -> Result<(), Error> {
+        if self.missing_backlog() == 0 {
+            return Err(Error::new(Code::InvState));
+        }
+
+        let handle = Self::new_tcp_socket(self.rbuf_size, self.sbuf_size, iface);
+        let tcp_socket = iface.get_socket::<TcpSocket<'_>>(handle);
+        // cannot fail, because we have successfully listened on this endpoint before
+        tcp_socket.listen(self.listen_ep.unwrap()).unwrap();
+        self.backlog.push(handle);
+        self.buffer_space += self.conn_space();
+        Ok(())
+    }
+
+    /// Hands out the next established connection as a new socket with given socket descriptor.
+    ///
+    /// The buffer space of the connection is transferred from this socket to the new socket.
+    pub fn accept(
+        &mut self,
+        sd: Sd,
+        caps: Selector,
+        iface: &mut DriverInterface<'_>,
+    ) -> Result<Self, Error> {
+        if self.state != State::Listening {
+            return Err(Error::new(Code::InvState));
+        }
+        if self.ready.is_empty() {
+            return Err(Error::new(Code::WouldBlock));
+        }
+
+        let channel = NetEventChannel::new_server(caps)?;
+        let socket = self.ready.pop_front().unwrap();
+        let space = self.conn_space();
+        self.buffer_space -= space;
+
+        // the new socket owns the connection now and removes it from the interface when it is
+        // removed itself. Thus, use a socket without buffers as our own smoltcp socket from now on.
+        if socket == self.socket {
+            self.socket = Self::new_tcp_socket(0, 0, iface);
+        }
+
+        // the connection might have been closed in the meantime, which is noticed on the next
+        // fetch_event call of the new socket
+        Ok(Socket {
+            sd,
+            socket,
+            ty: self.ty,
+            state: State::Connected,
+            connect_start: None,
+            _local_port: None,
+            buffer_space: space,
+            rbuf_size: self.rbuf_size,
+            sbuf_size: self.sbuf_size,
+
+            nagle: self.nagle,
+            keep_alive: self.keep_alive,
+            timeout: self.timeout,
+            linger: self.linger,
+            close_start: None,
+
+            broadcast: false,
+            groups: Vec::new(),
+
+            listen_ep: None,
+            max_backlog: 0,
+            backlog: Vec::new(),
+            ready: VecDeque::new(),
+
+            stats: SocketStats::default(),
+
+            channel,
+            send_queue: DataQueue::default(),
+
+            rfile: None,
+            sfile: None,
+        })
+    }
+
+    /// Sets the given option to given value
+    ///
+    /// The buffer sizes are not handled here, because they require the session's buffer budget
+    /// (see [`resize_buffers`](Socket::resize_buffers)).
+    pub fn set_option(
+        &mut self,
+        iface: &mut DriverInterface<'_>,
+        opt: SocketOpt,
+        value: u64,
+    ) -> Result<(), Error> {
+        match (self.ty, opt) {
+            (SocketType::Dgram, SocketOpt::BROADCAST) => {
+                self.broadcast = value != 0;
+                return Ok(());
+            },
+            (SocketType::Stream, _) => {},
+            _ => return Err(Error::new(Code::NotSup)),
+        }
+
+        match opt {
+            SocketOpt::NO_DELAY => self.nagle = value == 0,
+            SocketOpt::KEEP_ALIVE => self.keep_alive = to_smol_duration(value),
+            SocketOpt::TIMEOUT => self.timeout = to_smol_duration(value),
+            SocketOpt::LINGER => {
+                self.linger = match value {
+                    0 => None,
+                    n => Some(TimeDuration::from_nanos(n)),
+                }
+            },
+            _ => return Err(Error::new(Code::InvArgs)),
+        }
+
+        // apply the new options to the smoltcp sockets that are currently in use. note that the
+        // own smoltcp socket of listening sockets might have been handed out by accept already.
+        if self.listen_ep.is_some() {
+            for handle in self.backlog.iter().chain(self.ready.iter()) {
+                let tcp_socket = iface.get_socket::<TcpSocket<'_>>(*handle);
+                self.apply_tcp_options(tcp_socket);
+            }
+        }
+        else {
+            let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
+            self.apply_tcp_options(tcp_socket);
+        }
+        Ok(())
+    }
+
+    /// Returns the current value of the given option
+    pub fn get_option(&self, opt: SocketOpt) -> Result<u64, Error> {
+        match (self.ty, opt) {
+            (_, SocketOpt::RECV_BUF) => Ok(self.rbuf_size as u64),
+            (_, SocketOpt::SEND_BUF) => Ok(self.sbuf_size as u64),
+            (SocketType::Stream, SocketOpt::NO_DELAY) => Ok(!self.nagle as u64),
+            (SocketType::Stream, SocketOpt::KEEP_ALIVE) => Ok(from_smol_duration(self.keep_alive)),
+            (SocketType::Stream, SocketOpt::TIMEOUT) => Ok(from_smol_duration(self.timeout)),
+            (SocketType::Stream, SocketOpt::LINGER) => {
+                Ok(self.linger.map(|l| l.as_nanos() as u64).unwrap_or(0))
+            },
+            (SocketType::Stream, _) => Err(Error::new(Code::InvArgs)),
+            (SocketType::Dgram, SocketOpt::BROADCAST) => Ok(self.broadcast as u64),
+            _ => Err(Error::new(Code::NotSup)),
+        }
+    }
+
+    /// Adds this socket to the given multicast group
+    pub fn join_group(
+        &mut self,
+        iface: &mut DriverInterface<'_>,
+        addr: IpAddress,
+    ) -> Result<(), Error> {
+        if self.ty != SocketType::Dgram {
+            return Err(Error::new(Code::NotSup));
+        }
+
+        match addr {
+            IpAddress::Ipv4(a) if a.is_multicast() => {
+                if self.groups.contains(&a) {
+                    return Err(Error::new(Code::Exists));
+                }
+                crate::join_group(iface, a)?;
+                self.groups.push(a);
+                Ok(())
+            },
+            // smoltcp does not support MLD yet
+            IpAddress::Ipv6(a) if a.is_multicast() => Err(Error::new(Code::NotSup)),
+            _ => Err(Error::new(Code::InvArgs)),
+        }
+    }
+
+    /// Removes this socket from the given multicast group
+    pub fn leave_group(
+        &mut self,
+        iface: &mut DriverInterface<'_>,
+        addr: IpAddress,
+    ) -> Result<(), Error> {
+        let idx = match addr {
+            IpAddress::Ipv4(a) => self.groups.iter().position(|g| *g == a),
+            _ => None,
+        }
+        .ok_or_else(|| Error::new(Code::InvArgs))?;
+
+        crate::leave_group(iface, self.groups.remove(idx));
+        Ok(())
+    }
+
+    /// Replaces the buffers of this socket by buffers of the given sizes
+    ///
+    /// This is only supported for TCP sockets that are closed and have never been put into listen
+    /// mode. The caller is responsible to account for the changed buffer space.
+    pub fn resize_buffers(
+        &mut self,
+        iface: &mut DriverInterface<'_>,
+        rbuf_size: usize,
+        sbuf_size: usize,
+    ) -> Result<(), Error> {
+        if self.ty != SocketType::Stream {
+            return Err(Error::new(Code::NotSup));
+        }
+        if self.state != State::Closed || self.listen_ep.is_some() {
+            return Err(Error::new(Code::InvState));
+        }
+
+        iface.remove_socket(self.socket);
+        self.socket = Self::new_tcp_socket(rbuf_size, sbuf_size, iface);
+        self.rbuf_size = rbuf_size;
+        self.sbuf_size = sbuf_size;
+        self.buffer_space = rbuf_size + sbuf_size;
+        Ok(())
+    }
+
+    fn apply_tcp_options(&self, tcp_socket: &mut TcpSocket<'_>) {
+        tcp_socket.set_nagle_enabled(self.nagle);
+        tcp_socket.set_keep_alive(self.keep_alive);
+        tcp_socket.set_timeout(self.timeout);
+    }
+
+    /// Returns the local and the remote endpoint of this stream socket
+    pub fn endpoints(&self, iface: &mut DriverInterface<'_>) -> Option<(Endpoint, Endpoint)> {
+        match self.ty {
+            SocketType::Stream => {
+                let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
+                Some((
+                    to_m3_ep(tcp_socket.local_endpoint()),
+                    to_m3_ep(tcp_socket.remote_endpoint()),
+                ))
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the local and the remote endpoint of this socket, which are unspecified if unknown
+    pub fn addresses(&self, iface: &mut DriverInterface<'_>) -> (Endpoint, Endpoint) {
+        let unspec = Endpoint::unspecified();
+        match self.ty {
+            SocketType::Dgram => {
+                let udp_socket = iface.get_socket::<UdpSocket<'_>>(self.socket);
+                (to_m3_ep(udp_socket.endpoint()), unspec)
+            },
+            _ => self.endpoints(iface).unwrap_or((unspec, unspec)),
+        }
+    }
+
+    /// Returns a human-readable name of the socket's current state
+    pub fn state_name(&self, iface: &mut DriverInterface<'_>) -> String {
+        match (self.ty, self.state) {
+            (SocketType::Stream, State::Closing) => "Closing".to_string(),
+            (SocketType::Stream, _) => {
+                let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
+                tcp_socket.state().to_string()
+            },
+            (_, State::Bound) => "Bound".to_string(),
+            _ => "Closed".to_string(),
+        }
+    }
+
+    pub fn stats(&self) -> &SocketStats {
+        &self.stats
+    }
+
     pub fn connect(
         &mut self,
         remote_addr: IpAddr,
@@ -337,10 +791,7 @@ impl Socket {
             return Err(Error::new(Code::InvState));
         }
 
-        let remote_endpoint = IpEndpoint::new(
-            IpAddress::Ipv4(Ipv4Address::from_bytes(&remote_addr.0.to_be_bytes())),
-            remote_port,
-        );
+        let remote_endpoint = IpEndpoint::new(to_smol_addr(remote_addr), remote_port);
         let local_endpoint = IpEndpoint::from(*local_port);
 
         let (tcp_socket, cx) = iface.get_socket_and_context::<TcpSocket<'_>>(self.socket);
@@ -365,26 +816,82 @@ impl Socket {
             return Err(Error::new(Code::InvArgs));
         }
 
+        if self.state == State::Listening {
+            // there is nothing to close gracefully; just drop all pending connections
+            self.abort_backlog(iface);
+            self.state = State::Closing;
+            return Ok(());
+        }
+
         let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
         tcp_socket.close();
+
+        if let Some(linger) = self.linger {
+            if self.close_start.is_none() {
+                let now = TimeInstant::now();
+                self.close_start = Some(now);
+                crate::add_timeout(self.socket, now + linger);
+            }
+        }
         Ok(())
     }
 
+    fn abort_backlog(&mut self, iface: &mut DriverInterface<'_>) {
+        let own = self.socket;
+        for handle in self.backlog.drain(..).chain(self.ready.drain(..)) {
+            iface.get_socket::<TcpSocket<'_>>(handle).abort();
+            // our own smoltcp socket is removed together with this socket (see remove)
+            if handle != own {
+                iface.remove_socket(handle);
+            }
+        }
+    }
+
     pub fn abort(&mut self, iface: &mut DriverInterface<'_>) {
-        if self.ty == SocketType::Stream {
+        if self.listen_ep.is_some() {
+            self.abort_backlog(iface);
+        }
+        else if self.ty == SocketType::Stream {
             let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
             tcp_socket.abort();
+            if self.close_start.take().is_some() {
+                crate::remove_timeout(self.socket);
+            }
+        }
+
+        for group in self.groups.drain(..) {
+            crate::leave_group(iface, group);
         }
 
         self._local_port = None;
         self.state = State::Closed;
     }
 
-    pub fn receive<F>(&mut self, iface: &mut DriverInterface<'_>, func: F)
+    /// Removes the smoltcp socket of this socket from the interface
+    ///
+    /// The socket needs to be aborted before (see [`abort`](Socket::abort)) and cannot be used
+    /// afterwards.
+    pub fn remove(&mut self, iface: &mut DriverInterface<'_>) {
+        crate::remove_timeout(self.socket);
+        iface.remove_socket(self.socket);
+    }
+
+    /// Passes the next received data to `func`, unless it is denied by the packet filter
+    ///
+    /// Returns true if data has been received, independent of whether it was passed to `func`.
+    pub fn receive<F>(
+        &mut self,
+        sess: u64,
+        iface: &mut DriverInterface<'_>,
+        filter: &mut Filter,
+        func: F,
+    ) -> bool
     where
         F: FnOnce(&[u8], IpEndpoint) -> usize,
     {
-        match self.ty {
+        let ty = self.ty;
+        let stats = &mut self.stats;
+        match ty {
             SocketType::Stream => {
                 let tcp_socket = iface.get_socket::<TcpSocket<'_>>(self.socket);
                 if self.state == State::Connected || self.state == State::RemoteClosed {
@@ -393,27 +900,39 @@ impl Socket {
                     tcp_socket
                         .recv(|d| {
                             if !d.is_empty() {
-                                (func(d, addr), ())
+                                (Self::deliver(sess, ty, stats, filter, d, addr, func), true)
                             }
                             else {
-                                (0, ())
+                                (0, false)
                             }
                         })
-                        .ok();
+                        .unwrap_or(false)
+                }
+                else {
+                    false
                 }
             },
 
             SocketType::Dgram => {
                 let udp_socket = iface.get_socket::<UdpSocket<'_>>(self.socket);
-                if let Ok((data, remote_endpoint)) = udp_socket.recv() {
-                    func(data, remote_endpoint);
+                match udp_socket.recv() {
+                    Ok((data, remote_endpoint)) => {
+                        Self::deliver(sess, ty, stats, filter, data, remote_endpoint, func);
+                        true
+                    },
+                    Err(_) => false,
                 }
             },
 
             SocketType::Raw => {
                 let raw_socket = iface.get_socket::<RawSocket<'_>>(self.socket);
-                if let Ok(data) = raw_socket.recv() {
-                    func(data, IpEndpoint::UNSPECIFIED);
+                match raw_socket.recv() {
+                    Ok(data) => {
+                        let ep = IpEndpoint::UNSPECIFIED;
+                        Self::deliver(sess, ty, stats, filter, data, ep, func);
+                        true
+                    },
+                    Err(_) => false,
                 }
             },
 
@@ -421,9 +940,54 @@ impl Socket {
         }
     }
 
+    fn deliver<F>(
+        sess: u64,
+        ty: SocketType,
+        stats: &mut SocketStats,
+        filter: &mut Filter,
+        data: &[u8],
+        remote: IpEndpoint,
+        func: F,
+    ) -> usize
+    where
+        F: FnOnce(&[u8], IpEndpoint) -> usize,
+    {
+        // streams are filtered when the connection is established (see filter_conn)
+        let pkt = Packet {
+            dir: Direction::In,
+            proto: ty,
+            remote,
+            size: data.len(),
+        };
+        if ty != SocketType::Stream && !filter::allows(sess, filter, &pkt) {
+            // consume the data without passing it to the client
+            stats.drops += 1;
+            return data.len();
+        }
+
+        let amount = func(data, remote);
+        stats.rx_packets += 1;
+        stats.rx_bytes += amount as u64;
+        amount
+    }
+
+    fn is_broadcast(iface: &DriverInterface<'_>, addr: IpAddress) -> bool {
+        match addr {
+            IpAddress::Ipv4(a) => {
+                a.is_broadcast()
+                    || iface.ip_addrs().iter().any(|cidr| match cidr {
+                        IpCidr::Ipv4(c) => c.broadcast() == Some(a),
+                        _ => false,
+                    })
+            },
+            _ => false,
+        }
+    }
+
     fn send(
         ty: SocketType,
         socket: SocketHandle,
+        broadcast: bool,
         data: &[u8],
         dest_addr: IpAddr,
         dest_port: Port,
@@ -441,12 +1005,20 @@ impl Socket {
             },
 
             SocketType::Dgram => {
+                let dest_addr = to_smol_addr(dest_addr);
+                if !broadcast && Self::is_broadcast(iface, dest_addr) {
+                    // pretend that we sent it to not block the client
+                    log!(
+                        crate::LOG_ERR,
+                        "Dropping packet to broadcast address {} without permission",
+                        dest_addr
+                    );
+                    return data.len();
+                }
+
                 let udp_socket = iface.get_socket::<UdpSocket<'_>>(socket);
                 if udp_socket.can_send() {
-                    let rend = IpEndpoint::new(
-                        IpAddress::Ipv4(Ipv4Address::from_bytes(&dest_addr.0.to_be_bytes())),
-                        dest_port,
-                    );
+                    let rend = IpEndpoint::new(dest_addr, dest_port);
 
                     udp_socket.send_slice(data, rend).unwrap();
                     data.len()
@@ -475,12 +1047,16 @@ impl Socket {
         let socket = self.socket;
         let ty = self.ty;
         let sd = self.sd;
+        let broadcast = self.broadcast;
+        let stats = &mut self.stats;
         #[allow(clippy::blocks_in_if_conditions)]
         while self
             .send_queue
             .next_data(usize::MAX, &mut |data, ep: Endpoint| {
-                let amount = Self::send(ty, socket, data, ep.addr, ep.port, iface);
+                let amount = Self::send(ty, socket, broadcast, data, ep.addr, ep.port, iface);
                 if amount > 0 {
+                    stats.tx_packets += 1;
+                    stats.tx_bytes += amount as u64;
                     log_net(NetLogEvent::SubmitData, sd, amount);
                     log!(
                         crate::LOG_DATA,
@@ -502,32 +1078,48 @@ impl Socket {
         &mut self,
         sess: u64,
         iface: &mut DriverInterface<'_>,
-        event: NetEvent,
+        filter: &mut Filter,
+        mut event: NetEvent,
     ) -> bool {
         match event.msg_type() {
             NetEventType::DATA => {
                 let data = event.msg::<DataMessage>();
-                let ip = IpAddr(data.addr as u32);
-                let port = data.port as Port;
+                let ep = data.endpoint();
+
+                // streams are filtered when the connection is established (see filter_conn)
+                let pkt = Packet {
+                    dir: Direction::Out,
+                    proto: self.ty,
+                    remote: IpEndpoint::new(to_smol_addr(ep.addr), ep.port),
+                    size: data.size as usize,
+                };
+                if self.ty != SocketType::Stream && !filter::allows(sess, filter, &pkt) {
+                    // let the client know that its packet has not been sent
+                    self.stats.drops += 1;
+                    event.set_error(Code::NoPerm);
+                    return false;
+                }
 
                 let res = Self::send(
                     self.ty,
                     self.socket,
+                    self.broadcast,
                     &data.data[0..data.size as usize],
-                    ip,
-                    port,
+                    ep.addr,
+                    ep.port,
                     iface,
                 );
                 if res > 0 {
+                    self.stats.tx_packets += 1;
+                    self.stats.tx_bytes += res as u64;
                     log_net(NetLogEvent::SubmitData, self.sd, res);
                     log!(
                         crate::LOG_DATA,
-                        "[{}] socket {}: sent packet of {}b to {}:{}",
+                        "[{}] socket {}: sent packet of {}b to {}",
                         sess,
                         self.sd,
                         res,
-                        ip,
-                        port,
+                        ep,
                     );
                 }
 
@@ -535,12 +1127,11 @@ impl Socket {
                     // if insufficient buffer space is available, remember the event for later
                     log!(
                         crate::LOG_DATA,
-                        "[{}] socket {}: no buffer space, delaying send of {}b to {}:{}",
+                        "[{}] socket {}: no buffer space, delaying send of {}b to {}",
                         sess,
                         self.sd,
                         data.size as usize - res,
-                        ip,
-                        port,
+                        ep,
                     );
                     self.send_queue.append(event, res);
                     return true;
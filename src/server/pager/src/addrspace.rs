@@ -13,7 +13,9 @@
  * General Public License version 2 for more details.
  */
 
+use core::cmp;
 use m3::cap::Selector;
+use m3::cell::RefCell;
 use m3::cfg;
 use m3::col::Vec;
 use m3::com::{GateIStream, RecvGate, SGateArgs, SendGate};
@@ -22,6 +24,7 @@ use m3::goff;
 use m3::kif::{PageFlags, Perm};
 use m3::log;
 use m3::math;
+use m3::rc::Rc;
 use m3::reply_vmsg;
 use m3::serialize::M3Deserializer;
 use m3::server::SessId;
@@ -31,6 +34,8 @@ use m3::tiles::Activity;
 use resmng::childs;
 
 use crate::dataspace::DataSpace;
+use crate::physmem::PhysMem;
+use crate::shm;
 
 const MAX_VIRT_ADDR: goff = cfg::MEM_CAP_END as goff - 1;
 
@@ -156,10 +161,12 @@ impl AddrSpace {
         is.reply_error(Code::None)
     }
 
-    pub fn pagefault(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
-        let virt: goff = is.pop()?;
-        let access = PageFlags::from_bits_truncate(is.pop()?) & !PageFlags::U;
-        let access = Perm::from_bits_truncate(access.bits() as u32);
+    pub fn dataspace_mut(&mut self, idx: usize) -> Option<&mut DataSpace> {
+        self.ds.get_mut(idx)
+    }
+
+    pub fn pagefault(&mut self, virt: goff, access: PageFlags) -> Result<(), Error> {
+        let access = Perm::from_bits_truncate((access & !PageFlags::U).bits() as u32);
 
         log!(
             crate::LOG_DEF,
@@ -174,20 +181,19 @@ impl AddrSpace {
             return Err(Error::new(Code::InvArgs));
         }
 
-        self.pagefault_at(virt, access)?;
-
-        is.reply_error(Code::None)
+        self.pagefault_at(virt, access)
     }
 
     pub(crate) fn pagefault_at(&mut self, virt: goff, access: Perm) -> Result<(), Error> {
         if let Some(ds) = self.find_ds_mut(virt) {
-            if (ds.perm() & access) != access {
+            let perm = ds.perm_at(virt);
+            if (perm & access) != access {
                 log!(
                     crate::LOG_DEF,
                     "Access at {:#x} for {:#x} not allowed: {:#x}",
                     virt,
                     access,
-                    ds.perm()
+                    perm
                 );
                 return Err(Error::new(Code::InvArgs));
             }
@@ -237,6 +243,10 @@ impl AddrSpace {
         );
 
         self.check_map_args(virt, len, perm)?;
+        // we don't know the alignment of the file's memory
+        if flags.contains(MapFlags::LARGE) {
+            return Err(Error::new(Code::NotSup));
+        }
 
         let ds = DataSpace::new_extern(
             self.owner.unwrap(),
@@ -286,6 +296,13 @@ impl AddrSpace {
         );
 
         self.check_map_args(virt, len, perm)?;
+        if flags.contains(MapFlags::LARGE)
+            && (flags.contains(MapFlags::NOLPAGE)
+                || !math::is_aligned(virt, cfg::LPAGE_SIZE as goff)
+                || !math::is_aligned(len, cfg::LPAGE_SIZE as goff))
+        {
+            return Err(Error::new(Code::InvArgs));
+        }
 
         let ds = DataSpace::new_anon(
             self.owner.unwrap(),
@@ -338,6 +355,62 @@ impl AddrSpace {
         Ok((sel, virt))
     }
 
+    pub fn map_shm(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        if !self.has_owner() {
+            return Err(Error::new(Code::InvArgs));
+        }
+
+        let virt: goff = is.pop()?;
+        let len: goff = is.pop()?;
+        let perm = Perm::from_bits_truncate(is.pop::<u32>()?);
+        let create: bool = is.pop()?;
+        let name: &str = is.pop()?;
+
+        log!(
+            crate::LOG_DEF,
+            "[{}] pager::map_shm(name={}, virt={:#x}, len={:#x}, perm={:?}, create={})",
+            self.id(),
+            name,
+            virt,
+            len,
+            perm,
+            create,
+        );
+
+        self.check_map_args(virt, len, perm)?;
+
+        let mem = match create {
+            true => shm::create(name, len, self.child.unwrap(), perm)?,
+            false => shm::open(name, len, perm)?,
+        };
+        let ds = DataSpace::new_shm(
+            self.owner.unwrap(),
+            self.child.unwrap(),
+            virt,
+            len,
+            perm,
+            mem,
+        );
+        self.ds.push(ds);
+
+        reply_vmsg!(is, Code::None as u32, virt)
+    }
+
+    pub fn unlink_shm(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let name: &str = is.pop()?;
+
+        log!(
+            crate::LOG_DEF,
+            "[{}] pager::unlink_shm(name={})",
+            self.id(),
+            name,
+        );
+
+        shm::unlink(name)?;
+
+        is.reply_error(Code::None)
+    }
+
     pub fn unmap(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
         let virt: goff = is.pop()?;
 
@@ -350,6 +423,8 @@ impl AddrSpace {
 
         if let Some(idx) = self.find_ds_idx(virt) {
             self.ds.remove(idx);
+            // the dataspace might have been the last mapping of unlinked shared memory
+            shm::collect();
         }
         else {
             log!(crate::LOG_DEF, "No dataspace at {:#x}", virt);
@@ -359,6 +434,64 @@ impl AddrSpace {
         is.reply_error(Code::None)
     }
 
+    /// Removes all dataspaces that map the shared memory `mem`
+    pub fn unmap_shm(&mut self, mem: &Rc<RefCell<PhysMem>>) {
+        self.ds.retain(|ds| !ds.maps_shm(mem));
+    }
+
+    pub fn protect(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let virt: goff = is.pop()?;
+        let len: goff = is.pop()?;
+        let perm = Perm::from_bits_truncate(is.pop::<u32>()?);
+
+        log!(
+            crate::LOG_DEF,
+            "[{}] pager::protect(virt={:#x}, len={:#x}, perm={:?})",
+            self.id(),
+            virt,
+            len,
+            perm,
+        );
+
+        self.for_each_ds_in(virt, len, |ds, off, size| ds.protect(off, size, perm))?;
+
+        is.reply_error(Code::None)
+    }
+
+    pub fn dont_need(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let virt: goff = is.pop()?;
+        let len: goff = is.pop()?;
+
+        log!(
+            crate::LOG_DEF,
+            "[{}] pager::dont_need(virt={:#x}, len={:#x})",
+            self.id(),
+            virt,
+            len,
+        );
+
+        self.for_each_ds_in(virt, len, |ds, off, size| ds.dont_need(off, size))?;
+
+        is.reply_error(Code::None)
+    }
+
+    pub fn will_need(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
+        let virt: goff = is.pop()?;
+        let len: goff = is.pop()?;
+
+        log!(
+            crate::LOG_DEF,
+            "[{}] pager::will_need(virt={:#x}, len={:#x})",
+            self.id(),
+            virt,
+            len,
+        );
+
+        self.for_each_ds_in(virt, len, |ds, off, size| ds.will_need(off, size))?;
+
+        is.reply_error(Code::None)
+    }
+
     pub fn close(&mut self, is: &mut GateIStream<'_>) -> Result<(), Error> {
         log!(crate::LOG_DEF, "[{}] pager::close()", self.id());
 
@@ -382,6 +515,38 @@ impl AddrSpace {
         Ok(())
     }
 
+    // calls `func` for all dataspaces that overlap with `virt`..`virt`+`len` with the offset and size
+    // of the overlapping part within the dataspace
+    fn for_each_ds_in<F>(&mut self, virt: goff, len: goff, mut func: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut DataSpace, goff, goff) -> Result<(), Error>,
+    {
+        if !self.has_owner() {
+            return Err(Error::new(Code::InvArgs));
+        }
+        if len == 0
+            || !math::is_aligned(virt, cfg::PAGE_SIZE as goff)
+            || !math::is_aligned(len, cfg::PAGE_SIZE as goff)
+        {
+            return Err(Error::new(Code::InvArgs));
+        }
+
+        let mut found = false;
+        for ds in &mut self.ds {
+            if math::overlaps(ds.virt(), ds.virt() + ds.size(), virt, virt + len) {
+                let start = cmp::max(ds.virt(), virt);
+                let end = cmp::min(ds.virt() + ds.size(), virt + len);
+                func(ds, start - ds.virt(), end - start)?;
+                found = true;
+            }
+        }
+
+        match found {
+            true => Ok(()),
+            false => Err(Error::new(Code::NotFound)),
+        }
+    }
+
     fn find_ds_mut(&mut self, virt: goff) -> Option<&mut DataSpace> {
         self.find_ds_idx(virt).map(move |idx| &mut self.ds[idx])
     }
@@ -17,7 +17,7 @@ use m3::cap::Selector;
 use m3::cell::{RefCell, StaticCell};
 use m3::cfg;
 use m3::com::MemGate;
-use m3::errors::Error;
+use m3::errors::{Code, Error};
 use m3::goff;
 use m3::kif;
 use m3::log;
@@ -27,7 +27,7 @@ use m3::session::{ClientSession, MapFlags, M3FS};
 use resmng::childs;
 
 use crate::physmem::PhysMem;
-use crate::regions::RegionList;
+use crate::regions::{Protection, Region, RegionList};
 
 const MAX_ANON_PAGES: usize = 4;
 const MAX_EXT_PAGES: usize = 8;
@@ -68,11 +68,13 @@ pub struct DataSpace {
     child: childs::Id,
     virt: goff,
     size: goff,
-    perms: kif::Perm,
+    prot: Protection,
     flags: MapFlags,
     regions: RegionList,
     owner: Selector,
     file: Option<FileMapping>,
+    // the memory is shared with other address spaces (see shm)
+    shared: bool,
 }
 
 impl DataSpace {
@@ -92,11 +94,12 @@ impl DataSpace {
             child,
             virt,
             size,
-            perms,
+            prot: Protection::new(perms),
             flags,
             owner,
             regions: RegionList::new(owner, child, virt, size),
             file: Some(FileMapping::new(sel, off)),
+            shared: false,
         }
     }
 
@@ -113,25 +116,41 @@ impl DataSpace {
             child,
             virt,
             size,
-            perms,
+            prot: Protection::new(perms),
             flags,
             owner,
             regions: RegionList::new(owner, child, virt, size),
             file: None,
+            shared: false,
         }
     }
 
+    pub fn new_shm(
+        owner: Selector,
+        child: childs::Id,
+        virt: goff,
+        size: goff,
+        perms: kif::Perm,
+        mem: Rc<RefCell<PhysMem>>,
+    ) -> Self {
+        let mut ds = Self::new_anon(owner, child, virt, size, perms, MapFlags::SHARED);
+        ds.shared = true;
+        ds.regions.populate_with(mem);
+        ds
+    }
+
     pub fn clone_for(&self, owner: Selector) -> Self {
         DataSpace {
             id: self.id,
             child: self.child,
             virt: self.virt,
             size: self.size,
-            perms: self.perms,
+            prot: self.prot.clone(),
             flags: self.flags,
             owner,
             regions: RegionList::new(owner, self.child, self.virt, self.size),
             file: self.file.clone(),
+            shared: self.shared,
         }
     }
 
@@ -147,20 +166,75 @@ impl DataSpace {
         self.size
     }
 
-    pub fn perm(&self) -> kif::Perm {
-        self.perms
+    pub fn perm_at(&self, virt: goff) -> kif::Perm {
+        self.prot.perm_at(virt - self.virt)
     }
 
     pub fn inherit(&mut self, ds: &mut DataSpace) -> Result<(), Error> {
         self.id = ds.id;
 
+        // shared memory stays shared instead of using copy-on-write
+        if ds.shared {
+            self.prot = ds.prot.clone();
+            self.regions.share(&ds.regions);
+            return Ok(());
+        }
+
         // if it's not writable, but we have already regions, we can simply keep them
-        if !ds.perms.contains(kif::Perm::W) && !self.regions.is_empty() {
+        let writable = ds.prot.max_perm(0, ds.size).contains(kif::Perm::W);
+        if !writable && self.prot == ds.prot && !self.regions.is_empty() {
             return Ok(());
         }
 
-        let ds_perm = ds.perm();
-        self.regions.clone(&mut ds.regions, ds_perm)
+        self.prot = ds.prot.clone();
+        self.regions.clone(&mut ds.regions, &ds.prot)
+    }
+
+    /// Changes the permissions of `off`..`off`+`size` to `perm`
+    pub fn protect(&mut self, off: goff, size: goff, perm: kif::Perm) -> Result<(), Error> {
+        // we can't grant more permissions than we got for the file or shared memory
+        if (self.file.is_some() || self.shared) && !self.prot.perm().contains(perm) {
+            return Err(Error::new(Code::NoPerm));
+        }
+
+        self.prot.set(off, size, perm);
+        // the regions are mapped with the new permissions on the next page fault
+        self.regions.unmap_range(off, size);
+        Ok(())
+    }
+
+    /// Drops the memory of `off`..`off`+`size`
+    ///
+    /// The next access to anonymous memory yields zeros and the next access to a file mapping
+    /// yields the file's content again.
+    pub fn dont_need(&mut self, off: goff, size: goff) -> Result<(), Error> {
+        let anon = self.file.is_none();
+        self.regions.drop_range(off, size, anon, &self.prot)
+    }
+
+    /// Obtains and maps the memory of all accessible pages in `off`..`off`+`size`
+    pub fn will_need(&mut self, off: goff, size: goff) -> Result<(), Error> {
+        for pos in (off..off + size).step_by(cfg::PAGE_SIZE) {
+            if self.prot.perm_at(pos).is_empty() {
+                continue;
+            }
+
+            match self.handle_pf(self.virt + pos) {
+                // it's just a hint, so that we don't swap out memory for that
+                Err(e) if e.code() == Code::NoSpace => break,
+                res => res?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if this dataspace maps the shared memory `mem`
+    pub fn maps_shm(&self, mem: &Rc<RefCell<PhysMem>>) -> bool {
+        self.shared && self.regions.uses_mem(mem)
+    }
+
+    pub fn region_mut(&mut self, idx: usize) -> Option<&mut Region> {
+        self.regions.get_mut(idx)
     }
 
     pub fn populate(&mut self, sel: Selector) {
@@ -171,8 +245,12 @@ impl DataSpace {
         let pf_off = math::round_dn(virt - self.virt, cfg::PAGE_SIZE as goff);
         let reg = self.regions.pagefault(pf_off);
 
+        // if it has been swapped out, read it back from the swap file
+        if reg.is_swapped() {
+            reg.swap_in()?;
+        }
         // if it isn't backed with memory yet, allocate memory for it
-        if !reg.has_mem() {
+        else if !reg.has_mem() {
             if let Some(ref f) = self.file {
                 // get memory cap for the region
                 // TODO add a cache for that; we request the same caps over and over again
@@ -200,13 +278,15 @@ impl DataSpace {
                 }
 
                 // if it's writable and should not be shared, create a copy
-                if !self.flags.contains(MapFlags::SHARED) && self.perms.contains(kif::Perm::W) {
+                if !self.flags.contains(MapFlags::SHARED) && self.prot.perm().contains(kif::Perm::W)
+                {
                     let src = MemGate::new_owned_bind(sel);
                     let mut childs = childs::borrow_mut();
                     let child = childs.child_by_id_mut(self.child).unwrap();
                     let mgate = child.alloc_local(reg.size(), kif::Perm::RWX)?;
                     let mem = Rc::new(RefCell::new(PhysMem::new((self.owner, self.virt), mgate)?));
                     reg.set_mem(mem);
+                    reg.set_swappable();
                     reg.copy_from(&src);
                     reg.set_mem_off(0);
                 }
@@ -225,18 +305,28 @@ impl DataSpace {
                 );
             }
             else {
-                let max = if !self.flags.contains(MapFlags::NOLPAGE)
-                    && math::is_aligned(virt, cfg::LPAGE_SIZE as goff)
-                    && reg.size() >= cfg::LPAGE_SIZE as goff
-                {
-                    cfg::LPAGE_SIZE / cfg::PAGE_SIZE
+                // for large mappings, use a large page if the region covers the large page around
+                // the page fault. The memory is allocated with the same alignment so that it can be
+                // mapped as such.
+                let lpage_virt = math::round_dn(virt, cfg::LPAGE_SIZE as goff);
+                let large = self.flags.contains(MapFlags::LARGE)
+                    && lpage_virt >= self.virt
+                    && reg.shrink_to(lpage_virt - self.virt, cfg::LPAGE_SIZE as goff);
+
+                if !large {
+                    let max = if !self.flags.contains(MapFlags::NOLPAGE)
+                        && math::is_aligned(virt, cfg::LPAGE_SIZE as goff)
+                        && reg.size() >= cfg::LPAGE_SIZE as goff
+                    {
+                        cfg::LPAGE_SIZE / cfg::PAGE_SIZE
+                    }
+                    else {
+                        MAX_ANON_PAGES
+                    };
+
+                    // don't allocate too much at once
+                    reg.limit_to(pf_off, max as goff);
                 }
-                else {
-                    MAX_ANON_PAGES
-                };
-
-                // don't allocate too much at once
-                reg.limit_to(pf_off, max as goff);
 
                 log!(
                     crate::LOG_DEF,
@@ -252,6 +342,7 @@ impl DataSpace {
                     (self.owner, self.virt),
                     mgate,
                 )?)));
+                reg.set_swappable();
 
                 if !self.flags.contains(MapFlags::UNINIT) {
                     // zero the memory
@@ -261,14 +352,14 @@ impl DataSpace {
         }
         // if we have memory, but COW is in progress
         else if reg.is_cow() {
-            reg.handle_cow(self.perms)?;
+            reg.handle_cow(self.prot.max_perm(reg.offset(), reg.size()))?;
         }
         else if reg.is_mapped() {
             // nothing to do
             return Ok(());
         }
 
-        reg.map(self.perms)
+        reg.map(&self.prot, kif::Perm::RWX)
     }
 
     pub fn kill(&mut self) {
@@ -20,6 +20,8 @@ mod dataspace;
 mod mapper;
 mod physmem;
 mod regions;
+mod shm;
+mod swap;
 
 use core::ops::DerefMut;
 
@@ -30,14 +32,15 @@ use m3::com::{GateIStream, MGateArgs, MemGate, RecvGate, SGateArgs, SendGate};
 use m3::env;
 use m3::errors::{Code, Error, VerboseError};
 use m3::format;
-use m3::kif;
+use m3::goff;
+use m3::kif::{self, PageFlags};
 use m3::log;
 use m3::math;
 use m3::println;
 use m3::server::{
     CapExchange, Handler, RequestHandler, Server, SessId, SessionContainer, DEF_MSG_SIZE,
 };
-use m3::session::{ClientSession, Pager, PagerOp, ResMng, M3FS};
+use m3::session::{ClientSession, DeriveArgs, Pager, PagerOp, ResMng, M3FS};
 use m3::tcu::{Label, TileId};
 use m3::tiles::{Activity, ActivityArgs, ChildActivity};
 use m3::vfs;
@@ -50,7 +53,7 @@ pub const LOG_DEF: bool = false;
 
 static PGHDL: LazyStaticRefCell<PagerReqHandler> = LazyStaticRefCell::default();
 static REQHDL: LazyReadOnlyCell<RequestHandler> = LazyReadOnlyCell::default();
-static MOUNTS: LazyStaticRefCell<Vec<(String, String)>> = LazyStaticRefCell::default();
+static MOUNTS: LazyStaticRefCell<Vec<(String, DeriveArgs, String)>> = LazyStaticRefCell::default();
 static PMP_TILES: StaticRefCell<Vec<TileId>> = StaticRefCell::new(Vec::new());
 static SETTINGS: LazyStaticRefCell<PagerSettings> = LazyStaticRefCell::default();
 
@@ -62,10 +65,36 @@ struct PagerReqHandler {
 impl PagerReqHandler {
     fn close_sess(&mut self, _crt: usize, sid: SessId, rgate: &RecvGate) {
         log!(crate::LOG_DEF, "[{}] pager::close()", sid);
-        let crt = self.sessions.get(sid).unwrap().creator();
+        let (crt, child) = {
+            let aspace = self.sessions.get(sid).unwrap();
+            (aspace.creator(), aspace.child_id())
+        };
         self.sessions.remove(crt, sid);
         // ignore all potentially outstanding messages of this session
         rgate.drop_msgs_with(sid as Label);
+
+        if let Some(child) = child {
+            self.remove_shm_of(child);
+        }
+    }
+
+    // removes the shared memory of `child` if it has no address space left, because its memory
+    // is handed out again once the child is gone
+    fn remove_shm_of(&mut self, child: childs::Id) {
+        for i in 0..self.sessions.capacity() {
+            if matches!(self.sessions.get(i), Some(a) if a.child_id() == Some(child)) {
+                return;
+            }
+        }
+
+        for mem in shm::remove_owner(child) {
+            for i in 0..self.sessions.capacity() {
+                if let Some(aspace) = self.sessions.get_mut(i) {
+                    aspace.unmap_shm(&mem);
+                }
+            }
+        }
+        shm::collect();
     }
 }
 
@@ -147,22 +176,44 @@ impl Handler<AddrSpace> for PagerReqHandler {
     }
 }
 
-fn get_mount(name: &str) -> Result<String, VerboseError> {
-    for (n, mpath) in MOUNTS.borrow().iter() {
-        if n == name {
+fn get_mount(name: &str, args: &DeriveArgs) -> Result<String, VerboseError> {
+    for (n, a, mpath) in MOUNTS.borrow().iter() {
+        if n == name && a == args {
             return Ok(mpath.clone());
         }
     }
 
-    let id = MOUNTS.borrow().len();
-    let fs = M3FS::new(id, name).map_err(|e| {
-        VerboseError::new(e.code(), format!("Unable to open m3fs session {}", name))
-    })?;
-    let our_path = format!("/child-mount-{}", name);
+    let (fs, our_path) = if *args == DeriveArgs::default() {
+        let id = MOUNTS.borrow().len();
+        let fs = M3FS::new(id, name).map_err(|e| {
+            VerboseError::new(e.code(), format!("Unable to open m3fs session {}", name))
+        })?;
+        (fs, format!("/child-mount-{}", name))
+    }
+    else {
+        // derive a session with restricted access from our own session
+        let base_path = get_mount(name, &DeriveArgs::default())?;
+        let base = Activity::own().mounts().get_by_path(&base_path).unwrap();
+        let id = MOUNTS.borrow().len();
+        let fs = base
+            .borrow()
+            .as_any()
+            .downcast_ref::<M3FS>()
+            .unwrap()
+            .derive(id, args)
+            .map_err(|e| {
+                VerboseError::new(
+                    e.code(),
+                    format!("Unable to derive m3fs session {} with {:?}", name, args),
+                )
+            })?;
+        (fs, format!("/child-mount-{}-{}", name, id))
+    };
+
     Activity::own().mounts().add(&our_path, fs)?;
     MOUNTS
         .borrow_mut()
-        .push((name.to_string(), our_path.to_string()));
+        .push((name.to_string(), args.clone(), our_path.to_string()));
     Ok(our_path)
 }
 
@@ -194,13 +245,14 @@ fn start_child_async(child: &mut OwnChild) -> Result<(), VerboseError> {
 
     // create child activity
     let tile_usage = child.child_tile().unwrap();
-    let mut act = ChildActivity::new_with(
-        tile_usage.tile_obj().clone(),
-        ActivityArgs::new(child.name())
-            .resmng(ResMng::new(resmng_sgate))
-            .pager(Pager::new(sess, pager_sgate, child_sgate)?)
-            .kmem(child.kmem().unwrap()),
-    )?;
+    let mut args = ActivityArgs::new(child.name())
+        .resmng(ResMng::new(resmng_sgate))
+        .pager(Pager::new(sess, pager_sgate, child_sgate)?)
+        .kmem(child.kmem().unwrap());
+    if let Some(prio) = child.cfg().prio() {
+        args = args.prio(prio);
+    }
+    let mut act = ChildActivity::new_with(tile_usage.tile_obj().clone(), args)?;
 
     // TODO make that more flexible
     // add PMP EP for file system
@@ -222,7 +274,11 @@ fn start_child_async(child: &mut OwnChild) -> Result<(), VerboseError> {
 
     // mount file systems for childs
     for m in child.cfg().mounts() {
-        let path = get_mount(m.fs())?;
+        let mut args = DeriveArgs::default().readonly(m.readonly()).root(m.root());
+        if let Some((uid, gid)) = m.creds() {
+            args = args.creds(uid, gid);
+        }
+        let path = get_mount(m.fs(), &args)?;
         act.add_mount(m.path(), &path);
     }
 
@@ -244,8 +300,15 @@ fn handle_request(op: PagerOp, is: &mut GateIStream<'_>) -> Result<(), Error> {
     let mut hdl = PGHDL.borrow_mut();
     let sid = is.label() as SessId;
 
+    // page faults might need to swap out memory from other sessions
+    if op == PagerOp::PAGEFAULT {
+        let virt: goff = is.pop()?;
+        let access = PageFlags::from_bits_truncate(is.pop()?);
+        pagefault(&mut hdl.sessions, sid, virt, access)?;
+        is.reply_error(Code::None)
+    }
     // clone is special, because we need two sessions
-    if op == PagerOp::CLONE {
+    else if op == PagerOp::CLONE {
         let pid = hdl.sessions.get(sid).unwrap().parent();
         if let Some(pid) = pid {
             let (sess, psess) = hdl.sessions.get_two_mut(sid, pid);
@@ -260,9 +323,13 @@ fn handle_request(op: PagerOp, is: &mut GateIStream<'_>) -> Result<(), Error> {
         let aspace = hdl.sessions.get_mut(sid).unwrap();
 
         match op {
-            PagerOp::PAGEFAULT => aspace.pagefault(is),
             PagerOp::MAP_ANON => aspace.map_anon(is),
             PagerOp::UNMAP => aspace.unmap(is),
+            PagerOp::PROTECT => aspace.protect(is),
+            PagerOp::DONTNEED => aspace.dont_need(is),
+            PagerOp::WILLNEED => aspace.will_need(is),
+            PagerOp::MAP_SHM => aspace.map_shm(is),
+            PagerOp::UNLINK_SHM => aspace.unlink_shm(is),
             PagerOp::CLOSE => aspace
                 .close(is)
                 .map(|_| hdl.close_sess(0, is.label() as SessId, is.rgate())),
@@ -271,6 +338,27 @@ fn handle_request(op: PagerOp, is: &mut GateIStream<'_>) -> Result<(), Error> {
     }
 }
 
+fn pagefault(
+    sessions: &mut SessionContainer<AddrSpace>,
+    sid: SessId,
+    virt: goff,
+    access: PageFlags,
+) -> Result<(), Error> {
+    loop {
+        let aspace = sessions.get_mut(sid).unwrap();
+        match aspace.pagefault(virt, access) {
+            // if the child is out of memory, swap out some of its memory and try again
+            Err(e) if e.code() == Code::NoSpace && swap::enabled() => {
+                let child = aspace.child_id().unwrap();
+                if !swap::evict(sessions, child)? {
+                    return Err(e);
+                }
+            },
+            res => return res,
+        }
+    }
+}
+
 fn workloop(serv: &Server) {
     requests::workloop(
         || {
@@ -286,15 +374,32 @@ fn workloop(serv: &Server) {
 #[derive(Clone, Debug)]
 pub struct PagerSettings {
     fs_size: usize,
+    swap: Option<String>,
+    swap_size: goff,
 }
 
 fn parse_args() -> Result<PagerSettings, String> {
+    let mut swap = None;
+    let mut swap_size = 32 * 1024 * 1024;
+    for arg in env::args() {
+        if let Some(path) = arg.strip_prefix("swap=") {
+            swap = Some(path.to_string());
+        }
+        else if let Some(size) = arg.strip_prefix("swapsize=") {
+            swap_size = size
+                .parse::<goff>()
+                .map_err(|_| String::from("Failed to parse swap size"))?;
+        }
+    }
+
     Ok(PagerSettings {
         fs_size: env::args()
             .last()
             .ok_or("File system size missing")?
             .parse::<usize>()
             .map_err(|_| String::from("Failed to parse FS size"))?,
+        swap,
+        swap_size,
     })
 }
 
@@ -316,7 +421,16 @@ pub fn main() -> i32 {
     }
     MOUNTS
         .borrow_mut()
-        .push(("m3fs".to_string(), "/".to_string()));
+        .push(("m3fs".to_string(), DeriveArgs::default(), "/".to_string()));
+
+    // the swap file lives in the root FS, so that we can only set it up now
+    let (swap, swap_size) = {
+        let settings = SETTINGS.borrow();
+        (settings.swap.clone(), settings.swap_size)
+    };
+    if let Some(path) = swap {
+        swap::init(&path, swap_size).expect("Unable to create swap file");
+    }
 
     // create server
     let mut hdl = PagerReqHandler {
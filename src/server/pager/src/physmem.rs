@@ -35,10 +35,11 @@ pub fn copy_block(src: &MemGate, dst: &MemGate, src_off: goff, size: goff) {
     }
 }
 
-fn clear_block(mem: &MemGate, size: goff) {
+fn clear_block(mem: &MemGate, off: goff, size: goff) {
     let pages = size / cfg::PAGE_SIZE as goff;
     for i in 0..pages {
-        mem.write(&ZEROS[..], i * cfg::PAGE_SIZE as goff).unwrap();
+        mem.write(&ZEROS[..], off + i * cfg::PAGE_SIZE as goff)
+            .unwrap();
     }
 }
 
@@ -62,6 +63,13 @@ impl PhysMem {
         }
     }
 
+    pub fn new_shared(mem: MemGate) -> Self {
+        PhysMem {
+            mgate: mem,
+            owner_mem: None,
+        }
+    }
+
     pub fn new_bind(owner_mem: (Selector, goff), sel: Selector) -> Self {
         PhysMem {
             mgate: MemGate::new_bind(sel),
@@ -81,6 +89,10 @@ impl PhysMem {
         mem::replace(&mut self.mgate, mem)
     }
 
+    pub fn into_gate(self) -> MemGate {
+        self.mgate
+    }
+
     pub fn owner_mem(&self) -> Option<(Selector, goff)> {
         self.owner_mem
     }
@@ -93,7 +105,11 @@ impl PhysMem {
         self.owner_mem = None;
     }
 
-    pub fn clear(&self, size: goff) {
-        clear_block(&self.mgate, size);
+    pub fn clear(&self, off: goff, size: goff) {
+        clear_block(&self.mgate, off, size);
+    }
+
+    pub fn clear_gate(mem: &MemGate, size: goff) {
+        clear_block(mem, 0, size);
     }
 }
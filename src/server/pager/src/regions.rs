@@ -31,11 +31,106 @@ use m3::syscalls;
 use resmng::childs;
 
 use crate::physmem::{copy_block, PhysMem};
+use crate::swap::{self, Slot};
 
 bitflags! {
     struct RegionFlags : u64 {
-        const MAPPED = 0x1;
-        const COW    = 0x2;
+        const MAPPED     = 0x1;
+        const COW        = 0x2;
+        // the memory has been allocated for the child and can therefore be swapped out
+        const SWAPPABLE  = 0x4;
+        // the region has been accessed since the last run of the clock hand
+        const REFERENCED = 0x8;
+    }
+}
+
+/// The permissions of a dataspace, which can be changed for parts of it
+#[derive(Clone, PartialEq, Eq)]
+pub struct Protection {
+    perm: Perm,
+    // the changed parts as (offset, size, permissions), sorted by offset and not overlapping
+    ranges: Vec<(goff, goff, Perm)>,
+}
+
+impl Protection {
+    pub fn new(perm: Perm) -> Self {
+        Protection {
+            perm,
+            ranges: Vec::new(),
+        }
+    }
+
+    /// Returns the permissions of the dataspace without the changed parts
+    pub fn perm(&self) -> Perm {
+        self.perm
+    }
+
+    /// Returns the permissions at offset `off`
+    pub fn perm_at(&self, off: goff) -> Perm {
+        self.ranges
+            .iter()
+            .find(|(o, s, _)| off >= *o && off < o + s)
+            .map_or(self.perm, |r| r.2)
+    }
+
+    /// Returns the union of all permissions in `off`..`off`+`size`
+    pub fn max_perm(&self, off: goff, size: goff) -> Perm {
+        let mut res = Perm::empty();
+        self.for_each(off, size, |_, _, perm| {
+            res |= perm;
+            Ok(())
+        })
+        .unwrap();
+        res
+    }
+
+    /// Sets the permissions of `off`..`off`+`size` to `perm`
+    pub fn set(&mut self, off: goff, size: goff, perm: Perm) {
+        let end = off + size;
+        let mut ranges = Vec::new();
+        for (o, s, p) in self.ranges.drain(..) {
+            // keep the parts outside of the given range
+            if o < off {
+                ranges.push((o, cmp::min(o + s, off) - o, p));
+            }
+            if o + s > end {
+                let start = cmp::max(o, end);
+                ranges.push((start, o + s - start, p));
+            }
+        }
+        if perm != self.perm {
+            ranges.push((off, size, perm));
+        }
+        ranges.sort_unstable_by_key(|r| r.0);
+        self.ranges = ranges;
+    }
+
+    /// Calls `func` with the offset, size, and permissions of all parts of `off`..`off`+`size`
+    fn for_each<F>(&self, off: goff, size: goff, mut func: F) -> Result<(), Error>
+    where
+        F: FnMut(goff, goff, Perm) -> Result<(), Error>,
+    {
+        let end = off + size;
+        let mut pos = off;
+        for &(o, s, p) in &self.ranges {
+            if o + s <= pos {
+                continue;
+            }
+            if o >= end {
+                break;
+            }
+            if o > pos {
+                func(pos, o - pos, self.perm)?;
+                pos = o;
+            }
+            let part_end = cmp::min(o + s, end);
+            func(pos, part_end - pos, p)?;
+            pos = part_end;
+        }
+        if pos < end {
+            func(pos, end - pos, self.perm)?;
+        }
+        Ok(())
     }
 }
 
@@ -44,6 +139,7 @@ pub struct Region {
     child: childs::Id,
     mem: Option<Rc<RefCell<PhysMem>>>,
     mem_off: goff,
+    swap: Option<Rc<Slot>>,
     ds_off: goff,
     off: goff,
     size: goff,
@@ -58,6 +154,7 @@ impl Region {
             child,
             mem: None,
             mem_off: 0,
+            swap: None,
             ds_off,
             off,
             size,
@@ -72,6 +169,7 @@ impl Region {
             child: self.child,
             mem: self.mem.clone(),
             mem_off: self.mem_off,
+            swap: self.swap.clone(),
             ds_off: self.ds_off,
             off: self.off,
             size: self.size,
@@ -124,6 +222,109 @@ impl Region {
         self.flags.contains(RegionFlags::COW)
     }
 
+    pub fn set_swappable(&mut self) {
+        self.flags.insert(RegionFlags::SWAPPABLE);
+    }
+
+    // returns true if the region has memory that has not been allocated for the child
+    fn is_bound(&self) -> bool {
+        self.has_mem() && !self.flags.contains(RegionFlags::SWAPPABLE)
+    }
+
+    pub fn is_swapped(&self) -> bool {
+        self.swap.is_some()
+    }
+
+    /// Returns true if the memory of this region can be swapped out, which requires that it is not
+    /// shared with other regions
+    pub fn is_swappable(&self) -> bool {
+        self.flags.contains(RegionFlags::SWAPPABLE)
+            && !self.is_cow()
+            && self
+                .mem
+                .as_ref()
+                .map_or(false, |m| Rc::strong_count(m) == 1)
+    }
+
+    /// Clears the reference bit and returns its previous value
+    ///
+    /// As we have no access to the accessed bits in the page tables, a referenced region is
+    /// unmapped so that the next access causes a page fault, which sets the bit again.
+    pub fn test_and_clear_referenced(&mut self) -> bool {
+        let referenced = self.flags.contains(RegionFlags::REFERENCED);
+        if referenced {
+            self.flags.remove(RegionFlags::REFERENCED);
+            self.unmap();
+        }
+        referenced
+    }
+
+    /// Writes the memory of this region to the swap file and frees the memory
+    pub fn swap_out(&mut self) -> Result<(), Error> {
+        let slot = swap::write(self.mem.as_ref().unwrap().borrow().gate(), self.size)?;
+
+        log!(
+            crate::LOG_DEF,
+            "Swapped out {:#x}..{:#x} to {:?}",
+            self.virt(),
+            self.virt() + self.size - 1,
+            slot
+        );
+
+        self.swap = Some(Rc::new(slot));
+        self.free_mem()
+    }
+
+    /// Unmaps this region and removes its memory, which is free'd if nobody else uses it
+    ///
+    /// Requires that the memory has been allocated for the child (see `set_swappable`).
+    fn free_mem(&mut self) -> Result<(), Error> {
+        self.unmap();
+        match Rc::try_unwrap(self.mem.take().unwrap()) {
+            Ok(mem) => {
+                let mut childs = childs::borrow_mut();
+                let child = childs.child_by_id_mut(self.child).unwrap();
+                child.free_local(mem.into_inner().into_gate())
+            },
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Allocates new memory for this region and reads its content from the swap file
+    pub fn swap_in(&mut self) -> Result<(), Error> {
+        let mgate = {
+            let mut childs = childs::borrow_mut();
+            let child = childs.child_by_id_mut(self.child).unwrap();
+            child.alloc_local(self.size, Perm::RWX)?
+        };
+
+        let slot = self.swap.take().unwrap();
+        log!(
+            crate::LOG_DEF,
+            "Swapping in {:#x}..{:#x} from {:?}",
+            self.virt(),
+            self.virt() + self.size - 1,
+            slot
+        );
+
+        if let Err(e) = swap::read(&slot, &mgate) {
+            self.swap = Some(slot);
+            let mut childs = childs::borrow_mut();
+            let child = childs.child_by_id_mut(self.child).unwrap();
+            child.free_local(mgate).ok();
+            return Err(e);
+        }
+
+        let mut mem = PhysMem::new_with_mem((self.owner, self.ds_off), mgate);
+        // we'll probably not access the memory via the gate again
+        mem.deactivate();
+        self.mem = Some(Rc::new(RefCell::new(mem)));
+        self.mem_off = 0;
+        // the memory is our own copy now, even if the slot is shared with other regions
+        self.flags.remove(RegionFlags::COW);
+        Ok(())
+    }
+
     pub fn handle_cow(&mut self, ds_perms: Perm) -> Result<(), Error> {
         self.flags.remove(RegionFlags::COW);
 
@@ -224,6 +425,16 @@ impl Region {
         }
     }
 
+    /// Shrinks this region to the `size` bytes at offset `off`, if it covers them completely
+    pub fn shrink_to(&mut self, off: goff, size: goff) -> bool {
+        if off < self.off || off + size > self.off + self.size {
+            return false;
+        }
+        self.off = off;
+        self.size = size;
+        true
+    }
+
     pub fn copy_from(&self, src: &MemGate) {
         if let Some(ref mem) = self.mem {
             copy_block(src, mem.borrow().gate(), self.mem_off, self.size());
@@ -233,36 +444,56 @@ impl Region {
     }
 
     pub fn clear(&self) {
+        self.clear_part(0, self.size);
+    }
+
+    /// Zeros the `size` bytes at offset `off` within this region
+    pub fn clear_part(&self, off: goff, size: goff) {
         let mem = self.mem.as_ref().unwrap();
-        mem.borrow().clear(self.size);
+        mem.borrow().clear(self.mem_off + off, size);
         // see above
         mem.borrow_mut().deactivate();
     }
 
-    pub fn map(&mut self, perm: Perm) -> Result<(), Error> {
+    /// Maps this region with the permissions in `prot`, restricted to `mask`
+    pub fn map(&mut self, prot: &Protection, mask: Perm) -> Result<(), Error> {
         if let Some(ref mem) = self.mem {
-            syscalls::create_map(
-                (self.virt() >> cfg::PAGE_BITS as goff) as Selector,
-                self.owner,
-                mem.borrow().gate().sel(),
-                (self.mem_off >> cfg::PAGE_BITS as goff) as Selector,
-                (self.size as usize >> cfg::PAGE_BITS) as Selector,
-                perm,
-            )?;
-            self.flags.insert(RegionFlags::MAPPED);
+            let mem_sel = mem.borrow().gate().sel();
+            let mapped = self.is_mapped();
+            prot.for_each(self.off, self.size, |off, size, perm| {
+                let virt = self.ds_off + off;
+                let pages = (size >> cfg::PAGE_BITS as goff) as Selector;
+                let perm = perm & mask;
+                // leave inaccessible parts unmapped so that accesses fault
+                if perm.is_empty() {
+                    if mapped {
+                        let crd = CapRngDesc::new(
+                            CapType::MAPPING,
+                            (virt >> cfg::PAGE_BITS as goff) as Selector,
+                            pages,
+                        );
+                        syscalls::revoke(self.owner, crd, true).ok();
+                    }
+                    return Ok(());
+                }
+
+                syscalls::create_map(
+                    (virt >> cfg::PAGE_BITS as goff) as Selector,
+                    self.owner,
+                    mem_sel,
+                    ((self.mem_off + off - self.off) >> cfg::PAGE_BITS as goff) as Selector,
+                    pages,
+                    perm,
+                )
+            })?;
+            self.flags
+                .insert(RegionFlags::MAPPED | RegionFlags::REFERENCED);
         }
 
         Ok(())
     }
 
-    pub fn kill(&mut self) {
-        // don't revoke the mapping caps, if the address space got destroyed
-        self.flags.remove(RegionFlags::MAPPED);
-    }
-}
-
-impl Drop for Region {
-    fn drop(&mut self) {
+    pub fn unmap(&mut self) {
         if self.mem.is_some() && self.flags.contains(RegionFlags::MAPPED) {
             syscalls::revoke(
                 self.owner,
@@ -275,6 +506,18 @@ impl Drop for Region {
             )
             .ok();
         }
+        self.flags.remove(RegionFlags::MAPPED);
+    }
+
+    pub fn kill(&mut self) {
+        // don't revoke the mapping caps, if the address space got destroyed
+        self.flags.remove(RegionFlags::MAPPED);
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        self.unmap();
     }
 }
 
@@ -315,7 +558,7 @@ impl RegionList {
         self.regs.is_empty()
     }
 
-    pub fn clone(&mut self, rl: &mut RegionList, ds_perms: Perm) -> Result<(), Error> {
+    pub fn clone(&mut self, rl: &mut RegionList, prot: &Protection) -> Result<(), Error> {
         // for the case that we already have regions and the DS is writable, just remove them.
         // because there is no point in trying to keep them:
         // 1. we have already our own copy
@@ -327,15 +570,17 @@ impl RegionList {
         self.regs.clear();
 
         for r in &mut rl.regs {
+            let writable = prot.max_perm(r.off, r.size).contains(Perm::W);
+
             // make it readonly, if it's writable and we have not done that yet
-            if !r.is_cow() && ds_perms.contains(Perm::W) {
-                r.map(ds_perms ^ Perm::W)?;
+            if !r.is_cow() && writable {
+                r.map(prot, Perm::RWX ^ Perm::W)?;
             }
 
             let mut nreg = Box::new(r.clone_for(self.owner));
 
             // adjust flags
-            if ds_perms.contains(Perm::W) {
+            if writable {
                 r.flags.insert(RegionFlags::COW);
             }
             // for the clone, even readonly regions are mapped on demand
@@ -345,7 +590,90 @@ impl RegionList {
         Ok(())
     }
 
+    /// Drops the memory of all regions in `off`..`off`+`size`, so that it is obtained again on the
+    /// next page fault
+    ///
+    /// Only memory that has been allocated for the child is dropped. Regions that are only
+    /// partially covered keep their memory; if `clear` is true, the covered part is zeroed instead.
+    pub fn drop_range(
+        &mut self,
+        off: goff,
+        size: goff,
+        clear: bool,
+        prot: &Protection,
+    ) -> Result<(), Error> {
+        let end = off + size;
+        let mut idx = 0;
+        while idx < self.regs.len() {
+            let r = &mut self.regs[idx];
+            let (r_start, r_end) = (r.off, r.off + r.size);
+            if r_end <= off || r_start >= end || r.is_bound() {
+                idx += 1;
+                continue;
+            }
+
+            if r_start >= off && r_end <= end {
+                let mut r = self.regs.remove(idx);
+                if r.has_mem() {
+                    r.free_mem()?;
+                }
+                continue;
+            }
+
+            if clear {
+                // make sure that we have our own copy of the memory before we change it
+                if r.is_swapped() {
+                    r.swap_in()?;
+                }
+                if r.is_cow() {
+                    r.handle_cow(prot.max_perm(r_start, r_end - r_start))?;
+                    // the mapping might still refer to the old memory
+                    r.unmap();
+                }
+                if r.mem.as_ref().map_or(false, |m| Rc::strong_count(m) == 1) {
+                    let start = cmp::max(r_start, off);
+                    r.clear_part(start - r_start, cmp::min(r_end, end) - start);
+                }
+            }
+            idx += 1;
+        }
+        Ok(())
+    }
+
+    /// Unmaps all regions in `off`..`off`+`size`
+    pub fn unmap_range(&mut self, off: goff, size: goff) {
+        for r in &mut self.regs {
+            if r.off < off + size && r.off + r.size > off {
+                r.unmap();
+            }
+        }
+    }
+
+    /// Returns true if any region uses the memory `mem`
+    pub fn uses_mem(&self, mem: &Rc<RefCell<PhysMem>>) -> bool {
+        self.regs
+            .iter()
+            .any(|r| r.mem.as_ref().map_or(false, |m| Rc::ptr_eq(m, mem)))
+    }
+
+    /// Replaces our regions with regions that share the memory with the regions in `rl`
+    pub fn share(&mut self, rl: &RegionList) {
+        self.regs.clear();
+        for r in &rl.regs {
+            let mut nreg = Box::new(r.clone_for(self.owner));
+            nreg.flags.remove(RegionFlags::MAPPED);
+            self.regs.push(nreg);
+        }
+    }
+
     pub fn populate(&mut self, sel: Selector) {
+        self.populate_with(Rc::new(RefCell::new(PhysMem::new_bind(
+            (self.owner, self.ds_off),
+            sel,
+        ))));
+    }
+
+    pub fn populate_with(&mut self, mem: Rc<RefCell<PhysMem>>) {
         assert!(self.regs.is_empty());
         let mut r = Box::new(Region::new(
             self.owner,
@@ -354,13 +682,14 @@ impl RegionList {
             0,
             self.size,
         ));
-        r.set_mem(Rc::new(RefCell::new(PhysMem::new_bind(
-            (self.owner, self.ds_off),
-            sel,
-        ))));
+        r.set_mem(mem);
         self.regs.push(r);
     }
 
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut Region> {
+        self.regs.get_mut(idx).map(|r| r.as_mut())
+    }
+
     pub fn pagefault(&mut self, off: goff) -> &mut Region {
         let idx = self.do_pagefault(off);
         &mut self.regs[idx]
@@ -0,0 +1,178 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+// named shared-memory objects that can be mapped by all activities of the pager. The memory is
+// charged to the creator and lives at most as long as the creator. Each mapping holds a reference
+// to the memory and the object holds another one, so that the memory is free'd as soon as the
+// object has been unlinked and is not mapped anymore.
+
+use m3::cell::{RefCell, StaticRefCell};
+use m3::cfg;
+use m3::col::{String, ToString, Vec};
+use m3::com::MemGate;
+use m3::errors::{Code, Error};
+use m3::goff;
+use m3::kif::Perm;
+use m3::log;
+use m3::math;
+use m3::rc::Rc;
+use resmng::childs;
+
+use crate::physmem::PhysMem;
+
+struct SharedMem {
+    // None if the object has been unlinked
+    name: Option<String>,
+    mem: Rc<RefCell<PhysMem>>,
+    size: goff,
+    // the child that created the object and is charged for the memory
+    owner: childs::Id,
+    // the permissions of the creator, which are the maximum for all mappings
+    perm: Perm,
+}
+
+impl SharedMem {
+    fn has_name(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name)
+    }
+
+    fn free(self) {
+        let mem = Rc::try_unwrap(self.mem).ok().unwrap().into_inner();
+        let mut childs = childs::borrow_mut();
+        // if the owner is gone, resmng has free'd the memory already
+        if let Some(child) = childs.child_by_id_mut(self.owner) {
+            child.free_local(mem.into_gate()).ok();
+        }
+    }
+}
+
+static OBJECTS: StaticRefCell<Vec<SharedMem>> = StaticRefCell::new(Vec::new());
+
+/// Creates a new shared-memory object with given name and size for child `owner`, which is charged
+/// for the memory and gets the permissions `perm`
+pub fn create(
+    name: &str,
+    size: goff,
+    owner: childs::Id,
+    perm: Perm,
+) -> Result<Rc<RefCell<PhysMem>>, Error> {
+    if name.is_empty() || size == 0 {
+        return Err(Error::new(Code::InvArgs));
+    }
+
+    let mut objs = OBJECTS.borrow_mut();
+    if objs.iter().any(|o| o.has_name(name)) {
+        return Err(Error::new(Code::Exists));
+    }
+
+    let size = math::round_up(size, cfg::PAGE_SIZE as goff);
+    let mut mgate = {
+        let mut childs = childs::borrow_mut();
+        let child = childs.child_by_id_mut(owner).unwrap();
+        child.alloc_local(size, Perm::RWX)?
+    };
+    // clear the memory, because it might contain data of others
+    PhysMem::clear_gate(&mgate, size);
+    // we don't need to access it anymore
+    mgate.deactivate();
+
+    log!(
+        crate::LOG_DEF,
+        "Created shared memory {} with {:#x} bytes",
+        name,
+        size
+    );
+
+    let mem = Rc::new(RefCell::new(PhysMem::new_shared(mgate)));
+    objs.push(SharedMem {
+        name: Some(name.to_string()),
+        mem: mem.clone(),
+        size,
+        owner,
+        perm,
+    });
+    Ok(mem)
+}
+
+/// Returns the memory of the shared-memory object with given name, if it has at least `size` bytes
+/// and the creator has the permissions `perm`
+pub fn open(name: &str, size: goff, perm: Perm) -> Result<Rc<RefCell<PhysMem>>, Error> {
+    let objs = OBJECTS.borrow();
+    let obj = objs
+        .iter()
+        .find(|o| o.has_name(name))
+        .ok_or_else(|| Error::new(Code::NotFound))?;
+    if size > obj.size {
+        return Err(Error::new(Code::InvArgs));
+    }
+    if !obj.perm.contains(perm) {
+        return Err(Error::new(Code::NoPerm));
+    }
+    Ok(obj.mem.clone())
+}
+
+/// Removes the name of the shared-memory object `name`
+pub fn unlink(name: &str) -> Result<(), Error> {
+    {
+        let mut objs = OBJECTS.borrow_mut();
+        let obj = objs
+            .iter_mut()
+            .find(|o| o.has_name(name))
+            .ok_or_else(|| Error::new(Code::NotFound))?;
+        obj.name = None;
+
+        log!(
+            crate::LOG_DEF,
+            "Unlinked shared memory {} ({} mappings left)",
+            name,
+            Rc::strong_count(&obj.mem) - 1
+        );
+    }
+
+    collect();
+    Ok(())
+}
+
+/// Removes all objects of child `owner` and returns their memory, which needs to be unmapped from
+/// all address spaces before it is free'd by `collect`
+pub fn remove_owner(owner: childs::Id) -> Vec<Rc<RefCell<PhysMem>>> {
+    let mut objs = OBJECTS.borrow_mut();
+    let mut mems = Vec::new();
+    for obj in objs.iter_mut().filter(|o| o.owner == owner) {
+        if let Some(name) = obj.name.take() {
+            log!(
+                crate::LOG_DEF,
+                "Removing shared memory {} of exited creator",
+                name
+            );
+        }
+        mems.push(obj.mem.clone());
+    }
+    mems
+}
+
+/// Frees the memory of all unlinked objects that are not mapped anymore
+pub fn collect() {
+    let mut objs = OBJECTS.borrow_mut();
+    let mut i = 0;
+    while i < objs.len() {
+        if objs[i].name.is_none() && Rc::strong_count(&objs[i].mem) == 1 {
+            objs.remove(i).free();
+        }
+        else {
+            i += 1;
+        }
+    }
+}
@@ -0,0 +1,203 @@
+/*
+ * Copyright (C) 2022 Nils Asmussen, Barkhausen Institut
+ *
+ * This file is part of M3 (Microkernel-based SysteM for Heterogeneous Manycores).
+ *
+ * M3 is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License version 2 as
+ * published by the Free Software Foundation.
+ *
+ * M3 is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License version 2 for more details.
+ */
+
+// swaps out the anonymous memory of childs that exceed their memory quota into a file. The victims
+// are chosen by the clock algorithm over all regions of the child.
+
+use core::fmt;
+
+use m3::cell::StaticRefCell;
+use m3::cfg;
+use m3::com::MemGate;
+use m3::errors::Error;
+use m3::goff;
+use m3::io::{Read, Write};
+use m3::log;
+use m3::mem::{AlignedBuf, MemMap};
+use m3::server::{SessId, SessionContainer};
+use m3::vfs::{FileRef, GenericFile, OpenFlags, Seek, SeekMode, VFS};
+use resmng::childs;
+
+use crate::addrspace::AddrSpace;
+
+struct Swap {
+    file: FileRef<GenericFile>,
+    slots: MemMap,
+}
+
+impl Swap {
+    fn write_at(&mut self, mem: &MemGate, off: goff, size: goff) -> Result<(), Error> {
+        let mut buf = BUF.borrow_mut();
+        self.file.seek(off as usize, SeekMode::SET)?;
+        for pos in (0..size).step_by(cfg::PAGE_SIZE) {
+            mem.read(&mut buf[..], pos)?;
+            self.file.write_all(&buf[..])?;
+        }
+        Ok(())
+    }
+
+    fn read_at(&mut self, mem: &MemGate, off: goff, size: goff) -> Result<(), Error> {
+        let mut buf = BUF.borrow_mut();
+        self.file.seek(off as usize, SeekMode::SET)?;
+        for pos in (0..size).step_by(cfg::PAGE_SIZE) {
+            self.file.read_exact(&mut buf[..])?;
+            mem.write(&buf[..], pos)?;
+        }
+        Ok(())
+    }
+}
+
+// the position of the clock hand: the region with index `reg` in the dataspace with index `ds` of
+// session `sess`
+struct Hand {
+    sess: SessId,
+    ds: usize,
+    reg: usize,
+}
+
+impl Hand {
+    fn next_sess(&mut self) {
+        self.sess += 1;
+        self.ds = 0;
+        self.reg = 0;
+    }
+
+    fn next_ds(&mut self) {
+        self.ds += 1;
+        self.reg = 0;
+    }
+}
+
+static SWAP: StaticRefCell<Option<Swap>> = StaticRefCell::new(None);
+static HAND: StaticRefCell<Hand> = StaticRefCell::new(Hand {
+    sess: 0,
+    ds: 0,
+    reg: 0,
+});
+static BUF: StaticRefCell<AlignedBuf<{ cfg::PAGE_SIZE }>> =
+    StaticRefCell::new(AlignedBuf::new_zeroed());
+
+/// A range in the swap file that holds the content of a region
+pub struct Slot {
+    off: goff,
+    size: goff,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(s) = SWAP.borrow_mut().as_mut() {
+            s.slots.free(self.off, self.size);
+        }
+    }
+}
+
+impl fmt::Debug for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Slot[{:#x}..{:#x}]", self.off, self.off + self.size - 1)
+    }
+}
+
+/// Enables swapping into the file at `path`, which can hold up to `size` bytes
+pub fn init(path: &str, size: goff) -> Result<(), Error> {
+    let file = VFS::open(path, OpenFlags::RW | OpenFlags::CREATE | OpenFlags::TRUNC)?;
+    log!(
+        crate::LOG_DEF,
+        "Swapping into {} with {} KiB",
+        path,
+        size / 1024
+    );
+    SWAP.replace(Some(Swap {
+        file,
+        slots: MemMap::new(0, size),
+    }));
+    Ok(())
+}
+
+/// Returns true if swapping is enabled
+pub fn enabled() -> bool {
+    SWAP.borrow().is_some()
+}
+
+/// Writes the first `size` bytes of `mem` into a new slot in the swap file
+pub fn write(mem: &MemGate, size: goff) -> Result<Slot, Error> {
+    let mut swap = SWAP.borrow_mut();
+    let swap = swap.as_mut().unwrap();
+    let off = swap.slots.allocate(size, cfg::PAGE_SIZE as goff)?;
+    match swap.write_at(mem, off, size) {
+        Ok(_) => Ok(Slot { off, size }),
+        Err(e) => {
+            swap.slots.free(off, size);
+            Err(e)
+        },
+    }
+}
+
+/// Reads the content of the given slot into `mem`
+pub fn read(slot: &Slot, mem: &MemGate) -> Result<(), Error> {
+    let mut swap = SWAP.borrow_mut();
+    swap.as_mut().unwrap().read_at(mem, slot.off, slot.size)
+}
+
+/// Swaps out one region of the given child
+///
+/// The clock hand moves over the regions of all address spaces, but only considers the swappable
+/// regions of `child`. Referenced regions get a second chance by clearing their reference bit. The
+/// first region that has not been referenced since then is swapped out.
+///
+/// Returns false if the child has no region that could be swapped out.
+pub fn evict(sessions: &mut SessionContainer<AddrSpace>, child: childs::Id) -> Result<bool, Error> {
+    let mut hand = HAND.borrow_mut();
+
+    // the hand might start in the middle, so that we need two full rounds afterwards in the worst
+    // case: one to clear all reference bits and one to find the victim
+    let mut wraps = 0;
+    while wraps < 3 {
+        if hand.sess >= sessions.capacity() {
+            hand.sess = 0;
+            hand.ds = 0;
+            hand.reg = 0;
+            wraps += 1;
+            continue;
+        }
+
+        let reg = match sessions.get_mut(hand.sess) {
+            Some(aspace) if aspace.child_id() == Some(child) => match aspace.dataspace_mut(hand.ds)
+            {
+                Some(ds) => ds.region_mut(hand.reg),
+                None => {
+                    hand.next_sess();
+                    continue;
+                },
+            },
+            _ => {
+                hand.next_sess();
+                continue;
+            },
+        };
+
+        match reg {
+            Some(reg) => {
+                hand.reg += 1;
+                if reg.is_swappable() && !reg.test_and_clear_referenced() {
+                    reg.swap_out()?;
+                    return Ok(true);
+                }
+            },
+            None => hand.next_ds(),
+        }
+    }
+
+    Ok(false)
+}
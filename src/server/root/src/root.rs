@@ -66,13 +66,15 @@ fn start_child_async(child: &mut OwnChild) -> Result<(), VerboseError> {
             .label(tcu::Label::from(child.id())),
     )?;
 
-    let mut act = ChildActivity::new_with(
-        child.child_tile().unwrap().tile_obj().clone(),
-        ActivityArgs::new(child.name())
-            .resmng(ResMng::new(sgate))
-            .kmem(child.kmem().unwrap()),
-    )
-    .map_err(|e| VerboseError::new(e.code(), "Unable to create Activity".to_string()))?;
+    let mut args = ActivityArgs::new(child.name())
+        .resmng(ResMng::new(sgate))
+        .kmem(child.kmem().unwrap());
+    if let Some(prio) = child.cfg().prio() {
+        args = args.prio(prio);
+    }
+
+    let mut act = ChildActivity::new_with(child.child_tile().unwrap().tile_obj().clone(), args)
+        .map_err(|e| VerboseError::new(e.code(), "Unable to create Activity".to_string()))?;
 
     if Activity::own().mounts().get_by_path("/").is_some() {
         act.add_mount("/", "/");
@@ -20,7 +20,7 @@ use base::col::{BoxList, Vec};
 use base::errors::{Code, Error};
 use base::goff;
 use base::impl_boxitem;
-use base::kif;
+use base::kif::{self, tilemux::Priority, tilemux::PRIO_COUNT};
 use base::log;
 use base::math;
 use base::mem::{size_of, GlobAddr, MsgBuf};
@@ -129,6 +129,7 @@ pub enum Event {
 
 pub struct Activity {
     state: ActState,
+    prio: Priority,
     prev: Option<NonNull<Activity>>,
     next: Option<NonNull<Activity>>,
     aspace: Option<paging::AddrSpace<PTAllocator>>,
@@ -200,7 +201,13 @@ static IDLE: LazyStaticUnsafeCell<Box<Activity>> = LazyStaticUnsafeCell::default
 static OUR: LazyStaticUnsafeCell<Box<Activity>> = LazyStaticUnsafeCell::default();
 static CUR: StaticUnsafeCell<Option<Box<Activity>>> = StaticUnsafeCell::new(None);
 
-static RDY: StaticRefCell<BoxList<Activity>> = StaticRefCell::new(BoxList::new());
+// idle and TileMux itself are never ready, but are always least preferred
+const IDLE_PRIO: Priority = PRIO_COUNT as Priority;
+const EMPTY_LIST: BoxList<Activity> = BoxList::new();
+
+// one list of ready activities per priority
+static RDY: StaticRefCell<[BoxList<Activity>; PRIO_COUNT]> =
+    StaticRefCell::new([EMPTY_LIST; PRIO_COUNT]);
 static BLK: StaticRefCell<BoxList<Activity>> = StaticRefCell::new(BoxList::new());
 
 static BOOTSTRAP: StaticCell<bool> = StaticCell::new(true);
@@ -252,6 +259,7 @@ pub fn init() {
     unsafe {
         IDLE.set(Box::new(Activity::new(
             kif::tilemux::IDLE_ID,
+            IDLE_PRIO,
             idle_quota,
             quota::get_pt(quota::IDLE_ID).unwrap(),
             0,
@@ -259,6 +267,7 @@ pub fn init() {
         )));
         OUR.set(Box::new(Activity::new(
             kif::tilemux::ACT_ID,
+            IDLE_PRIO,
             our_quota,
             quota::get_pt(quota::IDLE_ID).unwrap(),
             0,
@@ -285,11 +294,21 @@ pub fn init() {
 
 pub fn add(
     id: Id,
+    prio: Priority,
     time_quota: quota::Id,
     pt_quota: quota::Id,
     eps_start: tcu::EpId,
 ) -> Result<(), Error> {
-    log!(crate::LOG_ACTS, "Created Activity {}", id);
+    log!(
+        crate::LOG_ACTS,
+        "Created Activity {} with priority {}",
+        id,
+        prio
+    );
+
+    if prio as usize >= PRIO_COUNT {
+        return Err(Error::new(Code::InvArgs));
+    }
 
     let time_quota = quota::get_time(time_quota).unwrap();
     if time_quota.total() == 0 {
@@ -313,7 +332,9 @@ pub fn add(
         (0, None)
     };
 
-    let mut act = Box::new(Activity::new(id, time_quota, pt_quota, eps_start, root_pt));
+    let mut act = Box::new(Activity::new(
+        id, prio, time_quota, pt_quota, eps_start, root_pt,
+    ));
 
     if pex_env().tile_desc.has_virtmem() {
         act.frames.push(frame);
@@ -366,7 +387,15 @@ pub fn cur() -> ActivityRef<'static> {
 }
 
 pub fn has_ready() -> bool {
-    !RDY.borrow().is_empty()
+    RDY.borrow().iter().any(|l| !l.is_empty())
+}
+
+/// Returns true if there is a ready activity with priority `prio` or a higher priority
+pub fn has_ready_with(prio: Priority) -> bool {
+    RDY.borrow()
+        .iter()
+        .take(prio as usize + 1)
+        .any(|l| !l.is_empty())
 }
 
 pub fn schedule(mut action: ScheduleAction) -> usize {
@@ -405,9 +434,11 @@ pub fn schedule(mut action: ScheduleAction) -> usize {
 
 fn do_schedule(mut action: ScheduleAction) -> usize {
     let now = TimeInstant::now();
+    // take the first activity with the highest priority
     let mut next = RDY
         .borrow_mut()
-        .pop_front()
+        .iter_mut()
+        .find_map(|l| l.pop_front())
         // safety: we know that idle is stored in a Box
         .unwrap_or_else(|| unsafe { Box::from_raw(IDLE.get_mut().as_mut()) });
 
@@ -419,6 +450,16 @@ fn do_schedule(mut action: ScheduleAction) -> usize {
                 .saturating_sub((now - old.scheduled).as_nanos() as u64),
         );
 
+        // don't switch to less important activities, unless the current one blocks
+        if matches!(action, ScheduleAction::Yield | ScheduleAction::Preempt) && next.prio > old.prio
+        {
+            put_back(next);
+            old.refill_budget();
+            old.cpu_time += now - old.scheduled;
+            old.scheduled = now;
+            return old.user_state_addr;
+        }
+
         // save TCU command registers; do that first while still running with that activity
         old.cmd.save();
 
@@ -427,17 +468,14 @@ fn do_schedule(mut action: ScheduleAction) -> usize {
 
         // are there messages left we care about?
         if action == ScheduleAction::Block && !old.can_block((old_id >> 16) as u16) {
-            // if the activity has budget left (or there is no one else ready), continue with it
-            if old.time_quota.left() > 0 || next.id() == kif::tilemux::IDLE_ID {
+            // if the activity has budget left and no one more important is ready (or there is no
+            // one else ready), continue with it
+            if (old.time_quota.left() > 0 && next.prio >= old.prio)
+                || next.id() == kif::tilemux::IDLE_ID
+            {
                 let next_id = tcu::TCU::xchg_activity(old_id).unwrap();
                 next.set_activity_reg(next_id);
-                if next.id() != kif::tilemux::IDLE_ID {
-                    let next_budget = TimeDuration::from_nanos(next.time_quota.left());
-                    make_ready(next, next_budget);
-                }
-                else {
-                    Box::into_raw(next);
-                }
+                put_back(next);
                 let last_sched = old.scheduled;
                 old.cpu_time += now - last_sched;
                 old.scheduled = now;
@@ -470,14 +508,7 @@ fn do_schedule(mut action: ScheduleAction) -> usize {
     next.state = ActState::Running;
 
     next.scheduled = now;
-    // budget is immediately refilled but we prefer other activities while a budget is 0 (see make_ready)
-    if next.time_quota.left() == 0 {
-        // to keep it simple, we divide the time slice by the number of users to ensure that activities
-        // that share a time slice don't receive more than their share in total. the better approach
-        // might be to actually schedule quotas and not activities, but that seems like overkill here.
-        next.time_quota
-            .set_left(next.time_quota.total() / next.time_quota.users());
-    }
+    next.refill_budget();
     let next_budget = next.time_quota.left();
 
     // restore TCU command registers
@@ -542,12 +573,26 @@ fn make_blocked(mut act: Box<Activity>) {
 
 fn make_ready(mut act: Box<Activity>, budget: TimeDuration) {
     act.state = ActState::Ready;
+    let mut rdy = RDY.borrow_mut();
+    let list = &mut rdy[act.prio as usize];
     // prefer activities with budget
     if !budget.is_zero() {
-        RDY.borrow_mut().push_front(act);
+        list.push_front(act);
+    }
+    else {
+        list.push_back(act);
+    }
+}
+
+// puts the given activity that has been chosen to run next back, because we continue with the
+// current activity
+fn put_back(next: Box<Activity>) {
+    if next.id() != kif::tilemux::IDLE_ID {
+        let next_budget = TimeDuration::from_nanos(next.time_quota.left());
+        make_ready(next, next_budget);
     }
     else {
-        RDY.borrow_mut().push_back(act);
+        Box::into_raw(next);
     }
 }
 
@@ -563,7 +608,11 @@ pub fn remove(id: Id, status: i32, notify: bool, sched: bool) {
         let old = match unsafe { &v.as_ref().state } {
             // safety: we don't access `v` afterwards
             ActState::Running => unsafe { CUR.set(None).unwrap() },
-            ActState::Ready => RDY.borrow_mut().remove_if(|v| v.id() == id).unwrap(),
+            ActState::Ready => {
+                // safety: see above
+                let prio = unsafe { v.as_ref().prio } as usize;
+                RDY.borrow_mut()[prio].remove_if(|v| v.id() == id).unwrap()
+            },
             ActState::Blocked => BLK.borrow_mut().remove_if(|v| v.id() == id).unwrap(),
         };
         // we now can't access `v` anymore
@@ -605,6 +654,7 @@ pub fn remove(id: Id, status: i32, notify: bool, sched: bool) {
 impl Activity {
     pub fn new(
         id: Id,
+        prio: Priority,
         time_quota: Rc<Quota<u64>>,
         pt_quota: Rc<PTQuota>,
         eps_start: tcu::EpId,
@@ -624,6 +674,7 @@ impl Activity {
             frames: Vec::new(),
             act_reg: id,
             state: ActState::Blocked,
+            prio,
             #[cfg(any(target_arch = "riscv64", target_arch = "x86_64"))]
             fpu_state: arch::FPUState::default(),
             user_state: arch::State::default(),
@@ -669,6 +720,10 @@ impl Activity {
         self.state
     }
 
+    pub fn prio(&self) -> Priority {
+        self.prio
+    }
+
     pub fn activity_reg(&self) -> tcu::Reg {
         self.act_reg
     }
@@ -707,6 +762,19 @@ impl Activity {
         TimeDuration::from_nanos(self.time_quota.left())
     }
 
+    fn refill_budget(&mut self) {
+        // budget is immediately refilled but we prefer other activities while a budget is 0 (see
+        // make_ready)
+        if self.time_quota.left() == 0 {
+            // to keep it simple, we divide the time slice by the number of users to ensure that
+            // activities that share a time slice don't receive more than their share in total. the
+            // better approach might be to actually schedule quotas and not activities, but that
+            // seems like overkill here.
+            self.time_quota
+                .set_left(self.time_quota.total() / self.time_quota.users());
+        }
+    }
+
     pub fn user_state(&mut self) -> &mut arch::State {
         &mut self.user_state
     }
@@ -836,7 +904,7 @@ impl Activity {
                 .left()
                 .saturating_sub(duration.as_nanos() as u64),
         );
-        if self.time_quota.left() == 0 && has_ready() {
+        if self.time_quota.left() == 0 && has_ready_with(self.prio) {
             crate::reg_scheduling(ScheduleAction::Preempt);
         }
     }
@@ -997,6 +1065,7 @@ impl Activity {
         let pte = self.translate(virt, kif::PageFlags::R);
         let phys = pte & !(cfg::PAGE_MASK as u64);
         let mut flags = kif::PageFlags::from_bits_truncate(pte & cfg::PAGE_MASK as u64);
+        flags.remove(kif::PageFlags::L);
         flags |= kif::PageFlags::FIXED;
         tcu::TCU::insert_tlb(self.id() as u16, virt, phys, flags).unwrap();
     }
@@ -67,8 +67,8 @@ pub fn remove(act: activities::Id) {
 pub fn reprogram() {
     // determine the remaining budget of the current activity, if there is any
     let budget = activities::try_cur().and_then(|cur| {
-        // don't use a budget if there is no ready activity or we're idling
-        if activities::has_ready() && cur.id() != kif::tilemux::IDLE_ID {
+        // don't use a budget if there is no ready activity that could preempt us or we're idling
+        if activities::has_ready_with(cur.prio()) && cur.id() != kif::tilemux::IDLE_ID {
             Some(cur.budget_left())
         }
         else {
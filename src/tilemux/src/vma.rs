@@ -138,8 +138,9 @@ pub fn handle_xlate(virt: usize, perm: PageFlags) {
             activities::remove_cur(1);
         }
         else {
+            // for large pages, we get the address of the small page and insert an entry for it
             let phys = pte & !(cfg::PAGE_MASK as u64);
-            let flags = PageFlags::from_bits_truncate(pte & cfg::PAGE_MASK as u64);
+            let flags = PageFlags::from_bits_truncate(pte & cfg::PAGE_MASK as u64) - PageFlags::L;
             tcu::TCU::insert_tlb(act.id() as u16, virt, phys, flags).unwrap();
         }
     }
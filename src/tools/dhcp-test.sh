@@ -0,0 +1,54 @@
+#!/bin/bash
+
+# Tests the DHCP client of the net service on host. For that, we create a TAP device with a dnsmasq
+# instance that acts as DHCP server, default gateway and nameserver, and run boot/rust-net-dhcp.xml
+# against it. Needs to be run via sudo from the M3 root directory and requires dnsmasq.
+
+if [ "$(id -u)" != "0" ] || [ "$SUDO_USER" = "" ]; then
+    echo "Usage: sudo -E $0" 1>&2
+    exit 1
+fi
+
+tap=m3tap0
+server=10.0.99.1
+lease=10.0.99.100
+# an address that is not within the leased subnet and therefore only reachable via the gateway
+remote=10.99.0.1
+# the MAC address of the net service (OWN_MAC in src/server/net/src/net.rs)
+mac=00:0a:35:03:02:03
+
+cleanup() {
+    [ "$dnsmasq" != "" ] && kill "$dnsmasq" 2>/dev/null
+    ip addr del "$remote/32" dev lo 2>/dev/null
+    ip link del "$tap" 2>/dev/null
+}
+trap cleanup EXIT INT TERM
+
+ip tuntap add dev "$tap" mode tap user "$SUDO_USER" || exit 1
+ip addr add "$server/24" dev "$tap"
+ip link set "$tap" up
+ip addr add "$remote/32" dev lo
+
+dnsmasq --keep-in-foreground --conf-file=/dev/null --no-resolv --no-hosts --pid-file= \
+    --interface="$tap" \
+    --dhcp-range="$lease,$lease,255.255.255.0,1h" \
+    --dhcp-host="$mac,$lease" \
+    --dhcp-option="option:router,$server" \
+    --dhcp-option="option:dns-server,$server" \
+    --address=/m3.test/10.0.99.42 &
+dnsmasq=$!
+
+log=$(mktemp)
+sudo -E -u "$SUDO_USER" M3_TARGET=host ./b run boot/rust-net-dhcp.xml 2>&1 | tee "$log"
+
+# the tests check the gateway indirectly by talking to $remote; make sure that it was leased
+res=0
+if ! grep -q "DHCP lease: ip=$lease/24, gateway=Some" "$log"; then
+    echo "The net service did not report the expected lease" 1>&2
+    res=1
+fi
+if ! grep -q "All tests successful!" "$log"; then
+    res=1
+fi
+rm -f "$log"
+exit $res
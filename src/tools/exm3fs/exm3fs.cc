@@ -46,7 +46,7 @@ static void export_rec(const char *dest, m3::inodeno_t dirno, const char *src) {
             m3::DirEntry *end = reinterpret_cast<m3::DirEntry *>(buffer + sb.blocksize);
             m3::DirEntry *e = begin;
             while(e >= begin && e < end && e->next > 0) {
-                if(e->name + e->namelen <= reinterpret_cast<char *>(end)) {
+                if(e->namelen > 0 && e->name + e->namelen <= reinterpret_cast<char *>(end)) {
                     if((e->namelen != 1 || strncmp(e->name, ".", 1) != 0) &&
                        (e->namelen != 2 || strncmp(e->name, "..", 2) != 0)) {
                         char epath[128];
@@ -67,7 +67,11 @@ static void export_rec(const char *dest, m3::inodeno_t dirno, const char *src) {
         size_t blockcount = (inode.size + sb.blocksize - 1) / sb.blocksize;
         size_t count = 0;
         for(uint32_t i = 0; i < blockcount; ++i) {
-            read_from_block(buffer, sb.blocksize, get_block_no(inode, i));
+            m3::blockno_t bno = get_block_no(inode, i);
+            if(bno == m3::HOLE_BNO)
+                memset(buffer, 0, sb.blocksize);
+            else
+                read_from_block(buffer, sb.blocksize, bno);
 
             size_t amount = i < blockcount - 1 ? sb.blocksize : inode.size - count;
             if(fwrite(buffer, 1, amount, f) != amount)
@@ -26,6 +26,8 @@
 FILE *file;
 m3::SuperBlock sb;
 static int exitcode = 0;
+// the number of additional uses of each block, if blocks can be shared
+static uint8_t *shared_uses = nullptr;
 
 static void set_inode(m3::Bitmap &inodes, m3::inodeno_t ino) {
     inodes.set(ino);
@@ -37,6 +39,45 @@ static void set_block(m3::Bitmap &blocks, m3::blockno_t no) {
     blocks.set(no);
 }
 
+static void set_data_block(m3::Bitmap &blocks, m3::blockno_t no) {
+    // data blocks may be shared; whether they are used too often is decided by check_refs
+    if(shared_uses && blocks.is_set(no)) {
+        if(shared_uses[no] == 0xFF)
+            errx(1, "Block number %u is used more than 256 times", no);
+        else
+            shared_uses[no]++;
+        return;
+    }
+    set_block(blocks, no);
+}
+
+static void check_refs() {
+    uint8_t *refs = new uint8_t[sb.total_blocks];
+    read_from_block(refs, sb.total_blocks, sb.first_refcnt_block());
+    for(m3::blockno_t bno = 0; bno < sb.total_blocks; ++bno) {
+        if(refs[bno] != shared_uses[bno]) {
+            errx(1, "Block %u has %u additional references, but %u additional uses", bno,
+                 refs[bno], shared_uses[bno]);
+        }
+    }
+    delete[] refs;
+}
+
+static void check_xattrs(m3::inodeno_t ino, m3::blockno_t bno) {
+    char *buffer = new char[sb.blocksize];
+    read_from_block(buffer, sb.blocksize, bno);
+
+    char *end = buffer + sb.blocksize;
+    m3::XAttrEntry *e = reinterpret_cast<m3::XAttrEntry *>(buffer);
+    while(e->data <= end && e->namelen > 0) {
+        if(e->data + e->namelen + e->valuelen > end)
+            errx(1, "Extended attributes of inode %u exceed block %u", ino, bno);
+        e = reinterpret_cast<m3::XAttrEntry *>(e->data + e->namelen + e->valuelen);
+    }
+
+    delete[] buffer;
+}
+
 static void collect_blocks_and_inodes(m3::inodeno_t ino, m3::Bitmap &blocks, m3::Bitmap &inodes) {
     if(inodes.is_set(ino))
         return;
@@ -49,6 +90,7 @@ static void collect_blocks_and_inodes(m3::inodeno_t ino, m3::Bitmap &blocks, m3:
 
     uint32_t block_count = (inode.size + sb.blocksize - 1) / sb.blocksize;
     if(M3FS_ISDIR(inode.mode)) {
+        uint32_t buckets = sb.hashed_dirs() ? sb.dir_buckets(inode.size) : 0;
         char *buffer = new char[sb.blocksize];
         for(uint32_t i = 0; i < block_count; ++i) {
             m3::blockno_t block = get_block_no(inode, i);
@@ -65,6 +107,17 @@ static void collect_blocks_and_inodes(m3::inodeno_t ino, m3::Bitmap &blocks, m3:
             m3::DirEntry *end = reinterpret_cast<m3::DirEntry *>(buffer + sb.blocksize);
             // actually next is not allowed to be 0. but to prevent endless looping here...
             while(e->next > 0 && e < end) {
+                if(e->namelen == 0) {
+                    e = reinterpret_cast<m3::DirEntry *>(reinterpret_cast<char *>(e) + e->next);
+                    continue;
+                }
+
+                if(i < buckets && (m3::DirEntry::hash(e->name, e->namelen) & (buckets - 1)) != i) {
+                    errx(1, "Entry '%.*s' of directory %u is in bucket %u, but belongs to %u",
+                         e->namelen, e->name, ino, i,
+                         m3::DirEntry::hash(e->name, e->namelen) & (buckets - 1));
+                }
+
                 if(!(e->namelen == 1 && strncmp(e->name, ".", 1) == 0) &&
                    !(e->namelen == 2 && strncmp(e->name, "..", 2) == 0)) {
                     if(e->nodeno >= sb.total_inodes) {
@@ -86,7 +139,8 @@ static void collect_blocks_and_inodes(m3::inodeno_t ino, m3::Bitmap &blocks, m3:
                      block_count, i);
                 break;
             }
-            set_block(blocks, block);
+            if(block != m3::HOLE_BNO)
+                set_data_block(blocks, block);
         }
     }
 
@@ -136,6 +190,13 @@ static void collect_blocks_and_inodes(m3::inodeno_t ino, m3::Bitmap &blocks, m3:
     else if(inode.dindirect != 0)
         errx(1, "Inode %u has %u extents, but double-indirect pointer is NOT 0", ino,
              inode.extents);
+
+    if(inode.xattr != 0) {
+        if(inode.xattr >= sb.total_blocks)
+            errx(1, "Inode %u has invalid extended-attribute block %u", ino, inode.xattr);
+        set_block(blocks, inode.xattr);
+        check_xattrs(ino, inode.xattr);
+    }
 }
 
 static void compare_bitmaps(const char *name, const m3::Bitmap &used, const m3::Bitmap &marked,
@@ -166,6 +227,28 @@ static void check_bitmap(const char *name, const m3::Bitmap &used, uint32_t tota
     compare_bitmaps(name, used, bm, total);
 }
 
+static void check_journal() {
+    if(sb.journal_blocks == 0)
+        return;
+
+    m3::JournalHeader hd, desc, commit;
+    read_from_block(&hd, sizeof(hd), sb.first_journal_block());
+    read_from_block(&desc, sizeof(desc), sb.first_journal_block() + 1);
+    // an all-zero journal has not been used yet
+    uint32_t seq = hd.magic == m3::JOURNAL_MAGIC ? hd.seq : 0;
+    if(desc.magic != m3::JOURNAL_MAGIC || desc.kind != m3::JOURNAL_DESC || desc.seq != seq)
+        return;
+    if(desc.count + 3 > sb.journal_blocks)
+        return;
+
+    read_from_block(&commit, sizeof(commit), sb.first_journal_block() + 2 + desc.count);
+    if(commit.magic == m3::JOURNAL_MAGIC && commit.kind == m3::JOURNAL_COMMIT &&
+       commit.seq == seq && commit.checksum == desc.checksum) {
+        errx(1, "Journal contains committed transaction %u; mount the file system to replay it",
+             seq);
+    }
+}
+
 static void usage(const char *name) {
     fprintf(stderr, "Usage: %s <image>\n", name);
     exit(EXIT_FAILURE);
@@ -198,10 +281,16 @@ int main(int argc, char **argv) {
     if(sb.free_inodes > sb.total_inodes)
         errx(1, "Free inodes is larger than total inodes");
 
+    // the bitmaps are not consistent before the journal has been replayed
+    check_journal();
+
     m3::Bitmap blocks(sb.total_blocks);
     m3::Bitmap inodes(sb.total_inodes);
 
-    // mark superblock, inode-bitmap and block-bitmap used
+    if(sb.reflink())
+        shared_uses = new uint8_t[sb.total_blocks]();
+
+    // mark superblock, inode-bitmap, block-bitmap, inodes, journal, and refcounts used
     for(m3::blockno_t bno = 0; bno < sb.first_data_block(); ++bno)
         blocks.set(bno);
 
@@ -211,6 +300,8 @@ int main(int argc, char **argv) {
     // now check if the bitmaps match
     check_bitmap("INode", inodes, sb.total_inodes, sb.free_inodes, sb.first_inodebm_block());
     check_bitmap("Block", blocks, sb.total_blocks, sb.free_blocks, sb.first_blockbm_block());
+    if(shared_uses)
+        check_refs();
 
     uint32_t first;
     if(sb.first_free_inode > (first = first_free(inodes, sb.total_inodes))) {
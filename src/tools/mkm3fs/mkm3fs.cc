@@ -30,9 +30,14 @@
 #include <string.h>
 #include <sys/dir.h>
 #include <sys/stat.h>
+#include <sys/xattr.h>
 #include <time.h>
 #include <unistd.h>
 
+#include <string>
+#include <utility>
+#include <vector>
+
 // undo stupid definition
 #undef direct
 
@@ -47,6 +52,9 @@
 enum {
     MAX_BLOCKS = 1024 * 1024,
     MAX_INODES = 4096,
+    // journal header, descriptor, commit, and the blocks of m3fs's meta buffer, because all changes
+    // of a request are committed as a single transaction
+    MIN_JOURNAL_BLOCKS = 3 + 128,
 };
 
 m3::SuperBlock sb;
@@ -190,13 +198,114 @@ static m3::DirEntry *write_dirent(m3::INode *dir, m3::DirEntry *prev, const char
     return entry;
 }
 
+static void copy_xattrs(const char *path, m3::INode *ino) {
+    static char names[m3::MAX_BLOCK_SIZE];
+    static char buffer[m3::MAX_BLOCK_SIZE];
+
+    ssize_t len = llistxattr(path, names, sizeof(names));
+    if(len < 0) {
+        // not all host file systems support extended attributes
+        if(errno == ENOTSUP)
+            return;
+        if(errno == ERANGE) {
+            fprintf(stderr, "Warning: ignored extended attributes of '%s' (too many)\n", path);
+            return;
+        }
+        err(1, "llistxattr of '%s' failed", path);
+    }
+
+    memset(buffer, 0, sizeof(buffer));
+    size_t off = 0;
+    for(char *name = names; name < names + len; name += strlen(name) + 1) {
+        char value[UINT8_MAX];
+        ssize_t vlen = lgetxattr(path, name, value, sizeof(value));
+        size_t nlen = strlen(name);
+        if(vlen < 0 && errno != ERANGE)
+            err(1, "lgetxattr of '%s' for '%s' failed", name, path);
+        if(vlen < 0 || nlen > UINT8_MAX ||
+           off + sizeof(m3::XAttrEntry) + nlen + static_cast<size_t>(vlen) > sb.blocksize) {
+            fprintf(stderr, "Warning: ignored extended attribute '%s' of '%s' (too large)\n",
+                    name, path);
+            continue;
+        }
+
+        m3::XAttrEntry *e = reinterpret_cast<m3::XAttrEntry *>(buffer + off);
+        e->namelen = static_cast<uint8_t>(nlen);
+        e->valuelen = static_cast<uint8_t>(vlen);
+        memcpy(e->data, name, nlen);
+        memcpy(e->data + nlen, value, static_cast<size_t>(vlen));
+        off += sizeof(m3::XAttrEntry) + nlen + static_cast<size_t>(vlen);
+    }
+
+    // the rest of the block is zero, which terminates the list
+    if(off > 0) {
+        ino->xattr = alloc_block(false);
+        PRINT("Writing extended attributes of %s to block %u\n", path, ino->xattr);
+        write_to_block(buffer, sb.blocksize, ino->xattr);
+    }
+}
+
+static size_t dirent_size(size_t namelen) {
+    // all entries should be 4-byte aligned
+    return sizeof(m3::DirEntry) + m3::Math::round_up(namelen, static_cast<size_t>(4));
+}
+
+static uint32_t dirent_bucket(const std::string &name, size_t buckets) {
+    return m3::DirEntry::hash(name.c_str(), name.size()) & (buckets - 1);
+}
+
+static void write_hashed_dir(const char *path, m3::INode *dir,
+                             const std::vector<std::pair<std::string, m3::inodeno_t>> &entries) {
+    // use the smallest number of buckets such that each bucket fits into a block
+    size_t buckets = 1;
+    while(true) {
+        std::vector<size_t> sizes(buckets, 0);
+        bool fits = true;
+        for(auto &e : entries) {
+            size_t &size = sizes[dirent_bucket(e.first, buckets)];
+            size += dirent_size(e.first.size());
+            fits &= size <= sb.blocksize;
+        }
+        if(fits)
+            break;
+
+        buckets *= 2;
+        if(buckets > sb.total_blocks)
+            errx(1, "Too many hash collisions in directory '%s'", path);
+    }
+
+    char *buffer = new char[sb.blocksize];
+    for(size_t b = 0; b < buckets; ++b) {
+        bool new_ext = blks_per_extent > 0 && (b % blks_per_extent) == 0;
+        m3::blockno_t bno = store_blockno(path, dir, alloc_block(new_ext), new_ext);
+
+        // empty buckets consist of a single empty entry spanning the whole block
+        memset(buffer, 0, sb.blocksize);
+        m3::DirEntry *last = reinterpret_cast<m3::DirEntry *>(buffer);
+        size_t off = 0;
+        for(auto &e : entries) {
+            if(dirent_bucket(e.first, buckets) != b)
+                continue;
+
+            last = reinterpret_cast<m3::DirEntry *>(buffer + off);
+            last->nodeno = e.second;
+            last->namelen = static_cast<uint32_t>(e.first.size());
+            last->next = static_cast<uint32_t>(dirent_size(e.first.size()));
+            memcpy(last->name, e.first.c_str(), e.first.size());
+            off += last->next;
+        }
+        last->next += sb.blocksize - off;
+
+        PRINT("Writing bucket %zu of directory %s to block %u\n", b, path, bno);
+        write_to_block(buffer, sb.blocksize, bno);
+    }
+    delete[] buffer;
+}
+
 static m3::inodeno_t copy(const char *path, m3::inodeno_t parent, int level) {
     static char buffer[m3::MAX_BLOCK_SIZE];
     struct stat st;
-    int fd = open(path, O_RDONLY);
-    if(fd < 0)
-        err(1, "open of '%s' failed", path);
-    if(fstat(fd, &st) != 0)
+    if(lstat(path, &st) != 0)
         err(1, "stat of '%s' failed", path);
     if(level == 0 && !S_ISDIR(st.st_mode))
         errx(1, "'%s' is no directory", path);
@@ -218,11 +327,22 @@ static m3::inodeno_t copy(const char *path, m3::inodeno_t parent, int level) {
     ino.indirect = 0;
     ino.dindirect = 0;
     ino.extents = 0;
+    // all files belong to root; the owner on the host is meaningless within M3
+    ino.uid = 0;
+    ino.gid = 0;
+    ino.xattr = 0;
+    memset(ino.reserved, 0, sizeof(ino.reserved));
 
     inode_bitmap->set(ino.inode);
     sb.free_inodes--;
 
+    copy_xattrs(path, &ino);
+
     if(S_ISREG(ino.mode)) {
+        int fd = open(path, O_RDONLY);
+        if(fd < 0)
+            err(1, "open of '%s' failed", path);
+
         ssize_t len;
         for(size_t i = 0; (len = read(fd, buffer, sb.blocksize)) > 0; i++) {
             bool new_ext = blks_per_extent > 0 && (i % blks_per_extent) == 0;
@@ -231,6 +351,20 @@ static m3::inodeno_t copy(const char *path, m3::inodeno_t parent, int level) {
             write_to_block(buffer, static_cast<size_t>(len), bno);
         }
         ino.size = static_cast<uint64_t>(st.st_size);
+        close(fd);
+    }
+    else if(S_ISLNK(ino.mode)) {
+        // the target is stored in a single block
+        ssize_t len = readlink(path, buffer, sizeof(buffer));
+        if(len < 0)
+            err(1, "readlink of '%s' failed", path);
+        if(len == 0 || static_cast<size_t>(len) > sb.blocksize)
+            errx(1, "Target of symlink '%s' is empty or too long", path);
+
+        m3::blockno_t bno = store_blockno(path, &ino, alloc_block(false), false);
+        PRINT("Writing target of symlink %s to block %u\n", path, bno);
+        write_to_block(buffer, static_cast<size_t>(len), bno);
+        ino.size = static_cast<uint64_t>(len);
     }
     else if(S_ISDIR(ino.mode)) {
         DIR *d = opendir(path);
@@ -238,21 +372,8 @@ static m3::inodeno_t copy(const char *path, m3::inodeno_t parent, int level) {
             err(1, "opendir of '%s' failed", path);
 
         struct dirent *e;
-        size_t diroff = 0;
-        m3::DirEntry *prev = nullptr, *newent = nullptr;
-        m3::blockno_t block = alloc_block(false);
-        ino.size = sb.blocksize;
-
-        ino.extents = 1;
-        ino.direct[0].start = block;
-        ino.direct[0].length = 1;
-
+        std::vector<std::pair<std::string, m3::inodeno_t>> entries;
         while((e = readdir(d))) {
-            if(newent) {
-                free(prev);
-                prev = newent;
-            }
-
             m3::inodeno_t inode;
             if(strcmp(e->d_name, ".") == 0)
                 inode = ino.inode;
@@ -264,40 +385,70 @@ static m3::inodeno_t copy(const char *path, m3::inodeno_t parent, int level) {
                 inode = copy(epath, ino.inode, level + 1);
                 delete[] epath;
             }
-
-            newent = write_dirent(&ino, prev, path, e->d_name, inode, diroff, block);
+            entries.push_back(std::make_pair(std::string(e->d_name), inode));
         }
+        closedir(d);
+
+        if(sb.hashed_dirs())
+            write_hashed_dir(path, &ino, entries);
+        else {
+            size_t diroff = 0;
+            m3::DirEntry *prev = nullptr, *newent = nullptr;
+            m3::blockno_t block = alloc_block(false);
+            ino.size = sb.blocksize;
+
+            ino.extents = 1;
+            ino.direct[0].start = block;
+            ino.direct[0].length = 1;
+
+            for(auto &ent : entries) {
+                if(newent) {
+                    free(prev);
+                    prev = newent;
+                }
+
+                newent = write_dirent(&ino, prev, path, ent.first.c_str(), ent.second, diroff,
+                                      block);
+            }
 
-        // set next of last entry to the end of the block
-        size_t newentlen = newent->next;
-        newent->next += sb.blocksize - diroff;
-        write_to_block(newent, newentlen, block, diroff - newentlen);
+            // set next of last entry to the end of the block
+            size_t newentlen = newent->next;
+            newent->next += sb.blocksize - diroff;
+            write_to_block(newent, newentlen, block, diroff - newentlen);
 
-        free(newent);
-        free(prev);
-        closedir(d);
+            free(newent);
+            free(prev);
+        }
     }
     else
-        fprintf(stderr, "Warning: ignored file '%s' (no regular file or directory)\n", path);
-    close(fd);
+        fprintf(stderr, "Warning: ignored file '%s' (no regular file, directory, or symlink)\n",
+                path);
 
     // write inode
     write_to_block(&ino, sizeof(ino), sb.first_inode_block(), ino.inode * sizeof(m3::INode));
     return ino.inode;
 }
 
+static void usage(const char *name) {
+    fprintf(stderr,
+            "Usage: %s <fsimage> <path> <blocks> <inodes> <blksperext> [-j <blocks>] [-rand]"
+            " [-hashdirs] [-reflink]\n",
+            name);
+    fprintf(stderr, "  <fsimage> is the image to create\n");
+    fprintf(stderr, "  <path> is the path of the host-directory to copy into the fs\n");
+    fprintf(stderr, "  <blocks> is the number of blocks the fs image should have\n");
+    fprintf(stderr, "  <inodes> is the number of inodes the fs image should have\n");
+    fprintf(stderr, "  <blksperext> the max. number of blocks per extent (0 = unlimited)\n");
+    fprintf(stderr, "  -j <blocks>: the number of blocks for the metadata journal (0 = none)\n");
+    fprintf(stderr, "  -rand: use random for the block allocation\n");
+    fprintf(stderr, "  -hashdirs: use hashed directories for fast lookups in large directories\n");
+    fprintf(stderr, "  -reflink: allow files to share data blocks (copy-on-write clones)\n");
+    exit(EXIT_FAILURE);
+}
+
 int main(int argc, char **argv) {
-    if(argc != 6 && argc != 7) {
-        fprintf(stderr, "Usage: %s <fsimage> <path> <blocks> <inodes> <blksperext> [-rand]\n",
-                argv[0]);
-        fprintf(stderr, "  <fsimage> is the image to create\n");
-        fprintf(stderr, "  <path> is the path of the host-directory to copy into the fs\n");
-        fprintf(stderr, "  <blocks> is the number of blocks the fs image should have\n");
-        fprintf(stderr, "  <inodes> is the number of inodes the fs image should have\n");
-        fprintf(stderr, "  <blksperext> the max. number of blocks per extent (0 = unlimited)\n");
-        fprintf(stderr, "  -rand: use random for the block allocation\n");
-        return EXIT_FAILURE;
-    }
+    if(argc < 6)
+        usage(argv[0]);
 
     srand(static_cast<uint>(time(nullptr)));
 
@@ -306,14 +457,30 @@ int main(int argc, char **argv) {
     sb.total_inodes = strtoul(argv[4], nullptr, 0);
     sb.free_blocks = sb.total_blocks;
     sb.free_inodes = sb.total_inodes;
+    sb.journal_blocks = 0;
+    sb.features = 0;
     blks_per_extent = strtoul(argv[5], nullptr, 0);
-    use_rand = argc == 7 && strcmp(argv[6], "-rand");
+    use_rand = false;
+    for(int i = 6; i < argc; ++i) {
+        if(strcmp(argv[i], "-rand") == 0)
+            use_rand = true;
+        else if(strcmp(argv[i], "-j") == 0 && i + 1 < argc)
+            sb.journal_blocks = strtoul(argv[++i], nullptr, 0);
+        else if(strcmp(argv[i], "-hashdirs") == 0)
+            sb.features |= m3::SuperBlock::FEAT_HASHED_DIRS;
+        else if(strcmp(argv[i], "-reflink") == 0)
+            sb.features |= m3::SuperBlock::FEAT_REFLINK;
+        else
+            usage(argv[0]);
+    }
     last_block = sb.first_data_block() - 1;
 
     if(sb.total_blocks > MAX_BLOCKS)
         errx(1, "Too many blocks. Max is %d", MAX_BLOCKS);
     if(sb.total_inodes > MAX_INODES)
         errx(1, "Too many inodes. Max is %d", MAX_INODES);
+    if(sb.journal_blocks != 0 && sb.journal_blocks < MIN_JOURNAL_BLOCKS)
+        errx(1, "Too few journal blocks. Min is %d", MIN_JOURNAL_BLOCKS);
     if(sb.first_data_block() > sb.free_blocks)
         errx(1, "Not enough blocks");
 
@@ -327,7 +494,7 @@ int main(int argc, char **argv) {
     // first, init the fs-image with zeros
     ftruncate(fileno(file), static_cast<off_t>(sb.blocksize * sb.total_blocks));
 
-    // mark superblock, inode and block bitmap and inode blocks as occupied
+    // mark superblock, inode and block bitmap, inode blocks, journal, and refcounts as occupied
     for(m3::blockno_t i = 0; i < sb.first_data_block(); ++i)
         block_bitmap->set(i);
     sb.free_blocks -= sb.first_data_block();
@@ -40,6 +40,23 @@ static void print_sb() {
     printf("  free_blocks: %u\n", sb.free_blocks);
     printf("  first_free_inode: %u\n", sb.first_free_inode);
     printf("  first_free_block: %u\n", sb.first_free_block);
+    printf("  journal_blocks: %u\n", sb.journal_blocks);
+    printf("  features: %#x%s\n", sb.features, sb.hashed_dirs() ? " (hashed dirs)" : "");
+}
+
+static void print_journal() {
+    if(sb.journal_blocks == 0) {
+        printf("No journal\n");
+        return;
+    }
+
+    m3::JournalHeader hd, desc;
+    read_from_block(&hd, sizeof(hd), sb.first_journal_block());
+    read_from_block(&desc, sizeof(desc), sb.first_journal_block() + 1);
+    printf("Journal (blocks %u..%u):\n", sb.first_journal_block(), sb.first_data_block() - 1);
+    printf("  header: magic=%#x seq=%u\n", hd.magic, hd.seq);
+    printf("  descriptor: magic=%#x kind=%u seq=%u count=%u checksum=%#x\n", desc.magic, desc.kind,
+           desc.seq, desc.count, desc.checksum);
 }
 
 static void print_bitmap(uint32_t total, const m3::Bitmap &bitmap) {
@@ -86,6 +103,10 @@ static void print_extents(m3::blockno_t bno, int indent, int depth) {
     read_from_block(extents, sb.blocksize, bno);
 
     for(uint i = 0; extents[i].length && i < sb.extents_per_block(); ++i) {
+        if(extents[i].start == 0 && depth == 0) {
+            printf("%*s%3u: hole (%u)\n", indent * 2, "", i, extents[i].length);
+            continue;
+        }
         printf("%*s%3u: %4u .. %4u (%u)\n", indent * 2, "", i, extents[i].start,
                extents[i].length ? extents[i].start + extents[i].length - 1 : 0, extents[i].length);
         if(extents[i].start != 0 && depth > 0)
@@ -95,6 +116,31 @@ static void print_extents(m3::blockno_t bno, int indent, int depth) {
     delete[] extents;
 }
 
+static void print_xattrs(m3::blockno_t bno, int indent) {
+    if(bno >= sb.total_blocks)
+        err(1, "Invalid block number %u (have only %u blocks)", bno, sb.total_blocks);
+
+    char *buffer = new char[sb.blocksize];
+    read_from_block(buffer, sb.blocksize, bno);
+
+    char *end = buffer + sb.blocksize;
+    m3::XAttrEntry *e = reinterpret_cast<m3::XAttrEntry *>(buffer);
+    while(e->data <= end && e->namelen > 0) {
+        if(e->data + e->namelen + e->valuelen > end) {
+            printf("%*sname=<invalid> value=<invalid>\n", indent * 2, "");
+            break;
+        }
+        // values are arbitrary bytes
+        printf("%*sname=%.*s value=", indent * 2, "", e->namelen, e->data);
+        for(size_t i = 0; i < e->valuelen; ++i)
+            printf("%02x", static_cast<unsigned char>(e->data[e->namelen + i]));
+        printf("\n");
+        e = reinterpret_cast<m3::XAttrEntry *>(e->data + e->namelen + e->valuelen);
+    }
+
+    delete[] buffer;
+}
+
 static void print_inode(m3::inodeno_t ino, bool all) {
     if(ino >= sb.total_inodes)
         err(1, "Invalid inode number %u (have only %u inodes)", ino, sb.total_inodes);
@@ -104,12 +150,18 @@ static void print_inode(m3::inodeno_t ino, bool all) {
     printf("  devno: %u\n", inode.devno);
     printf("  inode: %u\n", inode.inode);
     printf("  mode: %#04o\n", inode.mode);
+    printf("  uid: %u\n", inode.uid);
+    printf("  gid: %u\n", inode.gid);
     printf("  links: %u\n", inode.links);
     printf("  size: %" PRIu64 "\n", inode.size);
     print_time(inode.lastaccess, "lastaccess");
     print_time(inode.lastmod, "lastmod");
     printf("  extents: %u\n", inode.extents);
     for(int i = 0; i < m3::INODE_DIR_COUNT; ++i) {
+        if(inode.direct[i].start == 0 && inode.direct[i].length > 0) {
+            printf("  direct[%d]: hole (%u)\n", i, inode.direct[i].length);
+            continue;
+        }
         printf("  direct[%d]: %4u .. %4u (%u)\n", i, inode.direct[i].start,
                inode.direct[i].length ? inode.direct[i].start + inode.direct[i].length - 1 : 0,
                inode.direct[i].length);
@@ -120,6 +172,9 @@ static void print_inode(m3::inodeno_t ino, bool all) {
     printf("  dindirect: %u\n", inode.dindirect);
     if(all && inode.dindirect != 0)
         print_extents(inode.dindirect, 2, 1);
+    printf("  xattr: %u\n", inode.xattr);
+    if(all && inode.xattr != 0)
+        print_xattrs(inode.xattr, 2);
 }
 
 static void print_inodes() {
@@ -234,6 +289,9 @@ static void print_tree(m3::inodeno_t dirno, const char *path, int level) {
 
     if(S_ISDIR(inode.mode)) {
         printf("%*sListing of directory '%s' (%u)\n", level * 2, "", path, dirno);
+        if(sb.hashed_dirs()) {
+            printf("%*s%u buckets\n", (level + 1) * 2, "", sb.dir_buckets(inode.size));
+        }
         char *buffer = new char[sb.blocksize];
         size_t blockcount = (inode.size + sb.blocksize - 1) / sb.blocksize;
         for(uint32_t i = 0; i < blockcount; ++i) {
@@ -243,7 +301,7 @@ static void print_tree(m3::inodeno_t dirno, const char *path, int level) {
             m3::DirEntry *end = reinterpret_cast<m3::DirEntry *>(buffer + sb.blocksize);
             m3::DirEntry *e = begin;
             while(e >= begin && e < end && e->next > 0) {
-                if(e->name + e->namelen <= reinterpret_cast<char *>(end)) {
+                if(e->namelen > 0 && e->name + e->namelen <= reinterpret_cast<char *>(end)) {
                     printf("%*sino=%u len=%u next=%u name=%.*s\n", (level + 1) * 2, "", e->nodeno,
                            e->namelen, e->next, e->namelen, e->name);
 
@@ -260,6 +318,13 @@ static void print_tree(m3::inodeno_t dirno, const char *path, int level) {
         }
         delete[] buffer;
     }
+    else if(S_ISLNK(inode.mode)) {
+        char *buffer = new char[sb.blocksize];
+        read_from_block(buffer, sb.blocksize, get_block_no(inode, 0));
+        int len = static_cast<int>(inode.size < sb.blocksize ? inode.size : sb.blocksize);
+        printf("%*sSymlink '%s' (%u) -> '%.*s'\n", level * 2, "", path, dirno, len, buffer);
+        delete[] buffer;
+    }
 }
 
 static void usage(const char *name) {
@@ -268,6 +333,7 @@ static void usage(const char *name) {
     fprintf(stderr, "  sb             - show superblock\n");
     fprintf(stderr, "  ibm            - show inode bitmap\n");
     fprintf(stderr, "  bbm            - show block bitmap\n");
+    fprintf(stderr, "  journal        - show journal state\n");
     fprintf(stderr, "  inodes         - show all inodes\n");
     fprintf(stderr, "  tree           - show directory tree\n");
     fprintf(stderr, "  ino <n>        - show inode <n>\n");
@@ -276,6 +342,7 @@ static void usage(const char *name) {
     fprintf(stderr, "  inotext <n>    - show inode <n> as text\n");
     fprintf(stderr, "  dir <n>        - show block <n> as directory\n");
     fprintf(stderr, "  extents <n>    - show block <n> as extents\n");
+    fprintf(stderr, "  xattrs <n>     - show block <n> as extended attributes\n");
     fprintf(stderr, "  bytes <n>      - show block <n> as bytes\n");
     fprintf(stderr, "  text <n>       - show block <n> as text\n");
     exit(EXIT_FAILURE);
@@ -286,6 +353,7 @@ int main(int argc, char **argv) {
         usage(argv[0]);
     if(argc < 4 && (strcmp(argv[2], "ino") == 0 || strcmp(argv[2], "dir") == 0 ||
                     strcmp(argv[2], "extents") == 0 || strcmp(argv[2], "inoextents") == 0 ||
+                    strcmp(argv[2], "xattrs") == 0 ||
                     strcmp(argv[2], "inobytes") == 0 || strcmp(argv[2], "inotext") == 0 ||
                     strcmp(argv[2], "bytes") == 0 || strcmp(argv[2], "text") == 0)) {
         usage(argv[0]);
@@ -307,6 +375,8 @@ int main(int argc, char **argv) {
         print_inodebm();
     else if(strcmp(argv[2], "bbm") == 0)
         print_blockbm();
+    else if(strcmp(argv[2], "journal") == 0)
+        print_journal();
     else if(strcmp(argv[2], "inodes") == 0)
         print_inodes();
     else if(strcmp(argv[2], "tree") == 0)
@@ -335,6 +405,11 @@ int main(int argc, char **argv) {
         m3::blockno_t bno = strtoul(argv[3], nullptr, 0);
         print_as_extents(bno);
     }
+    else if(strcmp(argv[2], "xattrs") == 0) {
+        m3::blockno_t bno = strtoul(argv[3], nullptr, 0);
+        printf("Showing block %u as extended attributes:\n", bno);
+        print_xattrs(bno, 1);
+    }
     else if(strcmp(argv[2], "bytes") == 0) {
         m3::blockno_t bno = strtoul(argv[3], nullptr, 0);
         print_block_bytes(0, bno);